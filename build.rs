@@ -1,5 +1,5 @@
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn main() {
@@ -30,6 +30,14 @@ fn main() {
             println!("cargo:warning=FFmpeg repository already exists. Skipping clone.");
         }
         
+        // Libraries built ahead of FFmpeg or compiled into the wrapper
+        let deps_prefix = out_path.join("deps");
+        
+        // libwebp for the WebP image and animation encoders, which FFmpeg has no native version of
+        build_dependency(out_path, &deps_prefix, "libwebp", "https://chromium.googlesource.com/webm/libwebp", "v1.3.2",
+            BuildSystem::Autotools(&["--enable-libwebpmux", "--disable-libwebpdemux", "--disable-libwebpdecoder", "--disable-png",
+                "--disable-jpeg", "--disable-tiff", "--disable-gif", "--disable-wic", "--disable-sdl", "--disable-gl"]));
+        
         // Configure FFmpeg for WebAssembly compilation
         if !ffmpeg_dir.join(".configured").exists() {
             println!("cargo:warning=Configuring FFmpeg for WebAssembly...");
@@ -51,8 +59,20 @@ fn main() {
                     "--disable-network",
                     "--disable-iconv",
                     "--enable-small",
+                    "--enable-libwebp",
+                    // Animated WebP and APNG output
+                    "--enable-encoder=libwebp_anim,apng",
+                    "--enable-muxer=webp,apng",
+                    // HLS packages (playlists plus MPEG-TS or fMP4 segments)
+                    "--enable-muxer=hls,mpegts,mp4",
+                    // DASH packages (MPD manifest plus fMP4 segments)
+                    "--enable-muxer=dash",
+                    "--pkg-config-flags=--static",
+                    &format!("--extra-cflags=-I{}", deps_prefix.join("include").display()),
+                    &format!("--extra-ldflags=-L{}", deps_prefix.join("lib").display()),
                     &format!("--prefix={}", ffmpeg_dir.join("build").display())
                 ])
+                .env("PKG_CONFIG_PATH", deps_prefix.join("lib/pkgconfig"))
                 .status()
                 .expect("Failed to configure FFmpeg");
                 
@@ -92,6 +112,46 @@ fn main() {
         //     println!("cargo:warning=FFmpeg already installed. Skipping installation.");
         // }
         
+        // Optional libraries compiled into the wrapper
+        let mut wrapper_args: Vec<String> = Vec::new();
+        
+        let burn_subtitles = env::var("CARGO_FEATURE_BURN_SUBTITLES").is_ok();
+        let drawtext = env::var("CARGO_FEATURE_DRAWTEXT").is_ok();
+        
+        // Both text renderers use FreeType and the bundled font
+        if burn_subtitles || drawtext {
+            build_dependency(out_path, &deps_prefix, "freetype", "https://gitlab.freedesktop.org/freetype/freetype.git", "VER-2-13-2",
+                BuildSystem::Meson(&["-Dzlib=disabled", "-Dbzip2=disabled", "-Dpng=disabled", "-Dharfbuzz=disabled", "-Dbrotli=disabled"]));
+            
+            let font = bundled_font(out_path);
+            wrapper_args.extend([
+                "-DHAVE_FREETYPE".to_string(),
+                "-I".to_string(), deps_prefix.join("include").display().to_string(),
+                "-I".to_string(), deps_prefix.join("include/freetype2").display().to_string(),
+                "--embed-file".to_string(), format!("{}@/fonts/default.ttf", font.display()),
+            ]);
+        }
+        
+        if burn_subtitles {
+            build_dependency(out_path, &deps_prefix, "fribidi", "https://github.com/fribidi/fribidi.git", "v1.0.13",
+                BuildSystem::Meson(&["-Ddocs=false", "-Dbin=false", "-Dtests=false"]));
+            build_dependency(out_path, &deps_prefix, "harfbuzz", "https://github.com/harfbuzz/harfbuzz.git", "8.3.0",
+                BuildSystem::Meson(&["-Dfreetype=enabled", "-Dglib=disabled", "-Dgobject=disabled", "-Dcairo=disabled",
+                    "-Dicu=disabled", "-Dtests=disabled", "-Ddocs=disabled", "-Dutilities=disabled"]));
+            build_dependency(out_path, &deps_prefix, "libass", "https://github.com/libass/libass.git", "0.17.1",
+                BuildSystem::Autotools(&["--disable-fontconfig", "--disable-require-system-font-provider", "--disable-asm", "--disable-libunibreak"]));
+            
+            wrapper_args.extend([
+                "-DHAVE_LIBASS".to_string(),
+                "-lass".to_string(), "-lharfbuzz".to_string(), "-lfribidi".to_string(),
+            ]);
+        }
+        
+        // Linked last, after the libraries that depend on it
+        if burn_subtitles || drawtext {
+            wrapper_args.push("-lfreetype".to_string());
+        }
+        
         // Compile our C wrapper
         println!("cargo:warning=Compiling FFmpeg wrapper...");
         let wrapper_c = concat!(env!("CARGO_MANIFEST_DIR"), "/src/ffmpeg_wrapper.c");
@@ -104,16 +164,19 @@ fn main() {
                 "-o", &wrapper_js.to_string_lossy(),
                 "-I", &ffmpeg_dir.join("build/include").to_string_lossy(),
                 "-L", &ffmpeg_dir.join("build/lib").to_string_lossy(),
+                "-L", &deps_prefix.join("lib").to_string_lossy(),
                 "-lavformat", "-lavcodec", "-lswscale", "-lavutil", "-lswresample",
+                "-lwebpmux", "-lwebp", "-lsharpyuv",
                 "-s", "WASM=1",
                 "-s", "ALLOW_MEMORY_GROWTH=1",
                 "-s", "INITIAL_MEMORY=33554432", // 32MB initial heap
                 "-s", "MAXIMUM_MEMORY=536870912", // 512MB max heap
-                "-s", "EXPORTED_FUNCTIONS=['_malloc','_free','_init_ffmpeg','_transcode','_free_transcode_result']",
-                "-s", "EXPORTED_RUNTIME_METHODS=['ccall','cwrap']",
+                "-s", "EXPORTED_FUNCTIONS=['_malloc','_free','_init_ffmpeg','_transcode','_free_transcode_result','_get_encoder_pixel_formats','_get_encoder_sample_rates','_get_encoder_channel_layouts','_measure_loudness','_probe_input','_add_input','_clear_inputs','_get_build_features','_extract_cover','_extract_frame','_contact_sheet','_image_sequence_open','_image_sequence_next','_image_sequence_close','_split_open','_split_next','_split_close','_ladder_open','_ladder_step','_ladder_error','_ladder_progress','_ladder_close','_get_output_file_count','_get_output_file_name','_get_output_file_data','_get_output_file_size','_clear_output_files']",
+                "-s", "EXPORTED_RUNTIME_METHODS=['ccall','cwrap','UTF8ToString']",
                 "-O3",  // Optimization level
                 "--pre-js", concat!(env!("CARGO_MANIFEST_DIR"), "/src/ffmpeg_pre.js"),
             ])
+            .args(wrapper_args)
             .status()
             .expect("Failed to compile FFmpeg wrapper");
             
@@ -134,5 +197,120 @@ fn main() {
         println!("cargo:rustc-link-search=native={}", ffmpeg_dir.join("build/lib").display());
         println!("cargo:rerun-if-changed=src/ffmpeg_wrapper.c");
         println!("cargo:rerun-if-changed=src/ffmpeg_pre.js");
+        println!("cargo:rerun-if-env-changed=FFMPEG_WEB_FONT");
+    }
+}
+
+/// How a dependency library is configured
+enum BuildSystem {
+    Autotools(&'static [&'static str]),
+    Meson(&'static [&'static str]),
+}
+
+/// Meson cross file for Emscripten builds
+const MESON_CROSS_FILE: &str = "[binaries]
+c = 'emcc'
+cpp = 'em++'
+ar = 'emar'
+strip = 'emstrip'
+pkg-config = 'pkg-config'
+
+[host_machine]
+system = 'emscripten'
+cpu_family = 'wasm32'
+cpu = 'wasm32'
+endian = 'little'
+";
+
+/// Clone a library and install it statically into `prefix` with Emscripten
+fn build_dependency(out_path: &Path, prefix: &Path, name: &str, repo: &str, tag: &str, build_system: BuildSystem) {
+    let source_dir = out_path.join(name);
+    let pkg_config_path = prefix.join("lib/pkgconfig");
+    
+    if !source_dir.exists() {
+        println!("cargo:warning=Cloning {}...", name);
+        let _ = Command::new("git")
+            .args(["clone", "--depth", "1", "--branch", tag, repo, name])
+            .current_dir(out_path)
+            .status()
+            .unwrap_or_else(|_| panic!("Failed to clone {}", name));
+    }
+    
+    if source_dir.join(".built").exists() {
+        println!("cargo:warning={} already built. Skipping build.", name);
+        return;
+    }
+    
+    println!("cargo:warning=Building {}...", name);
+    match build_system {
+        BuildSystem::Autotools(args) => {
+            if !source_dir.join("configure").exists() {
+                let _ = Command::new("sh")
+                    .arg("autogen.sh")
+                    .current_dir(&source_dir)
+                    .status()
+                    .unwrap_or_else(|_| panic!("Failed to generate configure script for {}", name));
+            }
+            
+            let _ = Command::new("emconfigure")
+                .current_dir(&source_dir)
+                .arg("./configure")
+                .arg(format!("--prefix={}", prefix.display()))
+                .args(["--host=wasm32-unknown-emscripten", "--disable-shared", "--enable-static"])
+                .args(args)
+                .env("PKG_CONFIG_PATH", &pkg_config_path)
+                .status()
+                .unwrap_or_else(|_| panic!("Failed to configure {}", name));
+            
+            let _ = Command::new("emmake")
+                .current_dir(&source_dir)
+                .args(["make", "-j4", "install"])
+                .status()
+                .unwrap_or_else(|_| panic!("Failed to build {}", name));
+        }
+        BuildSystem::Meson(args) => {
+            let cross_file = out_path.join("emscripten-cross.txt");
+            std::fs::write(&cross_file, MESON_CROSS_FILE).unwrap();
+            
+            let _ = Command::new("meson")
+                .current_dir(&source_dir)
+                .args(["setup", "build", "--default-library=static", "--buildtype=release"])
+                .arg(format!("--cross-file={}", cross_file.display()))
+                .arg(format!("--prefix={}", prefix.display()))
+                .arg("--libdir=lib")
+                .args(args)
+                .env("PKG_CONFIG_PATH", &pkg_config_path)
+                .status()
+                .unwrap_or_else(|_| panic!("Failed to configure {}", name));
+            
+            let _ = Command::new("meson")
+                .current_dir(&source_dir)
+                .args(["install", "-C", "build"])
+                .status()
+                .unwrap_or_else(|_| panic!("Failed to build {}", name));
+        }
+    }
+    
+    std::fs::write(source_dir.join(".built"), "").unwrap();
+}
+
+/// Font bundled for subtitle and text overlay rendering
+const FONT_URL: &str = "https://github.com/notofonts/notofonts.github.io/raw/main/fonts/NotoSans/hinted/ttf/NotoSans-Regular.ttf";
+
+/// Path of the font to embed; FFMPEG_WEB_FONT can point at a local TTF/OTF instead
+fn bundled_font(out_path: &Path) -> PathBuf {
+    if let Ok(font) = env::var("FFMPEG_WEB_FONT") {
+        return PathBuf::from(font);
+    }
+    
+    let font = out_path.join("NotoSans-Regular.ttf");
+    if !font.exists() {
+        println!("cargo:warning=Downloading font...");
+        let _ = Command::new("curl")
+            .args(["-L", "-o", &font.to_string_lossy(), FONT_URL])
+            .status()
+            .expect("Failed to download subtitle font");
     }
+    
+    font
 }
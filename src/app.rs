@@ -1,10 +1,29 @@
 use crate::components::{
     file_input::FileInput,
     format_selector::FormatSelector,
-    codec_selector::CodecSelector,
+    codec_selector::{CodecSelector, channel_layout_supported},
     progress::Progress,
+    track_list::{TrackList, TrackEntry, track_selections},
+    subtitle_burn_in::SubtitleBurnIn,
+    text_overlay::TextOverlaySettings,
+    metadata_editor::{MetadataEditor, METADATA_FIELDS},
+    chapter_editor::ChapterEditor,
+    cover_art::CoverArtSettings,
+    frame_export::FrameExport,
+    contact_sheet::ContactSheetSettings,
+    animation_settings::AnimationSettingsEditor,
+    image_sequence::ImageSequenceSettings,
+    image_video::ImageVideoSettings,
+    concat_list::ConcatList,
+    split_settings::SplitSettingsForm,
+    hls_settings::HlsPackageSettings,
+    dash_settings::DashPackageSettings,
+    ladder_settings::RenditionLadder,
+    mp4_settings::Mp4LayoutSettings,
 };
+use crate::chapters;
 use crate::ffmpeg;
+use crate::zip;
 use yew::prelude::*;
 use wasm_bindgen::prelude::*;
 use web_sys::{Url, HtmlAnchorElement, File, FileReader};
@@ -12,6 +31,13 @@ use js_sys::Uint8Array;
 use wasm_bindgen::JsCast;
 use gloo::console::log;
 
+// A clip to join; `data` is None until the file has been read
+struct Clip {
+    id: u32,
+    name: String,
+    data: Option<Vec<u8>>,
+}
+
 pub struct App {
     input_file: Option<File>,
     input_data: Option<Vec<u8>>,
@@ -20,6 +46,65 @@ pub struct App {
     audio_codec: String,
     video_bitrate: String,
     audio_bitrate: String,
+    pixel_format: String,
+    supported_pixel_formats: Vec<String>,
+    sample_rate: String,
+    channel_layout: String,
+    supported_sample_rates: Vec<i32>,
+    supported_channel_layouts: Vec<String>,
+    loudnorm: bool,
+    loudnorm_target: String,
+    loudnorm_true_peak: String,
+    loudnorm_mode: String,
+    volume: String,
+    fade_in: String,
+    fade_out: String,
+    tracks: Vec<TrackEntry>,
+    drop_video: bool,
+    drop_audio: bool,
+    external_subtitles: Vec<ffmpeg::ExternalSubtitle>,
+    burn_available: bool,
+    burn_source: String,
+    burn_file: Option<(String, Vec<u8>)>,
+    burn_font_size: String,
+    burn_outline: String,
+    burn_position: String,
+    overlay_available: bool,
+    overlay_text: String,
+    overlay_position: String,
+    overlay_font_size: String,
+    overlay_color: String,
+    overlay_background: bool,
+    overlay_background_color: String,
+    metadata_mode: String,
+    metadata_fields: Vec<(String, String)>,
+    input_metadata: Vec<(String, String)>,
+    input_duration: f64,
+    chapters: Vec<ffmpeg::Chapter>,
+    chapters_edited: bool,
+    input_cover: Option<ffmpeg::StreamInfo>,
+    cover_mode: String,
+    cover_image: Option<(String, Vec<u8>)>,
+    frame_mode: String,
+    frame_time: String,
+    frame_count: String,
+    frame_format: String,
+    frame_width: String,
+    contact_sheet: ffmpeg::ContactSheet,
+    image_sequence: ffmpeg::ImageSequence,
+    split: ffmpeg::SplitSettings,
+    hls: ffmpeg::HlsSettings,
+    dash: ffmpeg::DashSettings,
+    ladder: ffmpeg::LadderSettings,
+    ladder_progress: Vec<u32>,
+    animation: ffmpeg::AnimationSettings,
+    mp4: ffmpeg::Mp4Settings,
+    image_video: ffmpeg::ImageVideo,
+    video_images: Vec<(String, Vec<u8>)>,
+    video_audio: Option<(String, Vec<u8>)>,
+    concat_clips: Vec<Clip>,
+    next_clip_id: u32,
+    concat: ffmpeg::ConcatSettings,
     resolution: String,
     custom_resolution: String,
     transcoding: bool,
@@ -37,6 +122,79 @@ pub enum Msg {
     SetAudioCodec(String),
     SetVideoBitrate(String),
     SetAudioBitrate(String),
+    SetPixelFormat(String),
+    SetSampleRate(String),
+    SetChannelLayout(String),
+    SetLoudnorm(bool),
+    SetLoudnormTarget(String),
+    SetLoudnormTruePeak(String),
+    SetLoudnormMode(String),
+    SetVolume(String),
+    SetFadeIn(String),
+    SetFadeOut(String),
+    SetTracks(Vec<TrackEntry>),
+    SetDropVideo(bool),
+    SetDropAudio(bool),
+    SubtitleSelected(File),
+    SubtitleLoaded(String, Vec<u8>),
+    ClearSubtitles,
+    SetBurnSource(String),
+    BurnFileSelected(File),
+    BurnFileLoaded(String, Vec<u8>),
+    SetBurnFontSize(String),
+    SetBurnOutline(String),
+    SetBurnPosition(String),
+    SetOverlayText(String),
+    SetOverlayPosition(String),
+    SetOverlayFontSize(String),
+    SetOverlayColor(String),
+    SetOverlayBackground(bool),
+    SetOverlayBackgroundColor(String),
+    SetMetadataMode(String),
+    SetMetadataField(String, String),
+    SetChapters(Vec<ffmpeg::Chapter>),
+    ChapterFileSelected(File),
+    ChapterFileLoaded(Vec<u8>),
+    ExportChapters(String),
+    SetCoverMode(String),
+    CoverImageSelected(File),
+    CoverImageLoaded(String, Vec<u8>),
+    ExtractCover,
+    SetFrameMode(String),
+    SetFrameTime(String),
+    SetFrameCount(String),
+    SetFrameFormat(String),
+    SetFrameWidth(String),
+    ExportFrames,
+    SetContactSheet(ffmpeg::ContactSheet),
+    CreateContactSheet,
+    SetImageSequence(ffmpeg::ImageSequence),
+    ExportImageSequence,
+    SetSplitSettings(ffmpeg::SplitSettings),
+    SplitInput,
+    SetHlsSettings(ffmpeg::HlsSettings),
+    CreateHlsPackage,
+    SetDashSettings(ffmpeg::DashSettings),
+    CreateDashPackage,
+    SetLadderSettings(ffmpeg::LadderSettings),
+    EncodeLadder,
+    LadderProgress(Vec<u32>),
+    PackageReady(String, Vec<u8>),
+    SetAnimationSettings(ffmpeg::AnimationSettings),
+    SetMp4Settings(ffmpeg::Mp4Settings),
+    SetImageVideo(ffmpeg::ImageVideo),
+    VideoImagesSelected(Vec<File>),
+    VideoImageLoaded(String, Vec<u8>),
+    VideoAudioSelected(File),
+    VideoAudioLoaded(String, Vec<u8>),
+    ClearVideoAudio,
+    CreateImageVideo,
+    ConcatFilesSelected(Vec<File>),
+    ConcatClipLoaded(u32, Vec<u8>),
+    MoveConcatClip(usize, usize),
+    RemoveConcatClip(usize),
+    SetConcatSettings(ffmpeg::ConcatSettings),
+    JoinClips,
     SetResolution(String),
     SetCustomResolution(String),
     StartTranscoding,
@@ -63,6 +221,69 @@ impl Component for App {
             audio_codec: "aac".to_string(),
             video_bitrate: "1000".to_string(),
             audio_bitrate: "128".to_string(),
+            pixel_format: "auto".to_string(),
+            supported_pixel_formats: ffmpeg::get_pixel_formats("h264"),
+            sample_rate: "same".to_string(),
+            channel_layout: "same".to_string(),
+            supported_sample_rates: ffmpeg::get_sample_rates("aac"),
+            supported_channel_layouts: ffmpeg::get_channel_layouts("aac"),
+            loudnorm: false,
+            loudnorm_target: "-16".to_string(),
+            loudnorm_true_peak: "-1".to_string(),
+            loudnorm_mode: "linear".to_string(),
+            volume: "0".to_string(),
+            fade_in: "0".to_string(),
+            fade_out: "0".to_string(),
+            tracks: Vec::new(),
+            drop_video: false,
+            drop_audio: false,
+            external_subtitles: Vec::new(),
+            burn_available: ffmpeg::subtitle_burn_in_available(),
+            burn_source: "none".to_string(),
+            burn_file: None,
+            burn_font_size: String::new(),
+            burn_outline: String::new(),
+            burn_position: String::new(),
+            overlay_available: ffmpeg::text_overlay_available(),
+            overlay_text: String::new(),
+            overlay_position: "bottom-left".to_string(),
+            overlay_font_size: String::new(),
+            overlay_color: "#ffffff".to_string(),
+            overlay_background: true,
+            overlay_background_color: "#000000".to_string(),
+            metadata_mode: "keep".to_string(),
+            metadata_fields: Vec::new(),
+            input_metadata: Vec::new(),
+            input_duration: 0.0,
+            chapters: Vec::new(),
+            chapters_edited: false,
+            input_cover: None,
+            cover_mode: "keep".to_string(),
+            cover_image: None,
+            frame_mode: "single".to_string(),
+            frame_time: "00:00:00".to_string(),
+            frame_count: "5".to_string(),
+            frame_format: "png".to_string(),
+            frame_width: String::new(),
+            contact_sheet: ffmpeg::ContactSheet {
+                timestamps: ffmpeg::text_overlay_available(),
+                header: ffmpeg::text_overlay_available(),
+                ..Default::default()
+            },
+            image_sequence: ffmpeg::ImageSequence::default(),
+            split: ffmpeg::SplitSettings::default(),
+            hls: ffmpeg::HlsSettings::default(),
+            dash: ffmpeg::DashSettings::default(),
+            ladder: ffmpeg::LadderSettings::default(),
+            ladder_progress: Vec::new(),
+            animation: ffmpeg::AnimationSettings::default(),
+            mp4: ffmpeg::Mp4Settings::default(),
+            image_video: ffmpeg::ImageVideo::default(),
+            video_images: Vec::new(),
+            video_audio: None,
+            concat_clips: Vec::new(),
+            next_clip_id: 0,
+            concat: ffmpeg::ConcatSettings::default(),
             resolution: "same".to_string(),
             custom_resolution: "1280x720".to_string(),
             transcoding: false,
@@ -83,38 +304,88 @@ impl Component for App {
                 self.processed_data = None;
                 
                 // Read file contents
-                let file_reader = FileReader::new().unwrap();
-                let file_reader_clone = file_reader.clone();
-                let link = ctx.link().clone();
-                
-                let onload = Closure::wrap(Box::new(move |_event: web_sys::Event| {
-                    let array_buffer = file_reader_clone.result().unwrap();
-                    let uint8array = js_sys::Uint8Array::new(&array_buffer);
-                    let mut buffer = vec![0; uint8array.length() as usize];
-                    uint8array.copy_to(&mut buffer[..]);
-                    link.send_message(Msg::FileLoaded(buffer));
-                }) as Box<dyn FnMut(web_sys::Event)>);
-                
-                file_reader.set_onload(Some(onload.as_ref().unchecked_ref()));
-                onload.forget();
-                
-                file_reader.read_as_array_buffer(&file).unwrap();
+                self.read_file(ctx, &file, Msg::FileLoaded);
                 true
             },
             Msg::FileLoaded(data) => {
+                self.add_log(ctx, format!("File loaded: {} bytes", data.len()));
+                
+                // Probe the streams to build the track list
+                match ffmpeg::probe(&data) {
+                    Ok(info) => {
+                        self.add_log(ctx, format!("Input format: {}, duration {:.1}s", info.format_name, info.duration));
+                        for stream in &info.streams {
+                            self.add_log(ctx, format!("Stream {}", stream));
+                        }
+                        if !info.chapters.is_empty() {
+                            self.add_log(ctx, format!("Input has {} chapters", info.chapters.len()));
+                        }
+                        self.input_metadata = info.metadata;
+                        self.input_duration = info.duration;
+                        self.chapters = info.chapters;
+                        
+                        // Cover art is handled on its own rather than as a track
+                        let (covers, streams): (Vec<_>, Vec<_>) = info.streams.into_iter().partition(|stream| stream.kind == "cover");
+                        self.input_cover = covers.into_iter().next();
+                        self.tracks = streams.into_iter().map(TrackEntry::new).collect();
+                    },
+                    Err(e) => {
+                        self.add_log(ctx, format!("Could not probe input: {}", e));
+                        self.input_metadata.clear();
+                        self.input_duration = 0.0;
+                        self.chapters.clear();
+                        self.input_cover = None;
+                        self.tracks.clear();
+                    },
+                }
+                
+                // Track indexes of the previous input no longer apply
+                if self.burn_source != "file" {
+                    self.burn_source = "none".to_string();
+                }
+                
+                // Edits were made against the previous input's tags and chapters
+                self.metadata_fields.clear();
+                self.chapters_edited = false;
+                
                 self.input_data = Some(data);
-                self.add_log(ctx, format!("File loaded: {} bytes", self.input_data.as_ref().unwrap().len()));
                 true
             },
             Msg::SetOutputFormat(format) => {
+                // Audio-only containers drop video, so audio has to stay
+                if ffmpeg::is_audio_only_format(&format) {
+                    self.drop_audio = false;
+                }
                 self.output_format = format;
                 true
             },
             Msg::SetVideoCodec(codec) => {
+                self.supported_pixel_formats = ffmpeg::get_pixel_formats(&codec);
+                
+                // Fall back to the container default if the new encoder can't take the chosen format
+                if !self.supported_pixel_formats.is_empty()
+                    && !self.supported_pixel_formats.contains(&self.pixel_format)
+                {
+                    self.pixel_format = "auto".to_string();
+                }
+                
                 self.video_codec = codec;
                 true
             },
             Msg::SetAudioCodec(codec) => {
+                self.supported_sample_rates = ffmpeg::get_sample_rates(&codec);
+                self.supported_channel_layouts = ffmpeg::get_channel_layouts(&codec);
+                
+                // Fall back to the source settings if the new encoder can't take the chosen ones
+                if let Ok(rate) = self.sample_rate.parse::<i32>() {
+                    if !self.supported_sample_rates.is_empty() && !self.supported_sample_rates.contains(&rate) {
+                        self.sample_rate = "same".to_string();
+                    }
+                }
+                if !channel_layout_supported(&self.supported_channel_layouts, &self.channel_layout) {
+                    self.channel_layout = "same".to_string();
+                }
+                
                 self.audio_codec = codec;
                 true
             },
@@ -126,6 +397,525 @@ impl Component for App {
                 self.audio_bitrate = bitrate;
                 true
             },
+            Msg::SetPixelFormat(pix_fmt) => {
+                self.pixel_format = pix_fmt;
+                true
+            },
+            Msg::SetSampleRate(rate) => {
+                self.sample_rate = rate;
+                true
+            },
+            Msg::SetChannelLayout(layout) => {
+                self.channel_layout = layout;
+                true
+            },
+            Msg::SetLoudnorm(enabled) => {
+                self.loudnorm = enabled;
+                true
+            },
+            Msg::SetLoudnormTarget(target) => {
+                self.loudnorm_target = target;
+                true
+            },
+            Msg::SetLoudnormTruePeak(true_peak) => {
+                self.loudnorm_true_peak = true_peak;
+                true
+            },
+            Msg::SetLoudnormMode(mode) => {
+                self.loudnorm_mode = mode;
+                true
+            },
+            Msg::SetVolume(volume) => {
+                self.volume = volume;
+                true
+            },
+            Msg::SetFadeIn(seconds) => {
+                self.fade_in = seconds;
+                true
+            },
+            Msg::SetFadeOut(seconds) => {
+                self.fade_out = seconds;
+                true
+            },
+            Msg::SetTracks(tracks) => {
+                self.tracks = tracks;
+                true
+            },
+            Msg::SetDropVideo(drop) => {
+                self.drop_video = drop;
+                true
+            },
+            Msg::SetDropAudio(drop) => {
+                self.drop_audio = drop;
+                true
+            },
+            Msg::SubtitleSelected(file) => {
+                let file_name = file.name();
+                self.read_file(ctx, &file, move |data| Msg::SubtitleLoaded(file_name.clone(), data));
+                false
+            },
+            Msg::SubtitleLoaded(file_name, data) => {
+                // Title the track after the file, e.g. "movie.en.srt" -> "movie.en"
+                let title = file_name
+                    .rsplit_once('.')
+                    .map(|(stem, _)| stem.to_string())
+                    .unwrap_or_else(|| file_name.clone());
+                
+                self.external_subtitles.push(ffmpeg::ExternalSubtitle {
+                    data,
+                    language: String::new(),
+                    title,
+                    default: false,
+                });
+                self.add_log(ctx, format!("Subtitle file added as a new track: {}", file_name));
+                true
+            },
+            Msg::ClearSubtitles => {
+                self.external_subtitles.clear();
+                self.add_log(ctx, "Subtitle files removed".to_string());
+                true
+            },
+            Msg::SetBurnSource(source) => {
+                self.burn_source = source;
+                true
+            },
+            Msg::BurnFileSelected(file) => {
+                let file_name = file.name();
+                self.read_file(ctx, &file, move |data| Msg::BurnFileLoaded(file_name.clone(), data));
+                false
+            },
+            Msg::BurnFileLoaded(file_name, data) => {
+                self.add_log(ctx, format!("Subtitle file to burn in: {}", file_name));
+                self.burn_file = Some((file_name, data));
+                true
+            },
+            Msg::SetBurnFontSize(size) => {
+                self.burn_font_size = size;
+                true
+            },
+            Msg::SetBurnOutline(outline) => {
+                self.burn_outline = outline;
+                true
+            },
+            Msg::SetBurnPosition(position) => {
+                self.burn_position = position;
+                true
+            },
+            Msg::SetOverlayText(text) => {
+                self.overlay_text = text;
+                true
+            },
+            Msg::SetOverlayPosition(position) => {
+                self.overlay_position = position;
+                true
+            },
+            Msg::SetOverlayFontSize(size) => {
+                self.overlay_font_size = size;
+                true
+            },
+            Msg::SetOverlayColor(color) => {
+                self.overlay_color = color;
+                true
+            },
+            Msg::SetOverlayBackground(enabled) => {
+                self.overlay_background = enabled;
+                true
+            },
+            Msg::SetOverlayBackgroundColor(color) => {
+                self.overlay_background_color = color;
+                true
+            },
+            Msg::SetMetadataMode(mode) => {
+                self.metadata_mode = mode;
+                true
+            },
+            Msg::SetMetadataField(key, value) => {
+                self.metadata_fields.retain(|(field, _)| *field != key);
+                self.metadata_fields.push((key, value));
+                true
+            },
+            Msg::SetChapters(chapters) => {
+                self.chapters = chapters;
+                self.chapters_edited = true;
+                true
+            },
+            Msg::ChapterFileSelected(file) => {
+                self.read_file(ctx, &file, Msg::ChapterFileLoaded);
+                false
+            },
+            Msg::ChapterFileLoaded(data) => {
+                match chapters::parse(&String::from_utf8_lossy(&data)) {
+                    Ok(chapters) => {
+                        self.add_log(ctx, format!("Imported {} chapters", chapters.len()));
+                        self.chapters = chapters;
+                        self.chapters_edited = true;
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not import chapters: {}", e)),
+                }
+                true
+            },
+            Msg::ExportChapters(format) => {
+                let (text, filename) = if format == "ffmetadata" {
+                    (chapters::to_ffmetadata(&self.chapters, self.input_duration), self.output_file_name("ffmetadata"))
+                } else {
+                    (chapters::to_list(&self.chapters), self.output_file_name("chapters.txt"))
+                };
+                save_file(&filename, "text/plain", text.as_bytes());
+                self.add_log(ctx, format!("Chapters exported to '{}'", filename));
+                false
+            },
+            Msg::SetCoverMode(mode) => {
+                self.cover_mode = mode;
+                true
+            },
+            Msg::CoverImageSelected(file) => {
+                let file_name = file.name();
+                self.read_file(ctx, &file, move |data| Msg::CoverImageLoaded(file_name.clone(), data));
+                false
+            },
+            Msg::CoverImageLoaded(file_name, data) => {
+                self.add_log(ctx, format!("Cover image: {}", file_name));
+                self.cover_image = Some((file_name, data));
+                true
+            },
+            Msg::ExtractCover => {
+                let (Some(data), Some(cover)) = (&self.input_data, &self.input_cover) else {
+                    return false;
+                };
+                
+                match ffmpeg::extract_cover(data) {
+                    Ok(image) => {
+                        let (extension, mime_type) = if cover.codec == "png" {
+                            ("cover.png", "image/png")
+                        } else {
+                            ("cover.jpg", "image/jpeg")
+                        };
+                        let filename = self.output_file_name(extension);
+                        save_file(&filename, mime_type, &image);
+                        self.add_log(ctx, format!("Cover art extracted to '{}'", filename));
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not extract cover art: {}", e)),
+                }
+                true
+            },
+            Msg::SetFrameMode(mode) => {
+                self.frame_mode = mode;
+                true
+            },
+            Msg::SetFrameTime(time) => {
+                self.frame_time = time;
+                true
+            },
+            Msg::SetFrameCount(count) => {
+                self.frame_count = count;
+                true
+            },
+            Msg::SetFrameFormat(format) => {
+                self.frame_format = format;
+                true
+            },
+            Msg::SetFrameWidth(width) => {
+                self.frame_width = width;
+                true
+            },
+            Msg::ExportFrames => {
+                let Some(data) = &self.input_data else {
+                    self.add_log(ctx, "Error: No input file data available".to_string());
+                    return true;
+                };
+                
+                // Height follows the aspect ratio
+                let size = self.frame_width.parse::<u32>().ok().filter(|width| *width > 0).map(|width| (width, 0));
+                
+                let frames = if self.frame_mode == "series" {
+                    let count = self.frame_count.parse::<u32>().unwrap_or(1).clamp(1, 100);
+                    ffmpeg::extract_frames(data, count, &self.frame_format, size)
+                } else {
+                    match chapters::parse_timestamp(&self.frame_time) {
+                        Some(timestamp) => ffmpeg::extract_frame(data, timestamp, &self.frame_format, size)
+                            .map(|image| vec![(timestamp, image)]),
+                        None => {
+                            self.add_log(ctx, format!("Error: Invalid frame time '{}'", self.frame_time));
+                            return true;
+                        },
+                    }
+                };
+                
+                match frames {
+                    Ok(frames) => {
+                        let extension = ffmpeg::get_file_extension(&self.frame_format);
+                        let mime_type = format!("image/{}", self.frame_format);
+                        for (timestamp, image) in &frames {
+                            let name = format!("{}.{}", chapters::format_timestamp(*timestamp).replace(':', "-"), extension);
+                            save_file(&self.output_file_name(&name), &mime_type, image);
+                        }
+                        self.add_log(ctx, format!("Exported {} frame(s) as {}", frames.len(), self.frame_format));
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not extract frames: {}", e)),
+                }
+                true
+            },
+            Msg::SetContactSheet(sheet) => {
+                self.contact_sheet = sheet;
+                true
+            },
+            Msg::SetAnimationSettings(animation) => {
+                self.animation = animation;
+                true
+            },
+            Msg::SetMp4Settings(mp4) => {
+                self.mp4 = mp4;
+                true
+            },
+            Msg::CreateContactSheet => {
+                let (Some(data), Some(file)) = (&self.input_data, &self.input_file) else {
+                    self.add_log(ctx, "Error: No input file data available".to_string());
+                    return true;
+                };
+                
+                match ffmpeg::contact_sheet(data, &file.name(), &self.contact_sheet) {
+                    Ok(image) => {
+                        let format = &self.contact_sheet.format;
+                        let filename = self.output_file_name(&format!("sheet.{}", ffmpeg::get_file_extension(format)));
+                        save_file(&filename, &format!("image/{}", format), &image);
+                        self.add_log(ctx, format!("Contact sheet saved as '{}'", filename));
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not create contact sheet: {}", e)),
+                }
+                true
+            },
+            Msg::SetImageSequence(sequence) => {
+                self.image_sequence = sequence;
+                true
+            },
+            Msg::ExportImageSequence => {
+                let Some(data) = &self.input_data else {
+                    self.add_log(ctx, "Error: No input file data available".to_string());
+                    return true;
+                };
+                
+                match ffmpeg::image_sequence(data, "frame", &self.image_sequence) {
+                    Ok((archive, count)) => {
+                        let filename = self.output_file_name("frames.zip");
+                        save_file(&filename, "application/zip", &archive);
+                        self.add_log(ctx, format!("Saved {} images as '{}'", count, filename));
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not export image sequence: {}", e)),
+                }
+                true
+            },
+            Msg::SetSplitSettings(settings) => {
+                self.split = settings;
+                true
+            },
+            Msg::SplitInput => {
+                let (Some(data), Some(file)) = (&self.input_data, &self.input_file) else {
+                    self.add_log(ctx, "Error: No input file data available".to_string());
+                    return true;
+                };
+                
+                let name = file.name();
+                let base_name = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&name);
+                match ffmpeg::split(data, base_name, &self.output_format, &self.split) {
+                    Ok((archive, count)) => {
+                        let filename = self.output_file_name("parts.zip");
+                        save_file(&filename, "application/zip", &archive);
+                        self.add_log(ctx, format!("Saved {} parts as '{}'", count, filename));
+                    },
+                    Err(e) => self.add_log(ctx, format!("Could not split input: {}", e)),
+                }
+                true
+            },
+            Msg::SetHlsSettings(settings) => {
+                self.hls = settings;
+                true
+            },
+            Msg::CreateHlsPackage => {
+                self.create_package(ctx, "hls");
+                true
+            },
+            Msg::SetDashSettings(settings) => {
+                self.dash = settings;
+                true
+            },
+            Msg::CreateDashPackage => {
+                self.create_package(ctx, "dash");
+                true
+            },
+            Msg::SetLadderSettings(settings) => {
+                self.ladder = settings;
+                self.ladder_progress.clear();
+                true
+            },
+            Msg::EncodeLadder => {
+                self.encode_ladder(ctx);
+                true
+            },
+            Msg::LadderProgress(progress) => {
+                // The overall bar follows the renditions together
+                self.progress = (progress.iter().sum::<u32>() / progress.len().max(1) as u32) as f64;
+                self.ladder_progress = progress;
+                true
+            },
+            Msg::PackageReady(filename, archive) => {
+                self.transcoding = false;
+                save_file(&filename, "application/zip", &archive);
+                self.add_log(ctx, format!("Package saved as '{}'", filename));
+                true
+            },
+            Msg::SetImageVideo(video) => {
+                self.image_video = video;
+                true
+            },
+            Msg::VideoImagesSelected(files) => {
+                self.video_images.clear();
+                for file in files {
+                    let file_name = file.name();
+                    self.read_file(ctx, &file, move |data| Msg::VideoImageLoaded(file_name.clone(), data));
+                }
+                true
+            },
+            Msg::VideoImageLoaded(file_name, data) => {
+                if file_name.to_ascii_lowercase().ends_with(".zip") {
+                    match zip::read_archive(&data) {
+                        Ok(files) => {
+                            let images: Vec<_> = files.into_iter().filter(|(name, _)| ffmpeg::is_image_file(name)).collect();
+                            self.add_log(ctx, format!("{} images read from '{}'", images.len(), file_name));
+                            self.video_images.extend(images);
+                        },
+                        Err(e) => self.add_log(ctx, format!("Could not read '{}': {}", file_name, e)),
+                    }
+                } else if ffmpeg::is_image_file(&file_name) {
+                    self.video_images.push((file_name, data));
+                } else {
+                    self.add_log(ctx, format!("Skipped '{}': not a supported image", file_name));
+                }
+                
+                // Files finish loading in any order
+                self.video_images.sort_by(|(a, _), (b, _)| ffmpeg::natural_cmp(a, b));
+                true
+            },
+            Msg::VideoAudioSelected(file) => {
+                let file_name = file.name();
+                self.read_file(ctx, &file, move |data| Msg::VideoAudioLoaded(file_name.clone(), data));
+                false
+            },
+            Msg::VideoAudioLoaded(file_name, data) => {
+                self.video_audio = Some((file_name, data));
+                true
+            },
+            Msg::ClearVideoAudio => {
+                self.video_audio = None;
+                true
+            },
+            Msg::CreateImageVideo => {
+                if self.video_images.is_empty() {
+                    self.add_log(ctx, "Error: No images selected".to_string());
+                    return true;
+                }
+                if ffmpeg::is_audio_only_format(&self.output_format)
+                    || ffmpeg::is_animation_format(&self.output_format)
+                    || ffmpeg::get_subtitle_formats().contains(&self.output_format)
+                {
+                    self.add_log(ctx, "Error: Choose a video output format".to_string());
+                    return true;
+                }
+                
+                self.transcoding = true;
+                self.progress = 0.0;
+                self.download_ready = false;
+                self.processed_data = None;
+                
+                let images: Vec<_> = self.video_images.iter().map(|(_, data)| data.clone()).collect();
+                let audio = self.video_audio.as_ref().map(|(_, data)| data.clone());
+                let video = self.image_video.clone();
+                let options = self.encoding_options();
+                self.add_log(ctx, format!("Encoding {} images at {} fps...", images.len(), video.fps));
+                
+                let link = ctx.link().clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match ffmpeg::images_to_video(&images, audio.as_deref(), &options, &video).await {
+                        Ok(output_data) => {
+                            link.send_message(Msg::TranscodingProgress(100.0));
+                            link.send_message(Msg::AddLog(format!("Video created! Output size: {} bytes", output_data.len())));
+                            link.send_message(Msg::TranscodingFinished(output_data));
+                        },
+                        Err(error) => link.send_message(Msg::TranscodingError(error.to_string())),
+                    }
+                });
+                true
+            },
+            Msg::ConcatFilesSelected(files) => {
+                for file in files {
+                    let id = self.next_clip_id;
+                    self.next_clip_id += 1;
+                    self.concat_clips.push(Clip { id, name: file.name(), data: None });
+                    self.read_file(ctx, &file, move |data| Msg::ConcatClipLoaded(id, data));
+                }
+                true
+            },
+            Msg::ConcatClipLoaded(id, data) => {
+                // The clip may have been removed while it loaded
+                if let Some(clip) = self.concat_clips.iter_mut().find(|clip| clip.id == id) {
+                    clip.data = Some(data);
+                }
+                true
+            },
+            Msg::MoveConcatClip(from, to) => {
+                if from < self.concat_clips.len() && to < self.concat_clips.len() {
+                    let clip = self.concat_clips.remove(from);
+                    self.concat_clips.insert(to, clip);
+                }
+                true
+            },
+            Msg::RemoveConcatClip(position) => {
+                if position < self.concat_clips.len() {
+                    self.concat_clips.remove(position);
+                }
+                true
+            },
+            Msg::SetConcatSettings(settings) => {
+                self.concat = settings;
+                true
+            },
+            Msg::JoinClips => {
+                let Some(clips) = self.concat_clips.iter().map(|clip| clip.data.clone()).collect::<Option<Vec<_>>>() else {
+                    self.add_log(ctx, "Error: Clips are still loading".to_string());
+                    return true;
+                };
+                if ffmpeg::is_animation_format(&self.output_format)
+                    || ffmpeg::get_subtitle_formats().contains(&self.output_format)
+                {
+                    self.add_log(ctx, "Error: Choose a video or audio output format".to_string());
+                    return true;
+                }
+                
+                self.transcoding = true;
+                self.progress = 0.0;
+                self.download_ready = false;
+                self.processed_data = None;
+                
+                let options = ffmpeg::TranscodeOptions {
+                    drop_video: self.drop_video,
+                    drop_audio: self.drop_audio,
+                    ..self.encoding_options()
+                };
+                let settings = self.concat.clone();
+                self.add_log(ctx, format!("Joining {} clips...", clips.len()));
+                
+                let link = ctx.link().clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match ffmpeg::concat(&clips, &options, &settings).await {
+                        Ok(output_data) => {
+                            link.send_message(Msg::TranscodingProgress(100.0));
+                            link.send_message(Msg::AddLog(format!("Clips joined! Output size: {} bytes", output_data.len())));
+                            link.send_message(Msg::TranscodingFinished(output_data));
+                        },
+                        Err(error) => link.send_message(Msg::TranscodingError(error.to_string())),
+                    }
+                });
+                true
+            },
             Msg::SetResolution(res) => {
                 self.resolution = res;
                 true
@@ -140,24 +930,26 @@ impl Component for App {
                     return true;
                 }
                 
+                let mut options = match self.transcode_options(&self.output_format) {
+                    Ok(options) => options,
+                    Err(error) => {
+                        self.add_log(ctx, error);
+                        return true;
+                    },
+                };
+                
                 self.transcoding = true;
                 self.progress = 0.0;
                 self.download_ready = false;
                 self.processed_data = None;
                 self.add_log(ctx, "Starting transcoding process...".to_string());
                 
-                // Get transcoding parameters
                 let input_data = self.input_data.as_ref().unwrap().clone();
-                let output_format = self.output_format.clone();
-                let video_codec = self.video_codec.clone();
-                let audio_codec = self.audio_codec.clone();
-                let video_bitrate = self.video_bitrate.parse::<i32>().unwrap_or(1000);
-                let audio_bitrate = self.audio_bitrate.parse::<i32>().unwrap_or(128);
-                let resolution = if self.resolution == "custom" {
-                    self.custom_resolution.clone()
-                } else {
-                    self.resolution.clone()
-                };
+                
+                // A subtitle-only input going to a subtitle format is a standalone conversion
+                let subtitle_conversion = ffmpeg::get_subtitle_formats().contains(&self.output_format)
+                    && !self.tracks.is_empty()
+                    && self.tracks.iter().all(|track| track.stream.kind == "subtitle");
                 
                 // Clone link for async context
                 let link = ctx.link().clone();
@@ -165,10 +957,17 @@ impl Component for App {
                 // Spawn transcoding task
                 wasm_bindgen_futures::spawn_local(async move {
                     link.send_message(Msg::AddLog(format!(
-                        "Transcoding to {} format with {}({}) and {}({}) codecs",
-                        output_format, video_codec, video_bitrate, audio_codec, audio_bitrate
+                        "Transcoding to {} format with {}({}, {}) and {}({}) codecs",
+                        options.output_format, options.video_codec, options.video_bitrate,
+                        options.pixel_format, options.audio_codec, options.audio_bitrate
                     )));
                     
+                    if options.drops_video() {
+                        link.send_message(Msg::AddLog("Dropping video: audio-only output".to_string()));
+                    } else if options.drop_audio {
+                        link.send_message(Msg::AddLog("Dropping audio: silent video output".to_string()));
+                    }
+                    
                     // Progress simulation for UI feedback
                     for i in 1..=9 {
                         let progress = (i as f64) * 10.0;
@@ -184,18 +983,20 @@ impl Component for App {
                         gloo::timers::callback::Timeout::new(100 * i, move || {}).forget();
                     }
                     
+                    if !measure_loudness(&link, &input_data, &mut options) {
+                        return;
+                    }
+                    
                     // Perform actual transcoding
                     link.send_message(Msg::AddLog("Processing file with FFmpeg...".to_string()));
                     
-                    match ffmpeg::transcode(
-                        &input_data,
-                        &output_format,
-                        &video_codec,
-                        &audio_codec,
-                        video_bitrate,
-                        audio_bitrate,
-                        &resolution,
-                    ) {
+                    let result = if subtitle_conversion {
+                        ffmpeg::convert_subtitles(&input_data, &options.output_format).await
+                    } else {
+                        ffmpeg::transcode(&input_data, &options).await
+                    };
+                    
+                    match result {
                         Ok(output_data) => {
                             link.send_message(Msg::TranscodingProgress(100.0));
                             link.send_message(Msg::AddLog(format!("Transcoding completed! Output size: {} bytes", output_data.len())));
@@ -233,20 +1034,6 @@ impl Component for App {
             Msg::DownloadFile => {
                 if let Some(data) = &self.processed_data {
                     log("Download button clicked, file data available");
-                    let window = web_sys::window().expect("no global window exists");
-                    let document = window.document().expect("no document exists");
-                    
-                    // Create a Uint8Array from our data
-                    let uint8arr = Uint8Array::new_with_length(data.len() as u32);
-                    for (i, &byte) in data.iter().enumerate() {
-                        uint8arr.set_index(i as u32, byte);
-                    }
-                    
-                    // Convert Uint8Array to Blob
-                    let array = js_sys::Array::new();
-                    array.push(&uint8arr.buffer());
-                    
-                    let mut blob_options = web_sys::BlobPropertyBag::new();
                     
                     // Set MIME type based on output format
                     let mime_type = match self.output_format.as_str() {
@@ -255,49 +1042,23 @@ impl Component for App {
                         "mkv" => "video/x-matroska",
                         "mov" => "video/quicktime",
                         "gif" => "image/gif",
+                        "webp" => "image/webp",
+                        "apng" => "image/apng",
                         "mp3" => "audio/mpeg",
                         "ogg" => "audio/ogg",
                         "wav" => "audio/wav",
+                        "flac" => "audio/flac",
+                        "opus" => "audio/ogg",
+                        "ipod" => "audio/mp4",
+                        "srt" => "application/x-subrip",
+                        "webvtt" => "text/vtt",
+                        "ass" => "text/x-ssa",
                         _ => "application/octet-stream",
                     };
                     
-                    blob_options.set_type(mime_type);
-                    
-                    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(
-                        &array, &blob_options).unwrap();
-                    
-                    // Create a download URL
-                    let url = Url::create_object_url_with_blob(&blob).unwrap();
-                    
-                    // Create a download link
-                    let a = document
-                        .create_element("a")
-                        .unwrap()
-                        .dyn_into::<HtmlAnchorElement>()
-                        .unwrap();
-                    
                     // Generate output filename
-                    let filename = match &self.input_file {
-                        Some(file) => {
-                            let name = file.name();
-                            if let Some(dot_pos) = name.rfind('.') {
-                                format!("{}.{}", &name[0..dot_pos], self.output_format)
-                            } else {
-                                format!("output.{}", self.output_format)
-                            }
-                        },
-                        None => format!("output.{}", self.output_format),
-                    };
-                    
-                    a.set_href(&url);
-                    a.set_download(&filename);
-                    a.set_attribute("style", "display: none").unwrap();
-                    
-                    document.body().unwrap().append_child(&a).unwrap();
-                    a.click();
-                    document.body().unwrap().remove_child(&a).unwrap();
-                    
-                    Url::revoke_object_url(&url).unwrap();
+                    let filename = self.output_file_name(ffmpeg::get_file_extension(&self.output_format));
+                    save_file(&filename, mime_type, data);
                     
                     self.add_log(ctx, format!("File '{}' downloaded", filename));
                 } else {
@@ -343,10 +1104,38 @@ impl Component for App {
                                 audio_codec={self.audio_codec.clone()}
                                 video_bitrate={self.video_bitrate.clone()}
                                 audio_bitrate={self.audio_bitrate.clone()}
+                                pixel_format={self.pixel_format.clone()}
+                                supported_pixel_formats={self.supported_pixel_formats.clone()}
+                                sample_rate={self.sample_rate.clone()}
+                                channel_layout={self.channel_layout.clone()}
+                                supported_sample_rates={self.supported_sample_rates.clone()}
+                                supported_channel_layouts={self.supported_channel_layouts.clone()}
+                                loudnorm={self.loudnorm}
+                                loudnorm_target={self.loudnorm_target.clone()}
+                                loudnorm_true_peak={self.loudnorm_true_peak.clone()}
+                                loudnorm_mode={self.loudnorm_mode.clone()}
+                                volume={self.volume.clone()}
+                                fade_in={self.fade_in.clone()}
+                                fade_out={self.fade_out.clone()}
+                                drop_video={self.drop_video}
+                                drop_audio={self.drop_audio}
+                                audio_only_format={ffmpeg::is_audio_only_format(&self.output_format)}
                                 on_video_codec_change={ctx.link().callback(Msg::SetVideoCodec)}
                                 on_audio_codec_change={ctx.link().callback(Msg::SetAudioCodec)}
                                 on_video_bitrate_change={ctx.link().callback(Msg::SetVideoBitrate)}
                                 on_audio_bitrate_change={ctx.link().callback(Msg::SetAudioBitrate)}
+                                on_pixel_format_change={ctx.link().callback(Msg::SetPixelFormat)}
+                                on_sample_rate_change={ctx.link().callback(Msg::SetSampleRate)}
+                                on_channel_layout_change={ctx.link().callback(Msg::SetChannelLayout)}
+                                on_loudnorm_change={ctx.link().callback(Msg::SetLoudnorm)}
+                                on_loudnorm_target_change={ctx.link().callback(Msg::SetLoudnormTarget)}
+                                on_loudnorm_true_peak_change={ctx.link().callback(Msg::SetLoudnormTruePeak)}
+                                on_loudnorm_mode_change={ctx.link().callback(Msg::SetLoudnormMode)}
+                                on_volume_change={ctx.link().callback(Msg::SetVolume)}
+                                on_fade_in_change={ctx.link().callback(Msg::SetFadeIn)}
+                                on_fade_out_change={ctx.link().callback(Msg::SetFadeOut)}
+                                on_drop_video_change={ctx.link().callback(Msg::SetDropVideo)}
+                                on_drop_audio_change={ctx.link().callback(Msg::SetDropAudio)}
                                 disabled={self.transcoding}
                             />
                             
@@ -388,6 +1177,221 @@ impl Component for App {
                             </div>
                         </div>
                         
+                        <TrackList
+                            tracks={self.tracks.clone()}
+                            on_change={ctx.link().callback(Msg::SetTracks)}
+                            disabled={self.transcoding}
+                        />
+                        
+                        <FileInput
+                            id="subtitle-upload"
+                            label="Add Subtitle File"
+                            accept=".srt,.vtt,.ass,.ssa"
+                            on_file_selected={ctx.link().callback(Msg::SubtitleSelected)}
+                            disabled={self.transcoding}
+                        />
+                        {
+                            if self.external_subtitles.is_empty() {
+                                html! {}
+                            } else {
+                                html! {
+                                    <div class="form-group">
+                                        { format!("{} subtitle file(s) will be added ", self.external_subtitles.len()) }
+                                        <button
+                                            onclick={ctx.link().callback(|_| Msg::ClearSubtitles)}
+                                            disabled={self.transcoding}
+                                        >
+                                            { "Remove" }
+                                        </button>
+                                    </div>
+                                }
+                            }
+                        }
+                        
+                        <SubtitleBurnIn
+                            source={self.burn_source.clone()}
+                            subtitle_streams={
+                                self.tracks
+                                    .iter()
+                                    .filter(|track| track.stream.kind == "subtitle" && track.stream.text_based)
+                                    .map(|track| track.stream.clone())
+                                    .collect::<Vec<_>>()
+                            }
+                            file_name={self.burn_file.as_ref().map(|(name, _)| name.clone())}
+                            font_size={self.burn_font_size.clone()}
+                            outline={self.burn_outline.clone()}
+                            position={self.burn_position.clone()}
+                            available={self.burn_available}
+                            on_source_change={ctx.link().callback(Msg::SetBurnSource)}
+                            on_file_selected={ctx.link().callback(Msg::BurnFileSelected)}
+                            on_font_size_change={ctx.link().callback(Msg::SetBurnFontSize)}
+                            on_outline_change={ctx.link().callback(Msg::SetBurnOutline)}
+                            on_position_change={ctx.link().callback(Msg::SetBurnPosition)}
+                            disabled={self.transcoding || self.drop_video || ffmpeg::is_audio_only_format(&self.output_format)}
+                        />
+                        
+                        <TextOverlaySettings
+                            text={self.overlay_text.clone()}
+                            position={self.overlay_position.clone()}
+                            font_size={self.overlay_font_size.clone()}
+                            color={self.overlay_color.clone()}
+                            background={self.overlay_background}
+                            background_color={self.overlay_background_color.clone()}
+                            available={self.overlay_available}
+                            on_text_change={ctx.link().callback(Msg::SetOverlayText)}
+                            on_position_change={ctx.link().callback(Msg::SetOverlayPosition)}
+                            on_font_size_change={ctx.link().callback(Msg::SetOverlayFontSize)}
+                            on_color_change={ctx.link().callback(Msg::SetOverlayColor)}
+                            on_background_change={ctx.link().callback(Msg::SetOverlayBackground)}
+                            on_background_color_change={ctx.link().callback(Msg::SetOverlayBackgroundColor)}
+                            disabled={self.transcoding || self.drop_video || ffmpeg::is_audio_only_format(&self.output_format)}
+                        />
+                        
+                        <MetadataEditor
+                            mode={self.metadata_mode.clone()}
+                            fields={self.current_metadata()}
+                            input_tags={self.input_metadata.clone()}
+                            on_mode_change={ctx.link().callback(Msg::SetMetadataMode)}
+                            on_field_change={ctx.link().callback(|(key, value)| Msg::SetMetadataField(key, value))}
+                            disabled={self.transcoding}
+                        />
+                        
+                        <ChapterEditor
+                            chapters={self.chapters.clone()}
+                            on_change={ctx.link().callback(Msg::SetChapters)}
+                            on_import={ctx.link().callback(Msg::ChapterFileSelected)}
+                            on_export={ctx.link().callback(Msg::ExportChapters)}
+                            disabled={self.transcoding}
+                        />
+                        
+                        <CoverArtSettings
+                            mode={self.cover_mode.clone()}
+                            input_cover={self.input_cover.clone()}
+                            image_name={self.cover_image.as_ref().map(|(name, _)| name.clone())}
+                            supported={ffmpeg::supports_cover_art(&self.output_format)}
+                            on_mode_change={ctx.link().callback(Msg::SetCoverMode)}
+                            on_image_selected={ctx.link().callback(Msg::CoverImageSelected)}
+                            on_extract={ctx.link().callback(|_| Msg::ExtractCover)}
+                            disabled={self.transcoding}
+                        />
+                        
+                        {
+                            if ffmpeg::is_animation_format(&self.output_format) {
+                                let estimated_size = self.tracks.iter()
+                                    .find(|track| track.stream.kind == "video")
+                                    .filter(|_| self.input_duration > 0.0)
+                                    .map(|track| self.animation.estimated_size(&self.output_format, &track.stream, self.input_duration));
+                                html! {
+                                    <AnimationSettingsEditor
+                                        format={self.output_format.clone()}
+                                        settings={self.animation.clone()}
+                                        estimated_size={estimated_size}
+                                        on_change={ctx.link().callback(Msg::SetAnimationSettings)}
+                                        disabled={self.transcoding}
+                                    />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        
+                        {
+                            if ffmpeg::is_mp4_format(&self.output_format) {
+                                html! {
+                                    <Mp4LayoutSettings
+                                        settings={self.mp4.clone()}
+                                        on_change={ctx.link().callback(Msg::SetMp4Settings)}
+                                        disabled={self.transcoding}
+                                    />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        
+                        <FrameExport
+                            mode={self.frame_mode.clone()}
+                            timestamp={self.frame_time.clone()}
+                            count={self.frame_count.clone()}
+                            format={self.frame_format.clone()}
+                            width={self.frame_width.clone()}
+                            on_mode_change={ctx.link().callback(Msg::SetFrameMode)}
+                            on_timestamp_change={ctx.link().callback(Msg::SetFrameTime)}
+                            on_count_change={ctx.link().callback(Msg::SetFrameCount)}
+                            on_format_change={ctx.link().callback(Msg::SetFrameFormat)}
+                            on_width_change={ctx.link().callback(Msg::SetFrameWidth)}
+                            on_export={ctx.link().callback(|_| Msg::ExportFrames)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <ContactSheetSettings
+                            sheet={self.contact_sheet.clone()}
+                            labels_available={self.overlay_available}
+                            on_change={ctx.link().callback(Msg::SetContactSheet)}
+                            on_create={ctx.link().callback(|_| Msg::CreateContactSheet)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <ImageSequenceSettings
+                            sequence={self.image_sequence.clone()}
+                            on_change={ctx.link().callback(Msg::SetImageSequence)}
+                            on_export={ctx.link().callback(|_| Msg::ExportImageSequence)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <SplitSettingsForm
+                            settings={self.split.clone()}
+                            has_chapters={!self.chapters.is_empty()}
+                            on_change={ctx.link().callback(Msg::SetSplitSettings)}
+                            on_split={ctx.link().callback(|_| Msg::SplitInput)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <HlsPackageSettings
+                            settings={self.hls.clone()}
+                            on_change={ctx.link().callback(Msg::SetHlsSettings)}
+                            on_create={ctx.link().callback(|_| Msg::CreateHlsPackage)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <DashPackageSettings
+                            settings={self.dash.clone()}
+                            on_change={ctx.link().callback(Msg::SetDashSettings)}
+                            on_create={ctx.link().callback(|_| Msg::CreateDashPackage)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <RenditionLadder
+                            settings={self.ladder.clone()}
+                            progress={self.ladder_progress.clone()}
+                            on_change={ctx.link().callback(Msg::SetLadderSettings)}
+                            on_encode={ctx.link().callback(|_| Msg::EncodeLadder)}
+                            disabled={self.transcoding || self.input_data.is_none()}
+                        />
+                        
+                        <ImageVideoSettings
+                            video={self.image_video.clone()}
+                            image_count={self.video_images.len()}
+                            audio_name={self.video_audio.as_ref().map(|(name, _)| name.clone())}
+                            on_change={ctx.link().callback(Msg::SetImageVideo)}
+                            on_images_selected={ctx.link().callback(Msg::VideoImagesSelected)}
+                            on_audio_selected={ctx.link().callback(Msg::VideoAudioSelected)}
+                            on_clear_audio={ctx.link().callback(|_| Msg::ClearVideoAudio)}
+                            on_create={ctx.link().callback(|_| Msg::CreateImageVideo)}
+                            disabled={self.transcoding}
+                        />
+                        
+                        <ConcatList
+                            clips={self.concat_clips.iter().map(|clip| (clip.name.clone(), clip.data.is_some())).collect::<Vec<_>>()}
+                            settings={self.concat.clone()}
+                            on_files_selected={ctx.link().callback(Msg::ConcatFilesSelected)}
+                            on_move={ctx.link().callback(|(from, to)| Msg::MoveConcatClip(from, to))}
+                            on_remove={ctx.link().callback(Msg::RemoveConcatClip)}
+                            on_change={ctx.link().callback(Msg::SetConcatSettings)}
+                            on_join={ctx.link().callback(|_| Msg::JoinClips)}
+                            disabled={self.transcoding}
+                        />
+                        
                         <div class="button-container">
                             <button 
                                 onclick={ctx.link().callback(|_| Msg::StartTranscoding)}
@@ -434,7 +1438,352 @@ impl Component for App {
     }
 }
 
+// Offer data to the user as a file download
+// First pass of loudness normalization, when it is on. Reports a failure and returns
+// false if the job can't go on.
+fn measure_loudness(link: &html::Scope<App>, input_data: &[u8], options: &mut ffmpeg::TranscodeOptions) -> bool {
+    let Some(loudness) = options.loudness.as_mut() else {
+        return true;
+    };
+    link.send_message(Msg::AddLog("Measuring loudness (pass 1 of 2)...".to_string()));
+    
+    match ffmpeg::measure_loudness(input_data, &options.channel_layout) {
+        Ok(stats) => {
+            link.send_message(Msg::AddLog(format!("Measured loudness: {}", stats)));
+            loudness.measured = Some(stats);
+            true
+        },
+        Err(error) => {
+            link.send_message(Msg::TranscodingError(error.to_string()));
+            false
+        }
+    }
+}
+
+fn save_file(filename: &str, mime_type: &str, data: &[u8]) {
+    let window = web_sys::window().expect("no global window exists");
+    let document = window.document().expect("no document exists");
+    
+    // Create a Uint8Array from our data
+    let uint8arr = Uint8Array::new_with_length(data.len() as u32);
+    uint8arr.copy_from(data);
+    
+    // Convert Uint8Array to Blob
+    let array = js_sys::Array::new();
+    array.push(&uint8arr.buffer());
+    
+    let mut blob_options = web_sys::BlobPropertyBag::new();
+    blob_options.set_type(mime_type);
+    
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(
+        &array, &blob_options).unwrap();
+    
+    // Create a download URL
+    let url = Url::create_object_url_with_blob(&blob).unwrap();
+    
+    // Create a download link
+    let a = document
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<HtmlAnchorElement>()
+        .unwrap();
+    
+    a.set_href(&url);
+    a.set_download(filename);
+    a.set_attribute("style", "display: none").unwrap();
+    
+    document.body().unwrap().append_child(&a).unwrap();
+    a.click();
+    document.body().unwrap().remove_child(&a).unwrap();
+    
+    Url::revoke_object_url(&url).unwrap();
+}
+
 impl App {
+    // The complete output settings for the main input in `output_format`, or why they can't be used
+    fn transcode_options(&self, output_format: &str) -> Result<ffmpeg::TranscodeOptions, String> {
+        let tracks = track_selections(&self.tracks);
+        if !self.tracks.is_empty() && tracks.is_empty() {
+            return Err("Error: No tracks selected".to_string());
+        }
+        
+        let cover_supported = ffmpeg::supports_cover_art(output_format);
+        if cover_supported && self.cover_mode == "replace" && self.cover_image.is_none() {
+            return Err("Error: No cover image selected".to_string());
+        }
+        
+        let burn_source = match self.burn_source.as_str() {
+            "none" => None,
+            "file" => match &self.burn_file {
+                Some((_, data)) => Some(ffmpeg::SubtitleSource::File(data.clone())),
+                None => {
+                    return Err("Error: No subtitle file selected to burn in".to_string());
+                },
+            },
+            index => index.parse::<i32>().ok().map(ffmpeg::SubtitleSource::Track),
+        };
+        
+        let mut options = ffmpeg::TranscodeOptions {
+            output_format: output_format.to_string(),
+            video_codec: self.video_codec.clone(),
+            audio_codec: self.audio_codec.clone(),
+            video_bitrate: self.video_bitrate.parse::<i32>().unwrap_or(1000),
+            audio_bitrate: self.audio_bitrate.parse::<i32>().unwrap_or(128),
+            resolution: if self.resolution == "custom" {
+                self.custom_resolution.clone()
+            } else {
+                self.resolution.clone()
+            },
+            pixel_format: self.pixel_format.clone(),
+            sample_rate: self.sample_rate.parse::<i32>().unwrap_or(0),
+            channel_layout: self.channel_layout.clone(),
+            loudness: if self.loudnorm && !self.drop_audio {
+                Some(ffmpeg::LoudnessNormalization {
+                    target_integrated: self.loudnorm_target.parse::<f64>().unwrap_or(-16.0),
+                    target_true_peak: self.loudnorm_true_peak.parse::<f64>().unwrap_or(-1.0),
+                    dynamic: self.loudnorm_mode == "dynamic",
+                    ..Default::default()
+                })
+            } else {
+                None
+            },
+            volume_db: self.volume.parse::<f64>().unwrap_or(0.0),
+            fade_in: self.fade_in.parse::<f64>().unwrap_or(0.0),
+            fade_out: self.fade_out.parse::<f64>().unwrap_or(0.0),
+            tracks,
+            drop_video: self.drop_video,
+            drop_audio: self.drop_audio,
+            external_subtitles: self.external_subtitles.clone(),
+            burn_subtitles: burn_source.map(|source| ffmpeg::BurnSubtitles {
+                source,
+                font_size: self.burn_font_size.parse::<f64>().ok(),
+                outline: self.burn_outline.parse::<f64>().ok(),
+                position: self.burn_position.parse::<f64>().ok(),
+            }),
+            text_overlay: if self.overlay_text.is_empty() {
+                None
+            } else {
+                Some(ffmpeg::TextOverlay {
+                    text: self.overlay_text.clone(),
+                    file_name: self.input_file.as_ref().map(|file| file.name()).unwrap_or_default(),
+                    position: self.overlay_position.clone(),
+                    font_size: self.overlay_font_size.parse::<u32>().ok(),
+                    color: self.overlay_color.clone(),
+                    // Half-transparent box
+                    background: self.overlay_background.then(|| format!("{}80", self.overlay_background_color)),
+                })
+            },
+            metadata_mode: match self.metadata_mode.as_str() {
+                "strip_location" => ffmpeg::MetadataMode::StripLocation,
+                "strip" => ffmpeg::MetadataMode::Strip,
+                _ => ffmpeg::MetadataMode::Keep,
+            },
+            metadata: self.metadata_fields.clone(),
+            chapters: self.chapters_edited.then(|| self.chapters.clone()),
+            cover_art: match (self.cover_mode.as_str(), &self.cover_image) {
+                ("remove", _) => ffmpeg::CoverArt::Remove,
+                ("replace", Some((_, image))) if cover_supported => ffmpeg::CoverArt::Replace(image.clone()),
+                _ => ffmpeg::CoverArt::Keep,
+            },
+            animation: self.animation.clone(),
+            mp4: self.mp4.clone(),
+            hls: self.hls.clone(),
+            dash: self.dash.clone(),
+            ..Default::default()
+        };
+        
+        // Nothing to burn onto without video
+        if options.drops_video() {
+            options.burn_subtitles = None;
+            options.text_overlay = None;
+        }
+        
+        Ok(options)
+    }
+    
+    // Encode the input as an HLS or DASH package, saved as a ZIP when it is done
+    fn create_package(&mut self, ctx: &Context<Self>, format: &'static str) {
+        let Some(input_data) = self.input_data.clone() else {
+            self.add_log(ctx, "Error: No input file data available".to_string());
+            return;
+        };
+        
+        let mut options = match self.transcode_options(format) {
+            Ok(options) => options,
+            Err(error) => {
+                self.add_log(ctx, error);
+                return;
+            },
+        };
+        
+        self.transcoding = true;
+        self.progress = 0.0;
+        
+        let (name, segment_duration) = if format == "hls" {
+            ("HLS", self.hls.segment_duration)
+        } else {
+            ("DASH", self.dash.segment_duration)
+        };
+        let filename = self.output_file_name(&format!("{}.zip", format));
+        self.add_log(ctx, format!("Packaging as {} with {}s segments...", name, segment_duration));
+        
+        let link = ctx.link().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if !measure_loudness(&link, &input_data, &mut options) {
+                return;
+            }
+            
+            let result = if format == "hls" {
+                ffmpeg::hls_package(&input_data, &options).await
+            } else {
+                ffmpeg::dash_package(&input_data, &options).await
+            };
+            
+            match result {
+                Ok((archive, count)) => {
+                    link.send_message(Msg::TranscodingProgress(100.0));
+                    link.send_message(Msg::AddLog(format!("{} package created with {} files", name, count)));
+                    link.send_message(Msg::PackageReady(filename, archive));
+                },
+                Err(error) => link.send_message(Msg::TranscodingError(error.to_string())),
+            }
+        });
+    }
+    
+    // Encode every rendition of the ladder from one decode, saved as a ZIP when it is done
+    fn encode_ladder(&mut self, ctx: &Context<Self>) {
+        let (Some(input_data), Some(file)) = (self.input_data.clone(), &self.input_file) else {
+            self.add_log(ctx, "Error: No input file data available".to_string());
+            return;
+        };
+        
+        let mut options = match self.transcode_options(&self.output_format) {
+            Ok(options) => options,
+            Err(error) => {
+                self.add_log(ctx, error);
+                return;
+            },
+        };
+        
+        self.transcoding = true;
+        self.progress = 0.0;
+        self.ladder_progress = vec![0; self.ladder.renditions.len()];
+        
+        let settings = self.ladder.clone();
+        let name = file.name();
+        let base_name = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&name).to_string();
+        let filename = self.output_file_name("ladder.zip");
+        self.add_log(ctx, format!("Encoding {} renditions from one decode...", settings.renditions.len()));
+        
+        let link = ctx.link().clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if !measure_loudness(&link, &input_data, &mut options) {
+                return;
+            }
+            
+            let progress_link = link.clone();
+            let result = ffmpeg::encode_ladder(&input_data, &base_name, &options, &settings, move |progress| {
+                progress_link.send_message(Msg::LadderProgress(progress));
+            }).await;
+            
+            match result {
+                Ok((archive, count)) => {
+                    link.send_message(Msg::AddLog(format!("Ladder encoded into {} files", count)));
+                    link.send_message(Msg::PackageReady(filename, archive));
+                },
+                Err(error) => link.send_message(Msg::TranscodingError(error.to_string())),
+            }
+        });
+    }
+    
+    // Output settings for jobs built from other files than the main input
+    fn encoding_options(&self) -> ffmpeg::TranscodeOptions {
+        ffmpeg::TranscodeOptions {
+            output_format: self.output_format.clone(),
+            video_codec: self.video_codec.clone(),
+            audio_codec: self.audio_codec.clone(),
+            video_bitrate: self.video_bitrate.parse::<i32>().unwrap_or(1000),
+            audio_bitrate: self.audio_bitrate.parse::<i32>().unwrap_or(128),
+            resolution: if self.resolution == "custom" {
+                self.custom_resolution.clone()
+            } else {
+                self.resolution.clone()
+            },
+            pixel_format: self.pixel_format.clone(),
+            sample_rate: self.sample_rate.parse::<i32>().unwrap_or(0),
+            channel_layout: self.channel_layout.clone(),
+            volume_db: self.volume.parse::<f64>().unwrap_or(0.0),
+            fade_in: self.fade_in.parse::<f64>().unwrap_or(0.0),
+            fade_out: self.fade_out.parse::<f64>().unwrap_or(0.0),
+            mp4: self.mp4.clone(),
+            ..Default::default()
+        }
+    }
+    
+    // Name for a file derived from the input, e.g. "clip.mov" -> "clip.mp4"
+    fn output_file_name(&self, extension: &str) -> String {
+        match &self.input_file {
+            Some(file) => {
+                let name = file.name();
+                if let Some(dot_pos) = name.rfind('.') {
+                    format!("{}.{}", &name[0..dot_pos], extension)
+                } else {
+                    format!("output.{}", extension)
+                }
+            },
+            None => format!("output.{}", extension),
+        }
+    }
+    
+    // Read a file into memory and deliver its contents as a message
+    fn read_file<F>(&self, ctx: &Context<Self>, file: &File, on_loaded: F)
+    where
+        F: Fn(Vec<u8>) -> Msg + 'static
+    {
+        let file_reader = FileReader::new().unwrap();
+        let file_reader_clone = file_reader.clone();
+        let link = ctx.link().clone();
+        
+        let onload = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            let array_buffer = file_reader_clone.result().unwrap();
+            let uint8array = js_sys::Uint8Array::new(&array_buffer);
+            let mut buffer = vec![0; uint8array.length() as usize];
+            uint8array.copy_to(&mut buffer[..]);
+            link.send_message(on_loaded(buffer));
+        }) as Box<dyn FnMut(web_sys::Event)>);
+        
+        file_reader.set_onload(Some(onload.as_ref().unchecked_ref()));
+        onload.forget();
+        
+        file_reader.read_as_array_buffer(file).unwrap();
+    }
+    
+    // Values shown in the metadata editor: edited ones, else what the input has
+    fn current_metadata(&self) -> Vec<(String, String)> {
+        METADATA_FIELDS
+            .iter()
+            .map(|(key, _)| {
+                let edited = self.metadata_fields.iter().find(|(field, _)| field == key);
+                let value = match edited {
+                    Some((_, value)) => value.clone(),
+                    None if *key == "language" => self
+                        .tracks
+                        .iter()
+                        .find(|track| track.stream.kind == "audio")
+                        .map(|track| track.stream.language.clone())
+                        .unwrap_or_default(),
+                    None => self
+                        .input_metadata
+                        .iter()
+                        .find(|(tag, _)| tag.eq_ignore_ascii_case(key))
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or_default(),
+                };
+                (key.to_string(), value)
+            })
+            .collect()
+    }
+    
     fn add_log(&mut self, ctx: &Context<Self>, message: String) {
         let timestamp = js_sys::Date::new_0().to_locale_time_string("en-US");
         let log_entry = format!("[{}] {}", timestamp, message);
@@ -0,0 +1,291 @@
+//! Chapter lists as text: FFMETADATA files and simple "00:00:00 Title" lists
+
+use crate::ffmpeg::Chapter;
+
+const FFMETADATA_HEADER: &str = ";FFMETADATA1";
+
+/// Format seconds as "HH:MM:SS", with milliseconds only when there are any
+pub fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (hours, minutes, secs, millis) = (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000);
+
+    if millis == 0 {
+        format!("{:02}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, secs, millis)
+    }
+}
+
+/// Parse a finite number; `f64` parsing alone also takes "inf" and "NaN"
+fn parse_number(text: &str) -> Option<f64> {
+    text.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// Parse "HH:MM:SS(.mmm)", "MM:SS(.mmm)" or plain seconds
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+
+    for part in text.trim().split(':') {
+        let value = parse_number(part)?;
+        if value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+
+    Some(seconds)
+}
+
+/// End of a chapter: its own end, else the next chapter's start, else the duration
+fn chapter_end(chapters: &[Chapter], index: usize, duration: f64) -> f64 {
+    let chapter = &chapters[index];
+
+    if chapter.end > chapter.start {
+        chapter.end
+    } else if let Some(next) = chapters.get(index + 1) {
+        next.start
+    } else {
+        duration.max(chapter.start)
+    }
+}
+
+/// Backslash-escape the characters FFMETADATA treats specially
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Write chapters as an FFMETADATA file, in milliseconds
+pub fn to_ffmetadata(chapters: &[Chapter], duration: f64) -> String {
+    let mut text = format!("{}\n", FFMETADATA_HEADER);
+
+    for (index, chapter) in chapters.iter().enumerate() {
+        text.push_str("\n[CHAPTER]\nTIMEBASE=1/1000\n");
+        text.push_str(&format!("START={}\n", (chapter.start * 1000.0).round() as i64));
+        text.push_str(&format!("END={}\n", (chapter_end(chapters, index, duration) * 1000.0).round() as i64));
+        text.push_str(&format!("title={}\n", escape_ffmetadata(&chapter.title)));
+    }
+
+    text
+}
+
+/// Write chapters as "00:00:00 Title" lines
+pub fn to_list(chapters: &[Chapter]) -> String {
+    chapters
+        .iter()
+        .map(|chapter| format!("{} {}\n", format_timestamp(chapter.start), chapter.title))
+        .collect()
+}
+
+/// Read an FFMETADATA file or a "00:00:00 Title" list, sorted by start time
+pub fn parse(text: &str) -> Result<Vec<Chapter>, String> {
+    let mut chapters = if text.trim_start().starts_with(FFMETADATA_HEADER) {
+        parse_ffmetadata(text)?
+    } else {
+        parse_list(text)?
+    };
+
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(chapters)
+}
+
+fn parse_list(text: &str) -> Result<Vec<Chapter>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (timestamp, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let start = parse_timestamp(timestamp)
+                .ok_or_else(|| format!("Invalid chapter line: {}", line))?;
+
+            // Allow "00:00 - Title" as well as "00:00 Title"
+            let title = title.trim_start();
+            let title = title.strip_prefix("- ").unwrap_or(title);
+
+            Ok(Chapter {
+                start,
+                end: 0.0,
+                title: title.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_ffmetadata(text: &str) -> Result<Vec<Chapter>, String> {
+    let mut chapters = Vec::new();
+    let mut current: Option<(Chapter, f64)> = None;
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        // A trailing backslash continues the value on the next line
+        let mut line = line.to_string();
+        while line.ends_with('\\') && !line.ends_with("\\\\") {
+            line.pop();
+            line.push('\n');
+            line.push_str(lines.next().unwrap_or_default());
+        }
+
+        if line.starts_with(';') || line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            chapters.extend(current.take().map(|(chapter, _)| chapter));
+            if line.trim() == "[CHAPTER]" {
+                current = Some((Chapter::default(), 1.0 / 1_000_000_000.0));
+            }
+            continue;
+        }
+
+        let Some((chapter, time_base)) = current.as_mut() else {
+            continue;
+        };
+        let Some((key, value)) = split_ffmetadata_line(&line) else {
+            continue;
+        };
+        let invalid = || format!("Invalid chapter line: {}", line);
+
+        match key.as_str() {
+            "TIMEBASE" => {
+                let (num, den) = value.split_once('/').ok_or_else(invalid)?;
+                let (num, den) = (parse_number(num).ok_or_else(invalid)?, parse_number(den).ok_or_else(invalid)?);
+                if num <= 0.0 || den <= 0.0 {
+                    return Err(invalid());
+                }
+                // Times already read are in the default base
+                chapter.start = chapter.start / *time_base * num / den;
+                chapter.end = chapter.end / *time_base * num / den;
+                *time_base = num / den;
+            },
+            "START" => chapter.start = parse_number(&value).ok_or_else(invalid)? * *time_base,
+            "END" => chapter.end = parse_number(&value).ok_or_else(invalid)? * *time_base,
+            "title" => chapter.title = value,
+            _ => {},
+        }
+    }
+
+    chapters.extend(current.map(|(chapter, _)| chapter));
+    Ok(chapters)
+}
+
+/// Split "key=value" at the first unescaped '=', removing escapes
+fn split_ffmetadata_line(line: &str) -> Option<(String, String)> {
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        let target = if in_value { &mut value } else { &mut key };
+        match c {
+            '\\' => target.extend(chars.next()),
+            '=' if !in_value => in_value = true,
+            _ => target.push(c),
+        }
+    }
+
+    in_value.then_some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(start: f64, end: f64, title: &str) -> Chapter {
+        Chapter { start, end, title: title.to_string() }
+    }
+
+    #[test]
+    fn ffmetadata_round_trip() {
+        let chapters = vec![
+            chapter(0.0, 90.5, "Intro"),
+            chapter(90.5, 600.0, "Part One"),
+            chapter(600.0, 1234.567, "Credits"),
+        ];
+
+        let text = to_ffmetadata(&chapters, 1234.567);
+        assert!(text.starts_with(FFMETADATA_HEADER));
+        assert_eq!(parse(&text).unwrap(), chapters);
+    }
+
+    #[test]
+    fn ffmetadata_fills_missing_ends() {
+        let chapters = vec![chapter(0.0, 0.0, "One"), chapter(30.0, 0.0, "Two")];
+
+        let parsed = parse(&to_ffmetadata(&chapters, 45.0)).unwrap();
+        assert_eq!(parsed, vec![chapter(0.0, 30.0, "One"), chapter(30.0, 45.0, "Two")]);
+    }
+
+    #[test]
+    fn ffmetadata_escapes_survive_round_trip() {
+        let chapters = vec![
+            chapter(0.0, 10.0, "a=b; c#d \\ e"),
+            chapter(10.0, 20.0, "ends with \\"),
+            chapter(20.0, 30.0, "two\nlines"),
+        ];
+
+        let text = to_ffmetadata(&chapters, 30.0);
+        assert!(text.contains("title=a\\=b\\; c\\#d \\\\ e\n"));
+        assert_eq!(parse(&text).unwrap(), chapters);
+    }
+
+    #[test]
+    fn ffmetadata_time_base() {
+        let text = ";FFMETADATA1\n[CHAPTER]\nTIMEBASE=1/90000\nSTART=0\nEND=900000\ntitle=Ten\n";
+
+        assert_eq!(parse(text).unwrap(), vec![chapter(0.0, 10.0, "Ten")]);
+    }
+
+    #[test]
+    fn ffmetadata_rejects_malformed_times() {
+        let lines = [
+            "START=abc", "END=1:00", "START=inf", "END=NaN",
+            "TIMEBASE=1000", "TIMEBASE=0/1", "TIMEBASE=x/1000", "TIMEBASE=1/inf", "TIMEBASE=NaN/1000",
+        ];
+        for line in lines {
+            let text = format!(";FFMETADATA1\n[CHAPTER]\n{}\ntitle=Bad\n", line);
+            assert!(parse(&text).is_err(), "{} should not parse", line);
+        }
+    }
+
+    #[test]
+    fn ffmetadata_missing_times_default_to_zero() {
+        let text = ";FFMETADATA1\n[CHAPTER]\ntitle=Untimed\n";
+
+        assert_eq!(parse(text).unwrap(), vec![chapter(0.0, 0.0, "Untimed")]);
+    }
+
+    #[test]
+    fn list_round_trip() {
+        let chapters = vec![chapter(0.0, 0.0, "Intro"), chapter(83.25, 0.0, "Verse"), chapter(3725.0, 0.0, "Outro")];
+
+        assert_eq!(parse(&to_list(&chapters)).unwrap(), chapters);
+        assert_eq!(parse("1:23 - Verse\n0:00 Intro\n").unwrap(), vec![chapter(0.0, 0.0, "Intro"), chapter(83.0, 0.0, "Verse")]);
+    }
+
+    #[test]
+    fn list_rejects_malformed_timestamps() {
+        for text in ["Intro\n", "1:xx Verse\n", "-5 Before\n", ":30 Half\n", "1::2 Gap\n", "inf Intro\n", "NaN Intro\n", "0:infinity Outro\n"] {
+            assert!(parse(text).is_err(), "{:?} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0.0), "00:00:00");
+        assert_eq!(format_timestamp(3723.5), "01:02:03.500");
+        assert_eq!(format_timestamp(-1.0), "00:00:00");
+
+        assert_eq!(parse_timestamp("01:02:03.500"), Some(3723.5));
+        assert_eq!(parse_timestamp("2:03"), Some(123.0));
+        assert_eq!(parse_timestamp(" 42.25 "), Some(42.25));
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("1:-2"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::chapters::{format_timestamp, parse_timestamp};
+use crate::ffmpeg::AnimationSettings;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+/// Estimates above this many bytes get a warning
+const LARGE_ANIMATION_SIZE: u64 = 10 * 1024 * 1024;
+
+const DITHER_MODES: [(&str, &str); 3] = [
+    ("floyd_steinberg", "Floyd-Steinberg (smoothest)"),
+    ("bayer", "Bayer (smaller files)"),
+    ("none", "None (banding)"),
+];
+
+const LOOP_MODES: [(i32, &str); 5] = [
+    (0, "Loop forever"),
+    (-1, "Play once"),
+    (1, "Repeat once"),
+    (2, "Repeat twice"),
+    (4, "Repeat 4 times"),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct AnimationSettingsEditorProps {
+    /// "gif", "webp" or "apng"
+    pub format: String,
+    pub settings: AnimationSettings,
+    /// Rough output size in bytes, when the input is known
+    pub estimated_size: Option<u64>,
+    pub on_change: Callback<AnimationSettings>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(AnimationSettingsEditor)]
+pub fn animation_settings_editor(props: &AnimationSettingsEditorProps) -> Html {
+    let on_fps_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let fps = input.value().parse().unwrap_or(settings.fps).clamp(1, 50);
+            callback.emit(AnimationSettings { fps, ..settings.clone() });
+        })
+    };
+
+    let on_width_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Empty keeps the video width
+            let width = input.value().parse().unwrap_or(0);
+            callback.emit(AnimationSettings { width, ..settings.clone() });
+        })
+    };
+
+    let on_colors_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let max_colors = input.value().parse().unwrap_or(settings.max_colors).clamp(2, 256);
+            callback.emit(AnimationSettings { max_colors, ..settings.clone() });
+        })
+    };
+
+    let on_dither_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(AnimationSettings { dither: select.value(), ..settings.clone() });
+        })
+    };
+
+    let on_quality_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let quality = input.value().parse().unwrap_or(settings.quality).min(100);
+            callback.emit(AnimationSettings { quality, ..settings.clone() });
+        })
+    };
+
+    let on_lossless_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(AnimationSettings { lossless: input.checked(), ..settings.clone() });
+        })
+    };
+
+    let on_loop_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let loop_count = select.value().parse().unwrap_or(0);
+            callback.emit(AnimationSettings { loop_count, ..settings.clone() });
+        })
+    };
+
+    let on_start_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let start = parse_timestamp(&input.value()).unwrap_or(settings.start);
+            input.set_value(&format_timestamp(start));
+            callback.emit(AnimationSettings { start, ..settings.clone() });
+        })
+    };
+
+    let on_duration_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Empty runs to the end
+            let duration = parse_timestamp(&input.value()).unwrap_or(0.0);
+            callback.emit(AnimationSettings { duration, ..settings.clone() });
+        })
+    };
+
+    let size_note = match props.estimated_size {
+        Some(size) if size > LARGE_ANIMATION_SIZE => html! {
+            <small class="warning">
+                { format!("Estimated size: {:.1} MB. Lower the frame rate, width, colors or length for a smaller file.", size as f64 / 1048576.0) }
+            </small>
+        },
+        Some(size) => html! {
+            <small>{ format!("Estimated size: {:.1} MB", size as f64 / 1048576.0) }</small>
+        },
+        None => html! {},
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "Animation Settings:" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="anim-start">{ "Start:" }</label>
+                    <input
+                        id="anim-start"
+                        type="text"
+                        value={format_timestamp(props.settings.start)}
+                        onchange={on_start_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="anim-duration">{ "Length:" }</label>
+                    <input
+                        id="anim-duration"
+                        type="text"
+                        placeholder="until the end"
+                        value={if props.settings.duration > 0.0 { format_timestamp(props.settings.duration) } else { String::new() }}
+                        onchange={on_duration_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="anim-fps">{ "Frame Rate:" }</label>
+                    <input
+                        id="anim-fps"
+                        type="number"
+                        min="1"
+                        max="50"
+                        value={props.settings.fps.to_string()}
+                        onchange={on_fps_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="anim-width">{ "Width (px):" }</label>
+                    <input
+                        id="anim-width"
+                        type="number"
+                        min="16"
+                        step="1"
+                        placeholder="original"
+                        value={if props.settings.width > 0 { props.settings.width.to_string() } else { String::new() }}
+                        onchange={on_width_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                {
+                    match props.format.as_str() {
+                        "gif" => html! {
+                            <>
+                                <div class="form-group">
+                                    <label for="anim-colors">{ "Max Colors:" }</label>
+                                    <input
+                                        id="anim-colors"
+                                        type="number"
+                                        min="2"
+                                        max="256"
+                                        value={props.settings.max_colors.to_string()}
+                                        onchange={on_colors_change}
+                                        disabled={props.disabled}
+                                    />
+                                </div>
+
+                                <div class="form-group">
+                                    <label for="anim-dither">{ "Dithering:" }</label>
+                                    <select
+                                        id="anim-dither"
+                                        value={props.settings.dither.clone()}
+                                        onchange={on_dither_change}
+                                        disabled={props.disabled}
+                                    >
+                                        {
+                                            for DITHER_MODES.iter().map(|(value, label)| html! {
+                                                <option value={*value} selected={props.settings.dither == *value}>{ *label }</option>
+                                            })
+                                        }
+                                    </select>
+                                </div>
+                            </>
+                        },
+                        "webp" => html! {
+                            <>
+                                <div class="form-group">
+                                    <label for="anim-quality">{ "Quality:" }</label>
+                                    <input
+                                        id="anim-quality"
+                                        type="number"
+                                        min="0"
+                                        max="100"
+                                        value={props.settings.quality.to_string()}
+                                        onchange={on_quality_change}
+                                        disabled={props.disabled || props.settings.lossless}
+                                    />
+                                </div>
+
+                                <div class="form-group">
+                                    <label>
+                                        <input
+                                            type="checkbox"
+                                            checked={props.settings.lossless}
+                                            onchange={on_lossless_change}
+                                            disabled={props.disabled}
+                                        />
+                                        { "Lossless" }
+                                    </label>
+                                </div>
+                            </>
+                        },
+                        _ => html! {
+                            <div class="form-group">
+                                <small>{ "APNG is always lossless" }</small>
+                            </div>
+                        },
+                    }
+                }
+
+                <div class="form-group">
+                    <label for="anim-loop">{ "Looping:" }</label>
+                    <select
+                        id="anim-loop"
+                        value={props.settings.loop_count.to_string()}
+                        onchange={on_loop_change}
+                        disabled={props.disabled}
+                    >
+                        {
+                            for LOOP_MODES.iter().map(|(value, label)| html! {
+                                <option value={value.to_string()} selected={props.settings.loop_count == *value}>{ *label }</option>
+                            })
+                        }
+                    </select>
+                </div>
+            </div>
+            { size_note }
+        </div>
+    }
+}
@@ -0,0 +1,143 @@
+use crate::chapters::{format_timestamp, parse_timestamp};
+use crate::components::file_input::FileInput;
+use crate::ffmpeg::Chapter;
+use web_sys::{Event, HtmlInputElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ChapterEditorProps {
+    pub chapters: Vec<Chapter>,
+    pub on_change: Callback<Vec<Chapter>>,
+    /// A chapter file (FFMETADATA or "00:00:00 Title" list) to replace the chapters with
+    pub on_import: Callback<web_sys::File>,
+    /// Download the chapters as "ffmetadata" or "list"
+    pub on_export: Callback<String>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+/// Keep chapters in start order after a retime
+fn sorted(mut chapters: Vec<Chapter>) -> Vec<Chapter> {
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    chapters
+}
+
+#[function_component(ChapterEditor)]
+pub fn chapter_editor(props: &ChapterEditorProps) -> Html {
+    let rows = props.chapters.iter().enumerate().map(|(position, chapter)| {
+        let on_start_change = {
+            let chapters = props.chapters.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut chapters = chapters.clone();
+                match parse_timestamp(&input.value()) {
+                    Some(start) => {
+                        chapters[position].start = start;
+                        // A fixed end would no longer match the new start
+                        chapters[position].end = 0.0;
+                    },
+                    None => input.set_value(&format_timestamp(chapters[position].start)),
+                }
+                callback.emit(sorted(chapters));
+            })
+        };
+
+        let on_title_change = {
+            let chapters = props.chapters.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut chapters = chapters.clone();
+                chapters[position].title = input.value();
+                callback.emit(chapters);
+            })
+        };
+
+        let on_delete = {
+            let chapters = props.chapters.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |_| {
+                let mut chapters = chapters.clone();
+                chapters.remove(position);
+                callback.emit(chapters);
+            })
+        };
+
+        html! {
+            <div class="track-entry">
+                <input
+                    class="chapter-start"
+                    type="text"
+                    value={format_timestamp(chapter.start)}
+                    onchange={on_start_change}
+                    disabled={props.disabled}
+                />
+                <input
+                    class="chapter-title"
+                    type="text"
+                    value={chapter.title.clone()}
+                    onchange={on_title_change}
+                    disabled={props.disabled}
+                />
+                <button onclick={on_delete} disabled={props.disabled}>{ "Delete" }</button>
+            </div>
+        }
+    });
+
+    let on_add = {
+        let chapters = props.chapters.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |_| {
+            let mut chapters = chapters.clone();
+            let start = chapters.last().map(|chapter| chapter.start + 60.0).unwrap_or(0.0);
+            chapters.push(Chapter {
+                start,
+                end: 0.0,
+                title: format!("Chapter {}", chapters.len() + 1),
+            });
+            callback.emit(chapters);
+        })
+    };
+
+    let on_export_ffmetadata = {
+        let callback = props.on_export.clone();
+        Callback::from(move |_| callback.emit("ffmetadata".to_string()))
+    };
+
+    let on_export_list = {
+        let callback = props.on_export.clone();
+        Callback::from(move |_| callback.emit("list".to_string()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "Chapters:" }</label>
+            <div class="track-list">
+                { for rows }
+            </div>
+            <div class="button-row">
+                <button onclick={on_add} disabled={props.disabled}>{ "Add Chapter" }</button>
+                <button
+                    onclick={on_export_ffmetadata}
+                    disabled={props.disabled || props.chapters.is_empty()}
+                >
+                    { "Export FFMETADATA" }
+                </button>
+                <button
+                    onclick={on_export_list}
+                    disabled={props.disabled || props.chapters.is_empty()}
+                >
+                    { "Export List" }
+                </button>
+            </div>
+            <FileInput
+                id="chapter-upload"
+                label="Import Chapters"
+                accept=".txt,.ffmetadata,.ini"
+                on_file_selected={props.on_import.clone()}
+                disabled={props.disabled}
+            />
+        </div>
+    }
+}
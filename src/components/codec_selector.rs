@@ -7,14 +7,57 @@ pub struct CodecSelectorProps {
     pub audio_codec: String,
     pub video_bitrate: String,
     pub audio_bitrate: String,
+    pub pixel_format: String,
+    #[prop_or_default]
+    pub supported_pixel_formats: Vec<String>,
+    pub sample_rate: String,
+    pub channel_layout: String,
+    #[prop_or_default]
+    pub supported_sample_rates: Vec<i32>,
+    #[prop_or_default]
+    pub supported_channel_layouts: Vec<String>,
+    pub loudnorm: bool,
+    pub loudnorm_target: String,
+    pub loudnorm_true_peak: String,
+    pub loudnorm_mode: String,
+    pub volume: String,
+    pub fade_in: String,
+    pub fade_out: String,
+    pub drop_video: bool,
+    pub drop_audio: bool,
+    /// The output format can't hold video, so video is always dropped
+    #[prop_or(false)]
+    pub audio_only_format: bool,
     pub on_video_codec_change: Callback<String>,
     pub on_audio_codec_change: Callback<String>,
     pub on_video_bitrate_change: Callback<String>,
     pub on_audio_bitrate_change: Callback<String>,
+    pub on_pixel_format_change: Callback<String>,
+    pub on_sample_rate_change: Callback<String>,
+    pub on_channel_layout_change: Callback<String>,
+    pub on_loudnorm_change: Callback<bool>,
+    pub on_loudnorm_target_change: Callback<String>,
+    pub on_loudnorm_true_peak_change: Callback<String>,
+    pub on_loudnorm_mode_change: Callback<String>,
+    pub on_volume_change: Callback<String>,
+    pub on_fade_in_change: Callback<String>,
+    pub on_fade_out_change: Callback<String>,
+    pub on_drop_video_change: Callback<bool>,
+    pub on_drop_audio_change: Callback<bool>,
     #[prop_or(false)]
     pub disabled: bool,
 }
 
+const SAMPLE_RATES: [i32; 9] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000];
+
+/// Whether an encoder's layout list allows a layout choice. Variants with the
+/// same channel count (e.g. "5.1(side)" for "5.1") count as a match.
+pub fn channel_layout_supported(supported: &[String], layout: &str) -> bool {
+    layout == "same"
+        || supported.is_empty()
+        || supported.iter().any(|l| l == layout || l.starts_with(&format!("{}(", layout)))
+}
+
 #[function_component(CodecSelector)]
 pub fn codec_selector(props: &CodecSelectorProps) -> Html {
     let on_video_codec_change = {
@@ -49,15 +92,147 @@ pub fn codec_selector(props: &CodecSelectorProps) -> Html {
         })
     };
 
+    let on_pixel_format_change = {
+        let callback = props.on_pixel_format_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_sample_rate_change = {
+        let callback = props.on_sample_rate_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_channel_layout_change = {
+        let callback = props.on_channel_layout_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_volume_change = {
+        let callback = props.on_volume_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_fade_in_change = {
+        let callback = props.on_fade_in_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_fade_out_change = {
+        let callback = props.on_fade_out_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_loudnorm_change = {
+        let callback = props.on_loudnorm_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.checked());
+        })
+    };
+
+    let on_loudnorm_target_change = {
+        let callback = props.on_loudnorm_target_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_loudnorm_true_peak_change = {
+        let callback = props.on_loudnorm_true_peak_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_loudnorm_mode_change = {
+        let callback = props.on_loudnorm_mode_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_drop_video_change = {
+        let callback = props.on_drop_video_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.checked());
+        })
+    };
+
+    let on_drop_audio_change = {
+        let callback = props.on_drop_audio_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.checked());
+        })
+    };
+
+    let video_disabled = props.disabled || props.drop_video || props.audio_only_format;
+    let audio_disabled = props.disabled || props.drop_audio;
+    let loudnorm_disabled = audio_disabled || props.audio_codec == "copy" || !props.loudnorm;
+
+    // An empty list means the encoder didn't advertise formats, so allow everything
+    let pix_fmt_unsupported = |pix_fmt: &str| {
+        !props.supported_pixel_formats.is_empty()
+            && !props.supported_pixel_formats.iter().any(|f| f == pix_fmt)
+    };
+
     html! {
         <>
+            <div class="form-group">
+                <label for="drop-video">
+                    <input
+                        id="drop-video"
+                        type="checkbox"
+                        checked={props.drop_video || props.audio_only_format}
+                        onchange={on_drop_video_change}
+                        disabled={props.disabled || props.audio_only_format || props.drop_audio}
+                    />
+                    { " Drop video (audio only)" }
+                </label>
+            </div>
+
+            <div class="form-group">
+                <label for="drop-audio">
+                    <input
+                        id="drop-audio"
+                        type="checkbox"
+                        checked={props.drop_audio}
+                        onchange={on_drop_audio_change}
+                        disabled={props.disabled || props.drop_video || props.audio_only_format}
+                    />
+                    { " Drop audio (silent video)" }
+                </label>
+            </div>
+
             <div class="form-group">
                 <label for="video-codec">{ "Video Codec:" }</label>
                 <select 
                     id="video-codec"
                     value={props.video_codec.clone()}
                     onchange={on_video_codec_change}
-                    disabled={props.disabled}
+                    disabled={video_disabled}
                 >
                     <option value="h264">{ "H.264 (AVC)" }</option>
                     <option value="h265">{ "H.265 (HEVC)" }</option>
@@ -78,17 +253,33 @@ pub fn codec_selector(props: &CodecSelectorProps) -> Html {
                     step="100"
                     value={props.video_bitrate.clone()}
                     onchange={on_video_bitrate_change}
-                    disabled={props.disabled || props.video_codec == "copy"}
+                    disabled={video_disabled || props.video_codec == "copy"}
                 />
             </div>
 
+            <div class="form-group">
+                <label for="pixel-format">{ "Pixel Format:" }</label>
+                <select
+                    id="pixel-format"
+                    value={props.pixel_format.clone()}
+                    onchange={on_pixel_format_change}
+                    disabled={video_disabled || props.video_codec == "copy"}
+                >
+                    <option value="auto">{ "Auto (container default)" }</option>
+                    <option value="yuv420p" disabled={pix_fmt_unsupported("yuv420p")}>{ "4:2:0 8-bit (yuv420p)" }</option>
+                    <option value="yuv422p" disabled={pix_fmt_unsupported("yuv422p")}>{ "4:2:2 8-bit (yuv422p)" }</option>
+                    <option value="yuv444p" disabled={pix_fmt_unsupported("yuv444p")}>{ "4:4:4 8-bit (yuv444p)" }</option>
+                    <option value="yuv420p10le" disabled={pix_fmt_unsupported("yuv420p10le")}>{ "4:2:0 10-bit (yuv420p10le)" }</option>
+                </select>
+            </div>
+
             <div class="form-group">
                 <label for="audio-codec">{ "Audio Codec:" }</label>
                 <select 
                     id="audio-codec"
                     value={props.audio_codec.clone()}
                     onchange={on_audio_codec_change}
-                    disabled={props.disabled}
+                    disabled={audio_disabled}
                 >
                     <option value="aac">{ "AAC" }</option>
                     <option value="mp3">{ "MP3" }</option>
@@ -108,9 +299,141 @@ pub fn codec_selector(props: &CodecSelectorProps) -> Html {
                     step="16"
                     value={props.audio_bitrate.clone()}
                     onchange={on_audio_bitrate_change}
-                    disabled={props.disabled || props.audio_codec == "copy"}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
                 />
             </div>
+
+            <div class="form-group">
+                <label for="volume">{ "Volume (dB):" }</label>
+                <input
+                    id="volume"
+                    type="number"
+                    min="-30"
+                    max="30"
+                    step="0.5"
+                    value={props.volume.clone()}
+                    onchange={on_volume_change}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="fade-in">{ "Fade In (s):" }</label>
+                <input
+                    id="fade-in"
+                    type="number"
+                    min="0"
+                    step="0.5"
+                    value={props.fade_in.clone()}
+                    onchange={on_fade_in_change}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="fade-out">{ "Fade Out (s):" }</label>
+                <input
+                    id="fade-out"
+                    type="number"
+                    min="0"
+                    step="0.5"
+                    value={props.fade_out.clone()}
+                    onchange={on_fade_out_change}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="sample-rate">{ "Sample Rate:" }</label>
+                <select
+                    id="sample-rate"
+                    value={props.sample_rate.clone()}
+                    onchange={on_sample_rate_change}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
+                >
+                    <option value="same">{ "Same as source" }</option>
+                    {
+                        for SAMPLE_RATES.iter().map(|rate| {
+                            let unsupported = !props.supported_sample_rates.is_empty()
+                                && !props.supported_sample_rates.contains(rate);
+                            html! {
+                                <option value={rate.to_string()} disabled={unsupported}>
+                                    { format!("{} Hz", rate) }
+                                </option>
+                            }
+                        })
+                    }
+                </select>
+            </div>
+
+            <div class="form-group">
+                <label for="channel-layout">{ "Channels:" }</label>
+                <select
+                    id="channel-layout"
+                    value={props.channel_layout.clone()}
+                    onchange={on_channel_layout_change}
+                    disabled={audio_disabled || props.audio_codec == "copy"}
+                >
+                    <option value="same">{ "Same as source" }</option>
+                    <option value="mono" disabled={!channel_layout_supported(&props.supported_channel_layouts, "mono")}>{ "Mono" }</option>
+                    <option value="stereo" disabled={!channel_layout_supported(&props.supported_channel_layouts, "stereo")}>{ "Stereo" }</option>
+                    <option value="5.1" disabled={!channel_layout_supported(&props.supported_channel_layouts, "5.1")}>{ "5.1 Surround" }</option>
+                </select>
+            </div>
+
+            <div class="form-group">
+                <label for="loudnorm">
+                    <input
+                        id="loudnorm"
+                        type="checkbox"
+                        checked={props.loudnorm}
+                        onchange={on_loudnorm_change}
+                        disabled={audio_disabled || props.audio_codec == "copy"}
+                    />
+                    { " Normalize loudness (EBU R128)" }
+                </label>
+            </div>
+
+            <div class="form-group">
+                <label for="loudnorm-target">{ "Target Loudness (LUFS):" }</label>
+                <input
+                    id="loudnorm-target"
+                    type="number"
+                    min="-40"
+                    max="-5"
+                    step="0.5"
+                    value={props.loudnorm_target.clone()}
+                    onchange={on_loudnorm_target_change}
+                    disabled={loudnorm_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="loudnorm-true-peak">{ "True Peak Ceiling (dBTP):" }</label>
+                <input
+                    id="loudnorm-true-peak"
+                    type="number"
+                    min="-9"
+                    max="0"
+                    step="0.1"
+                    value={props.loudnorm_true_peak.clone()}
+                    onchange={on_loudnorm_true_peak_change}
+                    disabled={loudnorm_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="loudnorm-mode">{ "Normalization Mode:" }</label>
+                <select
+                    id="loudnorm-mode"
+                    value={props.loudnorm_mode.clone()}
+                    onchange={on_loudnorm_mode_change}
+                    disabled={loudnorm_disabled}
+                >
+                    <option value="linear">{ "Linear (single gain)" }</option>
+                    <option value="dynamic">{ "Dynamic" }</option>
+                </select>
+            </div>
         </>
     }
 }
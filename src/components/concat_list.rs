@@ -0,0 +1,172 @@
+use crate::components::file_input::FileInput;
+use crate::ffmpeg::ConcatSettings;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ConcatListProps {
+    /// Clip names in join order, and whether each has finished loading
+    pub clips: Vec<(String, bool)>,
+    pub settings: ConcatSettings,
+    pub on_files_selected: Callback<Vec<web_sys::File>>,
+    /// Move the clip at the first position to the second
+    pub on_move: Callback<(usize, usize)>,
+    pub on_remove: Callback<usize>,
+    pub on_change: Callback<ConcatSettings>,
+    pub on_join: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(ConcatList)]
+pub fn concat_list(props: &ConcatListProps) -> Html {
+    let count = props.clips.len();
+    let rows = props.clips.iter().enumerate().map(|(position, (name, loaded))| {
+        let on_up = {
+            let callback = props.on_move.clone();
+            Callback::from(move |_| callback.emit((position, position - 1)))
+        };
+
+        let on_down = {
+            let callback = props.on_move.clone();
+            Callback::from(move |_| callback.emit((position, position + 1)))
+        };
+
+        let on_remove = {
+            let callback = props.on_remove.clone();
+            Callback::from(move |_| callback.emit(position))
+        };
+
+        html! {
+            <div class="track-entry">
+                <span>{ format!("{}. {}{}", position + 1, name, if *loaded { "" } else { " (loading)" }) }</span>
+                <button onclick={on_up} disabled={props.disabled || position == 0}>{ "Up" }</button>
+                <button onclick={on_down} disabled={props.disabled || position + 1 == count}>{ "Down" }</button>
+                <button onclick={on_remove} disabled={props.disabled}>{ "Remove" }</button>
+            </div>
+        }
+    });
+
+    let on_reencode_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(ConcatSettings { reencode: input.checked(), ..settings.clone() });
+        })
+    };
+
+    let on_transition_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(ConcatSettings { transition: select.value(), ..settings.clone() });
+        })
+    };
+
+    let on_transition_duration_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let transition_duration = input.value().parse::<f64>().unwrap_or(settings.transition_duration).clamp(0.0, 10.0);
+            callback.emit(ConcatSettings { transition_duration, ..settings.clone() });
+        })
+    };
+
+    let on_crossfade_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Empty cuts the audio
+            let audio_crossfade = input.value().parse::<f64>().unwrap_or(0.0).clamp(0.0, 10.0);
+            callback.emit(ConcatSettings { audio_crossfade, ..settings.clone() });
+        })
+    };
+
+    let on_join = {
+        let callback = props.on_join.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let all_loaded = props.clips.iter().all(|(_, loaded)| *loaded);
+
+    html! {
+        <div class="form-group">
+            <label>{ "Join Clips:" }</label>
+            <FileInput
+                id="concat-upload"
+                label="Add Clips"
+                accept="video/*,audio/*"
+                multiple=true
+                on_files_selected={props.on_files_selected.clone()}
+                disabled={props.disabled}
+            />
+            <div class="track-list">
+                { for rows }
+            </div>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="concat-transition">{ "Transition:" }</label>
+                    <select
+                        id="concat-transition"
+                        value={props.settings.transition.clone()}
+                        onchange={on_transition_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="none" selected={props.settings.transition == "none"}>{ "Cut" }</option>
+                        <option value="fade" selected={props.settings.transition == "fade"}>{ "Fade through black" }</option>
+                        <option value="dissolve" selected={props.settings.transition == "dissolve"}>{ "Dissolve" }</option>
+                        <option value="wipe" selected={props.settings.transition == "wipe"}>{ "Wipe" }</option>
+                    </select>
+                </div>
+
+                <div class="form-group">
+                    <label for="concat-transition-duration">{ "Transition Length (s):" }</label>
+                    <input
+                        id="concat-transition-duration"
+                        type="number"
+                        min="0"
+                        max="10"
+                        step="0.1"
+                        value={props.settings.transition_duration.to_string()}
+                        onchange={on_transition_duration_change}
+                        disabled={props.disabled || props.settings.transition == "none"}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="concat-crossfade">{ "Audio Crossfade (s):" }</label>
+                    <input
+                        id="concat-crossfade"
+                        type="number"
+                        min="0"
+                        max="10"
+                        step="0.1"
+                        placeholder="cut"
+                        value={if props.settings.audio_crossfade > 0.0 { props.settings.audio_crossfade.to_string() } else { String::new() }}
+                        onchange={on_crossfade_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+            </div>
+            <label>
+                <input
+                    type="checkbox"
+                    checked={props.settings.reencode || props.settings.overlaps()}
+                    onchange={on_reencode_change}
+                    disabled={props.disabled || props.settings.overlaps()}
+                />
+                { " Always re-encode" }
+            </label>
+            <small>
+                { "Clips with matching codecs are joined without re-encoding unless there are transitions. Otherwise they are re-encoded with the output settings above, at the size and frame rate of the first clip." }
+            </small>
+            <div class="button-row">
+                <button onclick={on_join} disabled={props.disabled || count < 2 || !all_loaded}>{ "Join Clips" }</button>
+            </div>
+        </div>
+    }
+}
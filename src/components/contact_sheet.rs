@@ -0,0 +1,164 @@
+use crate::ffmpeg::ContactSheet;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ContactSheetSettingsProps {
+    pub sheet: ContactSheet,
+    /// Whether the build can draw timestamps and the header
+    pub labels_available: bool,
+    pub on_change: Callback<ContactSheet>,
+    pub on_create: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+/// Callback for a numeric field; values outside `min..=max` are ignored
+fn number_field(
+    sheet: &ContactSheet,
+    callback: &Callback<ContactSheet>,
+    min: u32,
+    max: u32,
+    set: fn(&mut ContactSheet, u32),
+) -> Callback<Event> {
+    let sheet = sheet.clone();
+    let callback = callback.clone();
+    Callback::from(move |e: Event| {
+        let input: HtmlInputElement = e.target_unchecked_into();
+        let mut sheet = sheet.clone();
+        if let Some(value) = input.value().parse().ok().filter(|value| (min..=max).contains(value)) {
+            set(&mut sheet, value);
+        }
+        callback.emit(sheet);
+    })
+}
+
+#[function_component(ContactSheetSettings)]
+pub fn contact_sheet_settings(props: &ContactSheetSettingsProps) -> Html {
+    let on_columns_change = number_field(&props.sheet, &props.on_change, 1, 16, |sheet, value| sheet.columns = value);
+    let on_rows_change = number_field(&props.sheet, &props.on_change, 1, 16, |sheet, value| sheet.rows = value);
+    let on_tile_width_change = number_field(&props.sheet, &props.on_change, 32, 1920, |sheet, value| sheet.tile_width = value);
+
+    let on_format_change = {
+        let sheet = props.sheet.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(ContactSheet { format: select.value(), ..sheet.clone() });
+        })
+    };
+
+    let on_timestamps_change = {
+        let sheet = props.sheet.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(ContactSheet { timestamps: input.checked(), ..sheet.clone() });
+        })
+    };
+
+    let on_header_change = {
+        let sheet = props.sheet.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(ContactSheet { header: input.checked(), ..sheet.clone() });
+        })
+    };
+
+    let on_create = {
+        let callback = props.on_create.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "Contact Sheet:" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="sheet-columns">{ "Columns:" }</label>
+                    <input
+                        id="sheet-columns"
+                        type="number"
+                        min="1"
+                        max="16"
+                        value={props.sheet.columns.to_string()}
+                        onchange={on_columns_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sheet-rows">{ "Rows:" }</label>
+                    <input
+                        id="sheet-rows"
+                        type="number"
+                        min="1"
+                        max="16"
+                        value={props.sheet.rows.to_string()}
+                        onchange={on_rows_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sheet-tile-width">{ "Tile Width (px):" }</label>
+                    <input
+                        id="sheet-tile-width"
+                        type="number"
+                        min="32"
+                        max="1920"
+                        step="1"
+                        value={props.sheet.tile_width.to_string()}
+                        onchange={on_tile_width_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sheet-format">{ "Image Format:" }</label>
+                    <select
+                        id="sheet-format"
+                        value={props.sheet.format.clone()}
+                        onchange={on_format_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="png" selected={props.sheet.format == "png"}>{ "PNG" }</option>
+                        <option value="jpeg" selected={props.sheet.format == "jpeg"}>{ "JPEG" }</option>
+                    </select>
+                </div>
+
+                <div class="form-group">
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={props.sheet.timestamps}
+                            onchange={on_timestamps_change}
+                            disabled={props.disabled || !props.labels_available}
+                        />
+                        { "Timestamps" }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={props.sheet.header}
+                            onchange={on_header_change}
+                            disabled={props.disabled || !props.labels_available}
+                        />
+                        { "Header with file details" }
+                    </label>
+                    {
+                        if props.labels_available {
+                            html! {}
+                        } else {
+                            html! { <small>{ "Labels are not available in this build (enable the drawtext feature)" }</small> }
+                        }
+                    }
+                </div>
+            </div>
+            <div class="button-row">
+                <button onclick={on_create} disabled={props.disabled}>{ "Create Contact Sheet" }</button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,94 @@
+use crate::components::file_input::FileInput;
+use crate::ffmpeg::StreamInfo;
+use web_sys::{Event, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct CoverArtSettingsProps {
+    /// "keep", "remove" or "replace"
+    pub mode: String,
+    /// Cover art stream of the input, if it has one
+    pub input_cover: Option<StreamInfo>,
+    /// Name of the uploaded replacement image
+    pub image_name: Option<String>,
+    /// Whether the output format can carry cover art
+    pub supported: bool,
+    pub on_mode_change: Callback<String>,
+    pub on_image_selected: Callback<web_sys::File>,
+    pub on_extract: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(CoverArtSettings)]
+pub fn cover_art_settings(props: &CoverArtSettingsProps) -> Html {
+    let on_mode_change = {
+        let callback = props.on_mode_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_extract = {
+        let callback = props.on_extract.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let input_cover = match &props.input_cover {
+        Some(cover) => format!("Input cover: {} {}x{}", cover.codec, cover.width, cover.height),
+        None => "The input has no cover art".to_string(),
+    };
+
+    html! {
+        <div class="form-group">
+            <label for="cover-mode">{ "Cover Art:" }</label>
+            <select
+                id="cover-mode"
+                value={props.mode.clone()}
+                onchange={on_mode_change}
+                disabled={props.disabled || !props.supported}
+            >
+                <option value="keep" selected={props.mode == "keep"}>{ "Keep input cover" }</option>
+                <option value="remove" selected={props.mode == "remove"}>{ "Remove cover" }</option>
+                <option value="replace" selected={props.mode == "replace"}>{ "Replace with image" }</option>
+            </select>
+            {
+                if props.supported {
+                    html! { <small>{ input_cover }</small> }
+                } else {
+                    html! { <small>{ "The output format cannot carry cover art" }</small> }
+                }
+            }
+            {
+                if props.mode == "replace" && props.supported {
+                    html! {
+                        <>
+                            <FileInput
+                                id="cover-upload"
+                                label="Select Cover Image"
+                                accept="image/jpeg,image/png"
+                                on_file_selected={props.on_image_selected.clone()}
+                                disabled={props.disabled}
+                            />
+                            {
+                                if let Some(name) = &props.image_name {
+                                    html! { <small>{ format!("Cover image: {}", name) }</small> }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                        </>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <div class="button-row">
+                <button onclick={on_extract} disabled={props.disabled || props.input_cover.is_none()}>
+                    { "Extract Cover" }
+                </button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,57 @@
+use crate::ffmpeg::DashSettings;
+use web_sys::{Event, HtmlInputElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct DashPackageSettingsProps {
+    pub settings: DashSettings,
+    pub on_change: Callback<DashSettings>,
+    pub on_create: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(DashPackageSettings)]
+pub fn dash_package_settings(props: &DashPackageSettingsProps) -> Html {
+    let on_duration_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let segment_duration = input.value().parse::<f64>().ok().filter(|duration| *duration > 0.0).unwrap_or(settings.segment_duration);
+            callback.emit(DashSettings { segment_duration: segment_duration.clamp(1.0, 60.0) });
+        })
+    };
+
+    let on_create = {
+        let callback = props.on_create.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "DASH Package (ZIP):" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="dash-segment-duration">{ "Segment Length (s):" }</label>
+                    <input
+                        id="dash-segment-duration"
+                        type="number"
+                        min="1"
+                        max="60"
+                        step="any"
+                        value={props.settings.segment_duration.to_string()}
+                        onchange={on_duration_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+            </div>
+            <small>
+                { "Encoded with the codecs, bitrates and resolution above into fragmented MP4. The ZIP holds manifest.mpd, with the video and each audio track in their own adaptation sets, and the init and media segments." }
+            </small>
+            <div class="button-row">
+                <button onclick={on_create} disabled={props.disabled}>{ "Create DASH Package" }</button>
+            </div>
+        </div>
+    }
+}
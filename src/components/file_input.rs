@@ -3,7 +3,19 @@ use yew::prelude::*;
 
 #[derive(Properties, PartialEq)]
 pub struct FileInputProps {
+    #[prop_or_default]
     pub on_file_selected: Callback<web_sys::File>,
+    /// With `multiple`, receives every selected file instead
+    #[prop_or_default]
+    pub on_files_selected: Callback<Vec<web_sys::File>>,
+    #[prop_or_else(|| "file-upload".to_string())]
+    pub id: String,
+    #[prop_or_else(|| "Select Video File".to_string())]
+    pub label: String,
+    #[prop_or_else(|| "video/*,audio/*,.srt,.vtt,.ass,.ssa".to_string())]
+    pub accept: String,
+    #[prop_or(false)]
+    pub multiple: bool,
     #[prop_or(false)]
     pub disabled: bool,
 }
@@ -12,10 +24,17 @@ pub struct FileInputProps {
 pub fn file_input(props: &FileInputProps) -> Html {
     let on_change = {
         let on_file_selected = props.on_file_selected.clone();
+        let on_files_selected = props.on_files_selected.clone();
+        let multiple = props.multiple;
         Callback::from(move |e: Event| {
             let input: HtmlInputElement = e.target_unchecked_into();
             if let Some(files) = input.files() {
-                if let Some(file) = files.get(0) {
+                if multiple {
+                    let files: Vec<_> = (0..files.length()).filter_map(|i| files.get(i)).collect();
+                    if !files.is_empty() {
+                        on_files_selected.emit(files);
+                    }
+                } else if let Some(file) = files.get(0) {
                     on_file_selected.emit(file);
                 }
             }
@@ -24,13 +43,14 @@ pub fn file_input(props: &FileInputProps) -> Html {
 
     html! {
         <div class="file-input">
-            <label for="file-upload" class="file-label">
-                { "Select Video File" }
+            <label for={props.id.clone()} class="file-label">
+                { props.label.clone() }
             </label>
             <input 
-                id="file-upload"
+                id={props.id.clone()}
                 type="file" 
-                accept="video/*,audio/*"
+                accept={props.accept.clone()}
+                multiple={props.multiple}
                 onchange={on_change}
                 disabled={props.disabled}
             />
@@ -33,9 +33,17 @@ pub fn format_selector(props: &FormatSelectorProps) -> Html {
                 <option value="mkv">{ "MKV" }</option>
                 <option value="mov">{ "MOV" }</option>
                 <option value="gif">{ "GIF" }</option>
+                <option value="webp">{ "Animated WebP" }</option>
+                <option value="apng">{ "APNG" }</option>
                 <option value="mp3">{ "MP3 (audio only)" }</option>
                 <option value="ogg">{ "OGG (audio only)" }</option>
                 <option value="wav">{ "WAV (audio only)" }</option>
+                <option value="flac">{ "FLAC (audio only)" }</option>
+                <option value="opus">{ "Opus (audio only)" }</option>
+                <option value="ipod">{ "M4A (audio only)" }</option>
+                <option value="srt">{ "SRT (subtitles only)" }</option>
+                <option value="webvtt">{ "WebVTT (subtitles only)" }</option>
+                <option value="ass">{ "ASS (subtitles only)" }</option>
             </select>
         </div>
     }
@@ -0,0 +1,166 @@
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+const IMAGE_FORMATS: [(&str, &str); 3] = [
+    ("png", "PNG"),
+    ("jpeg", "JPEG"),
+    ("webp", "WebP"),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct FrameExportProps {
+    /// "single" for one frame at `timestamp`, "series" for `count` evenly spaced frames
+    pub mode: String,
+    /// Time of the single frame, as "HH:MM:SS(.mmm)" or seconds
+    pub timestamp: String,
+    pub count: String,
+    /// "png", "jpeg" or "webp"
+    pub format: String,
+    /// Image width in pixels; empty keeps the video size
+    pub width: String,
+    pub on_mode_change: Callback<String>,
+    pub on_timestamp_change: Callback<String>,
+    pub on_count_change: Callback<String>,
+    pub on_format_change: Callback<String>,
+    pub on_width_change: Callback<String>,
+    pub on_export: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(FrameExport)]
+pub fn frame_export(props: &FrameExportProps) -> Html {
+    let on_mode_change = {
+        let callback = props.on_mode_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_timestamp_change = {
+        let callback = props.on_timestamp_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_count_change = {
+        let callback = props.on_count_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_format_change = {
+        let callback = props.on_format_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_width_change = {
+        let callback = props.on_width_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_export = {
+        let callback = props.on_export.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let series = props.mode == "series";
+
+    html! {
+        <div class="form-group">
+            <label>{ "Still Frames:" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="frame-mode">{ "Frames:" }</label>
+                    <select
+                        id="frame-mode"
+                        value={props.mode.clone()}
+                        onchange={on_mode_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="single" selected={!series}>{ "One frame" }</option>
+                        <option value="series" selected={series}>{ "Evenly spaced frames" }</option>
+                    </select>
+                </div>
+
+                {
+                    if series {
+                        html! {
+                            <div class="form-group">
+                                <label for="frame-count">{ "Number of Frames:" }</label>
+                                <input
+                                    id="frame-count"
+                                    type="number"
+                                    min="1"
+                                    max="100"
+                                    step="1"
+                                    value={props.count.clone()}
+                                    onchange={on_count_change}
+                                    disabled={props.disabled}
+                                />
+                            </div>
+                        }
+                    } else {
+                        html! {
+                            <div class="form-group">
+                                <label for="frame-time">{ "Time:" }</label>
+                                <input
+                                    id="frame-time"
+                                    type="text"
+                                    placeholder="00:00:00"
+                                    value={props.timestamp.clone()}
+                                    onchange={on_timestamp_change}
+                                    disabled={props.disabled}
+                                />
+                            </div>
+                        }
+                    }
+                }
+
+                <div class="form-group">
+                    <label for="frame-format">{ "Image Format:" }</label>
+                    <select
+                        id="frame-format"
+                        value={props.format.clone()}
+                        onchange={on_format_change}
+                        disabled={props.disabled}
+                    >
+                        {
+                            for IMAGE_FORMATS.iter().map(|(value, label)| html! {
+                                <option value={*value} selected={props.format == *value}>{ *label }</option>
+                            })
+                        }
+                    </select>
+                </div>
+
+                <div class="form-group">
+                    <label for="frame-width">{ "Image Width (px):" }</label>
+                    <input
+                        id="frame-width"
+                        type="number"
+                        min="16"
+                        step="1"
+                        placeholder="original"
+                        value={props.width.clone()}
+                        onchange={on_width_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+            </div>
+            <div class="button-row">
+                <button onclick={on_export} disabled={props.disabled}>{ "Export Frames" }</button>
+            </div>
+        </div>
+    }
+}
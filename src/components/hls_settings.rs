@@ -0,0 +1,97 @@
+use crate::ffmpeg::HlsSettings;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct HlsPackageSettingsProps {
+    pub settings: HlsSettings,
+    pub on_change: Callback<HlsSettings>,
+    pub on_create: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(HlsPackageSettings)]
+pub fn hls_package_settings(props: &HlsPackageSettingsProps) -> Html {
+    let on_duration_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let segment_duration = input.value().parse::<f64>().ok().filter(|duration| *duration > 0.0).unwrap_or(settings.segment_duration);
+            callback.emit(HlsSettings { segment_duration: segment_duration.clamp(1.0, 60.0), ..settings.clone() });
+        })
+    };
+
+    let on_type_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(HlsSettings { segment_type: select.value(), ..settings.clone() });
+        })
+    };
+
+    let on_separate_audio_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(HlsSettings { separate_audio: input.checked(), ..settings.clone() });
+        })
+    };
+
+    let on_create = {
+        let callback = props.on_create.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "HLS Package (ZIP):" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="hls-segment-type">{ "Segments:" }</label>
+                    <select
+                        id="hls-segment-type"
+                        value={props.settings.segment_type.clone()}
+                        onchange={on_type_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="mpegts" selected={props.settings.segment_type == "mpegts"}>{ "MPEG-TS (.ts)" }</option>
+                        <option value="fmp4" selected={props.settings.segment_type == "fmp4"}>{ "Fragmented MP4 (.m4s)" }</option>
+                    </select>
+                </div>
+
+                <div class="form-group">
+                    <label for="hls-segment-duration">{ "Segment Length (s):" }</label>
+                    <input
+                        id="hls-segment-duration"
+                        type="number"
+                        min="1"
+                        max="60"
+                        step="any"
+                        value={props.settings.segment_duration.to_string()}
+                        onchange={on_duration_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+            </div>
+            <label>
+                <input
+                    type="checkbox"
+                    checked={props.settings.separate_audio}
+                    onchange={on_separate_audio_change}
+                    disabled={props.disabled}
+                />
+                { " Audio in its own playlist" }
+            </label>
+            <small>
+                { "Encoded with the codecs, bitrates and resolution above; H.264 and AAC play everywhere. The ZIP holds master.m3u8, the media playlists and their segments." }
+            </small>
+            <div class="button-row">
+                <button onclick={on_create} disabled={props.disabled}>{ "Create HLS Package" }</button>
+            </div>
+        </div>
+    }
+}
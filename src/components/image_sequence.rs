@@ -0,0 +1,146 @@
+use crate::chapters::{format_timestamp, parse_timestamp};
+use crate::ffmpeg::ImageSequence;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ImageSequenceSettingsProps {
+    pub sequence: ImageSequence,
+    pub on_change: Callback<ImageSequence>,
+    pub on_export: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(ImageSequenceSettings)]
+pub fn image_sequence_settings(props: &ImageSequenceSettingsProps) -> Html {
+    let on_format_change = {
+        let sequence = props.sequence.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(ImageSequence { format: select.value(), ..sequence.clone() });
+        })
+    };
+
+    let on_every_change = {
+        let sequence = props.sequence.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let every = input.value().parse().unwrap_or(sequence.every).max(1);
+            callback.emit(ImageSequence { every, ..sequence.clone() });
+        })
+    };
+
+    let on_start_change = {
+        let sequence = props.sequence.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let start = parse_timestamp(&input.value()).unwrap_or(sequence.start);
+            input.set_value(&format_timestamp(start));
+            callback.emit(ImageSequence { start, ..sequence.clone() });
+        })
+    };
+
+    let on_duration_change = {
+        let sequence = props.sequence.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Empty runs to the end
+            let duration = parse_timestamp(&input.value()).unwrap_or(0.0);
+            callback.emit(ImageSequence { duration, ..sequence.clone() });
+        })
+    };
+
+    let on_width_change = {
+        let sequence = props.sequence.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Empty keeps the video size
+            let width = input.value().parse().unwrap_or(0);
+            callback.emit(ImageSequence { width, ..sequence.clone() });
+        })
+    };
+
+    let on_export = {
+        let callback = props.on_export.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "Image Sequence (ZIP):" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="sequence-start">{ "Start:" }</label>
+                    <input
+                        id="sequence-start"
+                        type="text"
+                        value={format_timestamp(props.sequence.start)}
+                        onchange={on_start_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sequence-duration">{ "Length:" }</label>
+                    <input
+                        id="sequence-duration"
+                        type="text"
+                        placeholder="until the end"
+                        value={if props.sequence.duration > 0.0 { format_timestamp(props.sequence.duration) } else { String::new() }}
+                        onchange={on_duration_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sequence-every">{ "Every Nth Frame:" }</label>
+                    <input
+                        id="sequence-every"
+                        type="number"
+                        min="1"
+                        step="1"
+                        value={props.sequence.every.to_string()}
+                        onchange={on_every_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <label for="sequence-format">{ "Image Format:" }</label>
+                    <select
+                        id="sequence-format"
+                        value={props.sequence.format.clone()}
+                        onchange={on_format_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="png" selected={props.sequence.format == "png"}>{ "PNG" }</option>
+                        <option value="jpeg" selected={props.sequence.format == "jpeg"}>{ "JPEG" }</option>
+                    </select>
+                </div>
+
+                <div class="form-group">
+                    <label for="sequence-width">{ "Image Width (px):" }</label>
+                    <input
+                        id="sequence-width"
+                        type="number"
+                        min="16"
+                        step="1"
+                        placeholder="original"
+                        value={if props.sequence.width > 0 { props.sequence.width.to_string() } else { String::new() }}
+                        onchange={on_width_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+            </div>
+            <div class="button-row">
+                <button onclick={on_export} disabled={props.disabled}>{ "Export Image Sequence" }</button>
+            </div>
+        </div>
+    }
+}
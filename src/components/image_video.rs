@@ -0,0 +1,107 @@
+use crate::components::file_input::FileInput;
+use crate::ffmpeg::ImageVideo;
+use web_sys::{Event, HtmlInputElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ImageVideoSettingsProps {
+    pub video: ImageVideo,
+    /// Number of images loaded so far
+    pub image_count: usize,
+    /// Name of the soundtrack file, if one was chosen
+    pub audio_name: Option<String>,
+    pub on_change: Callback<ImageVideo>,
+    pub on_images_selected: Callback<Vec<web_sys::File>>,
+    pub on_audio_selected: Callback<web_sys::File>,
+    pub on_clear_audio: Callback<()>,
+    pub on_create: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(ImageVideoSettings)]
+pub fn image_video_settings(props: &ImageVideoSettingsProps) -> Html {
+    let on_fps_change = {
+        let video = props.video.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let fps = input.value().parse::<f64>().ok().filter(|fps| *fps > 0.0).unwrap_or(video.fps);
+            callback.emit(ImageVideo { fps: fps.clamp(0.1, 120.0) });
+        })
+    };
+
+    let on_clear_audio = {
+        let callback = props.on_clear_audio.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let on_create = {
+        let callback = props.on_create.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let length = props.image_count as f64 / props.video.fps;
+
+    html! {
+        <div class="form-group">
+            <label>{ "Video from Images:" }</label>
+            <FileInput
+                id="image-video-upload"
+                label="Select Images or a ZIP of Them"
+                accept="image/*,.zip"
+                multiple=true
+                on_files_selected={props.on_images_selected.clone()}
+                disabled={props.disabled}
+            />
+            {
+                if props.image_count > 0 {
+                    html! { <small>{ format!("{} images, {:.1}s of video, ordered by file name", props.image_count, length) }</small> }
+                } else {
+                    html! {}
+                }
+            }
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="image-video-fps">{ "Frame Rate (fps):" }</label>
+                    <input
+                        id="image-video-fps"
+                        type="number"
+                        min="0.1"
+                        max="120"
+                        step="any"
+                        value={props.video.fps.to_string()}
+                        onchange={on_fps_change}
+                        disabled={props.disabled}
+                    />
+                </div>
+
+                <div class="form-group">
+                    <FileInput
+                        id="image-video-audio"
+                        label="Add Audio Track"
+                        accept="audio/*,video/*"
+                        on_file_selected={props.on_audio_selected.clone()}
+                        disabled={props.disabled}
+                    />
+                    {
+                        if let Some(name) = &props.audio_name {
+                            html! {
+                                <small>
+                                    { format!("Audio: {} ", name) }
+                                    <button onclick={on_clear_audio} disabled={props.disabled}>{ "Remove" }</button>
+                                </small>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+            </div>
+            <small>{ "Uses the output format, codecs and resolution above; \"Same as source\" takes the size of the first image." }</small>
+            <div class="button-row">
+                <button onclick={on_create} disabled={props.disabled || props.image_count == 0}>{ "Create Video" }</button>
+            </div>
+        </div>
+    }
+}
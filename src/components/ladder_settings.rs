@@ -0,0 +1,133 @@
+use crate::components::progress::Progress;
+use crate::ffmpeg::{LadderSettings, Rendition, MAX_RENDITIONS};
+use web_sys::{Event, HtmlInputElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct RenditionLadderProps {
+    pub settings: LadderSettings,
+    /// Percentage done of each rendition while the ladder is encoded, empty otherwise
+    pub progress: Vec<u32>,
+    pub on_change: Callback<LadderSettings>,
+    pub on_encode: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(RenditionLadder)]
+pub fn rendition_ladder(props: &RenditionLadderProps) -> Html {
+    let count = props.settings.renditions.len();
+    let rows = props.settings.renditions.iter().enumerate().map(|(position, rendition)| {
+        let on_height_change = {
+            let settings = props.settings.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut settings = settings.clone();
+                let rendition = &mut settings.renditions[position];
+                // Even heights suit every chroma subsampling
+                rendition.height = input.value().parse::<u32>().map(|height| height.clamp(16, 4320) & !1).unwrap_or(rendition.height);
+                callback.emit(settings);
+            })
+        };
+
+        let on_bitrate_change = {
+            let settings = props.settings.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut settings = settings.clone();
+                let rendition = &mut settings.renditions[position];
+                rendition.video_bitrate = input.value().parse::<i32>().ok().filter(|bitrate| *bitrate > 0).unwrap_or(rendition.video_bitrate);
+                callback.emit(settings);
+            })
+        };
+
+        let on_remove = {
+            let settings = props.settings.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |_| {
+                let mut settings = settings.clone();
+                settings.renditions.remove(position);
+                callback.emit(settings);
+            })
+        };
+
+        html! {
+            <div class="track-entry">
+                <label>
+                    { "Height:" }
+                    <input
+                        class="rendition-height"
+                        type="number"
+                        min="16"
+                        max="4320"
+                        step="2"
+                        value={rendition.height.to_string()}
+                        onchange={on_height_change}
+                        disabled={props.disabled}
+                    />
+                </label>
+                <label>
+                    { "Video (kbps):" }
+                    <input
+                        class="rendition-bitrate"
+                        type="number"
+                        min="1"
+                        value={rendition.video_bitrate.to_string()}
+                        onchange={on_bitrate_change}
+                        disabled={props.disabled}
+                    />
+                </label>
+                {
+                    match props.progress.get(position) {
+                        Some(progress) => html! {
+                            <div class="rendition-progress"><Progress value={*progress as f64} /></div>
+                        },
+                        None => html! {},
+                    }
+                }
+                <button onclick={on_remove} disabled={props.disabled || count == 1}>{ "Remove" }</button>
+            </div>
+        }
+    });
+
+    let on_add = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |_| {
+            let mut settings = settings.clone();
+            // A step down from the smallest rendition so far
+            let rendition = match settings.renditions.last() {
+                Some(last) => Rendition {
+                    height: (last.height * 2 / 3).max(144) & !1,
+                    video_bitrate: (last.video_bitrate / 2).max(100),
+                },
+                None => Rendition { height: 1080, video_bitrate: 5000 },
+            };
+            settings.renditions.push(rendition);
+            callback.emit(settings);
+        })
+    };
+
+    let on_encode = {
+        let callback = props.on_encode.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "Rendition Ladder (ZIP):" }</label>
+            <div class="track-list">
+                { for rows }
+            </div>
+            <small>
+                { "The input is decoded once and every rendition encoded from it, in the output format with the settings above. Widths follow the aspect ratio of the input. Burned-in subtitles, text overlays, added subtitle files and cover art are not applied to renditions." }
+            </small>
+            <div class="button-row">
+                <button onclick={on_add} disabled={props.disabled || count >= MAX_RENDITIONS}>{ "Add Rendition" }</button>
+                <button onclick={on_encode} disabled={props.disabled || count == 0}>{ "Encode Ladder" }</button>
+            </div>
+        </div>
+    }
+}
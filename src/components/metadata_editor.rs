@@ -0,0 +1,116 @@
+use crate::ffmpeg::is_location_tag;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+/// Editable tags and their labels
+pub const METADATA_FIELDS: [(&str, &str); 6] = [
+    ("title", "Title"),
+    ("artist", "Artist"),
+    ("album", "Album"),
+    ("comment", "Comment"),
+    ("date", "Date"),
+    ("language", "Language"),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct MetadataEditorProps {
+    /// "keep", "strip_location" or "strip"
+    pub mode: String,
+    /// Values of `METADATA_FIELDS`, by key
+    pub fields: Vec<(String, String)>,
+    /// Container tags of the input
+    pub input_tags: Vec<(String, String)>,
+    pub on_mode_change: Callback<String>,
+    pub on_field_change: Callback<(String, String)>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(MetadataEditor)]
+pub fn metadata_editor(props: &MetadataEditorProps) -> Html {
+    let on_mode_change = {
+        let callback = props.on_mode_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let fields = METADATA_FIELDS.iter().map(|(key, label)| {
+        let value = props
+            .fields
+            .iter()
+            .find(|(field, _)| field == key)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default();
+
+        let on_change = {
+            let callback = props.on_field_change.clone();
+            let key = key.to_string();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                callback.emit((key.clone(), input.value()));
+            })
+        };
+
+        html! {
+            <div class="form-group">
+                <label for={format!("metadata-{}", key)}>{ format!("{}:", label) }</label>
+                <input
+                    id={format!("metadata-{}", key)}
+                    type="text"
+                    value={value}
+                    onchange={on_change}
+                    disabled={props.disabled}
+                />
+            </div>
+        }
+    });
+
+    let has_location = props.input_tags.iter().any(|(key, _)| is_location_tag(key));
+
+    html! {
+        <div class="form-group">
+            <label for="metadata-mode">{ "Metadata:" }</label>
+            <select
+                id="metadata-mode"
+                value={props.mode.clone()}
+                onchange={on_mode_change}
+                disabled={props.disabled}
+            >
+                <option value="keep" selected={props.mode == "keep"}>{ "Keep input metadata" }</option>
+                <option value="strip_location" selected={props.mode == "strip_location"}>{ "Remove location" }</option>
+                <option value="strip" selected={props.mode == "strip"}>{ "Strip all metadata" }</option>
+            </select>
+            {
+                if has_location && props.mode == "keep" {
+                    html! { <small>{ "The input contains location tags" }</small> }
+                } else {
+                    html! {}
+                }
+            }
+
+            <div class="settings-grid">
+                { for fields }
+            </div>
+
+            {
+                if props.input_tags.is_empty() {
+                    html! {}
+                } else {
+                    html! {
+                        <div class="metadata-tags">
+                            {
+                                for props.input_tags.iter().map(|(key, value)| html! {
+                                    <div class={classes!("metadata-tag", is_location_tag(key).then_some("location"))}>
+                                        { format!("{}: {}", key, value) }
+                                    </div>
+                                })
+                            }
+                        </div>
+                    }
+                }
+            }
+        </div>
+    }
+}
@@ -3,3 +3,20 @@ pub mod format_selector;
 pub mod codec_selector;
 pub mod transcoder;
 pub mod progress;
+pub mod track_list;
+pub mod subtitle_burn_in;
+pub mod text_overlay;
+pub mod metadata_editor;
+pub mod chapter_editor;
+pub mod cover_art;
+pub mod frame_export;
+pub mod contact_sheet;
+pub mod animation_settings;
+pub mod image_sequence;
+pub mod image_video;
+pub mod concat_list;
+pub mod split_settings;
+pub mod hls_settings;
+pub mod dash_settings;
+pub mod ladder_settings;
+pub mod mp4_settings;
@@ -0,0 +1,80 @@
+use crate::ffmpeg::Mp4Settings;
+use web_sys::{Event, HtmlInputElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct Mp4LayoutSettingsProps {
+    pub settings: Mp4Settings,
+    pub on_change: Callback<Mp4Settings>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(Mp4LayoutSettings)]
+pub fn mp4_layout_settings(props: &Mp4LayoutSettingsProps) -> Html {
+    let on_faststart_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(Mp4Settings { faststart: input.checked(), ..settings.clone() });
+        })
+    };
+
+    let on_fragmented_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let fragmented = input.checked();
+            // A fragmented file starts with its index anyway; the base offset only applies to fragments
+            callback.emit(Mp4Settings {
+                fragmented,
+                faststart: settings.faststart && !fragmented,
+                default_base_moof: settings.default_base_moof && fragmented,
+            });
+        })
+    };
+
+    let on_default_base_moof_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(Mp4Settings { default_base_moof: input.checked(), ..settings.clone() });
+        })
+    };
+
+    html! {
+        <div class="form-group">
+            <label>{ "MP4 Layout:" }</label>
+            <label>
+                <input
+                    type="checkbox"
+                    checked={props.settings.faststart}
+                    onchange={on_faststart_change}
+                    disabled={props.disabled || props.settings.fragmented}
+                />
+                { " Fast start (index at the front for web playback)" }
+            </label>
+            <label>
+                <input
+                    type="checkbox"
+                    checked={props.settings.fragmented}
+                    onchange={on_fragmented_change}
+                    disabled={props.disabled}
+                />
+                { " Fragmented (a fragment per keyframe after an empty index)" }
+            </label>
+            <label>
+                <input
+                    type="checkbox"
+                    checked={props.settings.default_base_moof}
+                    onchange={on_default_base_moof_change}
+                    disabled={props.disabled || !props.settings.fragmented}
+                />
+                { " Offsets relative to each fragment (default-base-is-moof, for Media Source players)" }
+            </label>
+        </div>
+    }
+}
@@ -0,0 +1,115 @@
+use crate::ffmpeg::SplitSettings;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SplitSettingsProps {
+    pub settings: SplitSettings,
+    /// Whether the input has chapter markers to split at
+    pub has_chapters: bool,
+    pub on_change: Callback<SplitSettings>,
+    pub on_split: Callback<()>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(SplitSettingsForm)]
+pub fn split_settings_form(props: &SplitSettingsProps) -> Html {
+    let on_mode_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(SplitSettings { mode: select.value(), ..settings.clone() });
+        })
+    };
+
+    let on_duration_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let minutes = input.value().parse::<f64>().ok().filter(|minutes| *minutes > 0.0);
+            let duration = minutes.map(|minutes| (minutes * 60.0).max(1.0)).unwrap_or(settings.duration);
+            callback.emit(SplitSettings { duration, ..settings.clone() });
+        })
+    };
+
+    let on_size_change = {
+        let settings = props.settings.clone();
+        let callback = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let size = input.value().parse::<f64>().ok().filter(|size| *size > 0.0).unwrap_or(settings.size);
+            callback.emit(SplitSettings { size: size.max(0.1), ..settings.clone() });
+        })
+    };
+
+    let on_split = {
+        let callback = props.on_split.clone();
+        Callback::from(move |_| callback.emit(()))
+    };
+
+    let mode = props.settings.mode.as_str();
+
+    html! {
+        <div class="form-group">
+            <label>{ "Split into Parts (ZIP):" }</label>
+            <div class="settings-grid">
+                <div class="form-group">
+                    <label for="split-mode">{ "Split:" }</label>
+                    <select
+                        id="split-mode"
+                        value={props.settings.mode.clone()}
+                        onchange={on_mode_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="duration" selected={mode == "duration"}>{ "Every N minutes" }</option>
+                        <option value="size" selected={mode == "size"}>{ "At most N MB each" }</option>
+                        <option value="chapters" selected={mode == "chapters"} disabled={!props.has_chapters}>{ "At chapters" }</option>
+                    </select>
+                </div>
+
+                {
+                    match mode {
+                        "duration" => html! {
+                            <div class="form-group">
+                                <label for="split-duration">{ "Part Length (min):" }</label>
+                                <input
+                                    id="split-duration"
+                                    type="number"
+                                    min="0.1"
+                                    step="any"
+                                    value={(props.settings.duration / 60.0).to_string()}
+                                    onchange={on_duration_change}
+                                    disabled={props.disabled}
+                                />
+                            </div>
+                        },
+                        "size" => html! {
+                            <div class="form-group">
+                                <label for="split-size">{ "Part Size (MB):" }</label>
+                                <input
+                                    id="split-size"
+                                    type="number"
+                                    min="0.1"
+                                    step="any"
+                                    value={props.settings.size.to_string()}
+                                    onchange={on_size_change}
+                                    disabled={props.disabled}
+                                />
+                            </div>
+                        },
+                        _ => html! {},
+                    }
+                }
+            </div>
+            <small>
+                { "Parts are copied without re-encoding into the output format above, cut at the first keyframe after each split point." }
+            </small>
+            <div class="button-row">
+                <button onclick={on_split} disabled={props.disabled || (mode == "chapters" && !props.has_chapters)}>{ "Split" }</button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,160 @@
+use crate::components::file_input::FileInput;
+use crate::ffmpeg::StreamInfo;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SubtitleBurnInProps {
+    /// "none", "file" or the index of an input subtitle stream
+    pub source: String,
+    /// Text subtitle streams of the input
+    pub subtitle_streams: Vec<StreamInfo>,
+    /// Name of the uploaded subtitle file, if any
+    pub file_name: Option<String>,
+    pub font_size: String,
+    pub outline: String,
+    pub position: String,
+    /// Whether the build includes libass
+    pub available: bool,
+    pub on_source_change: Callback<String>,
+    pub on_file_selected: Callback<web_sys::File>,
+    pub on_font_size_change: Callback<String>,
+    pub on_outline_change: Callback<String>,
+    pub on_position_change: Callback<String>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(SubtitleBurnIn)]
+pub fn subtitle_burn_in(props: &SubtitleBurnInProps) -> Html {
+    if !props.available {
+        return html! {
+            <div class="form-group">
+                <label>{ "Burn In Subtitles:" }</label>
+                <small>{ "Not available in this build (enable the burn-subtitles feature)" }</small>
+            </div>
+        };
+    }
+
+    let on_source_change = {
+        let callback = props.on_source_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_font_size_change = {
+        let callback = props.on_font_size_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_outline_change = {
+        let callback = props.on_outline_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_position_change = {
+        let callback = props.on_position_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let style_disabled = props.disabled || props.source == "none";
+
+    html! {
+        <div class="settings-grid">
+            <div class="form-group">
+                <label for="burn-source">{ "Burn In Subtitles:" }</label>
+                <select
+                    id="burn-source"
+                    value={props.source.clone()}
+                    onchange={on_source_change}
+                    disabled={props.disabled}
+                >
+                    <option value="none" selected={props.source == "none"}>{ "None" }</option>
+                    {
+                        for props.subtitle_streams.iter().map(|stream| {
+                            let value = stream.index.to_string();
+                            html! {
+                                <option value={value.clone()} selected={props.source == value}>
+                                    { format!("Track {}", stream) }
+                                </option>
+                            }
+                        })
+                    }
+                    <option value="file" selected={props.source == "file"}>{ "Uploaded file" }</option>
+                </select>
+            </div>
+
+            {
+                if props.source == "file" {
+                    html! {
+                        <div class="form-group">
+                            <FileInput
+                                id="burn-subtitle-upload"
+                                label={props.file_name.clone().unwrap_or_else(|| "Select Subtitle File".to_string())}
+                                accept=".srt,.vtt,.ass,.ssa"
+                                on_file_selected={props.on_file_selected.clone()}
+                                disabled={props.disabled}
+                            />
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+
+            <div class="form-group">
+                <label for="burn-font-size">{ "Font Size (px):" }</label>
+                <input
+                    id="burn-font-size"
+                    type="number"
+                    min="0"
+                    step="1"
+                    placeholder="auto"
+                    value={props.font_size.clone()}
+                    onchange={on_font_size_change}
+                    disabled={style_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="burn-outline">{ "Outline (px):" }</label>
+                <input
+                    id="burn-outline"
+                    type="number"
+                    min="0"
+                    step="0.5"
+                    placeholder="auto"
+                    value={props.outline.clone()}
+                    onchange={on_outline_change}
+                    disabled={style_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="burn-position">{ "Bottom Margin (% of height):" }</label>
+                <input
+                    id="burn-position"
+                    type="number"
+                    min="0"
+                    max="100"
+                    step="1"
+                    placeholder="auto"
+                    value={props.position.clone()}
+                    onchange={on_position_change}
+                    disabled={style_disabled}
+                />
+            </div>
+        </div>
+    }
+}
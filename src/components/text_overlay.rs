@@ -0,0 +1,171 @@
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+const POSITIONS: [(&str, &str); 7] = [
+    ("top-left", "Top left"),
+    ("top", "Top"),
+    ("top-right", "Top right"),
+    ("center", "Center"),
+    ("bottom-left", "Bottom left"),
+    ("bottom", "Bottom"),
+    ("bottom-right", "Bottom right"),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct TextOverlaySettingsProps {
+    /// Template text; empty disables the overlay
+    pub text: String,
+    pub position: String,
+    pub font_size: String,
+    pub color: String,
+    pub background: bool,
+    pub background_color: String,
+    /// Whether the build includes FreeType
+    pub available: bool,
+    pub on_text_change: Callback<String>,
+    pub on_position_change: Callback<String>,
+    pub on_font_size_change: Callback<String>,
+    pub on_color_change: Callback<String>,
+    pub on_background_change: Callback<bool>,
+    pub on_background_color_change: Callback<String>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(TextOverlaySettings)]
+pub fn text_overlay_settings(props: &TextOverlaySettingsProps) -> Html {
+    if !props.available {
+        return html! {
+            <div class="form-group">
+                <label>{ "Text Overlay:" }</label>
+                <small>{ "Not available in this build (enable the drawtext feature)" }</small>
+            </div>
+        };
+    }
+
+    let on_text_change = {
+        let callback = props.on_text_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_position_change = {
+        let callback = props.on_position_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            callback.emit(select.value());
+        })
+    };
+
+    let on_font_size_change = {
+        let callback = props.on_font_size_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_color_change = {
+        let callback = props.on_color_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let on_background_change = {
+        let callback = props.on_background_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.checked());
+        })
+    };
+
+    let on_background_color_change = {
+        let callback = props.on_background_color_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            callback.emit(input.value());
+        })
+    };
+
+    let style_disabled = props.disabled || props.text.is_empty();
+
+    html! {
+        <div class="settings-grid">
+            <div class="form-group">
+                <label for="overlay-text">{ "Text Overlay:" }</label>
+                <input
+                    id="overlay-text"
+                    type="text"
+                    placeholder="{filename} {timecode}"
+                    value={props.text.clone()}
+                    onchange={on_text_change}
+                    disabled={props.disabled}
+                />
+                <small>{ "Variables: {filename}, {timecode}, {pts} (seconds), {frame}" }</small>
+            </div>
+
+            <div class="form-group">
+                <label for="overlay-position">{ "Text Position:" }</label>
+                <select
+                    id="overlay-position"
+                    value={props.position.clone()}
+                    onchange={on_position_change}
+                    disabled={style_disabled}
+                >
+                    {
+                        for POSITIONS.iter().map(|(value, label)| html! {
+                            <option value={*value} selected={props.position == *value}>{ *label }</option>
+                        })
+                    }
+                </select>
+            </div>
+
+            <div class="form-group">
+                <label for="overlay-font-size">{ "Text Size (px):" }</label>
+                <input
+                    id="overlay-font-size"
+                    type="number"
+                    min="8"
+                    step="1"
+                    placeholder="auto"
+                    value={props.font_size.clone()}
+                    onchange={on_font_size_change}
+                    disabled={style_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label for="overlay-color">{ "Text Color:" }</label>
+                <input
+                    id="overlay-color"
+                    type="color"
+                    value={props.color.clone()}
+                    onchange={on_color_change}
+                    disabled={style_disabled}
+                />
+            </div>
+
+            <div class="form-group">
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={props.background}
+                        onchange={on_background_change}
+                        disabled={style_disabled}
+                    />
+                    { "Background box" }
+                </label>
+                <input
+                    type="color"
+                    value={props.background_color.clone()}
+                    onchange={on_background_color_change}
+                    disabled={style_disabled || !props.background}
+                />
+            </div>
+        </div>
+    }
+}
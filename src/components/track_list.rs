@@ -0,0 +1,182 @@
+use crate::ffmpeg::{StreamInfo, TrackSelection};
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+/// A probed input stream and what to do with it
+#[derive(Clone, PartialEq)]
+pub struct TrackEntry {
+    pub stream: StreamInfo,
+    pub include: bool,
+    pub copy: bool,
+    pub default: bool,
+}
+
+impl TrackEntry {
+    /// Video, audio and text subtitles are kept and re-encoded by default, other streams are left out
+    pub fn new(stream: StreamInfo) -> Self {
+        let keep = match stream.kind.as_str() {
+            "video" | "audio" => true,
+            "subtitle" => stream.text_based,
+            _ => false,
+        };
+        let mut entry = Self {
+            include: keep,
+            copy: false,
+            default: stream.default,
+            stream,
+        };
+        entry.copy = entry.copy_only();
+        entry
+    }
+
+    /// Whether the stream can only be passed through (data, attachments, bitmap subtitles)
+    pub fn copy_only(&self) -> bool {
+        match self.stream.kind.as_str() {
+            "video" | "audio" => false,
+            "subtitle" => !self.stream.text_based,
+            _ => true,
+        }
+    }
+}
+
+/// Included tracks in list order, as the transcoder expects them
+pub fn track_selections(tracks: &[TrackEntry]) -> Vec<TrackSelection> {
+    tracks
+        .iter()
+        .filter(|track| track.include)
+        .map(|track| TrackSelection {
+            index: track.stream.index,
+            copy: track.copy,
+            default: track.default,
+        })
+        .collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TrackListProps {
+    pub tracks: Vec<TrackEntry>,
+    pub on_change: Callback<Vec<TrackEntry>>,
+    #[prop_or(false)]
+    pub disabled: bool,
+}
+
+#[function_component(TrackList)]
+pub fn track_list(props: &TrackListProps) -> Html {
+    if props.tracks.is_empty() {
+        return html! {};
+    }
+
+    let rows = props.tracks.iter().enumerate().map(|(position, track)| {
+        let on_include_change = {
+            let tracks = props.tracks.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut tracks = tracks.clone();
+                tracks[position].include = input.checked();
+                callback.emit(tracks);
+            })
+        };
+
+        let on_default_change = {
+            let tracks = props.tracks.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                let mut tracks = tracks.clone();
+                let kind = tracks[position].stream.kind.clone();
+
+                // Only one default per stream type
+                for (i, other) in tracks.iter_mut().enumerate() {
+                    if other.stream.kind == kind {
+                        other.default = i == position && input.checked();
+                    }
+                }
+                callback.emit(tracks);
+            })
+        };
+
+        let on_mode_change = {
+            let tracks = props.tracks.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |e: Event| {
+                let select: HtmlSelectElement = e.target_unchecked_into();
+                let mut tracks = tracks.clone();
+                tracks[position].copy = select.value() == "copy";
+                callback.emit(tracks);
+            })
+        };
+
+        let on_move_up = {
+            let tracks = props.tracks.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |_| {
+                let mut tracks = tracks.clone();
+                tracks.swap(position - 1, position);
+                callback.emit(tracks);
+            })
+        };
+
+        let on_move_down = {
+            let tracks = props.tracks.clone();
+            let callback = props.on_change.clone();
+            Callback::from(move |_| {
+                let mut tracks = tracks.clone();
+                tracks.swap(position, position + 1);
+                callback.emit(tracks);
+            })
+        };
+
+        html! {
+            <div class="track-entry">
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={track.include}
+                        onchange={on_include_change}
+                        disabled={props.disabled}
+                    />
+                    { track.stream.to_string() }
+                </label>
+                <select
+                    value={if track.copy { "copy" } else { "encode" }}
+                    onchange={on_mode_change}
+                    disabled={props.disabled || !track.include || track.copy_only()}
+                >
+                    <option value="encode" selected={!track.copy}>{ "Re-encode" }</option>
+                    <option value="copy" selected={track.copy}>{ "Copy" }</option>
+                </select>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={track.default}
+                        onchange={on_default_change}
+                        disabled={props.disabled || !track.include}
+                    />
+                    { "Default" }
+                </label>
+                <button
+                    onclick={on_move_up}
+                    disabled={props.disabled || position == 0}
+                >
+                    { "↑" }
+                </button>
+                <button
+                    onclick={on_move_down}
+                    disabled={props.disabled || position + 1 == props.tracks.len()}
+                >
+                    { "↓" }
+                </button>
+            </div>
+        }
+    });
+
+    html! {
+        <div class="form-group">
+            <label>{ "Tracks:" }</label>
+            <div class="track-list">
+                { for rows }
+            </div>
+        </div>
+    }
+}
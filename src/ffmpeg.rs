@@ -1,4 +1,7 @@
+use crate::chapters;
+use crate::zip::ZipWriter;
 use wasm_bindgen::prelude::*;
+use std::cmp::Ordering;
 use std::fmt;
 
 #[wasm_bindgen]
@@ -24,9 +27,91 @@ extern "C" {
         audio_codec: &str,
         video_bitrate: i32,
         audio_bitrate: i32,
-        resolution: &str
+        resolution: &str,
+        options: &str
     ) -> js_sys::Promise;
     
+    #[wasm_bindgen(method, js_name = "getEncoderPixelFormats")]
+    fn get_encoder_pixel_formats(this: &FFmpegModule, codec_name: &str) -> String;
+    
+    #[wasm_bindgen(method, js_name = "getEncoderSampleRates")]
+    fn get_encoder_sample_rates(this: &FFmpegModule, codec_name: &str) -> String;
+    
+    #[wasm_bindgen(method, js_name = "getEncoderChannelLayouts")]
+    fn get_encoder_channel_layouts(this: &FFmpegModule, codec_name: &str) -> String;
+    
+    #[wasm_bindgen(method, js_name = "getBuildFeatures")]
+    fn get_build_features_js(this: &FFmpegModule) -> String;
+    
+    #[wasm_bindgen(method, js_name = "measureLoudness")]
+    fn measure_loudness_js(this: &FFmpegModule, input_data: &[u8], options: &str) -> String;
+    
+    #[wasm_bindgen(method, js_name = "probeInput")]
+    fn probe_input_js(this: &FFmpegModule, input_data: &[u8]) -> String;
+    
+    #[wasm_bindgen(method, catch, js_name = "extractCover")]
+    fn extract_cover_js(this: &FFmpegModule, input_data: &[u8]) -> Result<js_sys::Uint8Array, JsValue>;
+    
+    #[wasm_bindgen(method, catch, js_name = "extractFrame")]
+    fn extract_frame_js(
+        this: &FFmpegModule,
+        input_data: &[u8],
+        timestamp: f64,
+        format: &str,
+        width: u32,
+        height: u32
+    ) -> Result<js_sys::Uint8Array, JsValue>;
+    
+    #[wasm_bindgen(method, catch, js_name = "contactSheet")]
+    fn contact_sheet_js(this: &FFmpegModule, input_data: &[u8], format: &str, options: &str) -> Result<js_sys::Uint8Array, JsValue>;
+    
+    #[wasm_bindgen(method, js_name = "imageSequenceOpen")]
+    fn image_sequence_open_js(this: &FFmpegModule, input_data: &[u8], options: &str) -> u32;
+    
+    #[wasm_bindgen(method, catch, js_name = "imageSequenceNext")]
+    fn image_sequence_next_js(this: &FFmpegModule, sequence: u32, format: &str) -> Result<js_sys::Uint8Array, JsValue>;
+    
+    #[wasm_bindgen(method, js_name = "imageSequenceClose")]
+    fn image_sequence_close_js(this: &FFmpegModule, sequence: u32);
+    
+    #[wasm_bindgen(method, js_name = "splitOpen")]
+    fn split_open_js(this: &FFmpegModule, input_data: &[u8], format: &str, options: &str) -> u32;
+    
+    #[wasm_bindgen(method, catch, js_name = "splitNext")]
+    fn split_next_js(this: &FFmpegModule, splitter: u32) -> Result<js_sys::Uint8Array, JsValue>;
+    
+    #[wasm_bindgen(method, js_name = "splitClose")]
+    fn split_close_js(this: &FFmpegModule, splitter: u32);
+    
+    #[wasm_bindgen(method, js_name = "ladderOpen")]
+    fn ladder_open_js(
+        this: &FFmpegModule,
+        input_data: &[u8],
+        format: &str,
+        video_codec: &str,
+        audio_codec: &str,
+        audio_bitrate: i32,
+        options: &str
+    ) -> u32;
+    
+    #[wasm_bindgen(method, catch, js_name = "ladderStep")]
+    fn ladder_step_js(this: &FFmpegModule, ladder: u32) -> Result<bool, JsValue>;
+    
+    #[wasm_bindgen(method, js_name = "ladderProgress")]
+    fn ladder_progress_js(this: &FFmpegModule, ladder: u32, rendition: u32) -> u32;
+    
+    #[wasm_bindgen(method, js_name = "ladderClose")]
+    fn ladder_close_js(this: &FFmpegModule, ladder: u32);
+    
+    #[wasm_bindgen(method, js_name = "takeOutputFiles")]
+    fn take_output_files(this: &FFmpegModule) -> js_sys::Array;
+    
+    #[wasm_bindgen(method, js_name = "addInput")]
+    fn add_input(this: &FFmpegModule, input_data: &[u8]) -> i32;
+    
+    #[wasm_bindgen(method, js_name = "clearInputs")]
+    fn clear_inputs(this: &FFmpegModule);
+    
     #[wasm_bindgen(method, catch, js_name = "_init_ffmpeg")]
     fn init_ffmpeg_js(this: &FFmpegModule) -> Result<(), JsValue>;
 }
@@ -82,29 +167,671 @@ where
     });
 }
 
+/// EBU R128 loudness measurement of an input's audio
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessStats {
+    /// Integrated loudness in LUFS
+    pub integrated: f64,
+    /// Loudness range in LU
+    pub range: f64,
+    /// True peak in dBTP
+    pub true_peak: f64,
+    /// Relative gating threshold in LUFS
+    pub threshold: f64,
+}
+
+impl fmt::Display for LoudnessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "integrated {:.1} LUFS, range {:.1} LU, true peak {:.1} dBTP, threshold {:.1} LUFS",
+            self.integrated, self.range, self.true_peak, self.threshold
+        )
+    }
+}
+
+/// Two-pass loudness normalization settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoudnessNormalization {
+    /// Target integrated loudness in LUFS
+    pub target_integrated: f64,
+    /// True peak ceiling in dBTP
+    pub target_true_peak: f64,
+    /// Loudness range let through by dynamic mode, in LU
+    pub target_range: f64,
+    /// Follow short-term loudness instead of applying one linear gain
+    pub dynamic: bool,
+    /// First-pass measurement; measured during transcoding when absent
+    pub measured: Option<LoudnessStats>,
+}
+
+impl Default for LoudnessNormalization {
+    fn default() -> Self {
+        Self {
+            target_integrated: -16.0,
+            target_true_peak: -1.0,
+            target_range: 11.0,
+            dynamic: false,
+            measured: None,
+        }
+    }
+}
+
+/// A stream found in the input by `probe`
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamInfo {
+    /// Index of the stream in the input container
+    pub index: i32,
+    /// "video", "audio", "subtitle", "data", "attachment" or "cover" (embedded cover art)
+    pub kind: String,
+    pub codec: String,
+    pub width: i32,
+    pub height: i32,
+    pub fps: f64,
+    pub pixel_format: String,
+    pub sample_rate: i32,
+    pub channels: i32,
+    pub channel_layout: String,
+    /// For subtitles: text-based (convertible) rather than bitmap
+    pub text_based: bool,
+    /// Whether the input flags this stream as the default of its type
+    pub default: bool,
+    pub language: String,
+    pub title: String,
+    /// All tags of the stream, in input order
+    pub metadata: Vec<(String, String)>,
+}
+
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} ({})", self.index, self.kind, self.codec)?;
+        
+        match self.kind.as_str() {
+            "video" => write!(f, " {}x{} {:.2} fps", self.width, self.height, self.fps)?,
+            "audio" => write!(f, " {} Hz {}", self.sample_rate, self.channel_layout)?,
+            _ => {}
+        }
+        
+        if !self.language.is_empty() {
+            write!(f, " [{}]", self.language)?;
+        }
+        
+        if !self.title.is_empty() {
+            write!(f, " \"{}\"", self.title)?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Container and stream information of an input file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeInfo {
+    pub format_name: String,
+    /// Duration in seconds, 0 when unknown
+    pub duration: f64,
+    /// Container tags, in input order
+    pub metadata: Vec<(String, String)>,
+    pub chapters: Vec<Chapter>,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// A chapter, with times in seconds from the start of the input
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chapter {
+    pub start: f64,
+    /// End time, or 0 to run until the next chapter (or the end of the input)
+    pub end: f64,
+    pub title: String,
+}
+
+/// What happens to the input's metadata in the output
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MetadataMode {
+    /// Copy the container tags
+    #[default]
+    Keep,
+    /// Copy the container tags except location (GPS) tags
+    StripLocation,
+    /// Drop all container and stream tags, including the encoder tag
+    Strip,
+}
+
+/// What happens to the cover art of the output
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum CoverArt {
+    /// Copy the input's cover art, if the output format can hold it
+    #[default]
+    Keep,
+    /// Leave the cover art out
+    Remove,
+    /// Use this JPEG or PNG image instead of the input's cover art
+    Replace(Vec<u8>),
+}
+
+/// Settings for animated output (GIF, WebP and APNG), which is encoded from a range of the video
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationSettings {
+    pub fps: u32,
+    /// Width in pixels, or 0 to keep the video width; the height follows the aspect ratio
+    pub width: u32,
+    /// Times to repeat after the first play: 0 loops forever, -1 plays once
+    pub loop_count: i32,
+    /// Start of the range in seconds
+    pub start: f64,
+    /// Length of the range in seconds, or 0 to run to the end
+    pub duration: f64,
+    /// GIF palette size, 2 to 256
+    pub max_colors: u32,
+    /// GIF dithering: "none", "bayer" or "floyd_steinberg"
+    pub dither: String,
+    /// WebP quality, 0 to 100
+    pub quality: u32,
+    /// Lossless WebP; APNG is always lossless
+    pub lossless: bool,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            width: 480,
+            loop_count: 0,
+            start: 0.0,
+            duration: 0.0,
+            max_colors: 256,
+            dither: "floyd_steinberg".to_string(),
+            quality: 75,
+            lossless: false,
+        }
+    }
+}
+
+/// Segmenting of an HLS package
+#[derive(Clone, Debug, PartialEq)]
+pub struct HlsSettings {
+    /// Target segment length in seconds; segments start on keyframes, so they run a little longer
+    pub segment_duration: f64,
+    /// "mpegts" or "fmp4"
+    pub segment_type: String,
+    /// Give the audio its own playlist instead of muxing it into the video segments
+    pub separate_audio: bool,
+}
+
+impl Default for HlsSettings {
+    fn default() -> Self {
+        Self {
+            segment_duration: 6.0,
+            segment_type: "mpegts".to_string(),
+            separate_audio: false,
+        }
+    }
+}
+
+/// Segmenting of a DASH package
+#[derive(Clone, Debug, PartialEq)]
+pub struct DashSettings {
+    /// Target segment length in seconds; segments start on keyframes, so they run a little longer
+    pub segment_duration: f64,
+}
+
+impl Default for DashSettings {
+    fn default() -> Self {
+        Self { segment_duration: 4.0 }
+    }
+}
+
+/// Layout of MP4, M4A and MOV output
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mp4Settings {
+    /// Move the index to the front once the file is written, so playback can start
+    /// before it is fully downloaded
+    pub faststart: bool,
+    /// Write fragments, one per keyframe, after an empty index
+    pub fragmented: bool,
+    /// Address fragment data from the start of each fragment, as MSE players expect
+    pub default_base_moof: bool,
+}
+
+impl AnimationSettings {
+    /// Rough output size in bytes in `format` for a video stream and input duration
+    pub fn estimated_size(&self, format: &str, video: &StreamInfo, input_duration: f64) -> u64 {
+        let width = if self.width > 0 { self.width as f64 } else { video.width as f64 };
+        let height = if video.width > 0 { width * video.height as f64 / video.width as f64 } else { 0.0 };
+        
+        let mut length = (input_duration - self.start).max(0.0);
+        if self.duration > 0.0 {
+            length = length.min(self.duration);
+        }
+        
+        let bits_per_pixel = match format {
+            "webp" if self.lossless => 4.0,
+            "webp" => 0.2 + self.quality.min(100) as f64 / 100.0 * 1.3,
+            "apng" => 10.0,
+            _ => {
+                // LZW packs dithered pixels worse than flat areas
+                let compression = match self.dither.as_str() {
+                    "floyd_steinberg" => 0.6,
+                    "bayer" => 0.45,
+                    _ => 0.3,
+                };
+                (self.max_colors.clamp(2, 256) as f64).log2() * compression
+            }
+        };
+        
+        (width * height * length * self.fps as f64 * bits_per_pixel / 8.0) as u64
+    }
+}
+
+/// Whether a tag can give away where a recording was made
+pub fn is_location_tag(key: &str) -> bool {
+    let key = key.to_lowercase();
+    key.contains("location") || key.contains("gps")
+}
+
+/// Which input stream to keep, and how
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackSelection {
+    /// Index of the stream in the input container
+    pub index: i32,
+    /// Pass the stream through without re-encoding
+    pub copy: bool,
+    /// Mark the output stream as the default of its type
+    pub default: bool,
+}
+
+/// A subtitle file (SRT, WebVTT, ASS) to add as a new track
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalSubtitle {
+    pub data: Vec<u8>,
+    /// ISO 639-2 language code, empty to leave unset
+    pub language: String,
+    pub title: String,
+    pub default: bool,
+}
+
+/// Where burned-in subtitles come from
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubtitleSource {
+    /// A text subtitle stream of the input, by index
+    Track(i32),
+    /// An uploaded SRT/ASS/WebVTT file
+    File(Vec<u8>),
+}
+
+/// Subtitles rendered onto the video frames (needs the burn-subtitles build feature)
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurnSubtitles {
+    pub source: SubtitleSource,
+    /// Font size in output pixels, or the subtitle's own style
+    pub font_size: Option<f64>,
+    /// Outline width in output pixels, or the subtitle's own style
+    pub outline: Option<f64>,
+    /// Distance of the bottom line from the bottom edge, in percent of the height
+    pub position: Option<f64>,
+}
+
+/// Text stamped onto the video frames (needs the drawtext build feature)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextOverlay {
+    /// Text with optional {filename}, {pts}, {frame} and {timecode} placeholders
+    pub text: String,
+    /// Value of {filename}
+    pub file_name: String,
+    /// "top-left", "top", "top-right", "center", "bottom-left", "bottom" or "bottom-right"
+    pub position: String,
+    /// Font size in output pixels, or a twentieth of the height
+    pub font_size: Option<u32>,
+    /// Text color as "#rrggbb" or "#rrggbbaa"
+    pub color: String,
+    /// Background box color as "#rrggbb" or "#rrggbbaa", if any
+    pub background: Option<String>,
+}
+
+/// Settings for a single transcode job
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscodeOptions {
+    pub output_format: String,
+    pub video_codec: String,
+    pub audio_codec: String,
+    pub video_bitrate: i32,
+    pub audio_bitrate: i32,
+    pub resolution: String,
+    /// Encoder pixel format, or "auto" for the container default
+    pub pixel_format: String,
+    /// Output sample rate in Hz, or 0 to keep the source rate
+    pub sample_rate: i32,
+    /// Output channel layout ("mono", "stereo", "5.1"), or "same" to keep the source layout
+    pub channel_layout: String,
+    /// Loudness normalization, if enabled
+    pub loudness: Option<LoudnessNormalization>,
+    /// Audio gain in dB
+    pub volume_db: f64,
+    /// Audio fade-in length in seconds
+    pub fade_in: f64,
+    /// Audio fade-out length in seconds, ending with the output
+    pub fade_out: f64,
+    /// Streams to keep, in output order; empty keeps the first video and audio stream
+    pub tracks: Vec<TrackSelection>,
+    /// Leave video out of the output (implied for audio-only formats)
+    pub drop_video: bool,
+    /// Leave audio out of the output
+    pub drop_audio: bool,
+    /// Subtitle encoder for converted tracks, or "auto" for the container's format
+    pub subtitle_codec: String,
+    /// Subtitle files to add as tracks after the input's own
+    pub external_subtitles: Vec<ExternalSubtitle>,
+    /// Subtitles to render onto the video
+    pub burn_subtitles: Option<BurnSubtitles>,
+    /// Text or timecode to draw onto the video
+    pub text_overlay: Option<TextOverlay>,
+    /// Handling of the input's metadata
+    pub metadata_mode: MetadataMode,
+    /// Container tags to set after `metadata_mode` is applied; an empty value removes
+    /// the tag, "language" applies to the video and audio streams
+    pub metadata: Vec<(String, String)>,
+    /// Chapters replacing the input's; `None` keeps the input's, an empty list removes them
+    pub chapters: Option<Vec<Chapter>>,
+    /// Cover art of the output (MP3, M4A, FLAC, MP4, MOV and Matroska only)
+    pub cover_art: CoverArt,
+    /// Frame rate, range and encoding of GIF, WebP and APNG output; other formats ignore it
+    pub animation: AnimationSettings,
+    /// Segmenting of HLS output; other formats ignore it
+    pub hls: HlsSettings,
+    /// Segmenting of DASH output; other formats ignore it
+    pub dash: DashSettings,
+    /// Layout of MP4-family output; other formats ignore it
+    pub mp4: Mp4Settings,
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        Self {
+            output_format: "mp4".to_string(),
+            video_codec: "h264".to_string(),
+            audio_codec: "aac".to_string(),
+            video_bitrate: 1000,
+            audio_bitrate: 128,
+            resolution: "same".to_string(),
+            pixel_format: "auto".to_string(),
+            sample_rate: 0,
+            channel_layout: "same".to_string(),
+            loudness: None,
+            volume_db: 0.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            tracks: Vec::new(),
+            drop_video: false,
+            drop_audio: false,
+            subtitle_codec: "auto".to_string(),
+            external_subtitles: Vec::new(),
+            burn_subtitles: None,
+            text_overlay: None,
+            metadata_mode: MetadataMode::Keep,
+            metadata: Vec::new(),
+            chapters: None,
+            cover_art: CoverArt::Keep,
+            animation: AnimationSettings::default(),
+            hls: HlsSettings::default(),
+            dash: DashSettings::default(),
+            mp4: Mp4Settings::default(),
+        }
+    }
+}
+
+impl TranscodeOptions {
+    /// Whether the output will have no video, explicitly or because of the format
+    pub fn drops_video(&self) -> bool {
+        self.drop_video || is_audio_only_format(&self.output_format)
+    }
+    
+    /// The chapter list, if one is handed to the wrapper as an extra input
+    fn chapter_list(&self) -> Option<&Vec<Chapter>> {
+        self.chapters.as_ref().filter(|chapters| !chapters.is_empty())
+    }
+    
+    /// Files handed to the wrapper as extra inputs, in input number order
+    fn extra_inputs(&self) -> Vec<Vec<u8>> {
+        let mut inputs: Vec<Vec<u8>> = self.external_subtitles.iter().map(|subtitle| subtitle.data.clone()).collect();
+        
+        if let Some(BurnSubtitles { source: SubtitleSource::File(data), .. }) = &self.burn_subtitles {
+            inputs.push(data.clone());
+        }
+        
+        if let Some(chapters) = self.chapter_list() {
+            inputs.push(chapters::to_ffmetadata(chapters, 0.0).into_bytes());
+        }
+        
+        if let CoverArt::Replace(image) = &self.cover_art {
+            inputs.push(image.clone());
+        }
+        
+        inputs
+    }
+    
+    /// Encode the extended options as the "key=value:key=value" string the wrapper parses
+    fn to_option_string(&self) -> String {
+        let mut options = Vec::new();
+        
+        if self.drops_video() {
+            options.push("drop_video=1".to_string());
+        }
+        
+        if self.drop_audio {
+            options.push("drop_audio=1".to_string());
+        }
+        
+        if self.pixel_format != "auto" {
+            options.push(format!("pix_fmt={}", self.pixel_format));
+        }
+        
+        if self.sample_rate > 0 {
+            options.push(format!("sample_rate={}", self.sample_rate));
+        }
+        
+        if self.channel_layout != "same" {
+            options.push(format!("channel_layout={}", self.channel_layout));
+        }
+        
+        if self.volume_db != 0.0 {
+            options.push(format!("volume={}", self.volume_db));
+        }
+        
+        if self.fade_in > 0.0 {
+            options.push(format!("fade_in={}", self.fade_in));
+        }
+        
+        if self.fade_out > 0.0 {
+            options.push(format!("fade_out={}", self.fade_out));
+        }
+        
+        if !self.tracks.is_empty() {
+            let list = |tracks: Vec<&TrackSelection>| {
+                tracks.iter().map(|track| track.index.to_string()).collect::<Vec<_>>().join(",")
+            };
+            
+            options.push(format!("tracks={}", list(self.tracks.iter().collect())));
+            options.push(format!("copy={}", list(self.tracks.iter().filter(|track| track.copy).collect())));
+            options.push(format!("default={}", list(self.tracks.iter().filter(|track| track.default).collect())));
+        }
+        
+        if self.subtitle_codec != "auto" {
+            options.push(format!("subtitle_codec={}", self.subtitle_codec));
+        }
+        
+        // External subtitles are registered as extra inputs 1..n, in order
+        if !self.external_subtitles.is_empty() {
+            let inputs: Vec<String> = (1..=self.external_subtitles.len()).map(|input| input.to_string()).collect();
+            options.push(format!("subtitle_inputs={}", inputs.join(",")));
+            
+            for (input, subtitle) in (1..).zip(&self.external_subtitles) {
+                if !subtitle.language.is_empty() {
+                    options.push(format!("subtitle_language_{}={}", input, escape_option_value(&subtitle.language)));
+                }
+                if !subtitle.title.is_empty() {
+                    options.push(format!("subtitle_title_{}={}", input, escape_option_value(&subtitle.title)));
+                }
+                if subtitle.default {
+                    options.push(format!("subtitle_default_{}=1", input));
+                }
+            }
+        }
+        
+        if let Some(burn) = &self.burn_subtitles {
+            match &burn.source {
+                SubtitleSource::Track(index) => options.push(format!("burn_subtitles={}", index)),
+                // Registered right after the external subtitle files
+                SubtitleSource::File(_) => options.push(format!("burn_subtitles_input={}", self.external_subtitles.len() + 1)),
+            }
+            
+            if let Some(font_size) = burn.font_size {
+                options.push(format!("burn_font_size={}", font_size));
+            }
+            if let Some(outline) = burn.outline {
+                options.push(format!("burn_outline={}", outline));
+            }
+            if let Some(position) = burn.position {
+                options.push(format!("burn_position={}", position));
+            }
+        }
+        
+        if let Some(overlay) = &self.text_overlay {
+            options.push(format!("drawtext={}", escape_option_value(&overlay.text)));
+            options.push(format!("drawtext_filename={}", escape_option_value(&overlay.file_name)));
+            options.push(format!("drawtext_position={}", overlay.position));
+            options.push(format!("drawtext_color={}", overlay.color));
+            
+            if let Some(font_size) = overlay.font_size {
+                options.push(format!("drawtext_size={}", font_size));
+            }
+            if let Some(background) = &overlay.background {
+                options.push(format!("drawtext_box={}", background));
+            }
+        }
+        
+        // Extra inputs are numbered from 1 in the order extra_inputs() lists them
+        let burn_file_inputs = matches!(&self.burn_subtitles, Some(BurnSubtitles { source: SubtitleSource::File(_), .. })) as usize;
+        let chapters_input = self.external_subtitles.len() + burn_file_inputs + 1;
+        
+        match &self.chapters {
+            None => {},
+            Some(chapters) if chapters.is_empty() => options.push("chapters=none".to_string()),
+            Some(_) => options.push(format!("chapters_input={}", chapters_input)),
+        }
+        
+        match &self.cover_art {
+            CoverArt::Keep => {},
+            CoverArt::Remove => options.push("cover=none".to_string()),
+            // The cover image comes after the chapter list, if there is one
+            CoverArt::Replace(_) => options.push(format!("cover_input={}", chapters_input + self.chapter_list().is_some() as usize)),
+        }
+        
+        match self.metadata_mode {
+            MetadataMode::Keep => {},
+            MetadataMode::StripLocation => options.push("metadata=strip_location".to_string()),
+            MetadataMode::Strip => options.push("metadata=strip".to_string()),
+        }
+        
+        for (key, value) in &self.metadata {
+            options.push(format!("meta_{}={}", escape_option_value(key), escape_option_value(value)));
+        }
+        
+        if is_animation_format(&self.output_format) {
+            options.push(format!("anim_fps={}", self.animation.fps));
+            options.push(format!("anim_width={}", self.animation.width));
+            options.push(format!("anim_loop={}", self.animation.loop_count));
+            options.push(format!("anim_start={}", self.animation.start));
+            options.push(format!("anim_duration={}", self.animation.duration));
+            options.push(format!("gif_colors={}", self.animation.max_colors));
+            options.push(format!("gif_dither={}", self.animation.dither));
+            options.push(format!("anim_quality={}", self.animation.quality));
+            options.push(format!("anim_lossless={}", self.animation.lossless as i32));
+        }
+        
+        if self.output_format == "hls" {
+            options.push(format!("hls_time={}", self.hls.segment_duration));
+            options.push(format!("hls_segment_type={}", self.hls.segment_type));
+            if self.hls.separate_audio {
+                options.push("hls_separate_audio=1".to_string());
+            }
+        }
+        
+        if self.output_format == "dash" {
+            options.push(format!("dash_seg_duration={}", self.dash.segment_duration));
+        }
+        
+        if is_mp4_format(&self.output_format) {
+            if self.mp4.faststart {
+                options.push("mp4_faststart=1".to_string());
+            }
+            if self.mp4.fragmented {
+                options.push("mp4_fragmented=1".to_string());
+            }
+            if self.mp4.default_base_moof {
+                options.push("mp4_default_base_moof=1".to_string());
+            }
+        }
+        
+        if let Some(loudness) = &self.loudness {
+            options.push(format!("loudnorm={}", if loudness.dynamic { "dynamic" } else { "linear" }));
+            options.push(format!("loudnorm_i={}", loudness.target_integrated));
+            options.push(format!("loudnorm_tp={}", loudness.target_true_peak));
+            options.push(format!("loudnorm_lra={}", loudness.target_range));
+            
+            if let Some(measured) = &loudness.measured {
+                options.push(format!("measured_i={}", measured.integrated));
+                options.push(format!("measured_tp={}", measured.true_peak));
+            }
+        }
+        
+        options.join(":")
+    }
+}
+
+/// Escape a free-text option value so it survives the "key=value:key=value" parsing
+fn escape_option_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ':' | '=' | '\\' | '\'') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 /// Transcode a media file
 pub async fn transcode(
     input_data: &[u8],
-    output_format: &str,
-    video_codec: &str,
-    audio_codec: &str,
-    video_bitrate: i32,
-    audio_bitrate: i32,
-    resolution: &str,
+    options: &TranscodeOptions,
 ) -> Result<Vec<u8>, TranscodeError> {
+    if options.drops_video() && options.drop_audio {
+        return Err(TranscodeError("Cannot drop both video and audio".to_string()));
+    }
+    
+    // Dropped streams have no encoder
+    let video_codec = if options.drops_video() { "" } else { options.video_codec.as_str() };
+    let audio_codec = if options.drop_audio { "" } else { options.audio_codec.as_str() };
+    
+    let module = FFmpeg();
+    add_extra_inputs(&module, options)?;
+    
     // Create a promise for the transcoding operation
-    let promise = FFmpeg().transcode_async(
+    let promise = module.transcode_async(
         input_data,
-        output_format,
+        &options.output_format,
         video_codec,
         audio_codec,
-        video_bitrate,
-        audio_bitrate,
-        resolution
+        options.video_bitrate,
+        options.audio_bitrate,
+        &options.resolution,
+        &options.to_option_string()
     );
     
     // Convert the promise to a Rust future
-    let result = wasm_bindgen_futures::JsFuture::from(promise).await?;
+    let result = wasm_bindgen_futures::JsFuture::from(promise).await;
+    module.clear_inputs();
+    let result = result?;
     
     // Convert the JavaScript Uint8Array to a Rust Vec<u8>
     let uint8array = js_sys::Uint8Array::new(&result);
@@ -114,6 +841,622 @@ pub async fn transcode(
     Ok(output_data)
 }
 
+/// Hand the subtitle files, chapter list and cover image over as extra inputs,
+/// replacing those of an earlier job
+fn add_extra_inputs(module: &FFmpegModule, options: &TranscodeOptions) -> Result<(), TranscodeError> {
+    module.clear_inputs();
+    for data in options.extra_inputs() {
+        if module.add_input(&data) < 0 {
+            module.clear_inputs();
+            return Err(TranscodeError("Not enough memory for the extra input files".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Encode the input as an HLS package ready to upload: "master.m3u8", the media
+/// playlists and their segments in a ZIP archive. Every setting of `options` applies
+/// as in `transcode`, with its `hls` segmenting; the output format is ignored.
+/// Returns the archive and the number of files in it.
+pub async fn hls_package(input_data: &[u8], options: &TranscodeOptions) -> Result<(Vec<u8>, usize), TranscodeError> {
+    package(input_data, options, "hls").await
+}
+
+/// Encode the input as a DASH package ready to upload: "manifest.mpd" with separate
+/// video and audio adaptation sets, and the fragmented MP4 initialization and media
+/// segments, in a ZIP archive. Like `hls_package`, with the `dash` segmenting.
+pub async fn dash_package(input_data: &[u8], options: &TranscodeOptions) -> Result<(Vec<u8>, usize), TranscodeError> {
+    package(input_data, options, "dash").await
+}
+
+/// Transcode into a muxer that writes a set of files, and zip them
+async fn package(input_data: &[u8], options: &TranscodeOptions, format: &str) -> Result<(Vec<u8>, usize), TranscodeError> {
+    let options = TranscodeOptions {
+        output_format: format.to_string(),
+        ..options.clone()
+    };
+    // Files from a failed run are taken too, to free them
+    let result = transcode(input_data, &options).await;
+    let files = FFmpeg().take_output_files();
+    result?;
+    
+    let mut archive = ZipWriter::new();
+    for file in files.iter() {
+        let file = js_sys::Array::from(&file);
+        let name = file.get(0).as_string().unwrap_or_default();
+        archive.add_file(&name, &js_sys::Uint8Array::new(&file.get(1)).to_vec());
+    }
+    
+    if files.length() == 0 {
+        return Err(TranscodeError("The package has no files".to_string()));
+    }
+    Ok((archive.finish(), files.length() as usize))
+}
+
+/// Convert a standalone subtitle file to another subtitle format ("srt", "webvtt", "ass")
+pub async fn convert_subtitles(input_data: &[u8], format: &str) -> Result<Vec<u8>, TranscodeError> {
+    if !get_subtitle_formats().iter().any(|f| f == format) {
+        return Err(TranscodeError(format!("Unsupported subtitle format '{}'", format)));
+    }
+    
+    let options = TranscodeOptions {
+        output_format: format.to_string(),
+        ..Default::default()
+    };
+    
+    transcode(input_data, &options).await
+}
+
+/// Get the embedded cover art of the input, as the JPEG or PNG image it was stored as
+pub fn extract_cover(input_data: &[u8]) -> Result<Vec<u8>, TranscodeError> {
+    let image = FFmpeg().extract_cover_js(input_data)?;
+    Ok(image.to_vec())
+}
+
+/// Decode the frame nearest to `timestamp` (seconds from the start) and encode it as
+/// a "png", "jpeg" or "webp" image. `size` scales it to width x height; a 0 in
+/// either follows the display aspect ratio.
+pub fn extract_frame(input_data: &[u8], timestamp: f64, format: &str, size: Option<(u32, u32)>) -> Result<Vec<u8>, TranscodeError> {
+    if !get_image_formats().iter().any(|f| f == format) {
+        return Err(TranscodeError(format!("Unsupported image format '{}'", format)));
+    }
+    
+    let (width, height) = size.unwrap_or((0, 0));
+    let image = FFmpeg().extract_frame_js(input_data, timestamp, format, width, height)?;
+    Ok(image.to_vec())
+}
+
+/// Extract `count` evenly spaced frames, each from the middle of its share of the
+/// input, as (timestamp, image) pairs
+pub fn extract_frames(input_data: &[u8], count: u32, format: &str, size: Option<(u32, u32)>) -> Result<Vec<(f64, Vec<u8>)>, TranscodeError> {
+    let duration = probe(input_data)?.duration;
+    if duration <= 0.0 {
+        return Err(TranscodeError("The input duration is unknown".to_string()));
+    }
+    
+    (0..count)
+        .map(|i| {
+            let timestamp = duration * (i as f64 + 0.5) / count as f64;
+            extract_frame(input_data, timestamp, format, size).map(|image| (timestamp, image))
+        })
+        .collect()
+}
+
+/// Layout of a contact sheet: a grid of evenly spaced frames in one image
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContactSheet {
+    pub columns: u32,
+    pub rows: u32,
+    /// Width of each tile in pixels; the height follows the aspect ratio
+    pub tile_width: u32,
+    /// Stamp each tile with its time (needs the drawtext build feature)
+    pub timestamps: bool,
+    /// Put the file name, duration, resolution and codecs above the grid (needs the drawtext build feature)
+    pub header: bool,
+    /// "png" or "jpeg"
+    pub format: String,
+}
+
+impl Default for ContactSheet {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            rows: 4,
+            tile_width: 320,
+            timestamps: false,
+            header: false,
+            format: "png".to_string(),
+        }
+    }
+}
+
+/// Header lines of a contact sheet: the file name, then duration, resolution and codecs
+fn contact_sheet_header(file_name: &str, info: &ProbeInfo) -> String {
+    let mut details = vec![format!("Duration: {}", chapters::format_timestamp(info.duration.round()))];
+    
+    if let Some(video) = info.streams.iter().find(|stream| stream.kind == "video") {
+        details.push(format!("Resolution: {}x{}", video.width, video.height));
+        details.push(format!("Video: {}", video.codec));
+    }
+    if let Some(audio) = info.streams.iter().find(|stream| stream.kind == "audio") {
+        details.push(format!("Audio: {}", audio.codec));
+    }
+    
+    format!("{}\n{}", file_name, details.join("   "))
+}
+
+/// Render a contact sheet of the input; `file_name` is shown in the header
+pub fn contact_sheet(input_data: &[u8], file_name: &str, sheet: &ContactSheet) -> Result<Vec<u8>, TranscodeError> {
+    if sheet.format != "png" && sheet.format != "jpeg" {
+        return Err(TranscodeError(format!("Unsupported contact sheet format '{}'", sheet.format)));
+    }
+    
+    let mut options = vec![
+        format!("columns={}", sheet.columns),
+        format!("rows={}", sheet.rows),
+        format!("tile_width={}", sheet.tile_width),
+    ];
+    
+    if sheet.timestamps {
+        options.push("labels=1".to_string());
+    }
+    
+    if sheet.header {
+        let info = probe(input_data)?;
+        options.push(format!("header={}", escape_option_value(&contact_sheet_header(file_name, &info))));
+    }
+    
+    let image = FFmpeg().contact_sheet_js(input_data, &sheet.format, &options.join(":"))?;
+    Ok(image.to_vec())
+}
+
+/// Which frames of the video go into an image sequence, and how
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageSequence {
+    /// Keep every Nth frame; 1 keeps them all
+    pub every: u32,
+    /// Start of the range in seconds
+    pub start: f64,
+    /// Length of the range in seconds, or 0 to run to the end
+    pub duration: f64,
+    /// Image width in pixels, or 0 to keep the display size
+    pub width: u32,
+    /// "png" or "jpeg"
+    pub format: String,
+}
+
+impl Default for ImageSequence {
+    fn default() -> Self {
+        Self {
+            every: 1,
+            start: 0.0,
+            duration: 0.0,
+            width: 0,
+            format: "png".to_string(),
+        }
+    }
+}
+
+/// Export frames of the input as numbered images ("<base_name>_000001.png", ...) in a
+/// ZIP archive; returns the archive and the number of images. Each image goes into the
+/// archive as soon as it is encoded.
+pub fn image_sequence(input_data: &[u8], base_name: &str, sequence: &ImageSequence) -> Result<(Vec<u8>, usize), TranscodeError> {
+    if sequence.format != "png" && sequence.format != "jpeg" {
+        return Err(TranscodeError(format!("Unsupported image sequence format '{}'", sequence.format)));
+    }
+    
+    let options = [
+        format!("every={}", sequence.every.max(1)),
+        format!("start={}", sequence.start),
+        format!("duration={}", sequence.duration),
+        format!("width={}", sequence.width),
+    ];
+    
+    let module = FFmpeg();
+    let handle = module.image_sequence_open_js(input_data, &options.join(":"));
+    let extension = get_file_extension(&sequence.format);
+    let mut archive = ZipWriter::new();
+    let mut count = 0;
+    
+    let result = loop {
+        match module.image_sequence_next_js(handle, &sequence.format) {
+            Ok(image) if image.length() == 0 => break Ok(()),
+            Ok(image) => {
+                count += 1;
+                archive.add_file(&format!("{}_{:06}.{}", base_name, count, extension), &image.to_vec());
+            },
+            Err(e) => break Err(TranscodeError::from(e)),
+        }
+    };
+    
+    if handle != 0 {
+        module.image_sequence_close_js(handle);
+    }
+    result?;
+    
+    if count == 0 {
+        return Err(TranscodeError("No frames in the selected range".to_string()));
+    }
+    Ok((archive.finish(), count))
+}
+
+/// Where an input is cut into parts
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitSettings {
+    /// "duration", "size" or "chapters"
+    pub mode: String,
+    /// Length of each part in seconds
+    pub duration: f64,
+    /// Largest size of a part in megabytes
+    pub size: f64,
+}
+
+impl Default for SplitSettings {
+    fn default() -> Self {
+        Self {
+            mode: "duration".to_string(),
+            duration: 600.0,
+            size: 100.0,
+        }
+    }
+}
+
+/// Cut the input into parts in `format` without re-encoding, numbered
+/// "<base_name>_001.<ext>", ... in a ZIP archive; returns the archive and the number
+/// of parts. Cuts are moved forward to the next keyframe, so parts run a little
+/// longer than asked, and a part never holds less than one group of pictures.
+pub fn split(input_data: &[u8], base_name: &str, format: &str, settings: &SplitSettings) -> Result<(Vec<u8>, usize), TranscodeError> {
+    let options = [
+        format!("split_mode={}", settings.mode),
+        format!("split_duration={}", settings.duration),
+        format!("split_size={}", (settings.size * 1024.0 * 1024.0) as u64),
+    ];
+    
+    let module = FFmpeg();
+    let handle = module.split_open_js(input_data, format, &options.join(":"));
+    let extension = get_file_extension(format);
+    let mut archive = ZipWriter::new();
+    let mut count = 0;
+    
+    let result = loop {
+        match module.split_next_js(handle) {
+            Ok(part) if part.length() == 0 => break Ok(()),
+            Ok(part) => {
+                count += 1;
+                archive.add_file(&format!("{}_{:03}.{}", base_name, count, extension), &part.to_vec());
+            },
+            Err(e) => break Err(TranscodeError::from(e)),
+        }
+    };
+    
+    if handle != 0 {
+        module.split_close_js(handle);
+    }
+    result?;
+    
+    if count == 0 {
+        return Err(TranscodeError("The input has no packets to split".to_string()));
+    }
+    Ok((archive.finish(), count))
+}
+
+/// Whether a file name looks like a still image a video can be made from
+pub fn is_image_file(name: &str) -> bool {
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+    matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "webp" | "bmp" | "tif" | "tiff")
+}
+
+/// Compare file names so runs of digits order by value: "img2.png" before "img10.png"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                let mut y_digits = String::new();
+                while let Some(c) = a.next_if(char::is_ascii_digit) {
+                    x_digits.push(c);
+                }
+                while let Some(c) = b.next_if(char::is_ascii_digit) {
+                    y_digits.push(c);
+                }
+                
+                // Leading zeros don't count, then the longer number is bigger
+                let x_value = x_digits.trim_start_matches('0');
+                let y_value = y_digits.trim_start_matches('0');
+                let order = x_value.len().cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let order = x.to_lowercase().cmp(y.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+/// How a set of images becomes a video
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageVideo {
+    /// Images shown per second
+    pub fps: f64,
+}
+
+impl Default for ImageVideo {
+    fn default() -> Self {
+        Self { fps: 25.0 }
+    }
+}
+
+/// Encode images, in the order given, as the frames of a video. The output format,
+/// codecs, bitrates and resolution come from `options`; a resolution of "same" takes
+/// the size of the first image, and images of another shape are letterboxed. The
+/// first audio stream of `audio`, if given, becomes the soundtrack and is cut at the
+/// end of the video.
+pub async fn images_to_video(
+    images: &[Vec<u8>],
+    audio: Option<&[u8]>,
+    options: &TranscodeOptions,
+    video: &ImageVideo,
+) -> Result<Vec<u8>, TranscodeError> {
+    if images.is_empty() {
+        return Err(TranscodeError("No images selected".to_string()));
+    }
+    
+    let inputs: Vec<&[u8]> = images.iter().map(Vec::as_slice).chain(audio).collect();
+    let mut job_options = vec![
+        format!("images={}", images.len()),
+        format!("image_fps={}", video.fps),
+    ];
+    if audio.is_some() {
+        job_options.push(format!("audio_input={}", images.len() + 1));
+    }
+    
+    let audio_codec = if audio.is_some() { options.audio_codec.as_str() } else { "" };
+    transcode_inputs(&inputs, options, &options.video_codec, audio_codec, job_options).await
+}
+
+/// Settings for joining clips
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcatSettings {
+    /// Re-encode even when the clips could be joined by stream copy
+    pub reencode: bool,
+    /// Picture transition between clips: "none", "fade" (through black), "dissolve" or "wipe"
+    pub transition: String,
+    /// Length of the picture transition in seconds
+    pub transition_duration: f64,
+    /// Length of the audio crossfade in seconds, or 0 to cut
+    pub audio_crossfade: f64,
+}
+
+impl Default for ConcatSettings {
+    fn default() -> Self {
+        Self {
+            reencode: false,
+            transition: "none".to_string(),
+            transition_duration: 1.0,
+            audio_crossfade: 0.0,
+        }
+    }
+}
+
+impl ConcatSettings {
+    /// Whether neighbouring clips overlap, which always means re-encoding
+    pub fn overlaps(&self) -> bool {
+        (self.transition != "none" && self.transition_duration > 0.0) || self.audio_crossfade > 0.0
+    }
+}
+
+/// Join clips end to end, in the order given. When every clip has the same streams
+/// coded the same way (and the resolution is "same"), their packets are copied;
+/// otherwise they are decoded and re-encoded with the codecs in `options`, at the
+/// picture size and frame rate of the first clip with video and the sample rate and
+/// layout of the first with audio unless `options` sets them. Transitions overlap
+/// neighbouring clips by the longer of the transition and the crossfade, at most
+/// half of either clip, with both centred in the overlap.
+pub async fn concat(clips: &[Vec<u8>], options: &TranscodeOptions, settings: &ConcatSettings) -> Result<Vec<u8>, TranscodeError> {
+    if clips.len() < 2 {
+        return Err(TranscodeError("Select at least two clips to join".to_string()));
+    }
+    if options.drops_video() && options.drop_audio {
+        return Err(TranscodeError("Cannot drop both video and audio".to_string()));
+    }
+    
+    let inputs: Vec<&[u8]> = clips.iter().map(Vec::as_slice).collect();
+    let mut job_options = vec![format!("concat={}", clips.len())];
+    if settings.reencode {
+        job_options.push("concat_reencode=1".to_string());
+    }
+    if settings.transition != "none" && settings.transition_duration > 0.0 {
+        job_options.push(format!("transition={}", settings.transition));
+        job_options.push(format!("transition_duration={}", settings.transition_duration));
+    }
+    if settings.audio_crossfade > 0.0 {
+        job_options.push(format!("audio_crossfade={}", settings.audio_crossfade));
+    }
+    
+    let video_codec = if options.drops_video() { "" } else { options.video_codec.as_str() };
+    let audio_codec = if options.drop_audio { "" } else { options.audio_codec.as_str() };
+    transcode_inputs(&inputs, options, video_codec, audio_codec, job_options).await
+}
+
+/// Run a job whose media comes only from registered extra inputs; `job_options`
+/// go in front of the options from `options`
+async fn transcode_inputs(
+    inputs: &[&[u8]],
+    options: &TranscodeOptions,
+    video_codec: &str,
+    audio_codec: &str,
+    mut job_options: Vec<String>,
+) -> Result<Vec<u8>, TranscodeError> {
+    let module = FFmpeg();
+    module.clear_inputs();
+    for data in inputs {
+        if module.add_input(data) < 0 {
+            module.clear_inputs();
+            return Err(TranscodeError("Not enough memory for the input files".to_string()));
+        }
+    }
+    
+    let extra = options.to_option_string();
+    if !extra.is_empty() {
+        job_options.push(extra);
+    }
+    
+    let promise = module.transcode_async(
+        &[],
+        &options.output_format,
+        video_codec,
+        audio_codec,
+        options.video_bitrate,
+        options.audio_bitrate,
+        &options.resolution,
+        &job_options.join(":")
+    );
+    
+    let result = wasm_bindgen_futures::JsFuture::from(promise).await;
+    module.clear_inputs();
+    let result = result?;
+    
+    Ok(js_sys::Uint8Array::new(&result).to_vec())
+}
+
+/// One output of a rendition ladder
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rendition {
+    /// Frame height in pixels; the width follows the aspect ratio of the input
+    pub height: u32,
+    /// Video bitrate in kbps
+    pub video_bitrate: i32,
+}
+
+/// The renditions encoded for adaptive streaming
+#[derive(Clone, Debug, PartialEq)]
+pub struct LadderSettings {
+    pub renditions: Vec<Rendition>,
+}
+
+/// Most renditions a ladder can hold
+pub const MAX_RENDITIONS: usize = 8;
+
+impl Default for LadderSettings {
+    fn default() -> Self {
+        let rendition = |height, video_bitrate| Rendition { height, video_bitrate };
+        Self {
+            renditions: vec![
+                rendition(1080, 5000),
+                rendition(720, 2800),
+                rendition(480, 1400),
+                rendition(360, 800),
+            ],
+        }
+    }
+}
+
+/// Encode every rendition of `settings` from a single decode of the input, with the
+/// output format, codecs, tracks, audio settings, metadata and chapters of `options`
+/// (burned-in subtitles, text overlays, subtitle files and cover art are left out), as
+/// "<base_name>_<height>p_<kbps>k.<ext>" files in a ZIP archive; returns the archive
+/// and the number of files. The work is done in short steps that hand control back
+/// to the browser, and `on_progress` gets the percentage done of each rendition
+/// after every step.
+pub async fn encode_ladder(
+    input_data: &[u8],
+    base_name: &str,
+    options: &TranscodeOptions,
+    settings: &LadderSettings,
+    mut on_progress: impl FnMut(Vec<u32>),
+) -> Result<(Vec<u8>, usize), TranscodeError> {
+    if options.drops_video() {
+        return Err(TranscodeError("A ladder needs a video output format".to_string()));
+    }
+    
+    let ladder = settings
+        .renditions
+        .iter()
+        .map(|r| format!("{}@{}", r.height, r.video_bitrate))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut job_options = vec![format!("ladder={}", ladder)];
+    let extra = options.to_option_string();
+    if !extra.is_empty() {
+        job_options.push(extra);
+    }
+    
+    let audio_codec = if options.drop_audio { "" } else { options.audio_codec.as_str() };
+    let module = FFmpeg();
+    add_extra_inputs(&module, options)?;
+    let handle = module.ladder_open_js(
+        input_data,
+        &options.output_format,
+        &options.video_codec,
+        audio_codec,
+        options.audio_bitrate,
+        &job_options.join(":")
+    );
+    
+    let result = loop {
+        match module.ladder_step_js(handle) {
+            Ok(done) => {
+                on_progress((0..settings.renditions.len() as u32).map(|i| module.ladder_progress_js(handle, i)).collect());
+                if done {
+                    break Ok(());
+                }
+                yield_to_browser().await;
+            },
+            Err(e) => break Err(TranscodeError::from(e)),
+        }
+    };
+    
+    if handle != 0 {
+        module.ladder_close_js(handle);
+    }
+    module.clear_inputs();
+    // Files of a failed ladder are taken too, to free them
+    let files = module.take_output_files();
+    result?;
+    
+    let extension = get_file_extension(&options.output_format);
+    let mut archive = ZipWriter::new();
+    for file in files.iter() {
+        let file = js_sys::Array::from(&file);
+        let name = file.get(0).as_string().unwrap_or_default();
+        archive.add_file(&format!("{}_{}.{}", base_name, name, extension), &js_sys::Uint8Array::new(&file.get(1)).to_vec());
+    }
+    Ok((archive.finish(), files.length() as usize))
+}
+
+/// Let the browser repaint and handle events before carrying on
+async fn yield_to_browser() {
+    let window = web_sys::window().expect("no global window exists");
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let _ = window.set_timeout_with_callback(&resolve);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Get the still image formats frames can be extracted as
+pub fn get_image_formats() -> Vec<String> {
+    vec![
+        "png".to_string(),
+        "jpeg".to_string(),
+        "webp".to_string(),
+    ]
+}
+
 /// Get the available video codecs
 pub fn get_video_codecs() -> Vec<String> {
     // These are the codecs we support in our build
@@ -127,6 +1470,147 @@ pub fn get_video_codecs() -> Vec<String> {
     ]
 }
 
+/// Measure the EBU R128 loudness of the first audio stream, remixed to
+/// `channel_layout` ("same" to keep the source layout)
+pub fn measure_loudness(input_data: &[u8], channel_layout: &str) -> Result<LoudnessStats, TranscodeError> {
+    let options = if channel_layout == "same" {
+        String::new()
+    } else {
+        format!("channel_layout={}", channel_layout)
+    };
+    
+    let response = FFmpeg().measure_loudness_js(input_data, &options);
+    if let Some(error) = response.strip_prefix("error=") {
+        return Err(TranscodeError(format!("Loudness measurement failed: {}", error)));
+    }
+    
+    let mut stats = LoudnessStats {
+        integrated: f64::NEG_INFINITY,
+        range: 0.0,
+        true_peak: f64::NEG_INFINITY,
+        threshold: -70.0,
+    };
+    
+    for (key, value) in response.split(':').filter_map(|pair| pair.split_once('=')) {
+        let value = value.parse::<f64>().unwrap_or(f64::NEG_INFINITY);
+        match key {
+            "I" => stats.integrated = value,
+            "LRA" => stats.range = value,
+            "TP" => stats.true_peak = value,
+            "thresh" => stats.threshold = value,
+            _ => {}
+        }
+    }
+    
+    Ok(stats)
+}
+
+/// Read the container format and streams of an input file
+pub fn probe(input_data: &[u8]) -> Result<ProbeInfo, TranscodeError> {
+    let json = js_sys::JSON::parse(&FFmpeg().probe_input_js(input_data))?;
+    
+    let get = |object: &JsValue, key: &str| js_sys::Reflect::get(object, &JsValue::from_str(key)).unwrap_or(JsValue::UNDEFINED);
+    let get_string = |object: &JsValue, key: &str| get(object, key).as_string().unwrap_or_default();
+    let get_f64 = |object: &JsValue, key: &str| get(object, key).as_f64().unwrap_or(0.0);
+    let get_metadata = |object: &JsValue| -> Vec<(String, String)> {
+        let metadata = get(object, "metadata");
+        js_sys::Object::try_from(&metadata)
+            .map(|tags| {
+                js_sys::Object::entries(tags)
+                    .iter()
+                    .map(|entry| {
+                        let entry = js_sys::Array::from(&entry);
+                        (entry.get(0).as_string().unwrap_or_default(), entry.get(1).as_string().unwrap_or_default())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    
+    if let Some(error) = get(&json, "error").as_string() {
+        return Err(TranscodeError(format!("Probe failed: {}", error)));
+    }
+    
+    let streams = js_sys::Array::from(&get(&json, "streams"))
+        .iter()
+        .map(|stream| StreamInfo {
+            index: get_f64(&stream, "index") as i32,
+            kind: get_string(&stream, "type"),
+            codec: get_string(&stream, "codec"),
+            width: get_f64(&stream, "width") as i32,
+            height: get_f64(&stream, "height") as i32,
+            fps: get_f64(&stream, "fps"),
+            pixel_format: get_string(&stream, "pix_fmt"),
+            sample_rate: get_f64(&stream, "sample_rate") as i32,
+            channels: get_f64(&stream, "channels") as i32,
+            channel_layout: get_string(&stream, "channel_layout"),
+            text_based: get(&stream, "text_based").as_bool().unwrap_or(false),
+            default: get(&stream, "default").as_bool().unwrap_or(false),
+            language: get_string(&stream, "language"),
+            title: get_string(&stream, "title"),
+            metadata: get_metadata(&stream),
+        })
+        .collect();
+    
+    Ok(ProbeInfo {
+        format_name: get_string(&json, "format"),
+        duration: get_f64(&json, "duration"),
+        metadata: get_metadata(&json),
+        chapters: js_sys::Array::from(&get(&json, "chapters"))
+            .iter()
+            .map(|chapter| Chapter {
+                start: get_f64(&chapter, "start"),
+                end: get_f64(&chapter, "end"),
+                title: get_string(&chapter, "title"),
+            })
+            .collect(),
+        streams,
+    })
+}
+
+/// Whether this build can burn subtitles onto video (libass compiled in)
+pub fn subtitle_burn_in_available() -> bool {
+    has_build_feature("libass")
+}
+
+/// Whether this build can draw text onto video (FreeType compiled in)
+pub fn text_overlay_available() -> bool {
+    has_build_feature("freetype")
+}
+
+fn has_build_feature(name: &str) -> bool {
+    FFmpeg().get_build_features_js().split(',').any(|feature| feature == name)
+}
+
+/// Get the pixel formats supported by a video encoder
+pub fn get_pixel_formats(video_codec: &str) -> Vec<String> {
+    FFmpeg()
+        .get_encoder_pixel_formats(video_codec)
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Get the sample rates supported by an audio encoder
+pub fn get_sample_rates(audio_codec: &str) -> Vec<i32> {
+    FFmpeg()
+        .get_encoder_sample_rates(audio_codec)
+        .split(',')
+        .filter_map(|rate| rate.parse().ok())
+        .collect()
+}
+
+/// Get the channel layouts supported by an audio encoder
+pub fn get_channel_layouts(audio_codec: &str) -> Vec<String> {
+    FFmpeg()
+        .get_encoder_channel_layouts(audio_codec)
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
 /// Get the available audio codecs
 pub fn get_audio_codecs() -> Vec<String> {
     // These are the codecs we support in our build
@@ -139,6 +1623,46 @@ pub fn get_audio_codecs() -> Vec<String> {
     ]
 }
 
+/// Whether an output format can only hold audio
+pub fn is_audio_only_format(format: &str) -> bool {
+    matches!(format, "mp3" | "ogg" | "wav" | "flac" | "opus" | "ipod")
+}
+
+/// Whether an output format is an animated image encoded from a range of the video
+pub fn is_animation_format(format: &str) -> bool {
+    matches!(format, "gif" | "webp" | "apng")
+}
+
+/// Whether the output format can carry cover art
+pub fn supports_cover_art(format: &str) -> bool {
+    matches!(format, "mp3" | "flac" | "ogg" | "opus" | "ipod" | "mp4" | "mov" | "mkv")
+}
+
+/// Whether a format is of the MP4 family, with the layout options of `Mp4Settings`
+pub fn is_mp4_format(format: &str) -> bool {
+    matches!(format, "mp4" | "ipod" | "mov")
+}
+
+/// File extension for an output format
+pub fn get_file_extension(format: &str) -> &str {
+    match format {
+        "ipod" => "m4a",
+        "apng" => "png",
+        "jpeg" => "jpg",
+        "webvtt" => "vtt",
+        _ => format,
+    }
+}
+
+/// Get the standalone subtitle formats
+pub fn get_subtitle_formats() -> Vec<String> {
+    vec![
+        "srt".to_string(),
+        "webvtt".to_string(),
+        "ass".to_string(),
+    ]
+}
+
 /// Get the available output formats
 pub fn get_output_formats() -> Vec<String> {
     // These are the formats we support in our build
@@ -151,5 +1675,71 @@ pub fn get_output_formats() -> Vec<String> {
         "mp3".to_string(),
         "ogg".to_string(),
         "wav".to_string(),
+        "flac".to_string(),
+        "opus".to_string(),
+        "ipod".to_string(),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_input_numbers_follow_extra_inputs() {
+        let subtitle = ExternalSubtitle {
+            data: b"sub".to_vec(),
+            language: String::new(),
+            title: String::new(),
+            default: false,
+        };
+        let options = TranscodeOptions {
+            external_subtitles: vec![subtitle.clone(), subtitle],
+            burn_subtitles: Some(BurnSubtitles {
+                source: SubtitleSource::File(b"burn".to_vec()),
+                font_size: None,
+                outline: None,
+                position: None,
+            }),
+            chapters: Some(vec![Chapter { start: 0.0, end: 1.0, title: "One".to_string() }]),
+            cover_art: CoverArt::Replace(b"cover".to_vec()),
+            ..Default::default()
+        };
+
+        let inputs = options.extra_inputs();
+        let option_string = options.to_option_string();
+        let input = |key: &str| -> usize {
+            let value = option_string.split(':').find_map(|option| option.strip_prefix(key)).unwrap();
+            value.parse().unwrap()
+        };
+
+        assert_eq!(inputs[input("burn_subtitles_input=") - 1], b"burn");
+        assert!(inputs[input("chapters_input=") - 1].starts_with(b";FFMETADATA1"));
+        assert_eq!(inputs[input("cover_input=") - 1], b"cover");
+
+        let options = TranscodeOptions { chapters: Some(Vec::new()), ..options };
+        assert!(options.to_option_string().contains("chapters=none"));
+        assert_eq!(options.extra_inputs().len(), 4);
+        assert!(options.to_option_string().contains("cover_input=4"));
+    }
+
+    #[test]
+    fn audio_formats_with_cover_art() {
+        for format in ["mp3", "flac", "ogg", "opus", "ipod"] {
+            assert!(supports_cover_art(format), "{} should carry cover art", format);
+        }
+        assert!(!supports_cover_art("wav"));
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["img10.png", "img2.png", "IMG1.png", "img02.png", "img.png", "img1b.png", "img1a.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["img.png", "IMG1.png", "img1a.png", "img1b.png", "img2.png", "img02.png", "img10.png"]);
+
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("frame_0099", "frame_100"), Ordering::Less);
+        assert_eq!(natural_cmp("99999999999999999999999", "100000000000000000000000"), Ordering::Less);
+        assert_eq!(natural_cmp("a1", "a1"), Ordering::Equal);
+    }
+}
@@ -19,8 +19,252 @@ FFmpegModule['_update_progress_js'] = function(progress) {
   }
 };
 
+// List the pixel formats an encoder accepts (comma-separated)
+FFmpegModule['getEncoderPixelFormats'] = function(codecName) {
+  return FFmpegModule.ccall('get_encoder_pixel_formats', 'string', ['string'], [codecName]);
+};
+
+// List the sample rates an encoder accepts (comma-separated)
+FFmpegModule['getEncoderSampleRates'] = function(codecName) {
+  return FFmpegModule.ccall('get_encoder_sample_rates', 'string', ['string'], [codecName]);
+};
+
+// List the channel layouts an encoder accepts (comma-separated)
+FFmpegModule['getEncoderChannelLayouts'] = function(codecName) {
+  return FFmpegModule.ccall('get_encoder_channel_layouts', 'string', ['string'], [codecName]);
+};
+
+// List optional features compiled into this build (comma-separated)
+FFmpegModule['getBuildFeatures'] = function() {
+  return FFmpegModule.ccall('get_build_features', 'string', [], []);
+};
+
+// Measure EBU R128 loudness (first pass of loudness normalization)
+FFmpegModule['measureLoudness'] = function(inputData, options) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  var stats = FFmpegModule.ccall(
+    'measure_loudness',
+    'string',
+    ['number', 'number', 'string'],
+    [dataPtr, inputData.length, options]
+  );
+  
+  FFmpegModule._free(dataPtr);
+  return stats;
+};
+
+// Register an extra input (e.g. a subtitle file) for the next transcode.
+// The C side takes ownership of the buffer until clearInputs().
+FFmpegModule['addInput'] = function(inputData) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  var input = FFmpegModule._add_input(dataPtr, inputData.length);
+  if (input < 0) {
+    FFmpegModule._free(dataPtr);
+  }
+  return input;
+};
+
+FFmpegModule['clearInputs'] = function() {
+  FFmpegModule._clear_inputs();
+};
+
+FFmpegModule['probeInput'] = function(inputData) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  // probe_input returns a malloc'd JSON string that we own
+  var jsonPtr = FFmpegModule._probe_input(dataPtr, inputData.length);
+  var json = jsonPtr ? FFmpegModule.UTF8ToString(jsonPtr) : '{"error":"Out of memory"}';
+  
+  if (jsonPtr) FFmpegModule._free(jsonPtr);
+  FFmpegModule._free(dataPtr);
+  return json;
+};
+
+// Copy the output out of a TranscodeResult and free it; throws on failure.
+// Layout (wasm32): int success, int progress, char error_message[256],
+// uint8_t *output_data at 264, size_t output_size at 268
+function takeTranscodeResult(resultPtr) {
+  if (resultPtr === 0) {
+    throw new Error('No result returned');
+  }
+  
+  var success = FFmpegModule.getValue(resultPtr, 'i32');
+  var outputData = null;
+  var errorMsg = '';
+  
+  if (success) {
+    var outputDataPtr = FFmpegModule.getValue(resultPtr + 264, '*');
+    var outputSize = FFmpegModule.getValue(resultPtr + 268, 'i32') >>> 0;
+    outputData = FFmpegModule.HEAPU8.slice(outputDataPtr, outputDataPtr + outputSize);
+  } else {
+    errorMsg = FFmpegModule.UTF8ToString(resultPtr + 8);
+  }
+  
+  FFmpegModule.ccall('free_transcode_result', null, ['number'], [resultPtr]);
+  
+  if (!success) {
+    throw new Error(errorMsg);
+  }
+  return outputData;
+}
+
+// Extract the embedded cover picture
+FFmpegModule['extractCover'] = function(inputData) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  var resultPtr = FFmpegModule._extract_cover(dataPtr, inputData.length);
+  FFmpegModule._free(dataPtr);
+  
+  return takeTranscodeResult(resultPtr);
+};
+
+// Extract one frame as a PNG, JPEG or WebP image; width/height of 0 keep the aspect ratio
+FFmpegModule['extractFrame'] = function(inputData, timestamp, format, width, height) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  var resultPtr = FFmpegModule.ccall(
+    'extract_frame',
+    'number',
+    ['number', 'number', 'number', 'string', 'number', 'number'],
+    [dataPtr, inputData.length, timestamp, format, width, height]
+  );
+  FFmpegModule._free(dataPtr);
+  
+  return takeTranscodeResult(resultPtr);
+};
+
+// Render a grid of evenly spaced frames as one PNG or JPEG image
+FFmpegModule['contactSheet'] = function(inputData, format, options) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  var resultPtr = FFmpegModule.ccall(
+    'contact_sheet',
+    'number',
+    ['number', 'number', 'string', 'string'],
+    [dataPtr, inputData.length, format, options]
+  );
+  FFmpegModule._free(dataPtr);
+  
+  return takeTranscodeResult(resultPtr);
+};
+
+// Read frames one at a time for an image sequence. The C side takes ownership of
+// the buffer until imageSequenceClose().
+FFmpegModule['imageSequenceOpen'] = function(inputData, options) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  return FFmpegModule.ccall(
+    'image_sequence_open',
+    'number',
+    ['number', 'number', 'string'],
+    [dataPtr, inputData.length, options]
+  );
+};
+
+// The next image of the sequence; empty at the end
+FFmpegModule['imageSequenceNext'] = function(sequence, format) {
+  if (sequence === 0) {
+    throw new Error('Out of memory');
+  }
+  
+  var resultPtr = FFmpegModule.ccall('image_sequence_next', 'number', ['number', 'string'], [sequence, format]);
+  return takeTranscodeResult(resultPtr);
+};
+
+FFmpegModule['imageSequenceClose'] = function(sequence) {
+  FFmpegModule._image_sequence_close(sequence);
+};
+
+// Cut the input into parts one at a time. The C side takes ownership of the buffer
+// until splitClose().
+FFmpegModule['splitOpen'] = function(inputData, format, options) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  return FFmpegModule.ccall(
+    'split_open',
+    'number',
+    ['number', 'number', 'string', 'string'],
+    [dataPtr, inputData.length, format, options]
+  );
+};
+
+// The next part; empty at the end
+FFmpegModule['splitNext'] = function(splitter) {
+  if (splitter === 0) {
+    throw new Error('Out of memory');
+  }
+  
+  var resultPtr = FFmpegModule.ccall('split_next', 'number', ['number'], [splitter]);
+  return takeTranscodeResult(resultPtr);
+};
+
+FFmpegModule['splitClose'] = function(splitter) {
+  FFmpegModule._split_close(splitter);
+};
+
+// Encode a ladder of renditions a step at a time. The C side takes ownership of the
+// buffer until ladderClose(); the files are left for takeOutputFiles().
+FFmpegModule['ladderOpen'] = function(inputData, format, videoCodec, audioCodec, audioBitrate, options) {
+  var dataPtr = FFmpegModule._malloc(inputData.length);
+  FFmpegModule.HEAPU8.set(inputData, dataPtr);
+  
+  return FFmpegModule.ccall(
+    'ladder_open',
+    'number',
+    ['number', 'number', 'string', 'string', 'string', 'number', 'string'],
+    [dataPtr, inputData.length, format, videoCodec, audioCodec, audioBitrate, options]
+  );
+};
+
+// Work for a moment; true once every rendition is complete
+FFmpegModule['ladderStep'] = function(ladder) {
+  if (ladder === 0) {
+    throw new Error('Out of memory');
+  }
+  
+  var status = FFmpegModule._ladder_step(ladder);
+  if (status < 0) {
+    throw new Error(FFmpegModule.UTF8ToString(FFmpegModule._ladder_error(ladder)));
+  }
+  return status === 0;
+};
+
+FFmpegModule['ladderProgress'] = function(ladder, rendition) {
+  return ladder === 0 ? 0 : FFmpegModule._ladder_progress(ladder, rendition);
+};
+
+FFmpegModule['ladderClose'] = function(ladder) {
+  FFmpegModule._ladder_close(ladder);
+};
+
+// Take the files written by the last HLS or DASH transcode or ladder as [name, Uint8Array] pairs
+FFmpegModule['takeOutputFiles'] = function() {
+  var files = [];
+  var count = FFmpegModule._get_output_file_count();
+  
+  for (var i = 0; i < count; i++) {
+    var name = FFmpegModule.UTF8ToString(FFmpegModule._get_output_file_name(i));
+    var dataPtr = FFmpegModule._get_output_file_data(i);
+    var size = FFmpegModule._get_output_file_size(i) >>> 0;
+    files.push([name, FFmpegModule.HEAPU8.slice(dataPtr, dataPtr + size)]);
+  }
+  
+  FFmpegModule._clear_output_files();
+  return files;
+};
+
 // Asynchronous transcode function using ccall
-FFmpegModule['transcodeAsync'] = function(inputData, outputFormat, videoCodec, audioCodec, videoBitrate, audioBitrate, resolution) {
+FFmpegModule['transcodeAsync'] = function(inputData, outputFormat, videoCodec, audioCodec, videoBitrate, audioBitrate, resolution, options) {
   return new Promise(function(resolve, reject) {
     // Create Uint8Array from input data
     var dataPtr = FFmpegModule._malloc(inputData.length);
@@ -41,44 +285,18 @@ FFmpegModule['transcodeAsync'] = function(inputData, outputFormat, videoCodec, a
     var resultPtr = FFmpegModule.ccall(
       'transcode',
       'number',
-      ['number', 'number', 'string', 'string', 'string', 'number', 'number', 'string'],
-      [dataPtr, inputData.length, outputFormat, videoCodec, audioCodec, videoBitrate, audioBitrate, resolution]
+      ['number', 'number', 'string', 'string', 'string', 'number', 'number', 'string', 'string'],
+      [dataPtr, inputData.length, outputFormat, videoCodec, audioCodec, videoBitrate, audioBitrate, resolution, options]
     );
     
     // Free the input data memory
     FFmpegModule._free(dataPtr);
     
-    // Check if the transcode was successful
-    if (resultPtr === 0) {
-      reject(new Error('Transcoding failed: No result returned'));
-      return;
-    }
-    
-    // Extract result data
-    var success = FFmpegModule.getValue(resultPtr, 'i32');
-    
-    if (success) {
-      // Extract output data
-      var outputDataPtr = FFmpegModule.getValue(resultPtr + 12, '*');
-      var outputSize = FFmpegModule.getValue(resultPtr + 16, 'i64');
-      
-      // Create output buffer
-      var outputData = new Uint8Array(outputSize);
-      outputData.set(FFmpegModule.HEAPU8.subarray(outputDataPtr, outputDataPtr + outputSize));
-      
-      // Free the result struct
-      FFmpegModule.ccall('free_transcode_result', null, ['number'], [resultPtr]);
-      
-      resolve(outputData);
-    } else {
-      // Get error message
-      var errorMsgPtr = resultPtr + 8;
-      var errorMsg = FFmpegModule.UTF8ToString(errorMsgPtr);
-      
-      // Free the result struct
-      FFmpegModule.ccall('free_transcode_result', null, ['number'], [resultPtr]);
-      
-      reject(new Error('Transcoding failed: ' + errorMsg));
+    // Extract the output data and free the result struct
+    try {
+      resolve(takeTranscodeResult(resultPtr));
+    } catch (error) {
+      reject(new Error('Transcoding failed: ' + error.message));
     }
   });
 };
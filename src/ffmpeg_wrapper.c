@@ -5,7 +5,23 @@
 #include <libavutil/imgutils.h>
 #include <libavutil/opt.h>
 #include <libavutil/error.h>
+#include <libavutil/dict.h>
+#include <libavutil/pixdesc.h>
+#include <libavutil/channel_layout.h>
+#include <libavutil/audio_fifo.h>
+#include <libavutil/avstring.h>
+#include <libavutil/time.h>
+#include <libavutil/base64.h>
+#include <libavutil/intreadwrite.h>
 #include <libswresample/swresample.h>
+#ifdef HAVE_LIBASS
+#include <ass/ass.h>
+#endif
+#ifdef HAVE_FREETYPE
+#include <ft2build.h>
+#include FT_FREETYPE_H
+#endif
+#include <math.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
@@ -17,6 +33,7 @@ typedef struct {
     char error_message[256];
     uint8_t* output_data;
     size_t output_size;
+    size_t output_pos;  // Write position; muxers may seek back to patch headers
 } TranscodeResult;
 
 // Custom callback to update progress
@@ -38,21 +55,605 @@ void init_ffmpeg() {
 // Custom AVIOContext write callback
 static int write_packet(void *opaque, uint8_t *buf, int buf_size) {
     TranscodeResult *result = (TranscodeResult*)opaque;
+    size_t end = result->output_pos + buf_size;
     
     // Reallocate output buffer to accommodate new data
-    uint8_t* new_buffer = realloc(result->output_data, result->output_size + buf_size);
-    if (!new_buffer) {
-        return AVERROR(ENOMEM); // Memory allocation error
+    if (end > result->output_size) {
+        uint8_t* new_buffer = realloc(result->output_data, end);
+        if (!new_buffer) {
+            return AVERROR(ENOMEM); // Memory allocation error
+        }
+        
+        // A seek past the end leaves a gap
+        if (result->output_pos > result->output_size) {
+            memset(new_buffer + result->output_size, 0, result->output_pos - result->output_size);
+        }
+        result->output_data = new_buffer;
+        result->output_size = end;
+    }
+    
+    // Copy new data at the write position
+    memcpy(result->output_data + result->output_pos, buf, buf_size);
+    result->output_pos = end;
+    
+    return buf_size;
+}
+
+// Custom AVIOContext seek callback for the output buffer
+static int64_t seek_output(void *opaque, int64_t offset, int whence) {
+    TranscodeResult *result = (TranscodeResult*)opaque;
+    int64_t position;
+    
+    whence &= ~AVSEEK_FORCE;
+    if (whence == AVSEEK_SIZE) {
+        return result->output_size;
+    } else if (whence == SEEK_CUR) {
+        position = result->output_pos + offset;
+    } else if (whence == SEEK_END) {
+        position = result->output_size + offset;
+    } else {
+        position = offset;
+    }
+    
+    if (position < 0) {
+        return AVERROR(EINVAL);
+    }
+    
+    result->output_pos = position;
+    return position;
+}
+
+// Reads back what has been written to a memory output, for muxers that rewrite
+// their output in a second pass (MP4 faststart). The output may grow while it is
+// read, so the buffer is looked up on every read.
+typedef struct {
+    TranscodeResult *result;
+    size_t pos;
+} OutputReader;
+
+static int read_output(void *opaque, uint8_t *buf, int buf_size) {
+    OutputReader *reader = (OutputReader*)opaque;
+    TranscodeResult *result = reader->result;
+    
+    if (reader->pos >= result->output_size) {
+        return AVERROR_EOF;
+    }
+    
+    if ((size_t)buf_size > result->output_size - reader->pos) {
+        buf_size = (int)(result->output_size - reader->pos);
+    }
+    
+    memcpy(buf, result->output_data + reader->pos, buf_size);
+    reader->pos += buf_size;
+    
+    return buf_size;
+}
+
+static int64_t seek_output_reader(void *opaque, int64_t offset, int whence) {
+    OutputReader *reader = (OutputReader*)opaque;
+    int64_t position;
+    
+    whence &= ~AVSEEK_FORCE;
+    if (whence == AVSEEK_SIZE) {
+        return reader->result->output_size;
+    } else if (whence == SEEK_CUR) {
+        position = reader->pos + offset;
+    } else if (whence == SEEK_END) {
+        position = reader->result->output_size + offset;
+    } else {
+        position = offset;
+    }
+    
+    if (position < 0 || position > (int64_t)reader->result->output_size) {
+        return AVERROR(EINVAL);
+    }
+    
+    reader->pos = position;
+    return position;
+}
+
+// AVFormatContext.io_open for reading a memory output back; the output is the one
+// the muxer writes through its own AVIOContext
+static int open_output_reader(AVFormatContext *s, AVIOContext **pb, const char *url, int flags, AVDictionary **options) {
+    if ((flags & AVIO_FLAG_WRITE) || !s->pb) {
+        return AVERROR(ENOSYS);
+    }
+    
+    OutputReader *reader = calloc(1, sizeof(OutputReader));
+    if (!reader) {
+        return AVERROR(ENOMEM);
+    }
+    reader->result = (TranscodeResult*)s->pb->opaque;
+    
+    unsigned char *buffer = av_malloc(4096);
+    *pb = buffer ? avio_alloc_context(buffer, 4096, 0, reader, read_output, NULL, seek_output_reader) : NULL;
+    if (!*pb) {
+        av_free(buffer);
+        free(reader);
+        return AVERROR(ENOMEM);
+    }
+    
+    return 0;
+}
+
+// AVFormatContext.io_close2 for open_output_reader
+static int close_output_reader(AVFormatContext *s, AVIOContext *pb) {
+    if (!pb) {
+        return 0;
+    }
+    
+    free(pb->opaque);
+    av_freep(&pb->buffer);
+    avio_context_free(&pb);
+    return 0;
+}
+
+// In-memory input read by the demuxer through AVIO callbacks
+typedef struct {
+    const uint8_t *data;
+    size_t size;
+    size_t pos;
+} MemoryInput;
+
+#define INPUT_IO_BUFFER_SIZE 32768
+
+// Custom AVIOContext read callback
+static int read_memory(void *opaque, uint8_t *buf, int buf_size) {
+    MemoryInput *input = (MemoryInput*)opaque;
+    size_t remaining = input->size - input->pos;
+    
+    if (remaining == 0) {
+        return AVERROR_EOF;
+    }
+    
+    if ((size_t)buf_size > remaining) {
+        buf_size = (int)remaining;
     }
     
-    // Copy new data to the extended buffer
-    memcpy(new_buffer + result->output_size, buf, buf_size);
-    result->output_data = new_buffer;
-    result->output_size += buf_size;
+    memcpy(buf, input->data + input->pos, buf_size);
+    input->pos += buf_size;
     
     return buf_size;
 }
 
+// Custom AVIOContext seek callback
+static int64_t seek_memory(void *opaque, int64_t offset, int whence) {
+    MemoryInput *input = (MemoryInput*)opaque;
+    int64_t position;
+    
+    whence &= ~AVSEEK_FORCE;
+    if (whence == AVSEEK_SIZE) {
+        return input->size;
+    } else if (whence == SEEK_CUR) {
+        position = input->pos + offset;
+    } else if (whence == SEEK_END) {
+        position = input->size + offset;
+    } else {
+        position = offset;
+    }
+    
+    if (position < 0 || position > (int64_t)input->size) {
+        return AVERROR(EINVAL);
+    }
+    
+    input->pos = position;
+    return position;
+}
+
+// Open a demuxer over an in-memory input and read its stream info
+static int open_memory_input(MemoryInput *input, AVFormatContext **fmt_ctx) {
+    unsigned char *io_buffer = av_malloc(INPUT_IO_BUFFER_SIZE);
+    if (!io_buffer) {
+        return AVERROR(ENOMEM);
+    }
+    
+    AVIOContext *io_ctx = avio_alloc_context(io_buffer, INPUT_IO_BUFFER_SIZE, 0, input, read_memory, NULL, seek_memory);
+    if (!io_ctx) {
+        av_free(io_buffer);
+        return AVERROR(ENOMEM);
+    }
+    
+    *fmt_ctx = avformat_alloc_context();
+    if (!*fmt_ctx) {
+        av_freep(&io_ctx->buffer);
+        avio_context_free(&io_ctx);
+        return AVERROR(ENOMEM);
+    }
+    
+    (*fmt_ctx)->pb = io_ctx;
+    
+    // avformat_open_input frees the context on failure, but never a custom pb
+    int ret = avformat_open_input(fmt_ctx, NULL, NULL, NULL);
+    if (ret < 0) {
+        av_freep(&io_ctx->buffer);
+        avio_context_free(&io_ctx);
+        return ret;
+    }
+    
+    ret = avformat_find_stream_info(*fmt_ctx, NULL);
+    if (ret < 0) {
+        avformat_close_input(fmt_ctx);
+        av_freep(&io_ctx->buffer);
+        avio_context_free(&io_ctx);
+        return ret;
+    }
+    
+    return 0;
+}
+
+// Close an input opened with open_memory_input
+static void close_memory_input(AVFormatContext **fmt_ctx) {
+    if (!*fmt_ctx) {
+        return;
+    }
+    
+    AVIOContext *io_ctx = (*fmt_ctx)->pb;
+    avformat_close_input(fmt_ctx);
+    if (io_ctx) {
+        av_freep(&io_ctx->buffer);
+        avio_context_free(&io_ctx);
+    }
+}
+
+// Additional inputs registered from JavaScript ahead of a transcode (external
+// subtitle files, images, ...). The buffers are owned here until clear_inputs().
+#define MAX_EXTRA_INPUTS 64
+static MemoryInput *extra_inputs = NULL;
+static int nb_extra_inputs = 0;
+static int extra_inputs_capacity = 0;
+
+// Returns the input number (the main input is 0), or -1 when out of memory
+EMSCRIPTEN_KEEPALIVE
+int add_input(uint8_t* data, size_t size) {
+    if (nb_extra_inputs >= extra_inputs_capacity) {
+        int capacity = FFMAX(extra_inputs_capacity * 2, MAX_EXTRA_INPUTS);
+        MemoryInput *inputs = realloc(extra_inputs, capacity * sizeof(MemoryInput));
+        if (!inputs) {
+            return -1;
+        }
+        extra_inputs = inputs;
+        extra_inputs_capacity = capacity;
+    }
+    
+    extra_inputs[nb_extra_inputs].data = data;
+    extra_inputs[nb_extra_inputs].size = size;
+    extra_inputs[nb_extra_inputs].pos = 0;
+    
+    return ++nb_extra_inputs;
+}
+
+EMSCRIPTEN_KEEPALIVE
+void clear_inputs() {
+    for (int i = 0; i < nb_extra_inputs; i++) {
+        free((void *)extra_inputs[i].data);
+    }
+    
+    free(extra_inputs);
+    extra_inputs = NULL;
+    nb_extra_inputs = 0;
+    extra_inputs_capacity = 0;
+}
+
+// Open a registered extra input from the start
+static int open_extra_input(int input, AVFormatContext **fmt_ctx) {
+    if (input < 1 || input > nb_extra_inputs) {
+        return AVERROR(EINVAL);
+    }
+    
+    extra_inputs[input - 1].pos = 0;
+    return open_memory_input(&extra_inputs[input - 1], fmt_ctx);
+}
+
+// Files written by muxers that produce several (HLS and DASH packages) or by a
+// rendition ladder, held until clear_output_files(). Each file is written like a
+// single output, through its own AVIOContext.
+typedef struct {
+    char *name;
+    TranscodeResult data;
+} OutputFile;
+
+static OutputFile **output_files = NULL;
+static int nb_output_files = 0;
+
+// A scheme no protocol handles, for muxers that would otherwise write a local file
+// beside the output and rename it over
+#define PACKAGE_URL_SCHEME "package:"
+
+EMSCRIPTEN_KEEPALIVE
+void clear_output_files() {
+    for (int i = 0; i < nb_output_files; i++) {
+        free(output_files[i]->name);
+        free(output_files[i]->data.output_data);
+        free(output_files[i]);
+    }
+    
+    free(output_files);
+    output_files = NULL;
+    nb_output_files = 0;
+}
+
+EMSCRIPTEN_KEEPALIVE
+int get_output_file_count() {
+    return nb_output_files;
+}
+
+EMSCRIPTEN_KEEPALIVE
+const char* get_output_file_name(int index) {
+    return index >= 0 && index < nb_output_files ? output_files[index]->name : NULL;
+}
+
+EMSCRIPTEN_KEEPALIVE
+uint8_t* get_output_file_data(int index) {
+    return index >= 0 && index < nb_output_files ? output_files[index]->data.output_data : NULL;
+}
+
+EMSCRIPTEN_KEEPALIVE
+size_t get_output_file_size(int index) {
+    return index >= 0 && index < nb_output_files ? output_files[index]->data.output_size : 0;
+}
+
+// AVFormatContext.io_open for the registry. Opening a name again replaces the file,
+// as muxers do when they rewrite a playlist. Names are kept without the scheme of
+// the package URL.
+static int open_output_file(AVFormatContext *s, AVIOContext **pb, const char *url, int flags, AVDictionary **options) {
+    OutputFile *file = NULL;
+    
+    if (!(flags & AVIO_FLAG_WRITE)) {
+        return AVERROR(ENOSYS);
+    }
+    av_strstart(url, PACKAGE_URL_SCHEME, &url);
+    
+    for (int i = 0; i < nb_output_files && !file; i++) {
+        if (strcmp(output_files[i]->name, url) == 0) {
+            file = output_files[i];
+        }
+    }
+    
+    if (!file) {
+        OutputFile **files = realloc(output_files, (nb_output_files + 1) * sizeof(*files));
+        if (!files) {
+            return AVERROR(ENOMEM);
+        }
+        output_files = files;
+        
+        file = calloc(1, sizeof(OutputFile));
+        if (!file || !(file->name = strdup(url))) {
+            free(file);
+            return AVERROR(ENOMEM);
+        }
+        output_files[nb_output_files++] = file;
+    }
+    
+    file->data.output_size = 0;
+    file->data.output_pos = 0;
+    
+    unsigned char *buffer = av_malloc(4096);
+    *pb = buffer ? avio_alloc_context(buffer, 4096, 1, &file->data, NULL, write_packet, seek_output) : NULL;
+    if (!*pb) {
+        av_free(buffer);
+        return AVERROR(ENOMEM);
+    }
+    
+    return 0;
+}
+
+// AVFormatContext.io_close2 for the registry
+static int close_output_file(AVFormatContext *s, AVIOContext *pb) {
+    if (!pb) {
+        return 0;
+    }
+    
+    avio_flush(pb);
+    int ret = pb->error;
+    av_freep(&pb->buffer);
+    avio_context_free(&pb);
+    return ret;
+}
+
+// Point a package muxer at the registry, starting from an empty one
+static int open_package_output(AVFormatContext *output_ctx, const char *url, char *error, size_t error_size) {
+    av_freep(&output_ctx->url);
+    output_ctx->url = av_strdup(url);
+    if (!output_ctx->url) {
+        snprintf(error, error_size, "Out of memory");
+        return AVERROR(ENOMEM);
+    }
+    
+    clear_output_files();
+    output_ctx->io_open = open_output_file;
+    output_ctx->io_close2 = close_output_file;
+    return 0;
+}
+
+// Open a decoder for an input stream
+static int open_decoder(AVStream *stream, AVCodecContext **dec_ctx) {
+    const AVCodec *decoder = avcodec_find_decoder(stream->codecpar->codec_id);
+    if (!decoder) {
+        return AVERROR_DECODER_NOT_FOUND;
+    }
+    
+    *dec_ctx = avcodec_alloc_context3(decoder);
+    if (!*dec_ctx) {
+        return AVERROR(ENOMEM);
+    }
+    
+    int ret = avcodec_parameters_to_context(*dec_ctx, stream->codecpar);
+    (*dec_ctx)->pkt_timebase = stream->time_base;
+    if (ret >= 0) {
+        ret = avcodec_open2(*dec_ctx, decoder, NULL);
+    }
+    if (ret < 0) {
+        avcodec_free_context(dec_ctx);
+    }
+    
+    return ret;
+}
+
+// Open an input's first video stream (not its cover art) and its decoder
+static int open_video_input(MemoryInput *input, AVFormatContext **fmt_ctx, AVStream **stream, AVCodecContext **dec_ctx,
+                            char *error, size_t error_size) {
+    int ret = open_memory_input(input, fmt_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    *stream = NULL;
+    for (unsigned int i = 0; i < (*fmt_ctx)->nb_streams && !*stream; i++) {
+        AVStream *candidate = (*fmt_ctx)->streams[i];
+        if (candidate->codecpar->codec_type == AVMEDIA_TYPE_VIDEO && !(candidate->disposition & AV_DISPOSITION_ATTACHED_PIC)) {
+            *stream = candidate;
+        }
+    }
+    
+    if (!*stream) {
+        snprintf(error, error_size, "The input has no video stream");
+        return AVERROR_STREAM_NOT_FOUND;
+    }
+    
+    ret = open_decoder(*stream, dec_ctx);
+    if (ret == AVERROR_DECODER_NOT_FOUND) {
+        snprintf(error, error_size, "Unsupported video codec");
+    } else if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+    return ret;
+}
+
+// Decode the frame nearest to `timestamp` seconds from the start of the stream
+static int decode_frame_at(AVFormatContext *fmt_ctx, AVStream *stream, AVCodecContext *dec_ctx, double timestamp, AVFrame *frame) {
+    int64_t start = stream->start_time != AV_NOPTS_VALUE ? stream->start_time : 0;
+    int64_t target = start + av_rescale_q((int64_t)(timestamp * AV_TIME_BASE), AV_TIME_BASE_Q, stream->time_base);
+    
+    // Land on the keyframe before the target and decode forward from there. When
+    // seeking fails the input is read from wherever it is.
+    av_seek_frame(fmt_ctx, stream->index, target, AVSEEK_FLAG_BACKWARD);
+    avcodec_flush_buffers(dec_ctx);
+    
+    AVPacket *packet = av_packet_alloc();
+    AVFrame *decoded = av_frame_alloc();
+    if (!packet || !decoded) {
+        av_packet_free(&packet);
+        av_frame_free(&decoded);
+        return AVERROR(ENOMEM);
+    }
+    
+    int have_frame = 0;
+    int done = 0;
+    
+    while (!done) {
+        int flushing = 0;
+        int ret = av_read_frame(fmt_ctx, packet);
+        if (ret == AVERROR_EOF) {
+            flushing = 1;
+            avcodec_send_packet(dec_ctx, NULL);
+        } else if (ret < 0) {
+            break;
+        } else if (packet->stream_index != stream->index) {
+            av_packet_unref(packet);
+            continue;
+        } else {
+            // A damaged packet only costs its own frames
+            avcodec_send_packet(dec_ctx, packet);
+            av_packet_unref(packet);
+        }
+        
+        while (!done && avcodec_receive_frame(dec_ctx, decoded) >= 0) {
+            int64_t pts = decoded->best_effort_timestamp;
+            int64_t kept_pts = frame->best_effort_timestamp;
+            
+            if (have_frame && pts != AV_NOPTS_VALUE && pts > target) {
+                // Past the target: keep whichever of the two frames is nearer
+                if (kept_pts == AV_NOPTS_VALUE || pts - target < target - kept_pts) {
+                    av_frame_unref(frame);
+                    av_frame_move_ref(frame, decoded);
+                } else {
+                    av_frame_unref(decoded);
+                }
+                done = 1;
+            } else {
+                av_frame_unref(frame);
+                av_frame_move_ref(frame, decoded);
+                have_frame = 1;
+                done = pts != AV_NOPTS_VALUE && pts >= target;
+            }
+        }
+        
+        if (flushing) {
+            break;
+        }
+    }
+    
+    av_packet_free(&packet);
+    av_frame_free(&decoded);
+    
+    return have_frame ? 0 : AVERROR_INVALIDDATA;
+}
+
+// Look up an extended option, falling back to a default when unset
+static const char* get_option(AVDictionary *options, const char *key, const char *default_value) {
+    AVDictionaryEntry *entry = av_dict_get(options, key, NULL, 0);
+    return (entry && entry->value[0]) ? entry->value : default_value;
+}
+
+// Check whether an encoder accepts the given pixel format
+static int encoder_supports_pix_fmt(const AVCodec *encoder, enum AVPixelFormat pix_fmt) {
+    if (!encoder->pix_fmts) {
+        return 1; // Encoder doesn't advertise a list, let avcodec_open2 decide
+    }
+    
+    for (const enum AVPixelFormat *p = encoder->pix_fmts; *p != AV_PIX_FMT_NONE; p++) {
+        if (*p == pix_fmt) {
+            return 1;
+        }
+    }
+    
+    return 0;
+}
+
+// Pick a pixel format when the user leaves it on "auto"
+static enum AVPixelFormat default_pix_fmt(const AVOutputFormat *oformat, const AVCodec *encoder, enum AVPixelFormat source_pix_fmt) {
+    // Web-facing containers get 8-bit 4:2:0, the only format every player handles
+    const char *name = oformat->name;
+    if ((strstr(name, "mp4") || strstr(name, "mov") || strstr(name, "webm") || strstr(name, "matroska")) &&
+        encoder_supports_pix_fmt(encoder, AV_PIX_FMT_YUV420P)) {
+        return AV_PIX_FMT_YUV420P;
+    }
+    
+    if (encoder->pix_fmts) {
+        return avcodec_find_best_pix_fmt_of_list(encoder->pix_fmts, source_pix_fmt, 0, NULL);
+    }
+    
+    return AV_PIX_FMT_YUV420P;
+}
+
+// List the pixel formats supported by an encoder as a comma-separated string
+EMSCRIPTEN_KEEPALIVE
+const char* get_encoder_pixel_formats(const char* codec_name) {
+    static char formats[1024];
+    const AVCodec *encoder = avcodec_find_encoder_by_name(codec_name);
+    
+    formats[0] = '\0';
+    if (!encoder || !encoder->pix_fmts) {
+        return formats;
+    }
+    
+    for (const enum AVPixelFormat *p = encoder->pix_fmts; *p != AV_PIX_FMT_NONE; p++) {
+        const char *name = av_get_pix_fmt_name(*p);
+        if (!name || strlen(formats) + strlen(name) + 2 > sizeof(formats)) {
+            continue;
+        }
+        
+        if (formats[0]) {
+            strcat(formats, ",");
+        }
+        strcat(formats, name);
+    }
+    
+    return formats;
+}
+
 // Set progress callback
 EMSCRIPTEN_KEEPALIVE
 void set_progress_callback(ProgressCallback callback) {
@@ -109,635 +710,6909 @@ static int process_packet(
     return 0;
 }
 
-// Main transcoding function
-EMSCRIPTEN_KEEPALIVE
-TranscodeResult* transcode(
-    uint8_t* input_data, 
-    size_t input_size,
-    const char* output_format,
-    const char* video_codec_name,
-    const char* audio_codec_name,
-    int video_bitrate,
-    int audio_bitrate,
-    const char* resolution
-) {
-    AVFormatContext *input_ctx = NULL;
-    AVFormatContext *output_ctx = NULL;
-    AVIOContext *input_io_ctx = NULL;
-    AVIOContext *output_io_ctx = NULL;
-    AVCodecContext *video_dec_ctx = NULL;
-    AVCodecContext *audio_dec_ctx = NULL;
-    AVCodecContext *video_enc_ctx = NULL;
-    AVCodecContext *audio_enc_ctx = NULL;
-    const AVCodec *video_decoder = NULL;
-    const AVCodec *audio_decoder = NULL;
-    const AVCodec *video_encoder = NULL;
-    const AVCodec *audio_encoder = NULL;
-    AVFrame *video_frame = NULL;
-    AVFrame *audio_frame = NULL;
-    AVPacket *pkt = NULL;
-    SwsContext *sws_ctx = NULL;
-    SwrContext *swr_ctx = NULL;
-    int video_stream_idx = -1;
-    int audio_stream_idx = -1;
-    int out_video_stream_idx = -1;
-    int out_audio_stream_idx = -1;
-    unsigned char *input_io_buffer = NULL;
-    unsigned char *output_io_buffer = NULL;
-    int ret = 0;
-    int64_t total_frames = 0;
-    int64_t processed_frames = 0;
-    
-    // Allocate result structure
-    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
-    if (!result) {
-        fprintf(stderr, "Failed to allocate result structure\n");
-        return NULL;
+// Pick the supported sample rate closest to the requested one
+static int choose_sample_rate(const AVCodec *encoder, int requested) {
+    if (!encoder->supported_samplerates) {
+        return requested;
     }
     
-    // Set global result for progress tracking
-    global_result = result;
+    int best = encoder->supported_samplerates[0];
+    for (const int *p = encoder->supported_samplerates; *p; p++) {
+        if (*p == requested) {
+            return requested;
+        }
+        if (abs(*p - requested) < abs(best - requested)) {
+            best = *p;
+        }
+    }
     
-    // Initialize result
-    result->success = 0;
-    result->progress = 0;
-    result->output_data = NULL;
-    result->output_size = 0;
-    snprintf(result->error_message, sizeof(result->error_message), "");
+    return best;
+}
+
+// Check whether an encoder accepts the given sample rate
+static int encoder_supports_sample_rate(const AVCodec *encoder, int sample_rate) {
+    return choose_sample_rate(encoder, sample_rate) == sample_rate;
+}
+
+// Find the encoder layout matching the requested one, accepting variants
+// with the same channel count (e.g. "5.1(side)" for "5.1")
+static int choose_channel_layout(const AVCodec *encoder, const AVChannelLayout *requested, AVChannelLayout *chosen) {
+    if (!encoder->ch_layouts) {
+        return av_channel_layout_copy(chosen, requested);
+    }
     
-    // Create input IO context from memory buffer
-    input_io_buffer = av_malloc(input_size + AV_INPUT_BUFFER_PADDING_SIZE);
-    if (!input_io_buffer) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate input buffer");
-        goto cleanup;
+    const AVChannelLayout *fallback = NULL;
+    for (const AVChannelLayout *p = encoder->ch_layouts; p->nb_channels; p++) {
+        if (!av_channel_layout_compare(p, requested)) {
+            return av_channel_layout_copy(chosen, p);
+        }
+        if (!fallback && p->nb_channels == requested->nb_channels) {
+            fallback = p;
+        }
     }
     
-    memcpy(input_io_buffer, input_data, input_size);
-    memset(input_io_buffer + input_size, 0, AV_INPUT_BUFFER_PADDING_SIZE);
+    return fallback ? av_channel_layout_copy(chosen, fallback) : AVERROR(EINVAL);
+}
+
+// List the sample rates supported by an encoder as a comma-separated string
+EMSCRIPTEN_KEEPALIVE
+const char* get_encoder_sample_rates(const char* codec_name) {
+    static char rates[512];
+    const AVCodec *encoder = avcodec_find_encoder_by_name(codec_name);
     
-    input_io_ctx = avio_alloc_context(
-        input_io_buffer,
-        input_size,
-        0,
-        NULL,
-        NULL,  // No read callback needed, using buffer directly
-        NULL,  // No write callback needed for input
-        NULL   // No seek callback needed for this simple case
-    );
+    rates[0] = '\0';
+    if (!encoder || !encoder->supported_samplerates) {
+        return rates;
+    }
     
-    if (!input_io_ctx) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to create input IO context");
-        goto cleanup;
+    for (const int *p = encoder->supported_samplerates; *p; p++) {
+        size_t len = strlen(rates);
+        snprintf(rates + len, sizeof(rates) - len, "%s%d", len ? "," : "", *p);
     }
     
-    // Allocate input format context
-    input_ctx = avformat_alloc_context();
-    if (!input_ctx) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate input context");
-        goto cleanup;
+    return rates;
+}
+
+// List the channel layouts supported by an encoder as a comma-separated string
+EMSCRIPTEN_KEEPALIVE
+const char* get_encoder_channel_layouts(const char* codec_name) {
+    static char layouts[1024];
+    const AVCodec *encoder = avcodec_find_encoder_by_name(codec_name);
+    
+    layouts[0] = '\0';
+    if (!encoder || !encoder->ch_layouts) {
+        return layouts;
     }
     
-    input_ctx->pb = input_io_ctx;
+    for (const AVChannelLayout *p = encoder->ch_layouts; p->nb_channels; p++) {
+        char name[64];
+        if (av_channel_layout_describe(p, name, sizeof(name)) < 0 ||
+            strlen(layouts) + strlen(name) + 2 > sizeof(layouts)) {
+            continue;
+        }
+        
+        if (layouts[0]) {
+            strcat(layouts, ",");
+        }
+        strcat(layouts, name);
+    }
     
-    // Open input
-    ret = avformat_open_input(&input_ctx, NULL, NULL, NULL);
-    if (ret < 0) {
-        av_strerror(ret, result->error_message, sizeof(result->error_message));
-        goto cleanup;
+    return layouts;
+}
+
+// Read one sample from any planar or packed sample format as a double in [-1, 1]
+static double get_sample(uint8_t **data, enum AVSampleFormat fmt, int nb_channels, int channel, int index) {
+    int planar = av_sample_fmt_is_planar(fmt);
+    int plane = planar ? channel : 0;
+    int offset = planar ? index : index * nb_channels + channel;
+    
+    switch (av_get_packed_sample_fmt(fmt)) {
+    case AV_SAMPLE_FMT_U8:  return (((uint8_t*)data[plane])[offset] - 128) / 128.0;
+    case AV_SAMPLE_FMT_S16: return ((int16_t*)data[plane])[offset] / 32768.0;
+    case AV_SAMPLE_FMT_S32: return ((int32_t*)data[plane])[offset] / 2147483648.0;
+    case AV_SAMPLE_FMT_S64: return ((int64_t*)data[plane])[offset] / 9223372036854775808.0;
+    case AV_SAMPLE_FMT_FLT: return ((float*)data[plane])[offset];
+    case AV_SAMPLE_FMT_DBL: return ((double*)data[plane])[offset];
+    default:                return 0.0;
     }
+}
+
+// Write one sample, clipping for integer formats
+static void set_sample(uint8_t **data, enum AVSampleFormat fmt, int nb_channels, int channel, int index, double value) {
+    int planar = av_sample_fmt_is_planar(fmt);
+    int plane = planar ? channel : 0;
+    int offset = planar ? index : index * nb_channels + channel;
     
-    // Find stream info
-    ret = avformat_find_stream_info(input_ctx, NULL);
-    if (ret < 0) {
-        av_strerror(ret, result->error_message, sizeof(result->error_message));
-        goto cleanup;
+    switch (av_get_packed_sample_fmt(fmt)) {
+    case AV_SAMPLE_FMT_U8:  ((uint8_t*)data[plane])[offset] = av_clip_uint8(lrint(value * 128.0) + 128); break;
+    case AV_SAMPLE_FMT_S16: ((int16_t*)data[plane])[offset] = av_clip_int16(lrint(value * 32768.0)); break;
+    case AV_SAMPLE_FMT_S32: ((int32_t*)data[plane])[offset] = av_clipl_int32(llrint(value * 2147483648.0)); break;
+    case AV_SAMPLE_FMT_S64: ((int64_t*)data[plane])[offset] = (int64_t)(av_clipd(value, -1.0, 1.0) * 9223372036854775807.0); break;
+    case AV_SAMPLE_FMT_FLT: ((float*)data[plane])[offset] = (float)value; break;
+    case AV_SAMPLE_FMT_DBL: ((double*)data[plane])[offset] = value; break;
+    default: break;
     }
+}
+
+// ITU-R BS.1770 / EBU R128 loudness meter
+#define LOUDNESS_MAX_CHANNELS 8
+#define LOUDNESS_TP_TAPS 12
+#define LOUDNESS_TP_PHASES 4
+
+typedef struct {
+    int sample_rate;
+    int nb_channels;
+    double channel_weight[LOUDNESS_MAX_CHANNELS];
     
-    // Create output format context
-    ret = avformat_alloc_output_context2(&output_ctx, NULL, output_format, NULL);
-    if (ret < 0 || !output_ctx) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to create output context");
-        goto cleanup;
+    // K-weighting: high-shelf followed by high-pass, per channel state
+    double shelf_b[3], shelf_a[3];
+    double highpass_b[3], highpass_a[3];
+    double state[LOUDNESS_MAX_CHANNELS][4];
+    
+    // 100 ms sub-blocks of channel-weighted mean square energy
+    double block_energy;
+    int block_fill;
+    int block_size;
+    double *blocks;
+    int nb_blocks;
+    int blocks_capacity;
+    
+    // True peak via 4x windowed-sinc oversampling
+    double tp_filter[LOUDNESS_TP_PHASES][LOUDNESS_TP_TAPS];
+    double tp_history[LOUDNESS_MAX_CHANNELS][LOUDNESS_TP_TAPS];
+    double true_peak;
+} LoudnessMeter;
+
+static int loudness_meter_init(LoudnessMeter *meter, int sample_rate, const AVChannelLayout *layout) {
+    memset(meter, 0, sizeof(*meter));
+    
+    if (layout->nb_channels > LOUDNESS_MAX_CHANNELS) {
+        return AVERROR(EINVAL);
     }
     
-    // Create output IO context for memory output
-    output_io_buffer = av_malloc(4096);
-    if (!output_io_buffer) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate output buffer");
-        goto cleanup;
+    meter->sample_rate = sample_rate;
+    meter->nb_channels = layout->nb_channels;
+    meter->block_size = sample_rate / 10;
+    
+    // Surround channels are weighted +1.5 dB, LFE is excluded
+    for (int c = 0; c < meter->nb_channels; c++) {
+        enum AVChannel channel = av_channel_layout_channel_from_index(layout, c);
+        if (channel == AV_CHAN_LOW_FREQUENCY || channel == AV_CHAN_LOW_FREQUENCY_2) {
+            meter->channel_weight[c] = 0.0;
+        } else if (channel == AV_CHAN_SIDE_LEFT || channel == AV_CHAN_SIDE_RIGHT ||
+                   channel == AV_CHAN_BACK_LEFT || channel == AV_CHAN_BACK_RIGHT) {
+            meter->channel_weight[c] = 1.41;
+        } else {
+            meter->channel_weight[c] = 1.0;
+        }
     }
     
-    output_io_ctx = avio_alloc_context(
-        output_io_buffer,
-        4096,
-        1,
-        result,  // User data - pass result struct to write callback
-        NULL,    // No read callback needed for output
-        write_packet,  // Write callback
-        NULL     // No seek callback needed for this simple case
-    );
+    // Pre-filter (high shelf), derived for the actual sample rate
+    double f0 = 1681.974450955533;
+    double gain = 3.999843853973347;
+    double q = 0.7071752369554196;
+    double k = tan(M_PI * f0 / sample_rate);
+    double vh = pow(10.0, gain / 20.0);
+    double vb = pow(vh, 0.4996667741545416);
+    double a0 = 1.0 + k / q + k * k;
+    meter->shelf_b[0] = (vh + vb * k / q + k * k) / a0;
+    meter->shelf_b[1] = 2.0 * (k * k - vh) / a0;
+    meter->shelf_b[2] = (vh - vb * k / q + k * k) / a0;
+    meter->shelf_a[0] = 1.0;
+    meter->shelf_a[1] = 2.0 * (k * k - 1.0) / a0;
+    meter->shelf_a[2] = (1.0 - k / q + k * k) / a0;
     
-    if (!output_io_ctx) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to create output IO context");
-        goto cleanup;
+    // RLB weighting (high-pass)
+    f0 = 38.13547087602444;
+    q = 0.5003270373238773;
+    k = tan(M_PI * f0 / sample_rate);
+    meter->highpass_b[0] = 1.0;
+    meter->highpass_b[1] = -2.0;
+    meter->highpass_b[2] = 1.0;
+    meter->highpass_a[0] = 1.0;
+    meter->highpass_a[1] = 2.0 * (k * k - 1.0) / (1.0 + k / q + k * k);
+    meter->highpass_a[2] = (1.0 - k / q + k * k) / (1.0 + k / q + k * k);
+    
+    // Hann-windowed sinc interpolator, one polyphase branch per oversampled position
+    for (int phase = 0; phase < LOUDNESS_TP_PHASES; phase++) {
+        for (int tap = 0; tap < LOUDNESS_TP_TAPS; tap++) {
+            int n = tap * LOUDNESS_TP_PHASES + phase;
+            double x = (n - (LOUDNESS_TP_TAPS * LOUDNESS_TP_PHASES - 1) / 2.0) / LOUDNESS_TP_PHASES;
+            double sinc = fabs(x) < 1e-9 ? 1.0 : sin(M_PI * x) / (M_PI * x);
+            double window = 0.5 - 0.5 * cos(2.0 * M_PI * (n + 0.5) / (LOUDNESS_TP_TAPS * LOUDNESS_TP_PHASES));
+            meter->tp_filter[phase][tap] = sinc * window;
+        }
     }
     
-    output_ctx->pb = output_io_ctx;
+    return 0;
+}
+
+static void loudness_meter_free(LoudnessMeter *meter) {
+    av_freep(&meter->blocks);
+    meter->nb_blocks = 0;
+    meter->blocks_capacity = 0;
+}
+
+// Feed a frame of audio in any sample format
+static int loudness_meter_add_frame(LoudnessMeter *meter, const AVFrame *frame) {
+    enum AVSampleFormat fmt = frame->format;
     
-    // Find video and audio streams
-    for (unsigned int i = 0; i < input_ctx->nb_streams; i++) {
-        AVStream *in_stream = input_ctx->streams[i];
-        
-        if (in_stream->codecpar->codec_type == AVMEDIA_TYPE_VIDEO && video_stream_idx < 0) {
-            video_stream_idx = i;
-            
-            // Create output video stream
-            AVStream *out_stream = avformat_new_stream(output_ctx, NULL);
-            if (!out_stream) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to create output video stream");
-                goto cleanup;
-            }
-            
-            out_video_stream_idx = out_stream->index;
-            
-            // Find decoder
-            video_decoder = avcodec_find_decoder(in_stream->codecpar->codec_id);
-            if (!video_decoder) {
-                snprintf(result->error_message, sizeof(result->error_message), "Unsupported video codec");
-                goto cleanup;
-            }
-            
-            // Allocate decoder context
-            video_dec_ctx = avcodec_alloc_context3(video_decoder);
-            if (!video_dec_ctx) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate video decoder context");
-                goto cleanup;
-            }
-            
-            // Copy parameters from input stream to decoder context
-            ret = avcodec_parameters_to_context(video_dec_ctx, in_stream->codecpar);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Open decoder
-            ret = avcodec_open2(video_dec_ctx, video_decoder, NULL);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Find encoder
-            video_encoder = avcodec_find_encoder_by_name(video_codec_name);
-            if (!video_encoder) {
-                snprintf(result->error_message, sizeof(result->error_message), "Video encoder '%s' not found", video_codec_name);
-                goto cleanup;
-            }
-            
-            // Allocate encoder context
-            video_enc_ctx = avcodec_alloc_context3(video_encoder);
-            if (!video_enc_ctx) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate video encoder context");
-                goto cleanup;
-            }
-            
-            // Set video encoder parameters
-            int width = video_dec_ctx->width;
-            int height = video_dec_ctx->height;
+    for (int i = 0; i < frame->nb_samples; i++) {
+        for (int c = 0; c < meter->nb_channels; c++) {
+            double x = get_sample((uint8_t**)frame->extended_data, fmt, meter->nb_channels, c, i);
+            double *z = meter->state[c];
             
-            // Apply custom resolution if needed
-            if (resolution && strcmp(resolution, "same") != 0) {
-                if (sscanf(resolution, "%dx%d", &width, &height) != 2) {
-                    // Try preset resolutions
-                    if (strcmp(resolution, "720p") == 0) {
-                        width = 1280;
-                        height = 720;
-                    } else if (strcmp(resolution, "1080p") == 0) {
-                        width = 1920;
-                        height = 1080;
-                    } else if (strcmp(resolution, "480p") == 0) {
-                        width = 854;
-                        height = 480;
-                    } else if (strcmp(resolution, "360p") == 0) {
-                        width = 640;
-                        height = 360;
-                    }
+            // True peak: check the raw sample and the interpolated ones between
+            double *history = meter->tp_history[c];
+            memmove(history + 1, history, (LOUDNESS_TP_TAPS - 1) * sizeof(double));
+            history[0] = x;
+            for (int phase = 0; phase < LOUDNESS_TP_PHASES; phase++) {
+                double y = 0.0;
+                for (int tap = 0; tap < LOUDNESS_TP_TAPS; tap++) {
+                    y += meter->tp_filter[phase][tap] * history[tap];
                 }
+                meter->true_peak = FFMAX(meter->true_peak, fabs(y));
             }
+            meter->true_peak = FFMAX(meter->true_peak, fabs(x));
             
-            video_enc_ctx->height = height;
-            video_enc_ctx->width = width;
-            video_enc_ctx->sample_aspect_ratio = video_dec_ctx->sample_aspect_ratio;
-            video_enc_ctx->time_base = av_inv_q(input_ctx->streams[video_stream_idx]->r_frame_rate);
-            video_enc_ctx->framerate = input_ctx->streams[video_stream_idx]->r_frame_rate;
-            video_enc_ctx->gop_size = 25;
-            video_enc_ctx->max_b_frames = 3;
-            video_enc_ctx->pix_fmt = video_encoder->pix_fmts ? video_encoder->pix_fmts[0] : AV_PIX_FMT_YUV420P;
-            video_enc_ctx->bit_rate = video_bitrate * 1000;
-            
-            if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
-                video_enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
-            }
-            
-            // Open encoder
-            ret = avcodec_open2(video_enc_ctx, video_encoder, NULL);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Copy parameters to output stream
-            ret = avcodec_parameters_from_context(out_stream->codecpar, video_enc_ctx);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            out_stream->time_base = video_enc_ctx->time_base;
+            // Two biquads in transposed direct form II
+            double y1 = meter->shelf_b[0] * x + z[0];
+            z[0] = meter->shelf_b[1] * x - meter->shelf_a[1] * y1 + z[1];
+            z[1] = meter->shelf_b[2] * x - meter->shelf_a[2] * y1;
             
-            // Create scaling context if needed
-            if (video_dec_ctx->width != video_enc_ctx->width ||
-                video_dec_ctx->height != video_enc_ctx->height ||
-                video_dec_ctx->pix_fmt != video_enc_ctx->pix_fmt) {
-                
-                sws_ctx = sws_getContext(
-                    video_dec_ctx->width, video_dec_ctx->height, video_dec_ctx->pix_fmt,
-                    video_enc_ctx->width, video_enc_ctx->height, video_enc_ctx->pix_fmt,
-                    SWS_BICUBIC, NULL, NULL, NULL
-                );
-                
-                if (!sws_ctx) {
-                    snprintf(result->error_message, sizeof(result->error_message), "Failed to create scaling context");
-                    goto cleanup;
-                }
-            }
+            double y2 = meter->highpass_b[0] * y1 + z[2];
+            z[2] = meter->highpass_b[1] * y1 - meter->highpass_a[1] * y2 + z[3];
+            z[3] = meter->highpass_b[2] * y1 - meter->highpass_a[2] * y2;
             
-            // Estimate total frames for progress tracking
-            double duration_seconds = (double)input_ctx->duration / AV_TIME_BASE;
-            double fps = av_q2d(input_ctx->streams[video_stream_idx]->r_frame_rate);
-            total_frames = (int64_t)(duration_seconds * fps);
+            meter->block_energy += meter->channel_weight[c] * y2 * y2;
         }
         
-        else if (in_stream->codecpar->codec_type == AVMEDIA_TYPE_AUDIO && audio_stream_idx < 0) {
-            audio_stream_idx = i;
-            
-            // Create output audio stream
-            AVStream *out_stream = avformat_new_stream(output_ctx, NULL);
-            if (!out_stream) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to create output audio stream");
-                goto cleanup;
-            }
-            
-            out_audio_stream_idx = out_stream->index;
-            
-            // Find decoder
-            audio_decoder = avcodec_find_decoder(in_stream->codecpar->codec_id);
-            if (!audio_decoder) {
-                snprintf(result->error_message, sizeof(result->error_message), "Unsupported audio codec");
-                goto cleanup;
-            }
-            
-            // Allocate decoder context
-            audio_dec_ctx = avcodec_alloc_context3(audio_decoder);
-            if (!audio_dec_ctx) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate audio decoder context");
-                goto cleanup;
-            }
-            
-            // Copy parameters from input stream to decoder context
-            ret = avcodec_parameters_to_context(audio_dec_ctx, in_stream->codecpar);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Open decoder
-            ret = avcodec_open2(audio_dec_ctx, audio_decoder, NULL);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Find encoder
-            audio_encoder = avcodec_find_encoder_by_name(audio_codec_name);
-            if (!audio_encoder) {
-                snprintf(result->error_message, sizeof(result->error_message), "Audio encoder '%s' not found", audio_codec_name);
-                goto cleanup;
-            }
-            
-            // Allocate encoder context
-            audio_enc_ctx = avcodec_alloc_context3(audio_encoder);
-            if (!audio_enc_ctx) {
-                snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate audio encoder context");
-                goto cleanup;
-            }
-            
-            // Set audio encoder parameters
-            audio_enc_ctx->channels = audio_dec_ctx->channels;
-            audio_enc_ctx->channel_layout = av_get_default_channel_layout(audio_dec_ctx->channels);
-            audio_enc_ctx->sample_rate = audio_dec_ctx->sample_rate;
-            audio_enc_ctx->sample_fmt = audio_encoder->sample_fmts ? audio_encoder->sample_fmts[0] : AV_SAMPLE_FMT_FLTP;
-            audio_enc_ctx->time_base = (AVRational){1, audio_dec_ctx->sample_rate};
-            audio_enc_ctx->bit_rate = audio_bitrate * 1000;
-            
-            if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
-                audio_enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
-            }
-            
-            // Open encoder
-            ret = avcodec_open2(audio_enc_ctx, audio_encoder, NULL);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            // Copy parameters to output stream
-            ret = avcodec_parameters_from_context(out_stream->codecpar, audio_enc_ctx);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            out_stream->time_base = audio_enc_ctx->time_base;
-            
-            // Create audio resampling context if needed
-            if (audio_dec_ctx->channel_layout != audio_enc_ctx->channel_layout ||
-                audio_dec_ctx->sample_rate != audio_enc_ctx->sample_rate ||
-                audio_dec_ctx->sample_fmt != audio_enc_ctx->sample_fmt) {
-                
-                swr_ctx = swr_alloc();
-                if (!swr_ctx) {
-                    snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate resampling context");
-                    goto cleanup;
-                }
-                
-                av_opt_set_int(swr_ctx, "in_channel_count", audio_dec_ctx->channels, 0);
-                av_opt_set_int(swr_ctx, "in_sample_rate", audio_dec_ctx->sample_rate, 0);
-                av_opt_set_sample_fmt(swr_ctx, "in_sample_fmt", audio_dec_ctx->sample_fmt, 0);
-                
-                av_opt_set_int(swr_ctx, "out_channel_count", audio_enc_ctx->channels, 0);
-                av_opt_set_int(swr_ctx, "out_sample_rate", audio_enc_ctx->sample_rate, 0);
-                av_opt_set_sample_fmt(swr_ctx, "out_sample_fmt", audio_enc_ctx->sample_fmt, 0);
-                
-                if ((ret = swr_init(swr_ctx)) < 0) {
-                    av_strerror(ret, result->error_message, sizeof(result->error_message));
-                    goto cleanup;
+        if (++meter->block_fill == meter->block_size) {
+            if (meter->nb_blocks == meter->blocks_capacity) {
+                int capacity = meter->blocks_capacity ? meter->blocks_capacity * 2 : 1024;
+                double *blocks = av_realloc_array(meter->blocks, capacity, sizeof(double));
+                if (!blocks) {
+                    return AVERROR(ENOMEM);
                 }
+                meter->blocks = blocks;
+                meter->blocks_capacity = capacity;
             }
+            
+            meter->blocks[meter->nb_blocks++] = meter->block_energy / meter->block_size;
+            meter->block_energy = 0.0;
+            meter->block_fill = 0;
         }
     }
     
-    // Check if we have at least one stream to process
-    if (video_stream_idx < 0 && audio_stream_idx < 0) {
-        snprintf(result->error_message, sizeof(result->error_message), "No audio or video streams found");
-        goto cleanup;
+    return 0;
+}
+
+static double energy_to_lufs(double energy) {
+    return energy > 0.0 ? -0.691 + 10.0 * log10(energy) : -HUGE_VAL;
+}
+
+// Mean energy of the window of `length` sub-blocks ending at `end`
+static double loudness_window_energy(const LoudnessMeter *meter, int end, int length) {
+    double sum = 0.0;
+    for (int i = end - length; i < end; i++) {
+        sum += meter->blocks[i];
+    }
+    return sum / length;
+}
+
+// Loudness over the last 3 seconds, or -inf before enough audio was seen
+static double loudness_meter_short_term(const LoudnessMeter *meter) {
+    if (meter->nb_blocks < 30) {
+        return -HUGE_VAL;
     }
+    return energy_to_lufs(loudness_window_energy(meter, meter->nb_blocks, 30));
+}
+
+// Gated integrated loudness over 400 ms blocks; also returns the relative gate
+static double loudness_meter_integrated(const LoudnessMeter *meter, double *threshold) {
+    double sum = 0.0;
+    int count = 0;
     
-    // Write output header
-    ret = avformat_write_header(output_ctx, NULL);
-    if (ret < 0) {
-        av_strerror(ret, result->error_message, sizeof(result->error_message));
-        goto cleanup;
+    // Absolute gate at -70 LUFS
+    for (int end = 4; end <= meter->nb_blocks; end++) {
+        double energy = loudness_window_energy(meter, end, 4);
+        if (energy_to_lufs(energy) > -70.0) {
+            sum += energy;
+            count++;
+        }
+    }
+    if (!count) {
+        *threshold = -70.0;
+        return -HUGE_VAL;
     }
     
-    // Allocate frames and packet
-    video_frame = av_frame_alloc();
-    audio_frame = av_frame_alloc();
-    pkt = av_packet_alloc();
+    // Relative gate 10 LU below the absolute-gated loudness
+    *threshold = energy_to_lufs(sum / count) - 10.0;
+    sum = 0.0;
+    count = 0;
+    for (int end = 4; end <= meter->nb_blocks; end++) {
+        double energy = loudness_window_energy(meter, end, 4);
+        if (energy_to_lufs(energy) > *threshold) {
+            sum += energy;
+            count++;
+        }
+    }
     
-    if (!video_frame || !audio_frame || !pkt) {
-        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate frames or packet");
-        goto cleanup;
+    return count ? energy_to_lufs(sum / count) : -HUGE_VAL;
+}
+
+static int compare_doubles(const void *a, const void *b) {
+    double x = *(const double*)a;
+    double y = *(const double*)b;
+    return (x > y) - (x < y);
+}
+
+// Loudness range (EBU Tech 3342): spread of gated 3 s short-term loudness
+static double loudness_meter_range(const LoudnessMeter *meter) {
+    int nb_windows = meter->nb_blocks - 29;
+    if (nb_windows <= 0) {
+        return 0.0;
     }
     
-    // Main processing loop
-    while (1) {
-        ret = av_read_frame(input_ctx, pkt);
-        
-        // End of file
-        if (ret == AVERROR_EOF) {
-            // Flush decoders and encoders
-            if (video_dec_ctx) {
-                avcodec_send_packet(video_dec_ctx, NULL);
-                while (1) {
-                    ret = avcodec_receive_frame(video_dec_ctx, video_frame);
-                    if (ret == AVERROR_EOF || ret == AVERROR(EAGAIN)) {
-                        break;
-                    }
-                    
-                    // Scale frame if needed
-                    if (sws_ctx) {
-                        AVFrame *scaled_frame = av_frame_alloc();
-                        scaled_frame->format = video_enc_ctx->pix_fmt;
-                        scaled_frame->width = video_enc_ctx->width;
-                        scaled_frame->height = video_enc_ctx->height;
-                        av_frame_get_buffer(scaled_frame, 0);
-                        
-                        sws_scale(sws_ctx, (const uint8_t* const*)video_frame->data, video_frame->linesize,
-                            0, video_dec_ctx->height, scaled_frame->data, scaled_frame->linesize);
-                            
-                        scaled_frame->pts = av_rescale_q(video_frame->pts, 
-                            input_ctx->streams[video_stream_idx]->time_base, video_enc_ctx->time_base);
-                            
-                        process_packet(output_ctx, video_enc_ctx, scaled_frame, pkt, out_video_stream_idx);
-                        av_frame_free(&scaled_frame);
-                    } else {
-                        video_frame->pts = av_rescale_q(video_frame->pts, 
-                            input_ctx->streams[video_stream_idx]->time_base, video_enc_ctx->time_base);
-                            
-                        process_packet(output_ctx, video_enc_ctx, video_frame, pkt, out_video_stream_idx);
-                    }
-                }
-                
-                // Flush video encoder
-                process_packet(output_ctx, video_enc_ctx, NULL, pkt, out_video_stream_idx);
-            }
-            
-            if (audio_dec_ctx) {
-                avcodec_send_packet(audio_dec_ctx, NULL);
-                while (1) {
-                    ret = avcodec_receive_frame(audio_dec_ctx, audio_frame);
-                    if (ret == AVERROR_EOF || ret == AVERROR(EAGAIN)) {
-                        break;
-                    }
-                    
-                    // Resample frame if needed
-                    if (swr_ctx) {
-                        AVFrame *resampled_frame = av_frame_alloc();
-                        resampled_frame->format = audio_enc_ctx->sample_fmt;
-                        resampled_frame->channel_layout = audio_enc_ctx->channel_layout;
-                        resampled_frame->channels = audio_enc_ctx->channels;
-                        resampled_frame->sample_rate = audio_enc_ctx->sample_rate;
-                        resampled_frame->nb_samples = audio_frame->nb_samples;
-                        av_frame_get_buffer(resampled_frame, 0);
-                        
-                        swr_convert(swr_ctx, resampled_frame->data, resampled_frame->nb_samples,
-                            (const uint8_t**)audio_frame->data, audio_frame->nb_samples);
-                            
-                        resampled_frame->pts = av_rescale_q(audio_frame->pts, 
-                            input_ctx->streams[audio_stream_idx]->time_base, audio_enc_ctx->time_base);
-                            
-                        process_packet(output_ctx, audio_enc_ctx, resampled_frame, pkt, out_audio_stream_idx);
-                        av_frame_free(&resampled_frame);
-                    } else {
-                        audio_frame->pts = av_rescale_q(audio_frame->pts, 
-                            input_ctx->streams[audio_stream_idx]->time_base, audio_enc_ctx->time_base);
-                            
-                        process_packet(output_ctx, audio_enc_ctx, audio_frame, pkt, out_audio_stream_idx);
-                    }
-                }
-                
-                // Flush audio encoder
-                process_packet(output_ctx, audio_enc_ctx, NULL, pkt, out_audio_stream_idx);
-            }
-            
-            break;
-        } else if (ret < 0) {
-            av_strerror(ret, result->error_message, sizeof(result->error_message));
-            goto cleanup;
+    double *values = av_malloc_array(nb_windows, sizeof(double));
+    if (!values) {
+        return 0.0;
+    }
+    
+    double sum = 0.0;
+    int count = 0;
+    for (int end = 30; end <= meter->nb_blocks; end++) {
+        double energy = loudness_window_energy(meter, end, 30);
+        if (energy_to_lufs(energy) > -70.0) {
+            values[count++] = energy;
+            sum += energy;
         }
-        
-        // Process video
-        if (pkt->stream_index == video_stream_idx) {
-            ret = avcodec_send_packet(video_dec_ctx, pkt);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            while (ret >= 0) {
-                ret = avcodec_receive_frame(video_dec_ctx, video_frame);
-                if (ret == AVERROR(EAGAIN) || ret == AVERROR_EOF) {
-                    break;
-                } else if (ret < 0) {
-                    av_strerror(ret, result->error_message, sizeof(result->error_message));
-                    goto cleanup;
-                }
-                
-                processed_frames++;
-                if (total_frames > 0) {
-                    update_progress((int)((processed_frames * 100) / total_frames));
-                }
-                
-                // Scale frame if needed
-                if (sws_ctx) {
-                    AVFrame *scaled_frame = av_frame_alloc();
-                    scaled_frame->format = video_enc_ctx->pix_fmt;
-                    scaled_frame->width = video_enc_ctx->width;
-                    scaled_frame->height = video_enc_ctx->height;
-                    av_frame_get_buffer(scaled_frame, 0);
-                    
-                    sws_scale(sws_ctx, (const uint8_t* const*)video_frame->data, video_frame->linesize,
-                        0, video_dec_ctx->height, scaled_frame->data, scaled_frame->linesize);
-                        
-                    scaled_frame->pts = av_rescale_q(video_frame->pts, 
-                        input_ctx->streams[video_stream_idx]->time_base, video_enc_ctx->time_base);
-                        
-                    process_packet(output_ctx, video_enc_ctx, scaled_frame, pkt, out_video_stream_idx);
-                    av_frame_free(&scaled_frame);
-                } else {
-                    video_frame->pts = av_rescale_q(video_frame->pts, 
-                        input_ctx->streams[video_stream_idx]->time_base, video_enc_ctx->time_base);
-                        
-                    process_packet(output_ctx, video_enc_ctx, video_frame, pkt, out_video_stream_idx);
-                }
+    }
+    
+    double range = 0.0;
+    if (count) {
+        // Relative gate 20 LU below the absolute-gated loudness
+        double threshold = energy_to_lufs(sum / count) - 20.0;
+        int gated = 0;
+        for (int i = 0; i < count; i++) {
+            double lufs = energy_to_lufs(values[i]);
+            if (lufs > threshold) {
+                values[gated++] = lufs;
             }
         }
         
-        // Process audio
-        else if (pkt->stream_index == audio_stream_idx) {
-            ret = avcodec_send_packet(audio_dec_ctx, pkt);
-            if (ret < 0) {
-                av_strerror(ret, result->error_message, sizeof(result->error_message));
-                goto cleanup;
-            }
-            
-            while (ret >= 0) {
-                ret = avcodec_receive_frame(audio_dec_ctx, audio_frame);
-                if (ret == AVERROR(EAGAIN) || ret == AVERROR_EOF) {
-                    break;
-                } else if (ret < 0) {
-                    av_strerror(ret, result->error_message, sizeof(result->error_message));
-                    goto cleanup;
-                }
-                
-                // Resample frame if needed
-                if (swr_ctx) {
-                    AVFrame *resampled_frame = av_frame_alloc();
-                    resampled_frame->format = audio_enc_ctx->sample_fmt;
-                    resampled_frame->channel_layout = audio_enc_ctx->channel_layout;
-                    resampled_frame->channels = audio_enc_ctx->channels;
-                    resampled_frame->sample_rate = audio_enc_ctx->sample_rate;
-                    resampled_frame->nb_samples = audio_enc_ctx->sample_rate * audio_frame->nb_samples / audio_dec_ctx->sample_rate;
-                    av_frame_get_buffer(resampled_frame, 0);
-                    
-                    swr_convert(swr_ctx, resampled_frame->data, resampled_frame->nb_samples,
-                        (const uint8_t**)audio_frame->data, audio_frame->nb_samples);
-                        
-                    resampled_frame->pts = av_rescale_q(audio_frame->pts, 
-                        input_ctx->streams[audio_stream_idx]->time_base, audio_enc_ctx->time_base);
-                        
-                    process_packet(output_ctx, audio_enc_ctx, resampled_frame, pkt, out_audio_stream_idx);
-                    av_frame_free(&resampled_frame);
-                } else {
-                    audio_frame->pts = av_rescale_q(audio_frame->pts, 
-                        input_ctx->streams[audio_stream_idx]->time_base, audio_enc_ctx->time_base);
-                        
-                    process_packet(output_ctx, audio_enc_ctx, audio_frame, pkt, out_audio_stream_idx);
-                }
-            }
+        if (gated) {
+            qsort(values, gated, sizeof(double), compare_doubles);
+            range = values[(int)lrint(0.95 * (gated - 1))] - values[(int)lrint(0.10 * (gated - 1))];
         }
-        
-        av_packet_unref(pkt);
     }
     
-    // Write trailer
-    ret = av_write_trailer(output_ctx);
-    if (ret < 0) {
-        av_strerror(ret, result->error_message, sizeof(result->error_message));
-        goto cleanup;
-    }
+    av_free(values);
+    return range;
+}
+
+static double loudness_meter_true_peak_db(const LoudnessMeter *meter) {
+    return meter->true_peak > 0.0 ? 20.0 * log10(meter->true_peak) : -HUGE_VAL;
+}
+
+// Processing applied to resampled audio just before it is encoded
+typedef struct {
+    // Loudness normalization (second pass)
+    int loudnorm;             // 0 = off, 1 = linear, 2 = dynamic
+    double loudnorm_gain;     // linear gain reaching the target from the measured loudness
+    double target_i;          // target integrated loudness in LUFS
+    double target_tp;         // true peak ceiling in dBTP
+    double target_lra;        // loudness range the dynamic mode lets through, in LU
+    double current_gain;      // smoothed gain in dynamic mode
+    LoudnessMeter meter;      // short-term loudness of the input in dynamic mode
+    
+    // Gain and fades
+    double gain;              // static linear gain
+    int64_t fade_in;          // fade-in length in samples
+    int64_t fade_out;         // fade-out length in samples
+    int64_t duration;         // samples from the first one to the end of the output, 0 if unknown
+    int64_t start_pts;        // pts of the first sample, set from the first frame
+} AudioEffects;
+
+static double db_to_linear(double db) {
+    return pow(10.0, db / 20.0);
+}
+
+// Set up loudness normalization from the first-pass measurements
+static int init_loudnorm(AudioEffects *effects, AVDictionary *options, const AVCodecContext *enc_ctx,
+                         double measured_i, double measured_tp) {
+    const char *mode = get_option(options, "loudnorm", "off");
+    
+    if (strcmp(mode, "off") == 0) {
+        return 0;
+    }
+    
+    effects->loudnorm = strcmp(mode, "dynamic") == 0 ? 2 : 1;
+    effects->target_i = atof(get_option(options, "loudnorm_i", "-16"));
+    effects->target_tp = atof(get_option(options, "loudnorm_tp", "-1"));
+    effects->target_lra = atof(get_option(options, "loudnorm_lra", "11"));
+    
+    // Silent input: nothing to normalize
+    if (!isfinite(measured_i)) {
+        effects->loudnorm = 0;
+        return 0;
+    }
+    
+    // Linear gain, held back so the measured true peak stays under the ceiling
+    double gain_db = effects->target_i - measured_i;
+    if (effects->loudnorm == 1 && isfinite(measured_tp)) {
+        gain_db = FFMIN(gain_db, effects->target_tp - measured_tp);
+    }
+    
+    effects->loudnorm_gain = db_to_linear(gain_db);
+    effects->current_gain = effects->loudnorm_gain;
+    
+    if (effects->loudnorm == 2) {
+        return loudness_meter_init(&effects->meter, enc_ctx->sample_rate, &enc_ctx->ch_layout);
+    }
+    
+    return 0;
+}
+
+// Set up gain and fades; `duration` is the output length in samples (0 if unknown)
+static void init_gain_and_fades(AudioEffects *effects, AVDictionary *options, int sample_rate, int64_t duration) {
+    effects->gain = db_to_linear(atof(get_option(options, "volume", "0")));
+    effects->fade_in = (int64_t)(atof(get_option(options, "fade_in", "0")) * sample_rate);
+    effects->fade_out = (int64_t)(atof(get_option(options, "fade_out", "0")) * sample_rate);
+    effects->duration = duration;
+    effects->start_pts = AV_NOPTS_VALUE;
+}
+
+// Apply the configured effects to an encoder-format frame in place
+static int apply_audio_effects(AudioEffects *effects, AVFrame *frame) {
+    int nb_channels = frame->ch_layout.nb_channels;
+    double gain = 1.0;
+    double ceiling = 1.0;
+    
+    if (effects->loudnorm == 1) {
+        gain = effects->loudnorm_gain;
+    } else if (effects->loudnorm == 2) {
+        // Dynamic: follow short-term loudness toward the target, only correcting
+        // what falls outside half the allowed range around it
+        int ret = loudness_meter_add_frame(&effects->meter, frame);
+        if (ret < 0) {
+            return ret;
+        }
+        
+        double short_term = loudness_meter_short_term(&effects->meter);
+        double base_db = 20.0 * log10(effects->loudnorm_gain);
+        double wanted = effects->loudnorm_gain;
+        if (isfinite(short_term) && short_term > -70.0) {
+            double deviation = short_term + base_db - effects->target_i;
+            double allowed = effects->target_lra / 2.0;
+            double correction = deviation > allowed ? deviation - allowed : deviation < -allowed ? deviation + allowed : 0.0;
+            wanted = db_to_linear(base_db - correction);
+        }
+        
+        // Smooth gain changes over a few frames to avoid pumping
+        effects->current_gain += 0.1 * (wanted - effects->current_gain);
+        gain = effects->current_gain;
+        ceiling = db_to_linear(effects->target_tp);
+    }
+    
+    gain *= effects->gain;
+    
+    if (effects->start_pts == AV_NOPTS_VALUE) {
+        effects->start_pts = frame->pts;
+    }
+    
+    int fading = effects->fade_in > 0 || (effects->fade_out > 0 && effects->duration > 0);
+    if (gain == 1.0 && !fading) {
+        return 0;
+    }
+    
+    // Keep the frame's peak under the true-peak ceiling (instant attack limiter)
+    if (effects->loudnorm == 2) {
+        double peak = 0.0;
+        for (int c = 0; c < nb_channels; c++) {
+            for (int i = 0; i < frame->nb_samples; i++) {
+                peak = FFMAX(peak, fabs(get_sample(frame->extended_data, frame->format, nb_channels, c, i)));
+            }
+        }
+        if (peak * gain > ceiling) {
+            gain = ceiling / peak;
+            effects->current_gain = gain / effects->gain;
+        }
+    }
+    
+    for (int i = 0; i < frame->nb_samples; i++) {
+        double sample_gain = gain;
+        
+        // Linear fades relative to the first sample and the end of the output
+        if (fading) {
+            int64_t position = frame->pts + i - effects->start_pts;
+            if (effects->fade_in > 0 && position < effects->fade_in) {
+                sample_gain *= (double)position / effects->fade_in;
+            }
+            if (effects->fade_out > 0 && effects->duration > 0 && effects->duration - position < effects->fade_out) {
+                sample_gain *= FFMAX(0.0, (double)(effects->duration - position) / effects->fade_out);
+            }
+        }
+        
+        for (int c = 0; c < nb_channels; c++) {
+            double sample = get_sample(frame->extended_data, frame->format, nb_channels, c, i);
+            set_sample(frame->extended_data, frame->format, nb_channels, c, i, sample * sample_gain);
+        }
+    }
+    
+    return 0;
+}
+
+static void free_audio_effects(AudioEffects *effects) {
+    loudness_meter_free(&effects->meter);
+}
+
+// Resample a decoded audio frame (NULL to drain) into the encoder FIFO
+static int resample_audio_frame(
+    SwrContext *swr_ctx,
+    AVCodecContext *enc_ctx,
+    AVFrame *frame,
+    AVAudioFifo *fifo
+) {
+    int ret;
+    
+    // Formats already match, queue the decoded samples as-is
+    if (!swr_ctx) {
+        if (!frame) {
+            return 0;
+        }
+        return av_audio_fifo_write(fifo, (void **)frame->data, frame->nb_samples);
+    }
+    
+    AVFrame *resampled_frame = av_frame_alloc();
+    if (!resampled_frame) {
+        return AVERROR(ENOMEM);
+    }
+    
+    resampled_frame->format = enc_ctx->sample_fmt;
+    resampled_frame->sample_rate = enc_ctx->sample_rate;
+    resampled_frame->nb_samples = swr_get_out_samples(swr_ctx, frame ? frame->nb_samples : 0);
+    ret = av_channel_layout_copy(&resampled_frame->ch_layout, &enc_ctx->ch_layout);
+    if (ret < 0 || resampled_frame->nb_samples <= 0) {
+        av_frame_free(&resampled_frame);
+        return ret;
+    }
+    
+    ret = av_frame_get_buffer(resampled_frame, 0);
+    if (ret < 0) {
+        av_frame_free(&resampled_frame);
+        return ret;
+    }
+    
+    ret = swr_convert(swr_ctx, resampled_frame->data, resampled_frame->nb_samples,
+        frame ? (const uint8_t**)frame->data : NULL, frame ? frame->nb_samples : 0);
+    if (ret > 0) {
+        resampled_frame->nb_samples = ret;
+        ret = av_audio_fifo_write(fifo, (void **)resampled_frame->data, ret);
+    }
+    
+    av_frame_free(&resampled_frame);
+    return ret;
+}
+
+// Encode whole encoder-sized frames from the FIFO; on flush, also the remainder
+static int encode_audio_fifo(
+    AVFormatContext *output_fmt_ctx,
+    AVCodecContext *enc_ctx,
+    AVAudioFifo *fifo,
+    AudioEffects *effects,
+    AVPacket *pkt,
+    int stream_index,
+    int64_t *next_pts,
+    int flush
+) {
+    int ret = 0;
+    int frame_size = enc_ctx->frame_size > 0 ? enc_ctx->frame_size : 1024;
+    
+    if (*next_pts == AV_NOPTS_VALUE) {
+        *next_pts = 0;
+    }
+    
+    while (av_audio_fifo_size(fifo) >= frame_size || (flush && av_audio_fifo_size(fifo) > 0)) {
+        AVFrame *frame = av_frame_alloc();
+        if (!frame) {
+            return AVERROR(ENOMEM);
+        }
+        
+        frame->format = enc_ctx->sample_fmt;
+        frame->sample_rate = enc_ctx->sample_rate;
+        frame->nb_samples = FFMIN(av_audio_fifo_size(fifo), frame_size);
+        ret = av_channel_layout_copy(&frame->ch_layout, &enc_ctx->ch_layout);
+        if (ret >= 0) {
+            ret = av_frame_get_buffer(frame, 0);
+        }
+        if (ret >= 0) {
+            ret = av_audio_fifo_read(fifo, (void **)frame->data, frame->nb_samples);
+        }
+        if (ret >= 0) {
+            frame->pts = *next_pts;
+            *next_pts += frame->nb_samples;
+            ret = apply_audio_effects(effects, frame);
+        }
+        if (ret >= 0) {
+            ret = process_packet(output_fmt_ctx, enc_ctx, frame, pkt, stream_index);
+        }
+        
+        av_frame_free(&frame);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+    
+    return ret;
+}
+
+// ITU-R BS.775 downmix: centre and surrounds at -3 dB, LFE dropped,
+// normalized so the summed channels can't clip
+static void set_downmix_levels(SwrContext *swr_ctx) {
+    av_opt_set_double(swr_ctx, "center_mix_level", M_SQRT1_2, 0);
+    av_opt_set_double(swr_ctx, "surround_mix_level", M_SQRT1_2, 0);
+    av_opt_set_double(swr_ctx, "lfe_mix_level", 0.0, 0);
+    av_opt_set_double(swr_ctx, "rematrix_maxval", 1.0, 0);
+}
+
+// First pass: decode an audio stream (-1 for the main one) into a loudness meter,
+// remixed to `layout` when given so the measurement matches what will be encoded
+static int measure_input_loudness(uint8_t *input_data, size_t input_size, int stream_index, const AVChannelLayout *layout, LoudnessMeter *meter) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    AVCodecContext *dec_ctx = NULL;
+    SwrContext *swr_ctx = NULL;
+    AVChannelLayout source_layout = {0};
+    AVFrame *frame = NULL;
+    AVFrame *remixed_frame = NULL;
+    AVPacket *pkt = NULL;
+    int stream_idx;
+    int ret;
+    
+    ret = open_memory_input(&input, &fmt_ctx);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    stream_idx = av_find_best_stream(fmt_ctx, AVMEDIA_TYPE_AUDIO, stream_index, -1, NULL, 0);
+    if (stream_idx < 0) {
+        ret = stream_idx;
+        goto end;
+    }
+    
+    ret = open_decoder(fmt_ctx->streams[stream_idx], &dec_ctx);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    if (dec_ctx->ch_layout.order == AV_CHANNEL_ORDER_UNSPEC) {
+        av_channel_layout_default(&source_layout, dec_ctx->ch_layout.nb_channels);
+    } else {
+        av_channel_layout_copy(&source_layout, &dec_ctx->ch_layout);
+    }
+    if (!layout) {
+        layout = &source_layout;
+    }
+    
+    ret = loudness_meter_init(meter, dec_ctx->sample_rate, layout);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    if (av_channel_layout_compare(layout, &source_layout)) {
+        ret = swr_alloc_set_opts2(&swr_ctx,
+            layout, AV_SAMPLE_FMT_DBLP, dec_ctx->sample_rate,
+            &source_layout, dec_ctx->sample_fmt, dec_ctx->sample_rate,
+            0, NULL);
+        if (ret < 0) {
+            goto end;
+        }
+        set_downmix_levels(swr_ctx);
+        if ((ret = swr_init(swr_ctx)) < 0) {
+            goto end;
+        }
+    }
+    
+    frame = av_frame_alloc();
+    remixed_frame = av_frame_alloc();
+    pkt = av_packet_alloc();
+    if (!frame || !remixed_frame || !pkt) {
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    while (1) {
+        ret = av_read_frame(fmt_ctx, pkt);
+        if (ret < 0 && ret != AVERROR_EOF) {
+            goto end;
+        }
+        
+        int eof = ret == AVERROR_EOF;
+        if (!eof && pkt->stream_index != stream_idx) {
+            av_packet_unref(pkt);
+            continue;
+        }
+        
+        ret = avcodec_send_packet(dec_ctx, eof ? NULL : pkt);
+        av_packet_unref(pkt);
+        if (ret < 0 && eof) {
+            goto end;
+        }
+        if (ret < 0) {
+            // A damaged packet loses a frame, not the measurement
+            continue;
+        }
+        
+        while ((ret = avcodec_receive_frame(dec_ctx, frame)) >= 0) {
+            if (swr_ctx) {
+                remixed_frame->format = AV_SAMPLE_FMT_DBLP;
+                remixed_frame->sample_rate = dec_ctx->sample_rate;
+                remixed_frame->nb_samples = swr_get_out_samples(swr_ctx, frame->nb_samples);
+                ret = av_channel_layout_copy(&remixed_frame->ch_layout, layout);
+                if (ret >= 0) {
+                    ret = av_frame_get_buffer(remixed_frame, 0);
+                }
+                if (ret >= 0) {
+                    ret = swr_convert(swr_ctx, remixed_frame->data, remixed_frame->nb_samples,
+                        (const uint8_t**)frame->data, frame->nb_samples);
+                }
+                if (ret >= 0) {
+                    remixed_frame->nb_samples = ret;
+                    ret = loudness_meter_add_frame(meter, remixed_frame);
+                }
+                av_frame_unref(remixed_frame);
+            } else {
+                ret = loudness_meter_add_frame(meter, frame);
+            }
+            
+            av_frame_unref(frame);
+            if (ret < 0) {
+                goto end;
+            }
+        }
+        
+        if (ret != AVERROR(EAGAIN) && ret != AVERROR_EOF) {
+            goto end;
+        }
+        if (eof) {
+            break;
+        }
+    }
+    
+    ret = 0;
+    
+end:
+    av_frame_free(&frame);
+    av_frame_free(&remixed_frame);
+    av_packet_free(&pkt);
+    swr_free(&swr_ctx);
+    avcodec_free_context(&dec_ctx);
+    av_channel_layout_uninit(&source_layout);
+    close_memory_input(&fmt_ctx);
+    
+    return ret;
+}
+
+// Measure EBU R128 loudness of the first audio stream. Returns
+// "I=<LUFS>:LRA=<LU>:TP=<dBTP>:thresh=<LUFS>" or "error=<message>".
+EMSCRIPTEN_KEEPALIVE
+const char* measure_loudness(uint8_t* input_data, size_t input_size, const char* options_string) {
+    static char stats[256];
+    AVDictionary *options = NULL;
+    AVChannelLayout layout = {0};
+    LoudnessMeter meter = {0};
+    int ret = 0;
+    
+    if (options_string && options_string[0]) {
+        ret = av_dict_parse_string(&options, options_string, "=", ":", 0);
+    }
+    
+    const char *layout_name = get_option(options, "channel_layout", "same");
+    if (ret >= 0 && strcmp(layout_name, "same") != 0) {
+        ret = av_channel_layout_from_string(&layout, layout_name);
+    }
+    
+    if (ret >= 0) {
+        ret = measure_input_loudness(input_data, input_size, -1, layout.nb_channels ? &layout : NULL, &meter);
+    }
+    
+    if (ret < 0) {
+        char error[200];
+        av_strerror(ret, error, sizeof(error));
+        snprintf(stats, sizeof(stats), "error=%s", error);
+    } else {
+        double threshold;
+        double integrated = loudness_meter_integrated(&meter, &threshold);
+        snprintf(stats, sizeof(stats), "I=%.2f:LRA=%.2f:TP=%.2f:thresh=%.2f",
+            integrated, loudness_meter_range(&meter), loudness_meter_true_peak_db(&meter), threshold);
+    }
+    
+    loudness_meter_free(&meter);
+    av_channel_layout_uninit(&layout);
+    av_dict_free(&options);
+    
+    return stats;
+}
+
+// Text subtitles can be converted between formats, bitmap ones only copied
+static int is_text_subtitle(const AVCodecParameters *codecpar) {
+    const AVCodecDescriptor *desc = avcodec_descriptor_get(codecpar->codec_id);
+    return desc && (desc->props & AV_CODEC_PROP_TEXT_SUB);
+}
+
+// Overlays are blended straight into planar YUV frames of 8 to 16 bits
+static int pix_fmt_supports_blending(enum AVPixelFormat pix_fmt) {
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(pix_fmt);
+    if (!desc || desc->nb_components < 3 || (desc->flags & (AV_PIX_FMT_FLAG_RGB | AV_PIX_FMT_FLAG_PAL | AV_PIX_FMT_FLAG_BE))) {
+        return 0;
+    }
+    
+    for (int i = 0; i < 3; i++) {
+        if (desc->comp[i].plane != i || desc->comp[i].depth < 8 || desc->comp[i].depth > 16) {
+            return 0;
+        }
+    }
+    
+    return 1;
+}
+
+// Blend a solid color through an 8-bit coverage mask onto a YUV frame. A NULL
+// mask fills the whole rectangle. Colors are limited-range BT.601 below HD, BT.709 above.
+static void blend_mask(AVFrame *frame, const uint8_t *mask, int mask_stride, int x, int y, int w, int h,
+                       int r, int g, int b, int opacity) {
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(frame->format);
+    double kr = frame->height > 576 ? 0.2126 : 0.299;
+    double kb = frame->height > 576 ? 0.0722 : 0.114;
+    double luma = kr * r + (1 - kr - kb) * g + kb * b;
+    int color[3] = {
+        (int)lrint(16 + luma * 219 / 255),
+        (int)lrint(128 + (b - luma) / (2 * (1 - kb)) * 224 / 255),
+        (int)lrint(128 + (r - luma) / (2 * (1 - kr)) * 224 / 255),
+    };
+    
+    for (int p = 0; p < 3; p++) {
+        int sw = p ? desc->log2_chroma_w : 0;
+        int sh = p ? desc->log2_chroma_h : 0;
+        int depth = desc->comp[p].depth;
+        int target = color[p] << (depth - 8);
+        int plane_w = AV_CEIL_RSHIFT(frame->width, sw);
+        int plane_h = AV_CEIL_RSHIFT(frame->height, sh);
+        int x0 = FFMAX(x, 0) >> sw;
+        int y0 = FFMAX(y, 0) >> sh;
+        int x1 = FFMIN(AV_CEIL_RSHIFT(x + w, sw), plane_w);
+        int y1 = FFMIN(AV_CEIL_RSHIFT(y + h, sh), plane_h);
+        
+        for (int py = y0; py < y1; py++) {
+            uint8_t *row = frame->data[p] + py * frame->linesize[p];
+            int my = av_clip((py << sh) - y, 0, h - 1);
+            
+            for (int px = x0; px < x1; px++) {
+                // Subsampled planes take the coverage of their top-left luma sample
+                int mx = av_clip((px << sw) - x, 0, w - 1);
+                int alpha = (mask ? mask[my * mask_stride + mx] : 255) * opacity / 255;
+                if (!alpha) {
+                    continue;
+                }
+                
+                if (depth > 8) {
+                    uint16_t *value = (uint16_t *)row + px;
+                    *value = (*value * (255 - alpha) + target * alpha) / 255;
+                } else {
+                    row[px] = (row[px] * (255 - alpha) + target * alpha) / 255;
+                }
+            }
+        }
+    }
+}
+
+// Font compiled into the module by build.rs
+#define BUNDLED_FONT_PATH "/fonts/default.ttf"
+
+typedef struct SubtitleBurner SubtitleBurner;
+
+#ifdef HAVE_LIBASS
+
+struct SubtitleBurner {
+    ASS_Library *library;
+    ASS_Renderer *renderer;
+    ASS_Track *track;
+};
+
+static void free_subtitle_burner(SubtitleBurner **burner) {
+    if (!*burner) {
+        return;
+    }
+    
+    if ((*burner)->track) ass_free_track((*burner)->track);
+    if ((*burner)->renderer) ass_renderer_done((*burner)->renderer);
+    if ((*burner)->library) ass_library_done((*burner)->library);
+    av_freep(burner);
+}
+
+// Decode every event of a subtitle stream into the libass track
+static int load_ass_track(SubtitleBurner *burner, AVFormatContext *fmt_ctx, int stream_index, int64_t offset_ms) {
+    AVStream *stream = fmt_ctx->streams[stream_index];
+    AVCodecContext *dec_ctx = NULL;
+    AVPacket *pkt = av_packet_alloc();
+    int ret;
+    
+    if (!pkt) {
+        return AVERROR(ENOMEM);
+    }
+    
+    ret = open_decoder(stream, &dec_ctx);
+    if (ret < 0) {
+        av_packet_free(&pkt);
+        return ret;
+    }
+    
+    // Text decoders emit ASS events that come with their own style header
+    if (dec_ctx->subtitle_header) {
+        ass_process_codec_private(burner->track, (char *)dec_ctx->subtitle_header, dec_ctx->subtitle_header_size);
+    }
+    
+    while ((ret = av_read_frame(fmt_ctx, pkt)) >= 0) {
+        AVSubtitle sub;
+        int got_subtitle = 0;
+        
+        if (pkt->stream_index == stream_index &&
+            avcodec_decode_subtitle2(dec_ctx, &sub, &got_subtitle, pkt) >= 0 && got_subtitle) {
+            if (sub.pts != AV_NOPTS_VALUE) {
+                int64_t start = av_rescale_q(sub.pts, AV_TIME_BASE_Q, (AVRational){1, 1000}) + sub.start_display_time + offset_ms;
+                int64_t duration = sub.end_display_time - sub.start_display_time;
+                if (duration <= 0 && pkt->duration > 0) {
+                    duration = av_rescale_q(pkt->duration, stream->time_base, (AVRational){1, 1000});
+                }
+                
+                for (unsigned int i = 0; i < sub.num_rects; i++) {
+                    if (sub.rects[i]->ass) {
+                        ass_process_chunk(burner->track, sub.rects[i]->ass, strlen(sub.rects[i]->ass), start, duration);
+                    }
+                }
+            }
+            avsubtitle_free(&sub);
+        }
+        
+        av_packet_unref(pkt);
+    }
+    
+    avcodec_free_context(&dec_ctx);
+    av_packet_free(&pkt);
+    
+    return ret == AVERROR_EOF ? 0 : ret;
+}
+
+// Font size, outline and vertical position overrides, given in output pixels
+// and converted to the script's own resolution
+static void apply_burn_style(SubtitleBurner *burner, AVDictionary *options, int height) {
+    char font_size[32], outline[32], margin[32];
+    char *overrides[5];
+    int nb_overrides = 0;
+    int play_res_y = burner->track->PlayResY > 0 ? burner->track->PlayResY : 288;
+    double scale = (double)play_res_y / height;
+    
+    if (av_dict_get(options, "burn_font_size", NULL, 0)) {
+        snprintf(font_size, sizeof(font_size), "FontSize=%g", atof(get_option(options, "burn_font_size", "0")) * scale);
+        overrides[nb_overrides++] = font_size;
+    }
+    
+    if (av_dict_get(options, "burn_outline", NULL, 0)) {
+        snprintf(outline, sizeof(outline), "Outline=%g", atof(get_option(options, "burn_outline", "0")) * scale);
+        overrides[nb_overrides++] = outline;
+    }
+    
+    // Position is the distance of the bottom line from the bottom edge, in percent of the height
+    if (av_dict_get(options, "burn_position", NULL, 0)) {
+        snprintf(margin, sizeof(margin), "MarginV=%d", (int)(play_res_y * atof(get_option(options, "burn_position", "0")) / 100));
+        overrides[nb_overrides++] = margin;
+        overrides[nb_overrides++] = "Alignment=2";
+    }
+    
+    overrides[nb_overrides] = NULL;
+    
+    if (nb_overrides) {
+        ass_set_style_overrides(burner->library, overrides);
+        ass_process_force_style(burner->track);
+    }
+}
+
+// Set up rendering of a subtitle track of the input ("burn_subtitles=<index>")
+// or of an uploaded file ("burn_subtitles_input=<input>") onto encoded frames
+static int init_subtitle_burner(
+    SubtitleBurner **burner_ptr,
+    uint8_t *input_data,
+    size_t input_size,
+    int64_t input_start_time,
+    AVDictionary *options,
+    const AVCodecContext *enc_ctx,
+    char *error,
+    size_t error_size
+) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    int64_t offset_ms = 0;
+    int stream_index;
+    int ret;
+    
+    if (!pix_fmt_supports_blending(enc_ctx->pix_fmt)) {
+        snprintf(error, error_size, "Subtitle burn-in needs a planar YUV pixel format, not %s", av_get_pix_fmt_name(enc_ctx->pix_fmt));
+        return AVERROR(EINVAL);
+    }
+    
+    SubtitleBurner *burner = av_mallocz(sizeof(*burner));
+    *burner_ptr = burner;
+    if (!burner) {
+        snprintf(error, error_size, "Failed to allocate subtitle renderer");
+        return AVERROR(ENOMEM);
+    }
+    
+    burner->library = ass_library_init();
+    burner->renderer = burner->library ? ass_renderer_init(burner->library) : NULL;
+    burner->track = burner->library ? ass_new_track(burner->library) : NULL;
+    if (!burner->renderer || !burner->track) {
+        snprintf(error, error_size, "Failed to initialize libass");
+        return AVERROR(ENOMEM);
+    }
+    
+    ass_set_frame_size(burner->renderer, enc_ctx->width, enc_ctx->height);
+    ass_set_storage_size(burner->renderer, enc_ctx->width, enc_ctx->height);
+    ass_set_fonts(burner->renderer, BUNDLED_FONT_PATH, NULL, ASS_FONTPROVIDER_NONE, NULL, 0);
+    
+    // Open the subtitle source separately from the main demuxing pass
+    if (av_dict_get(options, "burn_subtitles_input", NULL, 0)) {
+        ret = open_extra_input(atoi(get_option(options, "burn_subtitles_input", "0")), &fmt_ctx);
+        stream_index = ret < 0 ? ret : av_find_best_stream(fmt_ctx, AVMEDIA_TYPE_SUBTITLE, -1, -1, NULL, 0);
+        
+        // Subtitle files start at zero, the input may not
+        if (input_start_time != AV_NOPTS_VALUE) {
+            offset_ms = av_rescale(input_start_time, 1000, AV_TIME_BASE);
+        }
+    } else {
+        ret = open_memory_input(&input, &fmt_ctx);
+        stream_index = atoi(get_option(options, "burn_subtitles", "-1"));
+        if (ret >= 0 && (stream_index < 0 || stream_index >= (int)fmt_ctx->nb_streams ||
+            fmt_ctx->streams[stream_index]->codecpar->codec_type != AVMEDIA_TYPE_SUBTITLE)) {
+            stream_index = AVERROR(EINVAL);
+        }
+    }
+    
+    if (ret < 0 || stream_index < 0) {
+        close_memory_input(&fmt_ctx);
+        snprintf(error, error_size, "No subtitles found to burn in");
+        return ret < 0 ? ret : AVERROR(EINVAL);
+    }
+    
+    if (!is_text_subtitle(fmt_ctx->streams[stream_index]->codecpar)) {
+        close_memory_input(&fmt_ctx);
+        snprintf(error, error_size, "Only text subtitles can be burned in");
+        return AVERROR(EINVAL);
+    }
+    
+    ret = load_ass_track(burner, fmt_ctx, stream_index, offset_ms);
+    close_memory_input(&fmt_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    apply_burn_style(burner, options, enc_ctx->height);
+    
+    return 0;
+}
+
+// Draw the subtitles active at `time_ms` onto a frame
+static void render_subtitles(SubtitleBurner *burner, AVFrame *frame, int64_t time_ms) {
+    int changed;
+    
+    for (ASS_Image *img = ass_render_frame(burner->renderer, burner->track, time_ms, &changed); img; img = img->next) {
+        // libass colors are RGBT, with transparency instead of alpha
+        blend_mask(frame, img->bitmap, img->stride, img->dst_x, img->dst_y, img->w, img->h,
+            img->color >> 24, (img->color >> 16) & 0xff, (img->color >> 8) & 0xff, 255 - (img->color & 0xff));
+    }
+}
+#else
+static void free_subtitle_burner(SubtitleBurner **burner) {
+}
+
+static int init_subtitle_burner(
+    SubtitleBurner **burner_ptr,
+    uint8_t *input_data,
+    size_t input_size,
+    int64_t input_start_time,
+    AVDictionary *options,
+    const AVCodecContext *enc_ctx,
+    char *error,
+    size_t error_size
+) {
+    snprintf(error, error_size, "Subtitle burn-in is not available in this build (enable the burn-subtitles feature)");
+    return AVERROR(ENOSYS);
+}
+
+static void render_subtitles(SubtitleBurner *burner, AVFrame *frame, int64_t time_ms) {
+}
+#endif
+
+typedef struct TextOverlay TextOverlay;
+
+#ifdef HAVE_FREETYPE
+#define TEXT_OVERLAY_MAX_SIZE 1024
+
+struct TextOverlay {
+    FT_Library library;
+    FT_Face face;
+    char *text;
+    char *filename;
+    char position[16];
+    int color[4];
+    int box_color[4];
+    int box;
+    AVRational frame_rate;
+    int64_t start_ms;
+    int64_t frame_number;
+};
+
+static void free_text_overlay(TextOverlay **overlay) {
+    if (!*overlay) {
+        return;
+    }
+    
+    if ((*overlay)->face) FT_Done_Face((*overlay)->face);
+    if ((*overlay)->library) FT_Done_FreeType((*overlay)->library);
+    av_freep(&(*overlay)->text);
+    av_freep(&(*overlay)->filename);
+    av_freep(overlay);
+}
+
+// Parse "RRGGBB" or "RRGGBBAA", with or without a leading '#'
+static int parse_color(const char *str, int rgba[4]) {
+    unsigned int value;
+    char *end;
+    size_t len;
+    
+    if (*str == '#') {
+        str++;
+    }
+    len = strlen(str);
+    if (len != 6 && len != 8) {
+        return AVERROR(EINVAL);
+    }
+    
+    value = strtoul(str, &end, 16);
+    if (*end) {
+        return AVERROR(EINVAL);
+    }
+    if (len == 6) {
+        value = (value << 8) | 0xff;
+    }
+    
+    rgba[0] = value >> 24;
+    rgba[1] = (value >> 16) & 0xff;
+    rgba[2] = (value >> 8) & 0xff;
+    rgba[3] = value & 0xff;
+    return 0;
+}
+
+// Load the bundled font at a pixel size
+static int load_overlay_font(TextOverlay *overlay, int font_size, char *error, size_t error_size) {
+    if (FT_Init_FreeType(&overlay->library) || FT_New_Face(overlay->library, BUNDLED_FONT_PATH, 0, &overlay->face)) {
+        snprintf(error, error_size, "Failed to load the overlay font");
+        return AVERROR_EXTERNAL;
+    }
+    
+    FT_Set_Pixel_Sizes(overlay->face, 0, font_size);
+    return 0;
+}
+
+// Set up drawing of the "drawtext" template onto encoded frames
+static int init_text_overlay(
+    TextOverlay **overlay_ptr,
+    AVDictionary *options,
+    const AVStream *in_stream,
+    const AVCodecContext *enc_ctx,
+    char *error,
+    size_t error_size
+) {
+    int font_size = atoi(get_option(options, "drawtext_size", "0"));
+    
+    if (!pix_fmt_supports_blending(enc_ctx->pix_fmt)) {
+        snprintf(error, error_size, "Text overlay needs a planar YUV pixel format, not %s", av_get_pix_fmt_name(enc_ctx->pix_fmt));
+        return AVERROR(EINVAL);
+    }
+    
+    TextOverlay *overlay = av_mallocz(sizeof(*overlay));
+    *overlay_ptr = overlay;
+    if (!overlay) {
+        snprintf(error, error_size, "Failed to allocate text overlay");
+        return AVERROR(ENOMEM);
+    }
+    
+    overlay->text = av_strdup(get_option(options, "drawtext", ""));
+    overlay->filename = av_strdup(get_option(options, "drawtext_filename", ""));
+    if (!overlay->text || !overlay->filename) {
+        snprintf(error, error_size, "Failed to allocate text overlay");
+        return AVERROR(ENOMEM);
+    }
+    
+    snprintf(overlay->position, sizeof(overlay->position), "%s", get_option(options, "drawtext_position", "bottom-left"));
+    overlay->box = av_dict_get(options, "drawtext_box", NULL, 0) != NULL;
+    if (parse_color(get_option(options, "drawtext_color", "ffffff"), overlay->color) < 0 ||
+        (overlay->box && parse_color(get_option(options, "drawtext_box", ""), overlay->box_color) < 0)) {
+        snprintf(error, error_size, "Invalid text overlay color");
+        return AVERROR(EINVAL);
+    }
+    
+    // Timecodes count frames at the output rate
+    overlay->frame_rate = enc_ctx->framerate.num > 0 ? enc_ctx->framerate : in_stream->avg_frame_rate;
+    if (overlay->frame_rate.num <= 0 || overlay->frame_rate.den <= 0) {
+        overlay->frame_rate = (AVRational){25, 1};
+    }
+    overlay->start_ms = in_stream->start_time == AV_NOPTS_VALUE ? 0 :
+        av_rescale_q(in_stream->start_time, in_stream->time_base, (AVRational){1, 1000});
+    
+    // Default to a twentieth of the frame height
+    if (font_size <= 0) {
+        font_size = FFMAX(enc_ctx->height / 20, 8);
+    }
+    
+    return load_overlay_font(overlay, font_size, error, error_size);
+}
+
+// Set up drawing of plain text (labels, headers) without a template. The box is
+// left out when box_color is NULL.
+static int init_label_overlay(
+    TextOverlay **overlay_ptr,
+    int font_size,
+    const char *position,
+    const char *color,
+    const char *box_color,
+    char *error,
+    size_t error_size
+) {
+    TextOverlay *overlay = av_mallocz(sizeof(*overlay));
+    *overlay_ptr = overlay;
+    if (!overlay) {
+        snprintf(error, error_size, "Failed to allocate text overlay");
+        return AVERROR(ENOMEM);
+    }
+    
+    snprintf(overlay->position, sizeof(overlay->position), "%s", position);
+    overlay->box = box_color != NULL;
+    if (parse_color(color, overlay->color) < 0 || (box_color && parse_color(box_color, overlay->box_color) < 0)) {
+        snprintf(error, error_size, "Invalid text overlay color");
+        return AVERROR(EINVAL);
+    }
+    
+    return load_overlay_font(overlay, FFMAX(font_size, 8), error, error_size);
+}
+
+// Height of one line of text in pixels
+static int text_line_height(const TextOverlay *overlay) {
+    return overlay->face->size->metrics.height >> 6;
+}
+
+// Replace {filename}, {pts}, {frame} and {timecode} in the template
+static void expand_text_template(const TextOverlay *overlay, int64_t time_ms, char *out, size_t out_size) {
+    static const struct { const char *name; int len; } variables[] = {
+        { "{filename}", 10 }, { "{pts}", 5 }, { "{frame}", 7 }, { "{timecode}", 10 },
+    };
+    int64_t elapsed_ms = FFMAX(time_ms - overlay->start_ms, 0);
+    size_t len = 0;
+    
+    out[0] = '\0';
+    for (const char *p = overlay->text; *p && len + 1 < out_size; ) {
+        int variable = -1;
+        
+        for (int i = 0; i < (int)FF_ARRAY_ELEMS(variables) && variable < 0; i++) {
+            if (!strncmp(p, variables[i].name, variables[i].len)) {
+                variable = i;
+            }
+        }
+        
+        if (variable < 0) {
+            out[len++] = *p++;
+            out[len] = '\0';
+            continue;
+        }
+        
+        switch (variable) {
+        case 0:
+            snprintf(out + len, out_size - len, "%s", overlay->filename);
+            break;
+        case 1:
+            snprintf(out + len, out_size - len, "%.3f", elapsed_ms / 1000.0);
+            break;
+        case 2:
+            snprintf(out + len, out_size - len, "%" PRId64, overlay->frame_number);
+            break;
+        case 3: {
+            // HH:MM:SS:FF, non-drop-frame at the rounded frame rate
+            int fps = FFMAX((int)lrint(av_q2d(overlay->frame_rate)), 1);
+            int64_t frames = av_rescale_q(elapsed_ms, (AVRational){1, 1000}, av_inv_q(overlay->frame_rate));
+            int64_t seconds = frames / fps;
+            snprintf(out + len, out_size - len, "%02d:%02d:%02d:%02d",
+                (int)(seconds / 3600), (int)(seconds / 60 % 60), (int)(seconds % 60), (int)(frames % fps));
+            break;
+        }
+        }
+        
+        len += strlen(out + len);
+        p += variables[variable].len;
+    }
+}
+
+// Decode one UTF-8 character, falling back to the raw byte on invalid input
+static uint32_t next_utf8_char(const char **str) {
+    const uint8_t *p = (const uint8_t *)*str;
+    int extra = p[0] >= 0xf0 ? 3 : p[0] >= 0xe0 ? 2 : p[0] >= 0xc0 ? 1 : 0;
+    uint32_t code = extra ? p[0] & (0x3f >> extra) : p[0];
+    
+    for (int i = 1; i <= extra; i++) {
+        if ((p[i] & 0xc0) != 0x80) {
+            *str += 1;
+            return p[0];
+        }
+        code = (code << 6) | (p[i] & 0x3f);
+    }
+    
+    *str += extra + 1;
+    return code;
+}
+
+// Width in pixels of one line of text
+static int measure_text_line(FT_Face face, const char *line, const char *end) {
+    int width = 0;
+    
+    while (line < end) {
+        if (!FT_Load_Char(face, next_utf8_char(&line), FT_LOAD_DEFAULT)) {
+            width += face->glyph->advance.x >> 6;
+        }
+    }
+    
+    return width;
+}
+
+// Draw text onto a frame at the overlay's position, one line per '\n'
+static void draw_text_block(TextOverlay *overlay, AVFrame *frame, const char *text) {
+    FT_Face face = overlay->face;
+    int line_height = face->size->metrics.height >> 6;
+    int ascender = face->size->metrics.ascender >> 6;
+    int margin = line_height / 2;
+    int padding = line_height / 4;
+    int nb_lines = 1, block_width = 0;
+    int x, y;
+    
+    for (const char *line = text, *end; ; line = end + 1) {
+        end = strchr(line, '\n');
+        block_width = FFMAX(block_width, measure_text_line(face, line, end ? end : line + strlen(line)));
+        if (!end) {
+            break;
+        }
+        nb_lines++;
+    }
+    
+    // Position names combine top/bottom with left/right; anything else is centered
+    x = strstr(overlay->position, "left") ? margin :
+        strstr(overlay->position, "right") ? frame->width - margin - block_width : (frame->width - block_width) / 2;
+    y = strstr(overlay->position, "top") ? margin :
+        strstr(overlay->position, "bottom") ? frame->height - margin - nb_lines * line_height : (frame->height - nb_lines * line_height) / 2;
+    
+    if (overlay->box) {
+        blend_mask(frame, NULL, 0, x - padding, y - padding, block_width + 2 * padding, nb_lines * line_height + 2 * padding,
+            overlay->box_color[0], overlay->box_color[1], overlay->box_color[2], overlay->box_color[3]);
+    }
+    
+    for (const char *line = text, *end; ; line = end + 1, y += line_height) {
+        const char *line_end;
+        int pen_x;
+        
+        end = strchr(line, '\n');
+        line_end = end ? end : line + strlen(line);
+        
+        // Lines follow the block's horizontal alignment
+        pen_x = strstr(overlay->position, "left") ? x :
+            strstr(overlay->position, "right") ? x + block_width - measure_text_line(face, line, line_end) :
+            x + (block_width - measure_text_line(face, line, line_end)) / 2;
+        
+        while (line < line_end) {
+            FT_GlyphSlot glyph;
+            
+            if (FT_Load_Char(face, next_utf8_char(&line), FT_LOAD_RENDER)) {
+                continue;
+            }
+            
+            glyph = face->glyph;
+            blend_mask(frame, glyph->bitmap.buffer, glyph->bitmap.pitch,
+                pen_x + glyph->bitmap_left, y + ascender - glyph->bitmap_top, glyph->bitmap.width, glyph->bitmap.rows,
+                overlay->color[0], overlay->color[1], overlay->color[2], overlay->color[3]);
+            pen_x += glyph->advance.x >> 6;
+        }
+        
+        if (!end) {
+            break;
+        }
+    }
+}
+
+// Draw the expanded template onto a frame
+static void render_text_overlay(TextOverlay *overlay, AVFrame *frame, int64_t time_ms) {
+    char text[TEXT_OVERLAY_MAX_SIZE];
+    
+    expand_text_template(overlay, time_ms, text, sizeof(text));
+    overlay->frame_number++;
+    draw_text_block(overlay, frame, text);
+}
+#else
+static void free_text_overlay(TextOverlay **overlay) {
+}
+
+static int init_text_overlay(
+    TextOverlay **overlay_ptr,
+    AVDictionary *options,
+    const AVStream *in_stream,
+    const AVCodecContext *enc_ctx,
+    char *error,
+    size_t error_size
+) {
+    snprintf(error, error_size, "Text overlay is not available in this build (enable the drawtext feature)");
+    return AVERROR(ENOSYS);
+}
+
+static void render_text_overlay(TextOverlay *overlay, AVFrame *frame, int64_t time_ms) {
+}
+
+static int init_label_overlay(
+    TextOverlay **overlay_ptr,
+    int font_size,
+    const char *position,
+    const char *color,
+    const char *box_color,
+    char *error,
+    size_t error_size
+) {
+    snprintf(error, error_size, "Text labels are not available in this build (enable the drawtext feature)");
+    return AVERROR(ENOSYS);
+}
+
+static int text_line_height(const TextOverlay *overlay) {
+    return 0;
+}
+
+static void draw_text_block(TextOverlay *overlay, AVFrame *frame, const char *text) {
+}
+#endif
+
+// Optional components compiled into this build, comma separated
+EMSCRIPTEN_KEEPALIVE
+const char* get_build_features() {
+    return ""
+#ifdef HAVE_LIBASS
+        "libass,"
+#endif
+#ifdef HAVE_FREETYPE
+        "freetype,"
+#endif
+        ;
+}
+
+// Upper bound on output streams in one job
+#define MAX_OUTPUT_STREAMS 32
+
+// Per output stream state: either a stream copy or a decode/encode pipeline
+typedef struct {
+    AVStream *in_stream;
+    AVStream *out_stream;
+    int copy;
+    AVCodecContext *dec_ctx;
+    AVCodecContext *enc_ctx;
+    
+    // Video
+    SwsContext *sws_ctx;
+    
+    // Audio
+    SwrContext *swr_ctx;
+    AVAudioFifo *fifo;
+    AVChannelLayout source_layout;
+    AudioEffects effects;
+    int64_t next_pts;
+    
+    // Streams read from an extra input rather than the main one
+    AVFormatContext *input_ctx;
+    AVPacket *next_pkt;
+    
+    // Subtitles and text rendered onto the video
+    SubtitleBurner *burner;
+    TextOverlay *text_overlay;
+} OutputStream;
+
+static void free_output_stream(OutputStream *ost) {
+    free_subtitle_burner(&ost->burner);
+    free_text_overlay(&ost->text_overlay);
+    close_memory_input(&ost->input_ctx);
+    av_packet_free(&ost->next_pkt);
+    avcodec_free_context(&ost->dec_ctx);
+    avcodec_free_context(&ost->enc_ctx);
+    if (ost->sws_ctx) sws_freeContext(ost->sws_ctx);
+    swr_free(&ost->swr_ctx);
+    if (ost->fifo) av_audio_fifo_free(ost->fifo);
+    av_channel_layout_uninit(&ost->source_layout);
+    free_audio_effects(&ost->effects);
+}
+
+// Parse a comma-separated list of stream indexes, returning how many were read
+static int parse_index_list(const char *list, int *indexes, int max_indexes) {
+    int count = 0;
+    
+    while (list && *list && count < max_indexes) {
+        char *end;
+        long value = strtol(list, &end, 10);
+        if (end == list) {
+            break;
+        }
+        
+        indexes[count++] = (int)value;
+        if (*end != ',') {
+            break;
+        }
+        list = end + 1;
+    }
+    
+    return count;
+}
+
+static int index_in_list(int index, const int *indexes, int count) {
+    for (int i = 0; i < count; i++) {
+        if (indexes[i] == index) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+// Containers that can hold only one stream of each type
+static int format_allows_multiple_streams(const AVOutputFormat *oformat) {
+    static const char *single_stream_formats[] = { "mp3", "wav", "adts", "flac", "gif", "srt", "webvtt", "ass", NULL };
+    
+    for (int i = 0; single_stream_formats[i]; i++) {
+        if (strcmp(oformat->name, single_stream_formats[i]) == 0) {
+            return 0;
+        }
+    }
+    
+    return 1;
+}
+
+// Muxers that write a set of files into the output file registry instead of one output
+static int format_writes_files(const AVOutputFormat *oformat) {
+    return strcmp(oformat->name, "hls") == 0 || strcmp(oformat->name, "dash") == 0;
+}
+
+// Muxers of the MP4 family, which take "movflags"
+static int format_has_movflags(const AVOutputFormat *oformat) {
+    static const char *mov_formats[] = { "mp4", "ipod", "mov", NULL };
+    
+    for (int i = 0; mov_formats[i]; i++) {
+        if (strcmp(oformat->name, mov_formats[i]) == 0) {
+            return 1;
+        }
+    }
+    
+    return 0;
+}
+
+// MP4 layout: the index moved to the front once the file is written (faststart),
+// or fragments after an empty index for players that stream them. The movflags go
+// to avformat_write_header() with the other muxer options.
+static int setup_mp4_layout(AVFormatContext *output_ctx, AVDictionary *options, AVDictionary **mux_options,
+                            char *error, size_t error_size) {
+    char movflags[128] = "";
+    int faststart = atoi(get_option(options, "mp4_faststart", "0"));
+    int fragmented = atoi(get_option(options, "mp4_fragmented", "0"));
+    int default_base_moof = atoi(get_option(options, "mp4_default_base_moof", "0"));
+    
+    if (!faststart && !fragmented && !default_base_moof) {
+        return 0;
+    }
+    if (!format_has_movflags(output_ctx->oformat)) {
+        snprintf(error, error_size, "%s has no MP4 layout options", output_ctx->oformat->name);
+        return AVERROR(EINVAL);
+    }
+    // The empty index of a fragmented file is already at the front
+    if (faststart && fragmented) {
+        snprintf(error, error_size, "Faststart and fragmented output can't be combined");
+        return AVERROR(EINVAL);
+    }
+    
+    if (fragmented) {
+        av_strlcat(movflags, "+frag_keyframe+empty_moov", sizeof(movflags));
+    }
+    if (default_base_moof) {
+        av_strlcat(movflags, "+default_base_moof", sizeof(movflags));
+    }
+    if (faststart) {
+        // The second pass shifts the media data up by reading the output back
+        av_strlcat(movflags, "+faststart", sizeof(movflags));
+        output_ctx->io_open = open_output_reader;
+        output_ctx->io_close2 = close_output_reader;
+    }
+    
+    av_dict_set(mux_options, "movflags", movflags, 0);
+    return 0;
+}
+
+// Write the header of a single-file output, with the MP4 layout from the options
+static int write_output_header(AVFormatContext *output_ctx, AVDictionary *options, char *error, size_t error_size) {
+    AVDictionary *mux_options = NULL;
+    
+    int ret = setup_mp4_layout(output_ctx, options, &mux_options, error, error_size);
+    if (ret >= 0) {
+        ret = avformat_write_header(output_ctx, &mux_options);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+        }
+    }
+    
+    av_dict_free(&mux_options);
+    return ret;
+}
+
+// Containers that can carry a JPEG/PNG cover picture
+static int format_supports_cover(const AVOutputFormat *oformat) {
+    static const char *cover_formats[] = { "mp3", "flac", "mp4", "ipod", "mov", "matroska", "ogg", "opus", NULL };
+    
+    for (int i = 0; cover_formats[i]; i++) {
+        if (strcmp(oformat->name, cover_formats[i]) == 0) {
+            return 1;
+        }
+    }
+    
+    return 0;
+}
+
+// Whether an output already has a stream of a type
+static int has_stream_type(const OutputStream *output_streams, int nb_output_streams, enum AVMediaType type) {
+    for (int o = 0; o < nb_output_streams; o++) {
+        if (output_streams[o].in_stream->codecpar->codec_type == type) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+// Create the output stream for an input track, keeping what identifies it
+static OutputStream* add_output_stream(AVFormatContext *output_ctx, OutputStream *output_streams, int *nb_output_streams, AVStream *in_stream) {
+    if (*nb_output_streams >= MAX_OUTPUT_STREAMS) {
+        return NULL;
+    }
+    
+    OutputStream *ost = &output_streams[*nb_output_streams];
+    ost->out_stream = avformat_new_stream(output_ctx, NULL);
+    if (!ost->out_stream) {
+        return NULL;
+    }
+    ost->in_stream = in_stream;
+    (*nb_output_streams)++;
+    
+    AVDictionaryEntry *language = av_dict_get(in_stream->metadata, "language", NULL, 0);
+    AVDictionaryEntry *title = av_dict_get(in_stream->metadata, "title", NULL, 0);
+    if (language) av_dict_set(&ost->out_stream->metadata, "language", language->value, 0);
+    if (title) av_dict_set(&ost->out_stream->metadata, "title", title->value, 0);
+    
+    ost->out_stream->disposition = in_stream->disposition;
+    
+    return ost;
+}
+
+// Pass a stream through to the output without decoding
+static int setup_copy_stream(OutputStream *ost, AVFormatContext *output_ctx, char *error, size_t error_size) {
+    AVStream *in_stream = ost->in_stream;
+    AVStream *out_stream = ost->out_stream;
+    
+    // avformat_query_codec returns 0 only when the muxer definitely can't hold the codec
+    if (avformat_query_codec(output_ctx->oformat, in_stream->codecpar->codec_id, FF_COMPLIANCE_NORMAL) == 0) {
+        snprintf(error, error_size, "Stream %d (%s) can't be copied into %s",
+            in_stream->index, avcodec_get_name(in_stream->codecpar->codec_id), output_ctx->oformat->name);
+        return AVERROR(EINVAL);
+    }
+    
+    int ret = avcodec_parameters_copy(out_stream->codecpar, in_stream->codecpar);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    // Codec tags are container-specific, let the muxer choose
+    out_stream->codecpar->codec_tag = 0;
+    out_stream->time_base = in_stream->time_base;
+    out_stream->sample_aspect_ratio = in_stream->sample_aspect_ratio;
+    ost->copy = 1;
+    
+    return 0;
+}
+
+// Apply a "WxH" or preset resolution; "same" leaves the size alone
+static void parse_resolution(const char *resolution, int *width, int *height) {
+    if (!resolution || strcmp(resolution, "same") == 0) {
+        return;
+    }
+    
+    if (sscanf(resolution, "%dx%d", width, height) != 2) {
+        // Try preset resolutions
+        if (strcmp(resolution, "720p") == 0) {
+            *width = 1280;
+            *height = 720;
+        } else if (strcmp(resolution, "1080p") == 0) {
+            *width = 1920;
+            *height = 1080;
+        } else if (strcmp(resolution, "480p") == 0) {
+            *width = 854;
+            *height = 480;
+        } else if (strcmp(resolution, "360p") == 0) {
+            *width = 640;
+            *height = 360;
+        }
+    }
+}
+
+// Set up decoding, scaling and encoding for a video stream
+static int setup_video_stream(
+    OutputStream *ost,
+    AVFormatContext *output_ctx,
+    const char *video_codec_name,
+    int video_bitrate,
+    const char *resolution,
+    AVDictionary *options,
+    char *error,
+    size_t error_size
+) {
+    AVStream *in_stream = ost->in_stream;
+    int ret;
+    
+    ret = open_decoder(in_stream, &ost->dec_ctx);
+    if (ret == AVERROR_DECODER_NOT_FOUND) {
+        snprintf(error, error_size, "Unsupported video codec");
+        return ret;
+    } else if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    AVCodecContext *video_dec_ctx = ost->dec_ctx;
+    
+    // Find encoder
+    const AVCodec *video_encoder = avcodec_find_encoder_by_name(video_codec_name);
+    if (!video_encoder) {
+        snprintf(error, error_size, "Video encoder '%s' not found", video_codec_name);
+        return AVERROR_ENCODER_NOT_FOUND;
+    }
+    
+    // Allocate encoder context
+    ost->enc_ctx = avcodec_alloc_context3(video_encoder);
+    if (!ost->enc_ctx) {
+        snprintf(error, error_size, "Failed to allocate video encoder context");
+        return AVERROR(ENOMEM);
+    }
+    AVCodecContext *video_enc_ctx = ost->enc_ctx;
+    
+    // Set video encoder parameters
+    int width = video_dec_ctx->width;
+    int height = video_dec_ctx->height;
+    
+    // Apply custom resolution if needed
+    parse_resolution(resolution, &width, &height);
+    
+    // Some demuxers can't tell the frame rate, fall back to 25 fps
+    AVRational frame_rate = in_stream->r_frame_rate.num ? in_stream->r_frame_rate : (AVRational){25, 1};
+    
+    video_enc_ctx->height = height;
+    video_enc_ctx->width = width;
+    video_enc_ctx->sample_aspect_ratio = video_dec_ctx->sample_aspect_ratio;
+    video_enc_ctx->time_base = av_inv_q(frame_rate);
+    video_enc_ctx->framerate = frame_rate;
+    video_enc_ctx->gop_size = 25;
+    video_enc_ctx->max_b_frames = 3;
+    
+    // Pixel format: explicit choice must be supported, otherwise use the container default
+    const char *pix_fmt_name = get_option(options, "pix_fmt", "auto");
+    if (strcmp(pix_fmt_name, "auto") == 0) {
+        video_enc_ctx->pix_fmt = default_pix_fmt(output_ctx->oformat, video_encoder, video_dec_ctx->pix_fmt);
+    } else {
+        enum AVPixelFormat pix_fmt = av_get_pix_fmt(pix_fmt_name);
+        if (pix_fmt == AV_PIX_FMT_NONE) {
+            snprintf(error, error_size, "Unknown pixel format '%s'", pix_fmt_name);
+            return AVERROR(EINVAL);
+        }
+        if (!encoder_supports_pix_fmt(video_encoder, pix_fmt)) {
+            snprintf(error, error_size, "Pixel format '%s' is not supported by encoder '%s'", pix_fmt_name, video_codec_name);
+            return AVERROR(EINVAL);
+        }
+        video_enc_ctx->pix_fmt = pix_fmt;
+    }
+    
+    video_enc_ctx->bit_rate = video_bitrate * 1000;
+    
+    if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+        video_enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+    }
+    
+    // Open encoder
+    ret = avcodec_open2(video_enc_ctx, video_encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    // Copy parameters to output stream
+    ret = avcodec_parameters_from_context(ost->out_stream->codecpar, video_enc_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    ost->out_stream->time_base = video_enc_ctx->time_base;
+    
+    // Create scaling context if needed
+    if (video_dec_ctx->width != video_enc_ctx->width ||
+        video_dec_ctx->height != video_enc_ctx->height ||
+        video_dec_ctx->pix_fmt != video_enc_ctx->pix_fmt) {
+        
+        ost->sws_ctx = sws_getContext(
+            video_dec_ctx->width, video_dec_ctx->height, video_dec_ctx->pix_fmt,
+            video_enc_ctx->width, video_enc_ctx->height, video_enc_ctx->pix_fmt,
+            SWS_BICUBIC, NULL, NULL, NULL
+        );
+        
+        if (!ost->sws_ctx) {
+            snprintf(error, error_size, "Failed to create scaling context");
+            return AVERROR(EINVAL);
+        }
+    }
+    
+    return 0;
+}
+
+// Set up decoding, resampling, effects and encoding for an audio stream
+static int setup_audio_stream(
+    OutputStream *ost,
+    AVFormatContext *input_ctx,
+    AVFormatContext *output_ctx,
+    uint8_t *input_data,
+    size_t input_size,
+    const char *audio_codec_name,
+    int audio_bitrate,
+    AVDictionary *options,
+    char *error,
+    size_t error_size
+) {
+    AVStream *in_stream = ost->in_stream;
+    int ret;
+    
+    ret = open_decoder(in_stream, &ost->dec_ctx);
+    if (ret == AVERROR_DECODER_NOT_FOUND) {
+        snprintf(error, error_size, "Unsupported audio codec");
+        return ret;
+    } else if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    AVCodecContext *audio_dec_ctx = ost->dec_ctx;
+    
+    // Find encoder
+    const AVCodec *audio_encoder = avcodec_find_encoder_by_name(audio_codec_name);
+    if (!audio_encoder) {
+        snprintf(error, error_size, "Audio encoder '%s' not found", audio_codec_name);
+        return AVERROR_ENCODER_NOT_FOUND;
+    }
+    
+    // Allocate encoder context
+    ost->enc_ctx = avcodec_alloc_context3(audio_encoder);
+    if (!ost->enc_ctx) {
+        snprintf(error, error_size, "Failed to allocate audio encoder context");
+        return AVERROR(ENOMEM);
+    }
+    AVCodecContext *audio_enc_ctx = ost->enc_ctx;
+    
+    // Source layout; older demuxers may leave the channel order unspecified
+    if (audio_dec_ctx->ch_layout.order == AV_CHANNEL_ORDER_UNSPEC) {
+        av_channel_layout_default(&ost->source_layout, audio_dec_ctx->ch_layout.nb_channels);
+    } else {
+        av_channel_layout_copy(&ost->source_layout, &audio_dec_ctx->ch_layout);
+    }
+    
+    // Channel layout: "same" keeps the source layout when the encoder allows it
+    const char *layout_name = get_option(options, "channel_layout", "same");
+    AVChannelLayout requested_layout = {0};
+    if (strcmp(layout_name, "same") == 0) {
+        av_channel_layout_copy(&requested_layout, &ost->source_layout);
+    } else if (av_channel_layout_from_string(&requested_layout, layout_name) < 0) {
+        snprintf(error, error_size, "Unknown channel layout '%s'", layout_name);
+        return AVERROR(EINVAL);
+    }
+    
+    ret = choose_channel_layout(audio_encoder, &requested_layout, &audio_enc_ctx->ch_layout);
+    if (ret < 0 && strcmp(layout_name, "same") == 0) {
+        // Source layout not encodable (e.g. 7.1 into MP3), downmix to stereo
+        AVChannelLayout stereo = AV_CHANNEL_LAYOUT_STEREO;
+        ret = choose_channel_layout(audio_encoder, &stereo, &audio_enc_ctx->ch_layout);
+    }
+    av_channel_layout_uninit(&requested_layout);
+    if (ret < 0) {
+        snprintf(error, error_size, "Channel layout '%s' is not supported by encoder '%s'", layout_name, audio_codec_name);
+        return ret;
+    }
+    
+    // Sample rate: 0 keeps the source rate, snapped to the nearest one the encoder allows
+    int sample_rate = atoi(get_option(options, "sample_rate", "0"));
+    if (sample_rate > 0 && !encoder_supports_sample_rate(audio_encoder, sample_rate)) {
+        snprintf(error, error_size, "Sample rate %d Hz is not supported by encoder '%s'", sample_rate, audio_codec_name);
+        return AVERROR(EINVAL);
+    }
+    
+    // Set audio encoder parameters
+    audio_enc_ctx->sample_rate = choose_sample_rate(audio_encoder, sample_rate > 0 ? sample_rate : audio_dec_ctx->sample_rate);
+    audio_enc_ctx->sample_fmt = audio_encoder->sample_fmts ? audio_encoder->sample_fmts[0] : AV_SAMPLE_FMT_FLTP;
+    audio_enc_ctx->time_base = (AVRational){1, audio_enc_ctx->sample_rate};
+    audio_enc_ctx->bit_rate = audio_bitrate * 1000;
+    
+    if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+        audio_enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+    }
+    
+    // Open encoder
+    ret = avcodec_open2(audio_enc_ctx, audio_encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    // Copy parameters to output stream
+    ret = avcodec_parameters_from_context(ost->out_stream->codecpar, audio_enc_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    ost->out_stream->time_base = audio_enc_ctx->time_base;
+    
+    // Create audio resampling context if needed
+    if (av_channel_layout_compare(&ost->source_layout, &audio_enc_ctx->ch_layout) ||
+        audio_dec_ctx->sample_rate != audio_enc_ctx->sample_rate ||
+        audio_dec_ctx->sample_fmt != audio_enc_ctx->sample_fmt) {
+        
+        ret = swr_alloc_set_opts2(&ost->swr_ctx,
+            &audio_enc_ctx->ch_layout, audio_enc_ctx->sample_fmt, audio_enc_ctx->sample_rate,
+            &ost->source_layout, audio_dec_ctx->sample_fmt, audio_dec_ctx->sample_rate,
+            0, NULL);
+        if (ret < 0) {
+            snprintf(error, error_size, "Failed to allocate resampling context");
+            return ret;
+        }
+        
+        set_downmix_levels(ost->swr_ctx);
+        
+        if ((ret = swr_init(ost->swr_ctx)) < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+    }
+    
+    // Gain and fades; the fade-out ends where the audio does
+    int64_t audio_duration = 0;
+    if (in_stream->duration != AV_NOPTS_VALUE) {
+        audio_duration = av_rescale_q(in_stream->duration, in_stream->time_base, audio_enc_ctx->time_base);
+    } else if (input_ctx->duration != AV_NOPTS_VALUE) {
+        audio_duration = av_rescale_q(input_ctx->duration, AV_TIME_BASE_Q, audio_enc_ctx->time_base);
+    }
+    init_gain_and_fades(&ost->effects, options, audio_enc_ctx->sample_rate, audio_duration);
+    
+    // Loudness normalization: the first-pass measurement covers the main audio
+    // stream, or "measured_stream" when set; any other track is measured here
+    if (strcmp(get_option(options, "loudnorm", "off"), "off") != 0) {
+        double measured_i = -HUGE_VAL;
+        double measured_tp = -HUGE_VAL;
+        int measured_stream = av_dict_get(options, "measured_stream", NULL, 0) ?
+            atoi(get_option(options, "measured_stream", "-1")) :
+            av_find_best_stream(input_ctx, AVMEDIA_TYPE_AUDIO, -1, -1, NULL, 0);
+        
+        if (av_dict_get(options, "measured_i", NULL, 0) && in_stream->index == measured_stream) {
+            measured_i = atof(get_option(options, "measured_i", "-inf"));
+            measured_tp = atof(get_option(options, "measured_tp", "-inf"));
+        } else {
+            LoudnessMeter meter = {0};
+            double threshold;
+            ret = measure_input_loudness(input_data, input_size, in_stream->index, &audio_enc_ctx->ch_layout, &meter);
+            if (ret >= 0) {
+                measured_i = loudness_meter_integrated(&meter, &threshold);
+                measured_tp = loudness_meter_true_peak_db(&meter);
+            }
+            loudness_meter_free(&meter);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                return ret;
+            }
+        }
+        
+        ret = init_loudnorm(&ost->effects, options, audio_enc_ctx, measured_i, measured_tp);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+    }
+    
+    // Encoders with a fixed frame size need samples regrouped after resampling
+    ost->fifo = av_audio_fifo_alloc(audio_enc_ctx->sample_fmt, audio_enc_ctx->ch_layout.nb_channels, 1);
+    if (!ost->fifo) {
+        snprintf(error, error_size, "Failed to allocate audio FIFO");
+        return AVERROR(ENOMEM);
+    }
+    ost->next_pts = AV_NOPTS_VALUE;
+    
+    return 0;
+}
+
+// Set up decoding and re-encoding of a text subtitle stream, by default into
+// the container's own subtitle format
+static int setup_subtitle_stream(OutputStream *ost, AVFormatContext *output_ctx, AVDictionary *options, char *error, size_t error_size) {
+    AVStream *in_stream = ost->in_stream;
+    int ret;
+    
+    if (!is_text_subtitle(in_stream->codecpar)) {
+        snprintf(error, error_size, "Subtitle stream %d (%s) is image-based and can only be copied",
+            in_stream->index, avcodec_get_name(in_stream->codecpar->codec_id));
+        return AVERROR(EINVAL);
+    }
+    
+    // Find encoder
+    const char *codec_name = get_option(options, "subtitle_codec", "auto");
+    const AVCodec *encoder = strcmp(codec_name, "auto") == 0
+        ? avcodec_find_encoder(output_ctx->oformat->subtitle_codec)
+        : avcodec_find_encoder_by_name(codec_name);
+    if (!encoder) {
+        snprintf(error, error_size, strcmp(codec_name, "auto") == 0
+            ? "Output format '%s' has no subtitle encoder" : "Subtitle encoder '%s' not found",
+            strcmp(codec_name, "auto") == 0 ? output_ctx->oformat->name : codec_name);
+        return AVERROR_ENCODER_NOT_FOUND;
+    }
+    
+    ret = open_decoder(in_stream, &ost->dec_ctx);
+    if (ret == AVERROR_DECODER_NOT_FOUND) {
+        snprintf(error, error_size, "Unsupported subtitle codec");
+        return ret;
+    } else if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    ost->enc_ctx = avcodec_alloc_context3(encoder);
+    if (!ost->enc_ctx) {
+        snprintf(error, error_size, "Failed to allocate subtitle encoder context");
+        return AVERROR(ENOMEM);
+    }
+    
+    // Text decoders produce ASS events; the encoders need the matching style header
+    if (ost->dec_ctx->subtitle_header) {
+        ost->enc_ctx->subtitle_header = av_mallocz(ost->dec_ctx->subtitle_header_size + 1);
+        if (!ost->enc_ctx->subtitle_header) {
+            snprintf(error, error_size, "Failed to allocate subtitle header");
+            return AVERROR(ENOMEM);
+        }
+        memcpy(ost->enc_ctx->subtitle_header, ost->dec_ctx->subtitle_header, ost->dec_ctx->subtitle_header_size);
+        ost->enc_ctx->subtitle_header_size = ost->dec_ctx->subtitle_header_size;
+    }
+    
+    ost->enc_ctx->time_base = AV_TIME_BASE_Q;
+    
+    if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+        ost->enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+    }
+    
+    ret = avcodec_open2(ost->enc_ctx, encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    ret = avcodec_parameters_from_context(ost->out_stream->codecpar, ost->enc_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        return ret;
+    }
+    
+    ost->out_stream->time_base = (AVRational){1, 1000};
+    
+    return 0;
+}
+
+// Largest encoded subtitle packet we accept
+#define SUBTITLE_MAX_SIZE (1024 * 1024)
+
+// Encode and mux one decoded subtitle
+static int encode_subtitle(OutputStream *ost, AVFormatContext *output_ctx, AVSubtitle *sub, AVPacket *pkt) {
+    if (sub->pts == AV_NOPTS_VALUE) {
+        return 0;
+    }
+    
+    // Fold the display offsets into pts, as the encoders expect
+    sub->pts += av_rescale_q(sub->start_display_time, (AVRational){1, 1000}, AV_TIME_BASE_Q);
+    sub->end_display_time -= sub->start_display_time;
+    sub->start_display_time = 0;
+    
+    int ret = av_new_packet(pkt, SUBTITLE_MAX_SIZE);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    int size = avcodec_encode_subtitle(ost->enc_ctx, pkt->data, pkt->size, sub);
+    if (size < 0) {
+        av_packet_unref(pkt);
+        return size;
+    }
+    av_shrink_packet(pkt, size);
+    
+    pkt->pts = av_rescale_q(sub->pts, AV_TIME_BASE_Q, ost->out_stream->time_base);
+    pkt->dts = pkt->pts;
+    pkt->duration = av_rescale_q(sub->end_display_time, (AVRational){1, 1000}, ost->out_stream->time_base);
+    pkt->stream_index = ost->out_stream->index;
+    
+    return av_interleaved_write_frame(output_ctx, pkt);
+}
+
+// Decode a subtitle packet and re-encode it
+static int transcode_subtitle_packet(OutputStream *ost, AVFormatContext *output_ctx, const AVPacket *packet, AVPacket *enc_pkt) {
+    AVSubtitle sub;
+    int got_subtitle = 0;
+    
+    int ret = avcodec_decode_subtitle2(ost->dec_ctx, &sub, &got_subtitle, packet);
+    if (ret < 0 || !got_subtitle) {
+        return ret < 0 ? ret : 0;
+    }
+    
+    // Formats like SRT carry the display time as the packet duration
+    if (sub.end_display_time == 0 && packet->duration > 0) {
+        sub.end_display_time = av_rescale_q(packet->duration, ost->in_stream->time_base, (AVRational){1, 1000});
+    }
+    
+    ret = encode_subtitle(ost, output_ctx, &sub, enc_pkt);
+    avsubtitle_free(&sub);
+    
+    return ret;
+}
+
+// Scale (if needed) and encode one decoded video frame
+static int encode_video_frame(OutputStream *ost, AVFormatContext *output_ctx, AVFrame *frame, AVPacket *pkt) {
+    AVFrame *enc_frame = frame;
+    int ret;
+    
+    if (ost->sws_ctx) {
+        enc_frame = av_frame_alloc();
+        if (!enc_frame) {
+            return AVERROR(ENOMEM);
+        }
+        
+        enc_frame->format = ost->enc_ctx->pix_fmt;
+        enc_frame->width = ost->enc_ctx->width;
+        enc_frame->height = ost->enc_ctx->height;
+        ret = av_frame_get_buffer(enc_frame, 0);
+        if (ret < 0) {
+            av_frame_free(&enc_frame);
+            return ret;
+        }
+        
+        sws_scale(ost->sws_ctx, (const uint8_t* const*)frame->data, frame->linesize,
+            0, frame->height, enc_frame->data, enc_frame->linesize);
+    }
+    
+    // Overlays are drawn on the frame as encoded
+    if ((ost->burner || ost->text_overlay) && frame->pts != AV_NOPTS_VALUE) {
+        int64_t time_ms = av_rescale_q(frame->pts, ost->in_stream->time_base, (AVRational){1, 1000});
+        
+        ret = enc_frame == frame ? av_frame_make_writable(enc_frame) : 0;
+        if (ret < 0) {
+            return ret;
+        }
+        if (ost->burner) {
+            render_subtitles(ost->burner, enc_frame, time_ms);
+        }
+        if (ost->text_overlay) {
+            render_text_overlay(ost->text_overlay, enc_frame, time_ms);
+        }
+    }
+    
+    // Let the encoder choose frame types instead of copying the decoder's
+    enc_frame->pict_type = AV_PICTURE_TYPE_NONE;
+    enc_frame->pts = frame->pts == AV_NOPTS_VALUE ? AV_NOPTS_VALUE :
+        av_rescale_q(frame->pts, ost->in_stream->time_base, ost->enc_ctx->time_base);
+    
+    ret = process_packet(output_ctx, ost->enc_ctx, enc_frame, pkt, ost->out_stream->index);
+    
+    if (enc_frame != frame) {
+        av_frame_free(&enc_frame);
+    }
+    
+    return ret;
+}
+
+// Resample a decoded audio frame and encode the whole encoder frames it completes
+static int encode_audio_frame(OutputStream *ost, AVFormatContext *output_ctx, AVFrame *frame, AVPacket *pkt) {
+    // First decoded frame sets the output start time
+    if (ost->next_pts == AV_NOPTS_VALUE) {
+        ost->next_pts = frame->pts == AV_NOPTS_VALUE ? 0 :
+            av_rescale_q(frame->pts, ost->in_stream->time_base, ost->enc_ctx->time_base);
+    }
+    
+    int ret = resample_audio_frame(ost->swr_ctx, ost->enc_ctx, frame, ost->fifo);
+    if (ret >= 0) {
+        ret = encode_audio_fifo(output_ctx, ost->enc_ctx, ost->fifo, &ost->effects, pkt,
+            ost->out_stream->index, &ost->next_pts, 0);
+    }
+    
+    return ret;
+}
+
+// Decode a packet (NULL drains the decoder) and encode every frame it yields
+static int transcode_packet(OutputStream *ost, AVFormatContext *output_ctx, const AVPacket *packet, AVFrame *frame, AVPacket *enc_pkt) {
+    if (ost->dec_ctx->codec_type == AVMEDIA_TYPE_SUBTITLE) {
+        return packet ? transcode_subtitle_packet(ost, output_ctx, packet, enc_pkt) : 0;
+    }
+    
+    int ret = avcodec_send_packet(ost->dec_ctx, packet);
+    if (ret < 0 && !(packet == NULL && ret == AVERROR_EOF)) {
+        return ret;
+    }
+    
+    while (1) {
+        ret = avcodec_receive_frame(ost->dec_ctx, frame);
+        if (ret == AVERROR(EAGAIN) || ret == AVERROR_EOF) {
+            return 0;
+        } else if (ret < 0) {
+            return ret;
+        }
+        
+        if (ost->dec_ctx->codec_type == AVMEDIA_TYPE_VIDEO) {
+            ret = encode_video_frame(ost, output_ctx, frame, enc_pkt);
+        } else {
+            ret = encode_audio_frame(ost, output_ctx, frame, enc_pkt);
+        }
+        
+        av_frame_unref(frame);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+}
+
+// Drain decoder, resampler and encoder at the end of the input
+static int flush_output_stream(OutputStream *ost, AVFormatContext *output_ctx, AVFrame *frame, AVPacket *enc_pkt) {
+    if (ost->copy || (ost->dec_ctx && ost->dec_ctx->codec_type == AVMEDIA_TYPE_SUBTITLE)) {
+        return 0;
+    }
+    
+    // Streams fed from a shared decoder have none of their own to drain
+    int ret = ost->dec_ctx ? transcode_packet(ost, output_ctx, NULL, frame, enc_pkt) : 0;
+    
+    // Drain the resampler and encode the leftover samples
+    if (ret >= 0 && ost->fifo) {
+        ret = resample_audio_frame(ost->swr_ctx, ost->enc_ctx, NULL, ost->fifo);
+        if (ret >= 0) {
+            ret = encode_audio_fifo(output_ctx, ost->enc_ctx, ost->fifo, &ost->effects, enc_pkt,
+                ost->out_stream->index, &ost->next_pts, 1);
+        }
+    }
+    
+    // Flush encoder
+    if (ret >= 0) {
+        ret = process_packet(output_ctx, ost->enc_ctx, NULL, enc_pkt, ost->out_stream->index);
+    }
+    
+    return ret;
+}
+
+// Remux a packet of a stream-copied track
+static int copy_packet(OutputStream *ost, AVFormatContext *output_ctx, AVPacket *pkt) {
+    av_packet_rescale_ts(pkt, ost->in_stream->time_base, ost->out_stream->time_base);
+    pkt->stream_index = ost->out_stream->index;
+    pkt->pos = -1;
+    
+    return av_interleaved_write_frame(output_ctx, pkt);
+}
+
+// Mux the packets of a stream read from an extra input up to `until`
+// (AV_TIME_BASE units), so they interleave with the main input
+static int mux_extra_input_stream(OutputStream *ost, AVFormatContext *output_ctx, int64_t until, AVFrame *frame, AVPacket *enc_pkt) {
+    AVPacket *pkt = ost->next_pkt;
+    int ret;
+    
+    while (1) {
+        // Read ahead one packet and hold it until its time has come
+        if (pkt->size == 0) {
+            ret = av_read_frame(ost->input_ctx, pkt);
+            if (ret == AVERROR_EOF) {
+                return 0;
+            } else if (ret < 0) {
+                return ret;
+            }
+            if (pkt->stream_index != ost->in_stream->index) {
+                av_packet_unref(pkt);
+                continue;
+            }
+        }
+        
+        int64_t ts = pkt->pts != AV_NOPTS_VALUE ? pkt->pts : pkt->dts;
+        if (ts != AV_NOPTS_VALUE && av_rescale_q(ts, ost->in_stream->time_base, AV_TIME_BASE_Q) > until) {
+            return 0;
+        }
+        
+        ret = ost->copy ? copy_packet(ost, output_ctx, pkt) : transcode_packet(ost, output_ctx, pkt, frame, enc_pkt);
+        av_packet_unref(pkt);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+}
+
+// Add the first subtitle stream of an extra input as a new track
+static int add_subtitle_input(
+    AVFormatContext *output_ctx,
+    OutputStream *output_streams,
+    int *nb_output_streams,
+    int input,
+    AVDictionary *options,
+    char *error,
+    size_t error_size
+) {
+    AVFormatContext *input_ctx = NULL;
+    char key[48];
+    
+    int ret = open_extra_input(input, &input_ctx);
+    if (ret < 0) {
+        snprintf(error, error_size, "Failed to read subtitle file %d", input);
+        return ret;
+    }
+    
+    int index = av_find_best_stream(input_ctx, AVMEDIA_TYPE_SUBTITLE, -1, -1, NULL, 0);
+    if (index < 0) {
+        close_memory_input(&input_ctx);
+        snprintf(error, error_size, "Subtitle file %d contains no subtitles", input);
+        return index;
+    }
+    
+    OutputStream *ost = add_output_stream(output_ctx, output_streams, nb_output_streams, input_ctx->streams[index]);
+    if (!ost) {
+        close_memory_input(&input_ctx);
+        snprintf(error, error_size, "Failed to create output stream for subtitle file %d", input);
+        return AVERROR(ENOMEM);
+    }
+    ost->input_ctx = input_ctx;
+    
+    ost->next_pkt = av_packet_alloc();
+    if (!ost->next_pkt) {
+        snprintf(error, error_size, "Failed to allocate packet");
+        return AVERROR(ENOMEM);
+    }
+    
+    snprintf(key, sizeof(key), "subtitle_language_%d", input);
+    if (av_dict_get(options, key, NULL, 0)) {
+        av_dict_set(&ost->out_stream->metadata, "language", get_option(options, key, ""), 0);
+    }
+    
+    snprintf(key, sizeof(key), "subtitle_title_%d", input);
+    if (av_dict_get(options, key, NULL, 0)) {
+        av_dict_set(&ost->out_stream->metadata, "title", get_option(options, key, ""), 0);
+    }
+    
+    snprintf(key, sizeof(key), "subtitle_default_%d", input);
+    ost->out_stream->disposition = atoi(get_option(options, key, "0")) ? AV_DISPOSITION_DEFAULT : 0;
+    
+    // Copy when the container takes the codec as-is, convert otherwise
+    if (avformat_query_codec(output_ctx->oformat, ost->in_stream->codecpar->codec_id, FF_COMPLIANCE_NORMAL) == 1) {
+        return setup_copy_stream(ost, output_ctx, error, error_size);
+    }
+    return setup_subtitle_stream(ost, output_ctx, options, error, error_size);
+}
+
+// Tags that can give away where a recording was made
+static int is_location_tag(const char *key) {
+    return av_stristr(key, "location") || av_stristr(key, "gps");
+}
+
+// Container metadata: "metadata=keep" (default) copies the input's tags,
+// "strip_location" copies all but location tags, "strip" drops every tag
+// taken from the input. "meta_<key>=value" then sets a tag, or removes it
+// when empty; "meta_language" goes on the video and audio streams instead.
+static void apply_output_metadata(AVFormatContext *output_ctx, AVFormatContext *input_ctx,
+                                  OutputStream *output_streams, int nb_output_streams, AVDictionary *options) {
+    const char *mode = get_option(options, "metadata", "keep");
+    const AVDictionaryEntry *entry = NULL;
+    
+    if (!strcmp(mode, "strip")) {
+        // Also leaves out the muxer's encoder tags
+        output_ctx->flags |= AVFMT_FLAG_BITEXACT;
+        
+        for (int i = 0; i < nb_output_streams; i++) {
+            if (!output_streams[i].input_ctx) {
+                av_dict_free(&output_streams[i].out_stream->metadata);
+            }
+        }
+    } else {
+        while ((entry = av_dict_get(input_ctx->metadata, "", entry, AV_DICT_IGNORE_SUFFIX))) {
+            if (strcmp(mode, "strip_location") || !is_location_tag(entry->key)) {
+                av_dict_set(&output_ctx->metadata, entry->key, entry->value, 0);
+            }
+        }
+    }
+    
+    entry = NULL;
+    while ((entry = av_dict_get(options, "meta_", entry, AV_DICT_IGNORE_SUFFIX))) {
+        const char *key = entry->key + strlen("meta_");
+        const char *value = entry->value[0] ? entry->value : NULL;
+        
+        if (!strcmp(key, "language")) {
+            for (int i = 0; i < nb_output_streams; i++) {
+                enum AVMediaType type = output_streams[i].in_stream->codecpar->codec_type;
+                if (type == AVMEDIA_TYPE_VIDEO || type == AVMEDIA_TYPE_AUDIO) {
+                    av_dict_set(&output_streams[i].out_stream->metadata, "language", value, 0);
+                }
+            }
+        } else if (key[0]) {
+            av_dict_set(&output_ctx->metadata, key, value, 0);
+        }
+    }
+}
+
+// Ogg has no picture streams; the cover goes in a METADATA_BLOCK_PICTURE Vorbis
+// comment on each audio stream instead, a base64 FLAC picture block
+static int set_picture_comment(AVFormatContext *output_ctx, const AVCodecParameters *par, const AVPacket *pkt) {
+    const char *mime = par->codec_id == AV_CODEC_ID_PNG ? "image/png" : "image/jpeg";
+    size_t mime_size = strlen(mime);
+    // Type, MIME type, description, size, depth, colors and data lengths
+    size_t block_size = 32 + mime_size + pkt->size;
+    
+    if (block_size > INT_MAX / 4 * 3 - 4) {
+        return AVERROR(EINVAL);
+    }
+    
+    uint8_t *block = av_malloc(block_size);
+    char *encoded = av_malloc(AV_BASE64_SIZE(block_size));
+    if (!block || !encoded) {
+        av_free(block);
+        av_free(encoded);
+        return AVERROR(ENOMEM);
+    }
+    
+    uint8_t *p = block;
+    AV_WB32(p, 3);  // Front cover
+    AV_WB32(p + 4, mime_size);
+    memcpy(p + 8, mime, mime_size);
+    p += 8 + mime_size;
+    AV_WB32(p, 0);  // No description
+    AV_WB32(p + 4, par->width);
+    AV_WB32(p + 8, par->height);
+    AV_WB32(p + 12, 24);
+    AV_WB32(p + 16, 0);
+    AV_WB32(p + 20, pkt->size);
+    memcpy(p + 24, pkt->data, pkt->size);
+    
+    int ret = av_base64_encode(encoded, AV_BASE64_SIZE(block_size), block, block_size) ? 0 : AVERROR(EINVAL);
+    for (unsigned int i = 0; i < output_ctx->nb_streams && ret >= 0; i++) {
+        if (output_ctx->streams[i]->codecpar->codec_type == AVMEDIA_TYPE_AUDIO) {
+            ret = av_dict_set(&output_ctx->streams[i]->metadata, "METADATA_BLOCK_PICTURE", encoded, 0);
+        }
+    }
+    
+    av_free(block);
+    av_free(encoded);
+    return ret;
+}
+
+// Cover art: "cover=keep" (default) keeps the input's attached picture, "cover=none"
+// drops it and "cover_input=<input>" uses an uploaded JPEG/PNG instead. The picture
+// gets its own output stream and is muxed as a single packet right after the header,
+// except in Ogg, where it is a Vorbis comment.
+static int add_cover_art(AVFormatContext *output_ctx, AVFormatContext *input_ctx, AVDictionary *options,
+                         AVPacket **cover_pkt, char *error, size_t error_size) {
+    AVFormatContext *image_ctx = NULL;
+    AVPacket *image_pkt = NULL;
+    const AVCodecParameters *par = NULL;
+    const AVPacket *source_pkt = NULL;
+    int uploaded = av_dict_get(options, "cover_input", NULL, 0) != NULL;
+    int ret = 0;
+    
+    if (uploaded) {
+        image_pkt = av_packet_alloc();
+        ret = image_pkt ? open_extra_input(atoi(get_option(options, "cover_input", "0")), &image_ctx) : AVERROR(ENOMEM);
+        if (ret >= 0) {
+            ret = av_read_frame(image_ctx, image_pkt);
+        }
+        if (ret < 0 || image_ctx->streams[image_pkt->stream_index]->codecpar->codec_type != AVMEDIA_TYPE_VIDEO) {
+            snprintf(error, error_size, "Failed to read the cover image");
+            ret = ret < 0 ? ret : AVERROR_INVALIDDATA;
+            goto end;
+        }
+        par = image_ctx->streams[image_pkt->stream_index]->codecpar;
+        source_pkt = image_pkt;
+    } else if (strcmp(get_option(options, "cover", "keep"), "none")) {
+        for (unsigned int i = 0; i < input_ctx->nb_streams && !par; i++) {
+            AVStream *stream = input_ctx->streams[i];
+            if ((stream->disposition & AV_DISPOSITION_ATTACHED_PIC) && stream->attached_pic.size > 0) {
+                par = stream->codecpar;
+                source_pkt = &stream->attached_pic;
+            }
+        }
+    }
+    
+    if (!par) {
+        goto end;
+    }
+    
+    if (!format_supports_cover(output_ctx->oformat) ||
+        (par->codec_id != AV_CODEC_ID_MJPEG && par->codec_id != AV_CODEC_ID_PNG)) {
+        // Only fail when the user asked for this picture
+        if (uploaded) {
+            snprintf(error, error_size, "%s can't hold %s cover art", output_ctx->oformat->name, avcodec_get_name(par->codec_id));
+            ret = AVERROR(EINVAL);
+        } else {
+            av_log(NULL, AV_LOG_WARNING, "%s can't hold %s cover art, dropping it\n",
+                output_ctx->oformat->name, avcodec_get_name(par->codec_id));
+        }
+        goto end;
+    }
+    
+    if (!strcmp(output_ctx->oformat->name, "ogg") || !strcmp(output_ctx->oformat->name, "opus")) {
+        ret = set_picture_comment(output_ctx, par, source_pkt);
+        if (ret < 0) {
+            snprintf(error, error_size, "Failed to add cover art");
+        }
+        goto end;
+    }
+    
+    AVStream *cover_stream = avformat_new_stream(output_ctx, NULL);
+    if (!cover_stream || avcodec_parameters_copy(cover_stream->codecpar, par) < 0 ||
+        !(*cover_pkt = av_packet_clone(source_pkt))) {
+        snprintf(error, error_size, "Failed to add cover art");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    cover_stream->codecpar->codec_tag = 0;
+    cover_stream->disposition = AV_DISPOSITION_ATTACHED_PIC;
+    cover_stream->time_base = (AVRational){1, 90000};
+    
+    (*cover_pkt)->stream_index = cover_stream->index;
+    (*cover_pkt)->pts = (*cover_pkt)->dts = 0;
+    (*cover_pkt)->flags |= AV_PKT_FLAG_KEY;
+    
+end:
+    av_packet_free(&image_pkt);
+    close_memory_input(&image_ctx);
+    return ret;
+}
+
+// Chapters are copied from the input unless "chapters=none";
+// "chapters_input=<input>" takes them from an extra input (an FFMETADATA file) instead
+static int apply_output_chapters(AVFormatContext *output_ctx, AVFormatContext *input_ctx, AVDictionary *options,
+                                 char *error, size_t error_size) {
+    AVFormatContext *source_ctx = input_ctx;
+    int64_t start_time = input_ctx->start_time != AV_NOPTS_VALUE ? input_ctx->start_time : 0;
+    int64_t offset = 0;
+    int ret = 0;
+    
+    if (!strcmp(get_option(options, "chapters", "keep"), "none")) {
+        return 0;
+    }
+    
+    if (av_dict_get(options, "chapters_input", NULL, 0)) {
+        source_ctx = NULL;
+        ret = open_extra_input(atoi(get_option(options, "chapters_input", "0")), &source_ctx);
+        if (ret < 0) {
+            snprintf(error, error_size, "Failed to read the chapter list");
+            return ret;
+        }
+        
+        // Chapter lists start at zero, the input may not
+        offset = start_time;
+    }
+    
+    if (source_ctx->nb_chapters) {
+        AVChapter **chapters = av_realloc_array(output_ctx->chapters, output_ctx->nb_chapters + source_ctx->nb_chapters, sizeof(*chapters));
+        if (!chapters) {
+            ret = AVERROR(ENOMEM);
+            goto end;
+        }
+        output_ctx->chapters = chapters;
+    }
+    
+    for (unsigned int i = 0; i < source_ctx->nb_chapters; i++) {
+        AVChapter *in_chapter = source_ctx->chapters[i];
+        AVChapter *out_chapter = av_mallocz(sizeof(*out_chapter));
+        int64_t shift = av_rescale_q(offset, AV_TIME_BASE_Q, in_chapter->time_base);
+        
+        if (!out_chapter) {
+            ret = AVERROR(ENOMEM);
+            goto end;
+        }
+        
+        out_chapter->id = in_chapter->id;
+        out_chapter->time_base = in_chapter->time_base;
+        out_chapter->start = in_chapter->start + shift;
+        out_chapter->end = in_chapter->end + shift;
+        
+        // Open-ended chapters run until the next one, or the end of the input
+        if (in_chapter->end == AV_NOPTS_VALUE || in_chapter->end <= in_chapter->start) {
+            if (i + 1 < source_ctx->nb_chapters) {
+                AVChapter *next = source_ctx->chapters[i + 1];
+                out_chapter->end = av_rescale_q(next->start, next->time_base, in_chapter->time_base) + shift;
+            } else if (input_ctx->duration != AV_NOPTS_VALUE) {
+                out_chapter->end = av_rescale_q(start_time + input_ctx->duration, AV_TIME_BASE_Q, in_chapter->time_base);
+            } else {
+                out_chapter->end = out_chapter->start;
+            }
+        }
+        
+        av_dict_copy(&out_chapter->metadata, in_chapter->metadata, 0);
+        output_ctx->chapters[output_ctx->nb_chapters++] = out_chapter;
+    }
+    
+end:
+    if (source_ctx != input_ctx) {
+        close_memory_input(&source_ctx);
+    }
+    if (ret < 0) {
+        snprintf(error, error_size, "Failed to copy chapters");
+    }
+    return ret;
+}
+
+// Reads a range of the input for animated output (GIF, WebP, APNG) as frames of
+// one pixel format at the output frame rate
+typedef struct {
+    AVFormatContext *fmt_ctx;
+    AVStream *stream;
+    AVCodecContext *dec_ctx;
+    struct SwsContext *sws_ctx;
+    AVPacket *packet;
+    AVFrame *decoded;
+    AVFrame *frame;
+    int fps;
+    double start;
+    double duration;
+    int64_t next_output;
+    int repeat;
+    int eof;
+    int done;
+} AnimationReader;
+
+static void animation_reader_close(AnimationReader *reader) {
+    sws_freeContext(reader->sws_ctx);
+    av_packet_free(&reader->packet);
+    av_frame_free(&reader->decoded);
+    av_frame_free(&reader->frame);
+    avcodec_free_context(&reader->dec_ctx);
+    close_memory_input(&reader->fmt_ctx);
+}
+
+// Open the input and size the output: anim_width (0 keeps the display width), anim_fps,
+// anim_start and anim_duration (0 runs to the end)
+static int animation_reader_open(AnimationReader *reader, MemoryInput *input, AVDictionary *options,
+                                 enum AVPixelFormat pix_fmt, char *error, size_t error_size) {
+    int ret = open_video_input(input, &reader->fmt_ctx, &reader->stream, &reader->dec_ctx, error, error_size);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    reader->fps = av_clip(atoi(get_option(options, "anim_fps", "10")), 1, 50);
+    reader->start = FFMAX(atof(get_option(options, "anim_start", "0")), 0.0);
+    reader->duration = FFMAX(atof(get_option(options, "anim_duration", "0")), 0.0);
+    
+    AVCodecParameters *par = reader->stream->codecpar;
+    AVRational sar = par->sample_aspect_ratio.num > 0 ? par->sample_aspect_ratio : (AVRational){1, 1};
+    int display_width = FFMAX(1, (int)av_rescale(par->width, sar.num, sar.den));
+    int width = atoi(get_option(options, "anim_width", "0"));
+    if (width <= 0) {
+        width = display_width;
+    }
+    
+    reader->packet = av_packet_alloc();
+    reader->decoded = av_frame_alloc();
+    reader->frame = av_frame_alloc();
+    if (!reader->packet || !reader->decoded || !reader->frame) {
+        snprintf(error, error_size, "Out of memory");
+        return AVERROR(ENOMEM);
+    }
+    
+    reader->frame->format = pix_fmt;
+    reader->frame->width = width;
+    reader->frame->height = FFMAX(1, (int)av_rescale(width, par->height, display_width));
+    ret = av_frame_get_buffer(reader->frame, 0);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+    return ret;
+}
+
+// Go back to the start of the range
+static void animation_reader_rewind(AnimationReader *reader) {
+    int64_t start = reader->stream->start_time != AV_NOPTS_VALUE ? reader->stream->start_time : 0;
+    av_seek_frame(reader->fmt_ctx, reader->stream->index,
+        start + av_rescale_q((int64_t)(reader->start * AV_TIME_BASE), AV_TIME_BASE_Q, reader->stream->time_base),
+        AVSEEK_FLAG_BACKWARD);
+    avcodec_flush_buffers(reader->dec_ctx);
+    
+    reader->next_output = 0;
+    reader->repeat = 0;
+    reader->eof = 0;
+    reader->done = 0;
+}
+
+// Produce the next output frame in reader->frame; returns 1, or 0 at the end of the range
+static int animation_reader_next(AnimationReader *reader) {
+    AVStream *stream = reader->stream;
+    
+    while (!reader->repeat) {
+        if (reader->done) {
+            return 0;
+        }
+        
+        int ret = avcodec_receive_frame(reader->dec_ctx, reader->decoded);
+        if (ret == AVERROR(EAGAIN)) {
+            ret = av_read_frame(reader->fmt_ctx, reader->packet);
+            if (ret < 0) {
+                reader->eof = 1;
+                avcodec_send_packet(reader->dec_ctx, NULL);
+            } else {
+                if (reader->packet->stream_index == stream->index) {
+                    avcodec_send_packet(reader->dec_ctx, reader->packet);
+                }
+                av_packet_unref(reader->packet);
+            }
+            continue;
+        } else if (ret < 0) {
+            reader->done = 1;
+            return ret == AVERROR_EOF ? 0 : ret;
+        }
+        
+        // A frame stands in for every output frame due up to its own time
+        int64_t pts = reader->decoded->best_effort_timestamp;
+        if (pts == AV_NOPTS_VALUE) {
+            reader->repeat = 1;
+        } else {
+            int64_t start = stream->start_time != AV_NOPTS_VALUE ? stream->start_time : 0;
+            double time = (pts - start) * av_q2d(stream->time_base) - reader->start;
+            
+            if (reader->duration > 0 && time >= reader->duration) {
+                av_frame_unref(reader->decoded);
+                reader->done = 1;
+                return 0;
+            }
+            
+            int64_t due = (int64_t)floor(time * reader->fps) + 1;
+            reader->repeat = (int)FFMAX(due - reader->next_output, 0);
+        }
+        
+        if (reader->repeat) {
+            AVFrame *frame = reader->decoded;
+            reader->sws_ctx = sws_getCachedContext(reader->sws_ctx, frame->width, frame->height, frame->format,
+                                                   reader->frame->width, reader->frame->height, reader->frame->format,
+                                                   SWS_BICUBIC, NULL, NULL, NULL);
+            if (!reader->sws_ctx) {
+                av_frame_unref(frame);
+                return AVERROR(EINVAL);
+            }
+            
+            // The encoder may still hold the previous frame
+            ret = av_frame_make_writable(reader->frame);
+            if (ret < 0) {
+                av_frame_unref(frame);
+                return ret;
+            }
+            sws_scale(reader->sws_ctx, (const uint8_t * const *)frame->data, frame->linesize, 0, frame->height,
+                      reader->frame->data, reader->frame->linesize);
+        }
+        av_frame_unref(reader->decoded);
+    }
+    
+    reader->repeat--;
+    reader->next_output++;
+    return 1;
+}
+
+// GIF output: the range is read twice, once to build an optimized palette from
+// its colors and once to map every frame onto that palette with dithering
+#define GIF_HISTOGRAM_BITS 5
+#define GIF_HISTOGRAM_SIZE (1 << (3 * GIF_HISTOGRAM_BITS))
+
+// A non-empty histogram cell: its color at GIF_HISTOGRAM_BITS per channel and pixel count
+typedef struct {
+    uint8_t rgb[3];
+    uint64_t count;
+} ColorCell;
+
+static int compare_cells_red(const void *a, const void *b) {
+    return ((const ColorCell *)a)->rgb[0] - ((const ColorCell *)b)->rgb[0];
+}
+
+static int compare_cells_green(const void *a, const void *b) {
+    return ((const ColorCell *)a)->rgb[1] - ((const ColorCell *)b)->rgb[1];
+}
+
+static int compare_cells_blue(const void *a, const void *b) {
+    return ((const ColorCell *)a)->rgb[2] - ((const ColorCell *)b)->rgb[2];
+}
+
+// Median cut: split the box with the widest channel range at its pixel-weighted
+// median until there are max_colors boxes, then average each box
+static int build_palette(const uint64_t *histogram, int max_colors, uint32_t *palette) {
+    static int (*const compare[3])(const void *, const void *) = { compare_cells_red, compare_cells_green, compare_cells_blue };
+    int boxes[257][2];
+    int nb_boxes = 1, nb_cells = 0;
+    
+    ColorCell *cells = av_malloc_array(GIF_HISTOGRAM_SIZE, sizeof(*cells));
+    if (!cells) {
+        return AVERROR(ENOMEM);
+    }
+    
+    for (int i = 0; i < GIF_HISTOGRAM_SIZE; i++) {
+        if (histogram[i]) {
+            cells[nb_cells].rgb[0] = i >> (2 * GIF_HISTOGRAM_BITS);
+            cells[nb_cells].rgb[1] = (i >> GIF_HISTOGRAM_BITS) & ((1 << GIF_HISTOGRAM_BITS) - 1);
+            cells[nb_cells].rgb[2] = i & ((1 << GIF_HISTOGRAM_BITS) - 1);
+            cells[nb_cells].count = histogram[i];
+            nb_cells++;
+        }
+    }
+    
+    boxes[0][0] = 0;
+    boxes[0][1] = nb_cells;
+    
+    while (nb_boxes < max_colors) {
+        int best = -1, best_channel = 0, best_range = 0;
+        
+        for (int b = 0; b < nb_boxes; b++) {
+            for (int c = 0; c < 3; c++) {
+                int lo = 255, hi = 0;
+                for (int i = boxes[b][0]; i < boxes[b][1]; i++) {
+                    lo = FFMIN(lo, cells[i].rgb[c]);
+                    hi = FFMAX(hi, cells[i].rgb[c]);
+                }
+                if (hi - lo > best_range) {
+                    best = b;
+                    best_channel = c;
+                    best_range = hi - lo;
+                }
+            }
+        }
+        
+        // Every box is a single color
+        if (best < 0) {
+            break;
+        }
+        
+        int first = boxes[best][0], end = boxes[best][1];
+        uint64_t total = 0, sum = 0;
+        qsort(cells + first, end - first, sizeof(*cells), compare[best_channel]);
+        for (int i = first; i < end; i++) {
+            total += cells[i].count;
+        }
+        
+        // Both halves keep at least one cell
+        int split = first + 1;
+        for (int i = first; i < end - 1; i++) {
+            sum += cells[i].count;
+            split = i + 1;
+            if (sum * 2 >= total) {
+                break;
+            }
+        }
+        
+        boxes[nb_boxes][0] = split;
+        boxes[nb_boxes][1] = end;
+        boxes[best][1] = split;
+        nb_boxes++;
+    }
+    
+    for (int b = 0; b < nb_boxes; b++) {
+        uint64_t total = 0, sum[3] = { 0, 0, 0 };
+        for (int i = boxes[b][0]; i < boxes[b][1]; i++) {
+            total += cells[i].count;
+            for (int c = 0; c < 3; c++) {
+                // Cell centers, back at 8 bits per channel
+                sum[c] += cells[i].count * ((cells[i].rgb[c] << (8 - GIF_HISTOGRAM_BITS)) | (1 << (7 - GIF_HISTOGRAM_BITS)));
+            }
+        }
+        total = FFMAX(total, 1);
+        palette[b] = 0xff000000u | (uint32_t)(sum[0] / total) << 16 | (uint32_t)(sum[1] / total) << 8 | (uint32_t)(sum[2] / total);
+    }
+    
+    av_free(cells);
+    return nb_cells ? nb_boxes : 0;
+}
+
+// Index of the palette color nearest to an RGB color, cached per histogram cell
+static int nearest_palette_color(const uint32_t *palette, int nb_colors, int16_t *cache, int r, int g, int b) {
+    int cell = (r >> (8 - GIF_HISTOGRAM_BITS)) << (2 * GIF_HISTOGRAM_BITS) |
+               (g >> (8 - GIF_HISTOGRAM_BITS)) << GIF_HISTOGRAM_BITS |
+               (b >> (8 - GIF_HISTOGRAM_BITS));
+    
+    if (cache[cell] < 0) {
+        int best = 0, best_distance = INT_MAX;
+        for (int i = 0; i < nb_colors; i++) {
+            int dr = (int)(palette[i] >> 16 & 0xff) - r;
+            int dg = (int)(palette[i] >> 8 & 0xff) - g;
+            int db = (int)(palette[i] & 0xff) - b;
+            int distance = dr * dr + dg * dg + db * db;
+            if (distance < best_distance) {
+                best = i;
+                best_distance = distance;
+            }
+        }
+        cache[cell] = best;
+    }
+    
+    return cache[cell];
+}
+
+// Map an RGB24 frame onto the palette of a PAL8 frame. dither is "none", "bayer"
+// (8x8 ordered) or "floyd_steinberg" (error diffusion); errors is 2 rows of
+// (width + 2) * 3 values of scratch space.
+static void quantize_frame(const AVFrame *rgb, AVFrame *pal, const uint32_t *palette, int nb_colors,
+                           int16_t *cache, const char *dither, int *errors) {
+    static const uint8_t bayer[8][8] = {
+        {  0, 32,  8, 40,  2, 34, 10, 42 }, { 48, 16, 56, 24, 50, 18, 58, 26 },
+        { 12, 44,  4, 36, 14, 46,  6, 38 }, { 60, 28, 52, 20, 62, 30, 54, 22 },
+        {  3, 35, 11, 43,  1, 33,  9, 41 }, { 51, 19, 59, 27, 49, 17, 57, 25 },
+        { 15, 47,  7, 39, 13, 45,  5, 37 }, { 63, 31, 55, 23, 61, 29, 53, 21 },
+    };
+    int use_bayer = strcmp(dither, "bayer") == 0;
+    int use_diffusion = strcmp(dither, "floyd_steinberg") == 0;
+    int row_size = (rgb->width + 2) * 3;
+    
+    memcpy(pal->data[1], palette, nb_colors * sizeof(*palette));
+    if (use_diffusion) {
+        memset(errors, 0, 2 * row_size * sizeof(*errors));
+    }
+    
+    for (int y = 0; y < rgb->height; y++) {
+        const uint8_t *src = rgb->data[0] + y * rgb->linesize[0];
+        uint8_t *dst = pal->data[0] + y * pal->linesize[0];
+        int *current = errors + (y & 1) * row_size;
+        int *next = errors + !(y & 1) * row_size;
+        
+        if (use_diffusion) {
+            memset(next, 0, row_size * sizeof(*next));
+        }
+        
+        for (int x = 0; x < rgb->width; x++) {
+            int color[3];
+            for (int c = 0; c < 3; c++) {
+                color[c] = src[x * 3 + c];
+                if (use_bayer) {
+                    color[c] += (bayer[y & 7][x & 7] - 32) / 4;
+                } else if (use_diffusion) {
+                    color[c] += current[(x + 1) * 3 + c] / 16;
+                }
+                color[c] = av_clip_uint8(color[c]);
+            }
+            
+            int index = nearest_palette_color(palette, nb_colors, cache, color[0], color[1], color[2]);
+            dst[x] = index;
+            
+            if (use_diffusion) {
+                int chosen[3] = { palette[index] >> 16 & 0xff, palette[index] >> 8 & 0xff, palette[index] & 0xff };
+                for (int c = 0; c < 3; c++) {
+                    int error = color[c] - chosen[c];
+                    current[(x + 2) * 3 + c] += error * 7;
+                    next[x * 3 + c] += error * 3;
+                    next[(x + 1) * 3 + c] += error * 5;
+                    next[(x + 2) * 3 + c] += error;
+                }
+            }
+        }
+    }
+}
+
+// Encode the range of the input as a GIF with a generated palette. Options: the reader's,
+// gif_colors (2-256), gif_dither and anim_loop (0 forever, -1 once, else extra plays)
+static int transcode_gif(MemoryInput *input, AVDictionary *options, TranscodeResult *result) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    AnimationReader reader = { 0 };
+    AVFormatContext *output_ctx = NULL;
+    AVCodecContext *enc_ctx = NULL;
+    AVDictionary *mux_options = NULL;
+    AVFrame *pal = NULL;
+    AVPacket *packet = NULL;
+    uint64_t *histogram = NULL;
+    int16_t *cache = NULL;
+    int *errors = NULL;
+    uint32_t palette[256] = { 0 };
+    int ret;
+    
+    int max_colors = av_clip(atoi(get_option(options, "gif_colors", "256")), 2, 256);
+    const char *dither = get_option(options, "gif_dither", "floyd_steinberg");
+    int loop = atoi(get_option(options, "anim_loop", "0"));
+    
+    ret = animation_reader_open(&reader, input, options, AV_PIX_FMT_RGB24, error, error_size);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    // Expected output frames, for progress
+    double range = reader.fmt_ctx->duration != AV_NOPTS_VALUE ?
+        reader.fmt_ctx->duration / (double)AV_TIME_BASE - reader.start : 0;
+    if (reader.duration > 0 && (range <= 0 || reader.duration < range)) {
+        range = reader.duration;
+    }
+    int64_t expected_frames = FFMAX((int64_t)(range * reader.fps), 1);
+    
+    // Pass 1: count the colors of the range
+    histogram = av_calloc(GIF_HISTOGRAM_SIZE, sizeof(*histogram));
+    cache = av_malloc_array(GIF_HISTOGRAM_SIZE, sizeof(*cache));
+    errors = av_calloc(2 * (reader.frame->width + 2) * 3, sizeof(*errors));
+    if (!histogram || !cache || !errors) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    animation_reader_rewind(&reader);
+    while ((ret = animation_reader_next(&reader)) > 0) {
+        for (int y = 0; y < reader.frame->height; y++) {
+            const uint8_t *row = reader.frame->data[0] + y * reader.frame->linesize[0];
+            for (int x = 0; x < reader.frame->width; x++) {
+                const uint8_t *p = row + x * 3;
+                histogram[(p[0] >> (8 - GIF_HISTOGRAM_BITS)) << (2 * GIF_HISTOGRAM_BITS) |
+                          (p[1] >> (8 - GIF_HISTOGRAM_BITS)) << GIF_HISTOGRAM_BITS |
+                          (p[2] >> (8 - GIF_HISTOGRAM_BITS))]++;
+            }
+        }
+        update_progress((int)FFMIN(reader.next_output * 40 / expected_frames, 40));
+    }
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    int nb_colors = build_palette(histogram, max_colors, palette);
+    if (nb_colors < 0) {
+        snprintf(error, error_size, "Out of memory");
+        ret = nb_colors;
+        goto end;
+    } else if (nb_colors == 0) {
+        snprintf(error, error_size, "No frames in the selected range");
+        ret = AVERROR(EINVAL);
+        goto end;
+    }
+    memset(cache, 0xff, GIF_HISTOGRAM_SIZE * sizeof(*cache));
+    
+    // Pass 2: encode every frame against the palette
+    const AVCodec *encoder = avcodec_find_encoder(AV_CODEC_ID_GIF);
+    if (!encoder) {
+        snprintf(error, error_size, "GIF encoder not found");
+        ret = AVERROR_ENCODER_NOT_FOUND;
+        goto end;
+    }
+    
+    enc_ctx = avcodec_alloc_context3(encoder);
+    pal = av_frame_alloc();
+    packet = av_packet_alloc();
+    if (!enc_ctx || !pal || !packet) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    // GIF frame delays are in hundredths of a second
+    enc_ctx->width = reader.frame->width;
+    enc_ctx->height = reader.frame->height;
+    enc_ctx->pix_fmt = AV_PIX_FMT_PAL8;
+    enc_ctx->time_base = (AVRational){1, 100};
+    ret = avcodec_open2(enc_ctx, encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    pal->format = AV_PIX_FMT_PAL8;
+    pal->width = enc_ctx->width;
+    pal->height = enc_ctx->height;
+    ret = av_frame_get_buffer(pal, 0);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    ret = avformat_alloc_output_context2(&output_ctx, NULL, "gif", NULL);
+    if (ret < 0) {
+        snprintf(error, error_size, "Failed to create output context");
+        goto end;
+    }
+    
+    AVStream *out_stream = avformat_new_stream(output_ctx, NULL);
+    unsigned char *io_buffer = av_malloc(4096);
+    if (!out_stream || !io_buffer) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    avcodec_parameters_from_context(out_stream->codecpar, enc_ctx);
+    out_stream->time_base = enc_ctx->time_base;
+    
+    output_ctx->pb = avio_alloc_context(io_buffer, 4096, 1, result, NULL, write_packet, NULL);
+    if (!output_ctx->pb) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Failed to create output IO context");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    av_dict_set_int(&mux_options, "loop", loop, 0);
+    ret = avformat_write_header(output_ctx, &mux_options);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    animation_reader_rewind(&reader);
+    for (;;) {
+        ret = animation_reader_next(&reader);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            goto end;
+        }
+        
+        if (ret > 0) {
+            ret = av_frame_make_writable(pal);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                goto end;
+            }
+            quantize_frame(reader.frame, pal, palette, nb_colors, cache, dither, errors);
+            pal->pts = av_rescale(reader.next_output - 1, 100, reader.fps);
+            pal->duration = av_rescale(reader.next_output, 100, reader.fps) - pal->pts;
+            ret = avcodec_send_frame(enc_ctx, pal);
+        } else {
+            ret = avcodec_send_frame(enc_ctx, NULL);
+        }
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            goto end;
+        }
+        
+        while ((ret = avcodec_receive_packet(enc_ctx, packet)) >= 0) {
+            av_packet_rescale_ts(packet, enc_ctx->time_base, out_stream->time_base);
+            packet->stream_index = out_stream->index;
+            ret = av_interleaved_write_frame(output_ctx, packet);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                goto end;
+            }
+        }
+        if (ret == AVERROR_EOF) {
+            break;
+        }
+        
+        update_progress((int)FFMIN(40 + reader.next_output * 60 / expected_frames, 99));
+    }
+    
+    ret = av_write_trailer(output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+end:
+    if (output_ctx) {
+        if (output_ctx->pb) {
+            av_freep(&output_ctx->pb->buffer);
+            avio_context_free(&output_ctx->pb);
+        }
+        avformat_free_context(output_ctx);
+    }
+    av_dict_free(&mux_options);
+    av_packet_free(&packet);
+    av_frame_free(&pal);
+    avcodec_free_context(&enc_ctx);
+    av_free(histogram);
+    av_free(cache);
+    av_free(errors);
+    animation_reader_close(&reader);
+    return ret;
+}
+
+// Encode the range of the input as an animated WebP or APNG. Options: the reader's,
+// anim_loop (0 forever, -1 once, else extra plays), and for WebP anim_quality (0-100)
+// and anim_lossless; APNG is always lossless
+static int transcode_animation(MemoryInput *input, AVDictionary *options, const char *format, TranscodeResult *result) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    AnimationReader reader = { 0 };
+    AVFormatContext *output_ctx = NULL;
+    AVCodecContext *enc_ctx = NULL;
+    AVDictionary *codec_options = NULL;
+    AVDictionary *mux_options = NULL;
+    AVPacket *packet = NULL;
+    int ret;
+    
+    int webp = strcmp(format, "webp") == 0;
+    int lossless = atoi(get_option(options, "anim_lossless", "0"));
+    int loop = atoi(get_option(options, "anim_loop", "0"));
+    
+    // Both containers count plays rather than repeats, with 0 for forever
+    int plays = loop == 0 ? 0 : loop < 0 ? 1 : loop + 1;
+    
+    enum AVPixelFormat pix_fmt = !webp ? AV_PIX_FMT_RGB24 : lossless ? AV_PIX_FMT_RGB32 : AV_PIX_FMT_YUV420P;
+    ret = animation_reader_open(&reader, input, options, pix_fmt, error, error_size);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    // Expected output frames, for progress
+    double range = reader.fmt_ctx->duration != AV_NOPTS_VALUE ?
+        reader.fmt_ctx->duration / (double)AV_TIME_BASE - reader.start : 0;
+    if (reader.duration > 0 && (range <= 0 || reader.duration < range)) {
+        range = reader.duration;
+    }
+    int64_t expected_frames = FFMAX((int64_t)(range * reader.fps), 1);
+    
+    const AVCodec *encoder = avcodec_find_encoder_by_name(webp ? "libwebp_anim" : "apng");
+    if (!encoder) {
+        snprintf(error, error_size, "%s encoder not found", webp ? "Animated WebP" : "APNG");
+        ret = AVERROR_ENCODER_NOT_FOUND;
+        goto end;
+    }
+    
+    enc_ctx = avcodec_alloc_context3(encoder);
+    packet = av_packet_alloc();
+    if (!enc_ctx || !packet) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    enc_ctx->width = reader.frame->width;
+    enc_ctx->height = reader.frame->height;
+    enc_ctx->pix_fmt = pix_fmt;
+    enc_ctx->time_base = (AVRational){1, reader.fps};
+    enc_ctx->framerate = (AVRational){reader.fps, 1};
+    if (webp) {
+        av_dict_set(&codec_options, "lossless", lossless ? "1" : "0", 0);
+        av_dict_set(&codec_options, "quality", get_option(options, "anim_quality", "75"), 0);
+    }
+    ret = avcodec_open2(enc_ctx, encoder, &codec_options);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    ret = avformat_alloc_output_context2(&output_ctx, NULL, format, NULL);
+    if (ret < 0) {
+        snprintf(error, error_size, "Failed to create output context");
+        goto end;
+    }
+    
+    AVStream *out_stream = avformat_new_stream(output_ctx, NULL);
+    unsigned char *io_buffer = av_malloc(4096);
+    if (!out_stream || !io_buffer) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    avcodec_parameters_from_context(out_stream->codecpar, enc_ctx);
+    out_stream->time_base = enc_ctx->time_base;
+    
+    // Both muxers seek back to fill in the loop and frame counts
+    output_ctx->pb = avio_alloc_context(io_buffer, 4096, 1, result, NULL, write_packet, seek_output);
+    if (!output_ctx->pb) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Failed to create output IO context");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    av_dict_set_int(&mux_options, webp ? "loop" : "plays", plays, 0);
+    ret = avformat_write_header(output_ctx, &mux_options);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    animation_reader_rewind(&reader);
+    while ((ret = animation_reader_next(&reader)) > 0) {
+        reader.frame->pts = reader.next_output - 1;
+        reader.frame->duration = 1;
+        ret = process_packet(output_ctx, enc_ctx, reader.frame, packet, out_stream->index);
+        if (ret < 0) {
+            break;
+        }
+        update_progress((int)FFMIN(reader.next_output * 99 / expected_frames, 99));
+    }
+    if (ret == 0) {
+        ret = process_packet(output_ctx, enc_ctx, NULL, packet, out_stream->index);
+    }
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    if (reader.next_output == 0) {
+        snprintf(error, error_size, "No frames in the selected range");
+        ret = AVERROR(EINVAL);
+        goto end;
+    }
+    
+    ret = av_write_trailer(output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+end:
+    if (output_ctx) {
+        if (output_ctx->pb) {
+            av_freep(&output_ctx->pb->buffer);
+            avio_context_free(&output_ctx->pb);
+        }
+        avformat_free_context(output_ctx);
+    }
+    av_dict_free(&codec_options);
+    av_dict_free(&mux_options);
+    av_packet_free(&packet);
+    avcodec_free_context(&enc_ctx);
+    animation_reader_close(&reader);
+    return ret;
+}
+
+// Decode the picture of a registered image input
+static int decode_image_input(int input, AVFrame *frame, char *error, size_t error_size) {
+    AVFormatContext *fmt_ctx = NULL;
+    AVCodecContext *dec_ctx = NULL;
+    AVStream *stream = NULL;
+    
+    extra_inputs[input - 1].pos = 0;
+    int ret = open_video_input(&extra_inputs[input - 1], &fmt_ctx, &stream, &dec_ctx, error, error_size);
+    if (ret >= 0) {
+        ret = decode_frame_at(fmt_ctx, stream, dec_ctx, 0.0, frame);
+        if (ret < 0) {
+            snprintf(error, error_size, "Could not decode image %d", input);
+        }
+    }
+    
+    avcodec_free_context(&dec_ctx);
+    close_memory_input(&fmt_ctx);
+    return ret;
+}
+
+// Scale an image into the middle of an output frame, keeping its display aspect
+// ratio; the rest of the frame is left black
+static int fit_image(struct SwsContext **sws_ctx, const AVFrame *image, AVFrame *out) {
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(out->format);
+    AVRational sar = image->sample_aspect_ratio.num > 0 ? image->sample_aspect_ratio : (AVRational){1, 1};
+    int display_width = FFMAX(1, (int)av_rescale(image->width, sar.num, sar.den));
+    int max_pixsteps[4];
+    
+    int width = out->width;
+    int height = (int)av_rescale(width, image->height, display_width);
+    if (height > out->height) {
+        height = out->height;
+        width = (int)av_rescale(height, display_width, image->height);
+    }
+    
+    // Offsets land on whole chroma samples
+    int x = ((out->width - width) / 2) & ~((1 << desc->log2_chroma_w) - 1);
+    int y = ((out->height - height) / 2) & ~((1 << desc->log2_chroma_h) - 1);
+    width = FFMAX(width, 1);
+    height = FFMAX(height, 1);
+    
+    *sws_ctx = sws_getCachedContext(*sws_ctx, image->width, image->height, image->format,
+                                    width, height, out->format, SWS_BICUBIC, NULL, NULL, NULL);
+    if (!*sws_ctx) {
+        return AVERROR(EINVAL);
+    }
+    
+    int ret = av_image_fill_black(out->data, (const ptrdiff_t[4]){ out->linesize[0], out->linesize[1], out->linesize[2], out->linesize[3] },
+                                  out->format, out->color_range, out->width, out->height);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    uint8_t *data[4] = { NULL };
+    av_image_fill_max_pixsteps(max_pixsteps, NULL, desc);
+    for (int p = 0; p < 4 && out->data[p]; p++) {
+        int chroma = (p == 1 || p == 2) && !(desc->flags & AV_PIX_FMT_FLAG_RGB);
+        data[p] = out->data[p] + (y >> (chroma ? desc->log2_chroma_h : 0)) * out->linesize[p] +
+                  (x >> (chroma ? desc->log2_chroma_w : 0)) * max_pixsteps[p];
+    }
+    
+    sws_scale(*sws_ctx, (const uint8_t * const *)image->data, image->linesize, 0, image->height, data, out->linesize);
+    return 0;
+}
+
+// Encode the registered image inputs 1..images, in order, as the frames of a video.
+// Options: images (the count), image_fps, and audio_input (an extra input after the
+// images whose first audio stream becomes the soundtrack, cut at the end of the video).
+// A resolution of "same" takes the size of the first image.
+static int transcode_images(
+    AVDictionary *options,
+    const char *output_format,
+    const char *video_codec_name,
+    const char *audio_codec_name,
+    int video_bitrate,
+    int audio_bitrate,
+    const char *resolution,
+    TranscodeResult *result
+) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    AVFormatContext *output_ctx = NULL;
+    AVCodecContext *enc_ctx = NULL;
+    struct SwsContext *sws_ctx = NULL;
+    AVFrame *image = NULL;
+    AVFrame *frame = NULL;
+    AVFrame *audio_frame = NULL;
+    AVPacket *packet = NULL;
+    OutputStream audio = { 0 };
+    int has_audio = 0;
+    int ret;
+    
+    int nb_images = atoi(get_option(options, "images", "0"));
+    int audio_input = atoi(get_option(options, "audio_input", "0"));
+    AVRational frame_rate = av_d2q(av_clipd(atof(get_option(options, "image_fps", "25")), 0.1, 120), 100000);
+    
+    if (nb_images < 1 || nb_images > nb_extra_inputs) {
+        snprintf(error, error_size, "No images to encode");
+        return AVERROR(EINVAL);
+    }
+    
+    image = av_frame_alloc();
+    frame = av_frame_alloc();
+    audio_frame = av_frame_alloc();
+    packet = av_packet_alloc();
+    if (!image || !frame || !audio_frame || !packet) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    // The first image sizes the video unless a resolution is set
+    ret = decode_image_input(1, image, error, error_size);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    ret = avformat_alloc_output_context2(&output_ctx, NULL, output_format, NULL);
+    if (ret < 0 || !output_ctx) {
+        snprintf(error, error_size, "Failed to create output context");
+        ret = ret < 0 ? ret : AVERROR(EINVAL);
+        goto end;
+    }
+    
+    unsigned char *io_buffer = av_malloc(4096);
+    output_ctx->pb = io_buffer ? avio_alloc_context(io_buffer, 4096, 1, result, NULL, write_packet, seek_output) : NULL;
+    if (!output_ctx->pb) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Failed to create output IO context");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    const AVCodec *encoder = avcodec_find_encoder_by_name(video_codec_name);
+    if (!encoder) {
+        snprintf(error, error_size, "Video encoder '%s' not found", video_codec_name);
+        ret = AVERROR_ENCODER_NOT_FOUND;
+        goto end;
+    }
+    
+    AVStream *video_stream = avformat_new_stream(output_ctx, NULL);
+    enc_ctx = avcodec_alloc_context3(encoder);
+    if (!video_stream || !enc_ctx) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    AVRational sar = image->sample_aspect_ratio.num > 0 ? image->sample_aspect_ratio : (AVRational){1, 1};
+    int width = FFMAX(1, (int)av_rescale(image->width, sar.num, sar.den));
+    int height = image->height;
+    parse_resolution(resolution, &width, &height);
+    
+    const char *pix_fmt_name = get_option(options, "pix_fmt", "auto");
+    enc_ctx->pix_fmt = strcmp(pix_fmt_name, "auto") == 0
+        ? default_pix_fmt(output_ctx->oformat, encoder, image->format)
+        : av_get_pix_fmt(pix_fmt_name);
+    if (enc_ctx->pix_fmt == AV_PIX_FMT_NONE || !encoder_supports_pix_fmt(encoder, enc_ctx->pix_fmt)) {
+        snprintf(error, error_size, "Pixel format '%s' is not supported by encoder '%s'", pix_fmt_name, video_codec_name);
+        ret = AVERROR(EINVAL);
+        goto end;
+    }
+    
+    // Subsampled formats need even dimensions
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(enc_ctx->pix_fmt);
+    enc_ctx->width = FFMAX(width & ~((1 << desc->log2_chroma_w) - 1), 2);
+    enc_ctx->height = FFMAX(height & ~((1 << desc->log2_chroma_h) - 1), 2);
+    enc_ctx->sample_aspect_ratio = (AVRational){1, 1};
+    enc_ctx->time_base = av_inv_q(frame_rate);
+    enc_ctx->framerate = frame_rate;
+    enc_ctx->gop_size = 25;
+    enc_ctx->max_b_frames = 3;
+    enc_ctx->bit_rate = video_bitrate * 1000;
+    if (output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+        enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+    }
+    
+    ret = avcodec_open2(enc_ctx, encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    ret = avcodec_parameters_from_context(video_stream->codecpar, enc_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    video_stream->time_base = enc_ctx->time_base;
+    
+    // Soundtrack from another file, muxed alongside like the extra input subtitles
+    if (audio_input > 0 && audio_codec_name && audio_codec_name[0]) {
+        AVFormatContext *audio_ctx = NULL;
+        MemoryInput *audio_mem = audio_input <= nb_extra_inputs ? &extra_inputs[audio_input - 1] : NULL;
+        
+        ret = audio_mem ? open_extra_input(audio_input, &audio_ctx) : AVERROR(EINVAL);
+        if (ret < 0) {
+            snprintf(error, error_size, "Failed to read the audio file");
+            goto end;
+        }
+        
+        int index = av_find_best_stream(audio_ctx, AVMEDIA_TYPE_AUDIO, -1, -1, NULL, 0);
+        if (index < 0) {
+            close_memory_input(&audio_ctx);
+            snprintf(error, error_size, "The audio file contains no audio");
+            ret = index;
+            goto end;
+        }
+        
+        int nb_streams = 0;
+        OutputStream *ost = add_output_stream(output_ctx, &audio, &nb_streams, audio_ctx->streams[index]);
+        has_audio = ost != NULL;
+        audio.input_ctx = audio_ctx;
+        audio.next_pkt = av_packet_alloc();
+        if (!ost || !audio.next_pkt) {
+            snprintf(error, error_size, "Out of memory");
+            ret = AVERROR(ENOMEM);
+            goto end;
+        }
+        
+        ret = setup_audio_stream(&audio, audio_ctx, output_ctx, (uint8_t *)audio_mem->data, audio_mem->size,
+            audio_codec_name, audio_bitrate, options, error, error_size);
+        if (ret < 0) {
+            goto end;
+        }
+    }
+    
+    ret = write_output_header(output_ctx, options, error, error_size);
+    if (ret < 0) {
+        goto end;
+    }
+    
+    frame->format = enc_ctx->pix_fmt;
+    frame->width = enc_ctx->width;
+    frame->height = enc_ctx->height;
+    frame->color_range = enc_ctx->color_range;
+    ret = av_frame_get_buffer(frame, 0);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    for (int i = 1; i <= nb_images; i++) {
+        if (i > 1) {
+            av_frame_unref(image);
+            ret = decode_image_input(i, image, error, error_size);
+            if (ret < 0) {
+                goto end;
+            }
+        }
+        
+        // Audio up to this frame's time goes in first
+        if (has_audio) {
+            int64_t now = av_rescale_q(i - 1, enc_ctx->time_base, AV_TIME_BASE_Q);
+            ret = mux_extra_input_stream(&audio, output_ctx, now, audio_frame, packet);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                goto end;
+            }
+        }
+        
+        ret = av_frame_make_writable(frame);
+        if (ret >= 0) {
+            ret = fit_image(&sws_ctx, image, frame);
+        }
+        if (ret < 0) {
+            snprintf(error, error_size, "Could not scale image %d", i);
+            goto end;
+        }
+        
+        frame->pts = i - 1;
+        ret = process_packet(output_ctx, enc_ctx, frame, packet, video_stream->index);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            goto end;
+        }
+        
+        update_progress(i * 99 / nb_images);
+    }
+    
+    ret = process_packet(output_ctx, enc_ctx, NULL, packet, video_stream->index);
+    if (ret >= 0 && has_audio) {
+        // The soundtrack stops with the last image
+        int64_t end = av_rescale_q(nb_images, enc_ctx->time_base, AV_TIME_BASE_Q);
+        ret = mux_extra_input_stream(&audio, output_ctx, end, audio_frame, packet);
+        if (ret >= 0) {
+            ret = flush_output_stream(&audio, output_ctx, audio_frame, packet);
+        }
+    }
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    ret = av_write_trailer(output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+end:
+    free_output_stream(&audio);
+    if (output_ctx) {
+        if (output_ctx->pb) {
+            av_freep(&output_ctx->pb->buffer);
+            avio_context_free(&output_ctx->pb);
+        }
+        avformat_free_context(output_ctx);
+    }
+    sws_freeContext(sws_ctx);
+    av_packet_free(&packet);
+    av_frame_free(&audio_frame);
+    av_frame_free(&frame);
+    av_frame_free(&image);
+    avcodec_free_context(&enc_ctx);
+    return ret;
+}
+
+// One input of a concatenation
+typedef struct {
+    AVFormatContext *fmt_ctx;
+    AVStream *video;
+    AVStream *audio;
+    AVCodecContext *video_dec;
+    AVCodecContext *audio_dec;
+    SwrContext *swr_ctx;
+    int64_t start;      // First timestamp, AV_TIME_BASE units
+} ConcatClip;
+
+// Encoders and running positions shared by all clips of a re-encoded concatenation
+typedef struct {
+    AVFormatContext *output_ctx;
+    AVCodecContext *video_enc;
+    AVCodecContext *audio_enc;
+    AVStream *video_stream;
+    AVStream *audio_stream;
+    struct SwsContext *sws_ctx;
+    
+    // The last picture is repeated until the next one is due, which gives a constant frame rate
+    AVFrame *held;
+    int has_held;
+    int64_t next_video_pts;
+    
+    AVAudioFifo *fifo;
+    AudioEffects effects;
+    int64_t next_audio_pts;
+    int64_t audio_samples;  // Samples queued so far, silence included
+    
+    // Transitions: the end of each clip is held back so the next one can overlap it.
+    // Pictures over an overlap are blended and samples mixed, each over a window
+    // centred in the overlap.
+    int transition;
+    int64_t transition_frames;
+    AVFrame **tail;         // Ring of held-back pictures
+    int tail_size;
+    int tail_start;
+    int tail_count;
+    int tail_capacity;      // Pictures to hold back at the end of the current clip
+    int overlap_frames;
+    int overlap_frames_left;
+    AVFrame *black;
+    
+    AVAudioFifo *incoming;  // Decoded samples on their way to `pending` or the mix
+    AVAudioFifo *pending;   // Held-back samples
+    int64_t pending_capacity;
+    int64_t crossfade_samples;
+    int64_t overlap_samples;
+    int64_t overlap_samples_left;
+    uint8_t **mix_a;
+    uint8_t **mix_b;
+    
+    AVPacket *pkt;
+} ConcatOutput;
+
+enum {
+    TRANSITION_NONE,
+    TRANSITION_FADE,
+    TRANSITION_DISSOLVE,
+    TRANSITION_WIPE,
+};
+
+// Samples mixed at a time across a join
+#define MIX_CHUNK 4096
+
+static void concat_clip_close_decoders(ConcatClip *clip) {
+    avcodec_free_context(&clip->video_dec);
+    avcodec_free_context(&clip->audio_dec);
+    swr_free(&clip->swr_ctx);
+}
+
+static int concat_clip_open(ConcatClip *clip, int input, char *error, size_t error_size) {
+    int ret = open_extra_input(input, &clip->fmt_ctx);
+    if (ret < 0) {
+        snprintf(error, error_size, "Could not read clip %d", input);
+        return ret;
+    }
+    
+    for (unsigned int i = 0; i < clip->fmt_ctx->nb_streams; i++) {
+        AVStream *stream = clip->fmt_ctx->streams[i];
+        if (stream->codecpar->codec_type == AVMEDIA_TYPE_VIDEO && !clip->video &&
+            !(stream->disposition & AV_DISPOSITION_ATTACHED_PIC)) {
+            clip->video = stream;
+        } else if (stream->codecpar->codec_type == AVMEDIA_TYPE_AUDIO && !clip->audio) {
+            clip->audio = stream;
+        }
+    }
+    
+    if (!clip->video && !clip->audio) {
+        snprintf(error, error_size, "Clip %d has no audio or video", input);
+        return AVERROR_STREAM_NOT_FOUND;
+    }
+    
+    clip->start = clip->fmt_ctx->start_time != AV_NOPTS_VALUE ? clip->fmt_ctx->start_time : 0;
+    return 0;
+}
+
+static void concat_clip_close(ConcatClip *clip) {
+    concat_clip_close_decoders(clip);
+    close_memory_input(&clip->fmt_ctx);
+}
+
+static int same_extradata(const AVCodecParameters *a, const AVCodecParameters *b) {
+    return a->extradata_size == b->extradata_size &&
+        (a->extradata_size == 0 || memcmp(a->extradata, b->extradata, a->extradata_size) == 0);
+}
+
+// Clips can be joined without re-encoding when every one has the same streams,
+// coded the same way, and the output container can hold them
+static int clips_can_be_copied(const ConcatClip *clips, int nb_clips, const AVOutputFormat *oformat) {
+    const AVCodecParameters *video = clips[0].video ? clips[0].video->codecpar : NULL;
+    const AVCodecParameters *audio = clips[0].audio ? clips[0].audio->codecpar : NULL;
+    
+    if ((video && avformat_query_codec(oformat, video->codec_id, FF_COMPLIANCE_NORMAL) == 0) ||
+        (audio && avformat_query_codec(oformat, audio->codec_id, FF_COMPLIANCE_NORMAL) == 0)) {
+        return 0;
+    }
+    
+    for (int i = 1; i < nb_clips; i++) {
+        if (!clips[i].video != !video || !clips[i].audio != !audio) {
+            return 0;
+        }
+        
+        const AVCodecParameters *v = clips[i].video ? clips[i].video->codecpar : NULL;
+        if (v && (v->codec_id != video->codec_id || v->width != video->width || v->height != video->height ||
+                  v->format != video->format || !same_extradata(v, video))) {
+            return 0;
+        }
+        
+        const AVCodecParameters *a = clips[i].audio ? clips[i].audio->codecpar : NULL;
+        if (a && (a->codec_id != audio->codec_id || a->sample_rate != audio->sample_rate ||
+                  a->ch_layout.nb_channels != audio->ch_layout.nb_channels || !same_extradata(a, audio))) {
+            return 0;
+        }
+    }
+    
+    return 1;
+}
+
+// Join the clips' packets as they are, shifting each clip's timestamps to start
+// where the previous one ended
+static int concat_copy(ConcatClip *clips, int nb_clips, AVFormatContext *output_ctx, AVDictionary *options,
+                       AVPacket *pkt, TranscodeResult *result) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    AVStream *out_video = NULL;
+    AVStream *out_audio = NULL;
+    int64_t last_dts[2] = { AV_NOPTS_VALUE, AV_NOPTS_VALUE };
+    int64_t offset = 0;
+    int ret;
+    
+    AVStream *sources[2] = { clips[0].video, clips[0].audio };
+    AVStream **outputs[2] = { &out_video, &out_audio };
+    for (int k = 0; k < 2; k++) {
+        if (!sources[k]) {
+            continue;
+        }
+        
+        *outputs[k] = avformat_new_stream(output_ctx, NULL);
+        if (!*outputs[k]) {
+            snprintf(error, error_size, "Out of memory");
+            return AVERROR(ENOMEM);
+        }
+        ret = avcodec_parameters_copy((*outputs[k])->codecpar, sources[k]->codecpar);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        (*outputs[k])->codecpar->codec_tag = 0;
+        (*outputs[k])->time_base = sources[k]->time_base;
+    }
+    
+    ret = write_output_header(output_ctx, options, error, error_size);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    for (int i = 0; i < nb_clips; i++) {
+        ConcatClip *clip = &clips[i];
+        int64_t clip_end = 0;
+        
+        while ((ret = av_read_frame(clip->fmt_ctx, pkt)) >= 0) {
+            int k = clip->video && pkt->stream_index == clip->video->index ? 0 :
+                    clip->audio && pkt->stream_index == clip->audio->index ? 1 : -1;
+            if (k < 0) {
+                av_packet_unref(pkt);
+                continue;
+            }
+            
+            AVStream *in = k == 0 ? clip->video : clip->audio;
+            AVStream *out = *outputs[k];
+            int64_t shift = av_rescale_q(offset - clip->start, AV_TIME_BASE_Q, in->time_base);
+            
+            if (pkt->pts != AV_NOPTS_VALUE) {
+                pkt->pts += shift;
+                clip_end = FFMAX(clip_end, av_rescale_q(pkt->pts + pkt->duration, in->time_base, AV_TIME_BASE_Q) - offset);
+            }
+            if (pkt->dts != AV_NOPTS_VALUE) {
+                pkt->dts += shift;
+            }
+            av_packet_rescale_ts(pkt, in->time_base, out->time_base);
+            
+            // Rounding at a join can leave a timestamp where the last clip ended
+            if (pkt->dts != AV_NOPTS_VALUE && last_dts[k] != AV_NOPTS_VALUE && pkt->dts <= last_dts[k]) {
+                pkt->dts = last_dts[k] + 1;
+                if (pkt->pts != AV_NOPTS_VALUE && pkt->pts < pkt->dts) {
+                    pkt->pts = pkt->dts;
+                }
+            }
+            if (pkt->dts != AV_NOPTS_VALUE) {
+                last_dts[k] = pkt->dts;
+            }
+            
+            if (clip->fmt_ctx->duration > 0 && pkt->pts != AV_NOPTS_VALUE) {
+                int64_t done = av_rescale_q(pkt->pts, out->time_base, AV_TIME_BASE_Q) - offset;
+                update_progress((int)((i * 100 + av_clip(done * 100 / clip->fmt_ctx->duration, 0, 100)) * 99 / (nb_clips * 100)));
+            }
+            
+            pkt->stream_index = out->index;
+            pkt->pos = -1;
+            ret = av_interleaved_write_frame(output_ctx, pkt);
+            av_packet_unref(pkt);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                return ret;
+            }
+        }
+        if (ret != AVERROR_EOF) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        
+        offset += clip_end > 0 ? clip_end : FFMAX(clip->fmt_ctx->duration, 0);
+    }
+    
+    return 0;
+}
+
+// Overlap in AV_TIME_BASE units between two clips for a requested length: at most
+// half of either clip, so a clip never overlaps both neighbours at once
+static int64_t join_overlap(const ConcatClip *a, const ConcatClip *b, int64_t requested) {
+    if (a->fmt_ctx->duration > 0) requested = FFMIN(requested, a->fmt_ctx->duration / 2);
+    if (b->fmt_ctx->duration > 0) requested = FFMIN(requested, b->fmt_ctx->duration / 2);
+    return FFMAX(requested, 0);
+}
+
+// The overlap asked for between clips: long enough for both the picture transition and the crossfade
+static int64_t requested_overlap(AVDictionary *options) {
+    double video = strcmp(get_option(options, "transition", "none"), "none") != 0 ?
+        atof(get_option(options, "transition_duration", "0")) : 0.0;
+    double audio = atof(get_option(options, "audio_crossfade", "0"));
+    return (int64_t)(av_clipd(FFMAX(video, audio), 0.0, 60.0) * AV_TIME_BASE);
+}
+
+// Progress through a transition `window` steps long centred in an overlap of
+// `length` steps, at step `k`: 0 is all outgoing clip, 1 all incoming. Without a
+// window the clips cut over in the middle.
+static double transition_position(int64_t k, int64_t length, int64_t window) {
+    if (window <= 0) {
+        return 2 * k + 1 >= length ? 1.0 : 0.0;
+    }
+    double start = (length - window) / 2.0;
+    return av_clipd((k + 0.5 - start) / window, 0.0, 1.0);
+}
+
+// Move up to `nb_samples` samples from one FIFO to another
+static int move_samples(AVAudioFifo *from, AVAudioFifo *to, uint8_t **buffer, int64_t nb_samples) {
+    while (nb_samples > 0) {
+        int n = av_audio_fifo_read(from, (void **)buffer, (int)FFMIN(nb_samples, MIX_CHUNK));
+        if (n <= 0) {
+            return n;
+        }
+        int ret = av_audio_fifo_write(to, (void **)buffer, n);
+        if (ret < 0) {
+            return ret;
+        }
+        nb_samples -= n;
+    }
+    return 0;
+}
+
+// Pass the incoming samples on towards the encoder: over a join they are crossfaded
+// (equal power) with the held-back end of the previous clip, otherwise the last
+// `pending_capacity` samples stay behind for the next join
+static int concat_take_samples(ConcatOutput *out) {
+    AVCodecContext *enc_ctx = out->audio_enc;
+    int nb_channels = enc_ctx->ch_layout.nb_channels;
+    int ret = 0;
+    
+    while (ret >= 0 && av_audio_fifo_size(out->incoming) > 0) {
+        if (out->overlap_samples_left > 0) {
+            int n = (int)FFMIN(FFMIN(av_audio_fifo_size(out->incoming), out->overlap_samples_left), MIX_CHUNK);
+            n = av_audio_fifo_read(out->pending, (void **)out->mix_a, n);
+            if (n <= 0) {
+                // Nothing left to mix with
+                out->overlap_samples_left = 0;
+                continue;
+            }
+            ret = av_audio_fifo_read(out->incoming, (void **)out->mix_b, n);
+            if (ret < 0) {
+                break;
+            }
+            
+            for (int i = 0; i < n; i++) {
+                int64_t k = out->overlap_samples - out->overlap_samples_left + i;
+                double t = transition_position(k, out->overlap_samples, FFMIN(out->crossfade_samples, out->overlap_samples));
+                double gain_out = cos(t * M_PI_2);
+                double gain_in = sin(t * M_PI_2);
+                for (int c = 0; c < nb_channels; c++) {
+                    double mixed = get_sample(out->mix_a, enc_ctx->sample_fmt, nb_channels, c, i) * gain_out +
+                                   get_sample(out->mix_b, enc_ctx->sample_fmt, nb_channels, c, i) * gain_in;
+                    set_sample(out->mix_a, enc_ctx->sample_fmt, nb_channels, c, i, mixed);
+                }
+            }
+            
+            out->overlap_samples_left -= n;
+            ret = av_audio_fifo_write(out->fifo, (void **)out->mix_a, n);
+        } else {
+            ret = move_samples(out->incoming, out->pending, out->mix_a, av_audio_fifo_size(out->incoming));
+            if (ret >= 0 && av_audio_fifo_size(out->pending) > out->pending_capacity) {
+                ret = move_samples(out->pending, out->fifo, out->mix_a, av_audio_fifo_size(out->pending) - out->pending_capacity);
+            }
+        }
+    }
+    
+    if (ret >= 0) {
+        ret = encode_audio_fifo(out->output_ctx, enc_ctx, out->fifo, &out->effects, out->pkt,
+            out->audio_stream->index, &out->next_audio_pts, 0);
+    }
+    return ret;
+}
+
+// Queue silence behind the decoded audio
+static int queue_silence(ConcatOutput *out, int64_t nb_samples) {
+    AVCodecContext *enc_ctx = out->audio_enc;
+    int ret = 0;
+    
+    while (nb_samples > 0 && ret >= 0) {
+        // The mix buffer is reused by the crossfade, so it is cleared every time
+        int n = (int)FFMIN(nb_samples, MIX_CHUNK);
+        av_samples_set_silence(out->mix_b, 0, n, enc_ctx->ch_layout.nb_channels, enc_ctx->sample_fmt);
+        ret = av_audio_fifo_write(out->incoming, (void **)out->mix_b, n);
+        if (ret >= 0) {
+            out->audio_samples += n;
+            nb_samples -= n;
+            ret = concat_take_samples(out);
+        }
+    }
+    
+    return ret;
+}
+
+// Rows in plane `plane` of a picture `height` rows tall
+static int plane_rows(const AVPixFmtDescriptor *desc, int plane, int height) {
+    int chroma = (plane == 1 || plane == 2) && !(desc->flags & AV_PIX_FMT_FLAG_RGB);
+    return chroma ? AV_CEIL_RSHIFT(height, desc->log2_chroma_h) : height;
+}
+
+// Mix picture `b` into `a` in place: t = 0 leaves `a`, 1 gives `b`
+static void dissolve_pictures(AVFrame *a, const AVFrame *b, double t) {
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(a->format);
+    int weight = (int)lrint(t * 256);
+    int wide = desc->comp[0].depth > 8;
+    
+    for (int p = 0; p < av_pix_fmt_count_planes(a->format); p++) {
+        int bytes = av_image_get_linesize(a->format, a->width, p);
+        for (int y = 0; y < plane_rows(desc, p, a->height); y++) {
+            uint8_t *dst = a->data[p] + y * a->linesize[p];
+            const uint8_t *src = b->data[p] + y * b->linesize[p];
+            if (wide) {
+                uint16_t *dst16 = (uint16_t *)dst;
+                const uint16_t *src16 = (const uint16_t *)src;
+                for (int x = 0; x < bytes / 2; x++) {
+                    dst16[x] = (uint16_t)((dst16[x] * (256 - weight) + src16[x] * weight + 128) >> 8);
+                }
+            } else {
+                for (int x = 0; x < bytes; x++) {
+                    dst[x] = (uint8_t)((dst[x] * (256 - weight) + src[x] * weight + 128) >> 8);
+                }
+            }
+        }
+    }
+}
+
+// Show picture `b` over the left part of `a`, from nothing at t = 0 to all of it at 1
+static void wipe_pictures(AVFrame *a, const AVFrame *b, double t) {
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(a->format);
+    int x = (int)lrint(t * a->width) & ~((1 << desc->log2_chroma_w) - 1);
+    
+    for (int p = 0; p < av_pix_fmt_count_planes(a->format) && x > 0; p++) {
+        int bytes = av_image_get_linesize(a->format, x, p);
+        for (int y = 0; y < plane_rows(desc, p, a->height); y++) {
+            memcpy(a->data[p] + y * a->linesize[p], b->data[p] + y * b->linesize[p], bytes);
+        }
+    }
+}
+
+// Blend the outgoing picture `a` towards the incoming `b` at transition position `t`
+static int blend_transition(ConcatOutput *out, AVFrame *a, const AVFrame *b, double t) {
+    switch (out->transition) {
+    case TRANSITION_DISSOLVE:
+        dissolve_pictures(a, b, t);
+        return 0;
+    case TRANSITION_WIPE:
+        wipe_pictures(a, b, t);
+        return 0;
+    case TRANSITION_FADE:
+        // Out to black over the first half, in from black over the second
+        if (t < 0.5) {
+            dissolve_pictures(a, out->black, 2 * t);
+            return 0;
+        } else {
+            int ret = av_frame_copy(a, b);
+            if (ret >= 0) {
+                dissolve_pictures(a, out->black, 2 * (1 - t));
+            }
+            return ret;
+        }
+    default:
+        return t >= 0.5 ? av_frame_copy(a, b) : 0;
+    }
+}
+
+// Encode a picture for the next frame slot. Over a join it is blended with the
+// held-back end of the previous clip; otherwise the last `tail_capacity` pictures
+// stay behind for the next join.
+static int concat_output_picture(ConcatOutput *out, AVFrame *picture) {
+    int64_t pts = out->next_video_pts++;
+    AVFrame *frame;
+    int ret = 0;
+    
+    if (out->overlap_frames_left > 0 && out->tail_count > 0) {
+        int k = out->overlap_frames - out->overlap_frames_left--;
+        frame = out->tail[out->tail_start];
+        out->tail_start = (out->tail_start + 1) % out->tail_size;
+        out->tail_count--;
+        
+        ret = av_frame_make_writable(frame);
+        if (ret >= 0) {
+            ret = blend_transition(out, frame, picture,
+                transition_position(k, out->overlap_frames, FFMIN(out->transition_frames, out->overlap_frames)));
+        }
+    } else if (out->tail_capacity > 0) {
+        // References the picture's buffer; the held picture is copied before it changes
+        AVFrame *copy = av_frame_clone(picture);
+        if (!copy) {
+            return AVERROR(ENOMEM);
+        }
+        copy->pts = pts;
+        out->tail[(out->tail_start + out->tail_count++) % out->tail_size] = copy;
+        if (out->tail_count <= out->tail_capacity) {
+            return 0;
+        }
+        
+        frame = out->tail[out->tail_start];
+        out->tail_start = (out->tail_start + 1) % out->tail_size;
+        out->tail_count--;
+        pts = frame->pts;
+    } else {
+        picture->pts = pts;
+        return process_packet(out->output_ctx, out->video_enc, picture, out->pkt, out->video_stream->index);
+    }
+    
+    if (ret >= 0) {
+        frame->pts = pts;
+        ret = process_packet(out->output_ctx, out->video_enc, frame, out->pkt, out->video_stream->index);
+    }
+    av_frame_free(&frame);
+    return ret;
+}
+
+// Encode the held picture for every frame slot before `until` (encoder time base)
+static int emit_held_frames(ConcatOutput *out, int64_t until) {
+    while (out->has_held && out->next_video_pts < until) {
+        int ret = concat_output_picture(out, out->held);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+    return 0;
+}
+
+// Take a decoded picture of a clip starting at `offset` (AV_TIME_BASE) into the output
+static int concat_video_frame(ConcatOutput *out, ConcatClip *clip, AVFrame *frame, int64_t offset, int64_t *clip_end) {
+    int64_t ts = frame->best_effort_timestamp != AV_NOPTS_VALUE ? frame->best_effort_timestamp : frame->pts;
+    int64_t time = ts == AV_NOPTS_VALUE ? *clip_end :
+        FFMAX(av_rescale_q(ts, clip->video->time_base, AV_TIME_BASE_Q) - clip->start, 0);
+    
+    AVRational rate = clip->video->avg_frame_rate.num ? clip->video->avg_frame_rate : out->video_enc->framerate;
+    int64_t duration = frame->duration > 0 ? av_rescale_q(frame->duration, clip->video->time_base, AV_TIME_BASE_Q)
+                                           : av_rescale_q(1, av_inv_q(rate), AV_TIME_BASE_Q);
+    *clip_end = FFMAX(*clip_end, time + duration);
+    
+    int ret = emit_held_frames(out, av_rescale_q_rnd(offset + time, AV_TIME_BASE_Q, out->video_enc->time_base, AV_ROUND_NEAR_INF));
+    if (ret >= 0) {
+        ret = av_frame_make_writable(out->held);
+    }
+    if (ret >= 0) {
+        ret = fit_image(&out->sws_ctx, frame, out->held);
+        out->has_held = 1;
+    }
+    return ret;
+}
+
+// Take decoded samples of a clip whose audio began at sample `clip_base` into the output
+static int concat_audio_frame(ConcatOutput *out, ConcatClip *clip, AVFrame *frame, int64_t clip_base) {
+    int rate = out->audio_enc->sample_rate;
+    int ret;
+    
+    // Gaps in the clip's audio (or a late start) are filled with silence
+    if (frame->pts != AV_NOPTS_VALUE) {
+        int64_t time = av_rescale_q(frame->pts, clip->audio->time_base, AV_TIME_BASE_Q) - clip->start;
+        int64_t expected = clip_base + av_rescale(time, rate, AV_TIME_BASE);
+        if (expected - out->audio_samples > rate / 20) {
+            ret = queue_silence(out, expected - out->audio_samples);
+            if (ret < 0) {
+                return ret;
+            }
+        }
+    }
+    
+    ret = resample_audio_frame(clip->swr_ctx, out->audio_enc, frame, out->incoming);
+    if (ret > 0) {
+        out->audio_samples += ret;
+    }
+    return ret >= 0 ? concat_take_samples(out) : ret;
+}
+
+// Send a packet (NULL drains) to one of a clip's decoders and take every frame it yields
+static int concat_decode(ConcatOutput *out, ConcatClip *clip, AVCodecContext *dec_ctx, const AVPacket *packet,
+                         AVFrame *frame, int64_t offset, int64_t audio_base, int64_t *video_end) {
+    int ret = avcodec_send_packet(dec_ctx, packet);
+    if (ret < 0 && !(packet == NULL && ret == AVERROR_EOF)) {
+        // A damaged packet loses a frame, not the whole job
+        return packet ? 0 : ret;
+    }
+    
+    while ((ret = avcodec_receive_frame(dec_ctx, frame)) >= 0) {
+        ret = dec_ctx == clip->video_dec ? concat_video_frame(out, clip, frame, offset, video_end)
+                                         : concat_audio_frame(out, clip, frame, audio_base);
+        av_frame_unref(frame);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+    
+    return ret == AVERROR(EAGAIN) || ret == AVERROR_EOF ? 0 : ret;
+}
+
+// Set up the shared encoders: the first clip with video sets the picture size and
+// frame rate, the first with audio the sample rate and channel layout, unless the
+// options choose them
+static int concat_open_encoders(
+    ConcatOutput *out,
+    ConcatClip *clips,
+    int nb_clips,
+    const char *video_codec_name,
+    const char *audio_codec_name,
+    int video_bitrate,
+    int audio_bitrate,
+    const char *resolution,
+    AVDictionary *options,
+    char *error,
+    size_t error_size
+) {
+    AVStream *first_video = NULL;
+    AVStream *first_audio = NULL;
+    int ret;
+    
+    for (int i = 0; i < nb_clips; i++) {
+        if (!first_video) first_video = clips[i].video;
+        if (!first_audio) first_audio = clips[i].audio;
+    }
+    
+    if (first_video && video_codec_name && video_codec_name[0]) {
+        const AVCodec *encoder = avcodec_find_encoder_by_name(video_codec_name);
+        if (!encoder) {
+            snprintf(error, error_size, "Video encoder '%s' not found", video_codec_name);
+            return AVERROR_ENCODER_NOT_FOUND;
+        }
+        
+        out->video_stream = avformat_new_stream(out->output_ctx, NULL);
+        out->video_enc = avcodec_alloc_context3(encoder);
+        if (!out->video_stream || !out->video_enc) {
+            snprintf(error, error_size, "Out of memory");
+            return AVERROR(ENOMEM);
+        }
+        AVCodecContext *enc_ctx = out->video_enc;
+        AVCodecParameters *par = first_video->codecpar;
+        
+        AVRational sar = par->sample_aspect_ratio.num > 0 ? par->sample_aspect_ratio : (AVRational){1, 1};
+        int width = FFMAX(1, (int)av_rescale(par->width, sar.num, sar.den));
+        int height = par->height;
+        parse_resolution(resolution, &width, &height);
+        
+        const char *pix_fmt_name = get_option(options, "pix_fmt", "auto");
+        enc_ctx->pix_fmt = strcmp(pix_fmt_name, "auto") == 0
+            ? default_pix_fmt(out->output_ctx->oformat, encoder, par->format)
+            : av_get_pix_fmt(pix_fmt_name);
+        if (enc_ctx->pix_fmt == AV_PIX_FMT_NONE || !encoder_supports_pix_fmt(encoder, enc_ctx->pix_fmt)) {
+            snprintf(error, error_size, "Pixel format '%s' is not supported by encoder '%s'", pix_fmt_name, video_codec_name);
+            return AVERROR(EINVAL);
+        }
+        
+        // Some demuxers can't tell the frame rate, fall back to 25 fps
+        AVRational frame_rate = first_video->r_frame_rate.num ? first_video->r_frame_rate : (AVRational){25, 1};
+        const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(enc_ctx->pix_fmt);
+        enc_ctx->width = FFMAX(width & ~((1 << desc->log2_chroma_w) - 1), 2);
+        enc_ctx->height = FFMAX(height & ~((1 << desc->log2_chroma_h) - 1), 2);
+        enc_ctx->sample_aspect_ratio = (AVRational){1, 1};
+        enc_ctx->time_base = av_inv_q(frame_rate);
+        enc_ctx->framerate = frame_rate;
+        enc_ctx->gop_size = 25;
+        enc_ctx->max_b_frames = 3;
+        enc_ctx->bit_rate = video_bitrate * 1000;
+        if (out->output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+            enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+        }
+        
+        ret = avcodec_open2(enc_ctx, encoder, NULL);
+        if (ret >= 0) {
+            ret = avcodec_parameters_from_context(out->video_stream->codecpar, enc_ctx);
+        }
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        out->video_stream->time_base = enc_ctx->time_base;
+        
+        out->held = av_frame_alloc();
+        if (!out->held) {
+            snprintf(error, error_size, "Out of memory");
+            return AVERROR(ENOMEM);
+        }
+        out->held->format = enc_ctx->pix_fmt;
+        out->held->width = enc_ctx->width;
+        out->held->height = enc_ctx->height;
+        out->held->color_range = enc_ctx->color_range;
+        ret = av_frame_get_buffer(out->held, 0);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+    }
+    
+    if (first_audio && audio_codec_name && audio_codec_name[0]) {
+        const AVCodec *encoder = avcodec_find_encoder_by_name(audio_codec_name);
+        if (!encoder) {
+            snprintf(error, error_size, "Audio encoder '%s' not found", audio_codec_name);
+            return AVERROR_ENCODER_NOT_FOUND;
+        }
+        
+        out->audio_stream = avformat_new_stream(out->output_ctx, NULL);
+        out->audio_enc = avcodec_alloc_context3(encoder);
+        if (!out->audio_stream || !out->audio_enc) {
+            snprintf(error, error_size, "Out of memory");
+            return AVERROR(ENOMEM);
+        }
+        AVCodecContext *enc_ctx = out->audio_enc;
+        AVCodecParameters *par = first_audio->codecpar;
+        
+        const char *layout_name = get_option(options, "channel_layout", "same");
+        AVChannelLayout requested_layout = {0};
+        if (strcmp(layout_name, "same") != 0) {
+            ret = av_channel_layout_from_string(&requested_layout, layout_name);
+        } else if (par->ch_layout.order == AV_CHANNEL_ORDER_UNSPEC) {
+            av_channel_layout_default(&requested_layout, par->ch_layout.nb_channels);
+            ret = 0;
+        } else {
+            ret = av_channel_layout_copy(&requested_layout, &par->ch_layout);
+        }
+        if (ret >= 0) {
+            ret = choose_channel_layout(encoder, &requested_layout, &enc_ctx->ch_layout);
+        }
+        if (ret < 0 && strcmp(layout_name, "same") == 0) {
+            AVChannelLayout stereo = AV_CHANNEL_LAYOUT_STEREO;
+            ret = choose_channel_layout(encoder, &stereo, &enc_ctx->ch_layout);
+        }
+        av_channel_layout_uninit(&requested_layout);
+        if (ret < 0) {
+            snprintf(error, error_size, "Channel layout '%s' is not supported by encoder '%s'", layout_name, audio_codec_name);
+            return ret;
+        }
+        
+        int sample_rate = atoi(get_option(options, "sample_rate", "0"));
+        enc_ctx->sample_rate = choose_sample_rate(encoder, sample_rate > 0 ? sample_rate : par->sample_rate);
+        enc_ctx->sample_fmt = encoder->sample_fmts ? encoder->sample_fmts[0] : AV_SAMPLE_FMT_FLTP;
+        enc_ctx->time_base = (AVRational){1, enc_ctx->sample_rate};
+        enc_ctx->bit_rate = audio_bitrate * 1000;
+        if (out->output_ctx->oformat->flags & AVFMT_GLOBALHEADER) {
+            enc_ctx->flags |= AV_CODEC_FLAG_GLOBAL_HEADER;
+        }
+        
+        ret = avcodec_open2(enc_ctx, encoder, NULL);
+        if (ret >= 0) {
+            ret = avcodec_parameters_from_context(out->audio_stream->codecpar, enc_ctx);
+        }
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        out->audio_stream->time_base = enc_ctx->time_base;
+        
+        out->fifo = av_audio_fifo_alloc(enc_ctx->sample_fmt, enc_ctx->ch_layout.nb_channels, 1);
+        out->incoming = av_audio_fifo_alloc(enc_ctx->sample_fmt, enc_ctx->ch_layout.nb_channels, 1);
+        out->pending = av_audio_fifo_alloc(enc_ctx->sample_fmt, enc_ctx->ch_layout.nb_channels, 1);
+        ret = av_samples_alloc_array_and_samples(&out->mix_a, NULL, enc_ctx->ch_layout.nb_channels, MIX_CHUNK, enc_ctx->sample_fmt, 0);
+        if (ret >= 0) {
+            ret = av_samples_alloc_array_and_samples(&out->mix_b, NULL, enc_ctx->ch_layout.nb_channels, MIX_CHUNK, enc_ctx->sample_fmt, 0);
+        }
+        if (!out->fifo || !out->incoming || !out->pending || ret < 0) {
+            snprintf(error, error_size, "Failed to allocate audio FIFO");
+            return AVERROR(ENOMEM);
+        }
+        out->next_audio_pts = 0;
+        
+        // Gain and fades apply to the joined audio as a whole
+        int64_t duration = 0;
+        for (int i = 0; i < nb_clips; i++) {
+            duration += FFMAX(clips[i].fmt_ctx->duration, 0);
+            if (i > 0) {
+                duration -= join_overlap(&clips[i - 1], &clips[i], requested_overlap(options));
+            }
+        }
+        init_gain_and_fades(&out->effects, options, enc_ctx->sample_rate, av_rescale(duration, enc_ctx->sample_rate, AV_TIME_BASE));
+    }
+    
+    if (!out->video_enc && !out->audio_enc) {
+        snprintf(error, error_size, "Nothing to encode: the clips have no streams the output can hold");
+        return AVERROR(EINVAL);
+    }
+    
+    const char *transition = get_option(options, "transition", "none");
+    out->transition = strcmp(transition, "fade") == 0 ? TRANSITION_FADE :
+                      strcmp(transition, "dissolve") == 0 ? TRANSITION_DISSOLVE :
+                      strcmp(transition, "wipe") == 0 ? TRANSITION_WIPE : TRANSITION_NONE;
+    
+    if (out->audio_enc) {
+        out->crossfade_samples = (int64_t)(av_clipd(atof(get_option(options, "audio_crossfade", "0")), 0.0, 60.0) * out->audio_enc->sample_rate);
+    }
+    
+    if (out->video_enc) {
+        const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(out->video_enc->pix_fmt);
+        if (out->transition != TRANSITION_NONE && (desc->flags & (AV_PIX_FMT_FLAG_PAL | AV_PIX_FMT_FLAG_BITSTREAM))) {
+            snprintf(error, error_size, "Transitions can't be drawn in pixel format %s", desc->name);
+            return AVERROR(EINVAL);
+        }
+        
+        if (out->transition != TRANSITION_NONE) {
+            out->transition_frames = av_rescale_q((int64_t)(atof(get_option(options, "transition_duration", "0")) * AV_TIME_BASE),
+                                                  AV_TIME_BASE_Q, out->video_enc->time_base);
+        }
+        
+        // Room for the longest overlap, plus the picture being added
+        out->tail_size = (int)av_rescale_q_rnd(requested_overlap(options), AV_TIME_BASE_Q, out->video_enc->time_base, AV_ROUND_UP) + 2;
+        out->tail = av_calloc(out->tail_size, sizeof(*out->tail));
+        if (!out->tail) {
+            snprintf(error, error_size, "Out of memory");
+            return AVERROR(ENOMEM);
+        }
+        
+        if (out->transition == TRANSITION_FADE) {
+            out->black = av_frame_alloc();
+            if (!out->black) {
+                snprintf(error, error_size, "Out of memory");
+                return AVERROR(ENOMEM);
+            }
+            out->black->format = out->held->format;
+            out->black->width = out->held->width;
+            out->black->height = out->held->height;
+            out->black->color_range = out->held->color_range;
+            ret = av_frame_get_buffer(out->black, 0);
+            if (ret >= 0) {
+                ret = av_image_fill_black(out->black->data, (const ptrdiff_t[4]){ out->black->linesize[0], out->black->linesize[1], out->black->linesize[2], out->black->linesize[3] },
+                                          out->black->format, out->black->color_range, out->black->width, out->black->height);
+            }
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                return ret;
+            }
+        }
+    }
+    
+    return 0;
+}
+
+// Open a clip's decoders, and a resampler into the output audio format
+static int concat_clip_open_decoders(ConcatOutput *out, ConcatClip *clip, char *error, size_t error_size) {
+    int ret;
+    
+    if (out->video_enc && clip->video) {
+        ret = open_decoder(clip->video, &clip->video_dec);
+        if (ret < 0) {
+            snprintf(error, error_size, "Unsupported video codec %s", avcodec_get_name(clip->video->codecpar->codec_id));
+            return ret;
+        }
+    }
+    
+    if (out->audio_enc && clip->audio) {
+        ret = open_decoder(clip->audio, &clip->audio_dec);
+        if (ret < 0) {
+            snprintf(error, error_size, "Unsupported audio codec %s", avcodec_get_name(clip->audio->codecpar->codec_id));
+            return ret;
+        }
+        
+        AVChannelLayout source_layout = {0};
+        if (clip->audio_dec->ch_layout.order == AV_CHANNEL_ORDER_UNSPEC) {
+            av_channel_layout_default(&source_layout, clip->audio_dec->ch_layout.nb_channels);
+        } else {
+            av_channel_layout_copy(&source_layout, &clip->audio_dec->ch_layout);
+        }
+        
+        ret = swr_alloc_set_opts2(&clip->swr_ctx,
+            &out->audio_enc->ch_layout, out->audio_enc->sample_fmt, out->audio_enc->sample_rate,
+            &source_layout, clip->audio_dec->sample_fmt, clip->audio_dec->sample_rate,
+            0, NULL);
+        av_channel_layout_uninit(&source_layout);
+        if (ret >= 0) {
+            set_downmix_levels(clip->swr_ctx);
+            ret = swr_init(clip->swr_ctx);
+        }
+        if (ret < 0) {
+            snprintf(error, error_size, "Failed to set up resampling for the clip's audio");
+            return ret;
+        }
+    }
+    
+    return 0;
+}
+
+// Decode every clip in turn and encode them as one: pictures are letterboxed into
+// the output size at a constant frame rate, audio is resampled to one format, and
+// a clip missing either gets black frames or silence for its length
+static int concat_encode(ConcatClip *clips, int nb_clips, ConcatOutput *out, AVDictionary *options,
+                         AVPacket *packet, AVFrame *frame, TranscodeResult *result) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    int64_t offset = 0;
+    int ret;
+    
+    ret = write_output_header(out->output_ctx, options, error, error_size);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    for (int i = 0; i < nb_clips; i++) {
+        ConcatClip *clip = &clips[i];
+        int64_t audio_base = out->audio_samples;
+        int64_t video_end = 0;
+        
+        // Hold back as much of this clip's end as overlaps the next one
+        int64_t overlap = i + 1 < nb_clips ? join_overlap(clip, &clips[i + 1], requested_overlap(options)) : 0;
+        if (out->video_enc) {
+            out->tail_capacity = (int)av_rescale_q_rnd(overlap, AV_TIME_BASE_Q, out->video_enc->time_base, AV_ROUND_NEAR_INF);
+            out->tail_capacity = FFMIN(out->tail_capacity, out->tail_size - 2);
+            overlap = av_rescale_q(out->tail_capacity, out->video_enc->time_base, AV_TIME_BASE_Q);
+        }
+        if (out->audio_enc) {
+            out->pending_capacity = av_rescale(overlap, out->audio_enc->sample_rate, AV_TIME_BASE);
+        }
+        
+        ret = concat_clip_open_decoders(out, clip, error, error_size);
+        if (ret < 0) {
+            return ret;
+        }
+        
+        if (out->video_enc && !clip->video_dec) {
+            ret = av_frame_make_writable(out->held);
+            if (ret >= 0) {
+                ret = av_image_fill_black(out->held->data, (const ptrdiff_t[4]){ out->held->linesize[0], out->held->linesize[1], out->held->linesize[2], out->held->linesize[3] },
+                                          out->held->format, out->held->color_range, out->held->width, out->held->height);
+            }
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                return ret;
+            }
+            out->has_held = 1;
+        }
+        
+        while ((ret = av_read_frame(clip->fmt_ctx, packet)) >= 0) {
+            AVCodecContext *dec_ctx = clip->video_dec && packet->stream_index == clip->video->index ? clip->video_dec :
+                                      clip->audio_dec && packet->stream_index == clip->audio->index ? clip->audio_dec : NULL;
+            AVStream *stream = clip->fmt_ctx->streams[packet->stream_index];
+            int64_t ts = packet->pts;
+            
+            ret = dec_ctx ? concat_decode(out, clip, dec_ctx, packet, frame, offset, audio_base, &video_end) : 0;
+            av_packet_unref(packet);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                return ret;
+            }
+            
+            if (dec_ctx && ts != AV_NOPTS_VALUE && clip->fmt_ctx->duration > 0) {
+                int64_t done = av_rescale_q(ts, stream->time_base, AV_TIME_BASE_Q) - clip->start;
+                update_progress((int)((i * 100 + av_clip(done * 100 / clip->fmt_ctx->duration, 0, 100)) * 99 / (nb_clips * 100)));
+            }
+        }
+        if (ret != AVERROR_EOF) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        
+        // Drain the decoders and the resampler
+        if (clip->video_dec) {
+            ret = concat_decode(out, clip, clip->video_dec, NULL, frame, offset, audio_base, &video_end);
+        }
+        if (ret >= 0 && clip->audio_dec) {
+            ret = concat_decode(out, clip, clip->audio_dec, NULL, frame, offset, audio_base, &video_end);
+            if (ret >= 0) {
+                ret = resample_audio_frame(clip->swr_ctx, out->audio_enc, NULL, out->incoming);
+                if (ret > 0) {
+                    out->audio_samples += ret;
+                }
+            }
+            if (ret >= 0) {
+                ret = concat_take_samples(out);
+            }
+        }
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        
+        // The clip lasts as long as its longer stream; the shorter one is padded
+        int64_t clip_length = video_end;
+        if (out->audio_enc) {
+            clip_length = FFMAX(clip_length, av_rescale(out->audio_samples - audio_base, AV_TIME_BASE, out->audio_enc->sample_rate));
+        }
+        if (clip_length <= 0) {
+            clip_length = FFMAX(clip->fmt_ctx->duration, 0);
+        }
+        
+        if (out->audio_enc) {
+            int64_t target = audio_base + av_rescale(clip_length, out->audio_enc->sample_rate, AV_TIME_BASE);
+            ret = queue_silence(out, target - out->audio_samples);
+            
+            // A clip shorter than the overlap into it still finishes the crossfade
+            if (ret >= 0 && out->overlap_samples_left > 0) {
+                ret = queue_silence(out, out->overlap_samples_left);
+            }
+        }
+        if (ret >= 0 && out->video_enc) {
+            ret = emit_held_frames(out, av_rescale_q_rnd(offset + clip_length, AV_TIME_BASE_Q, out->video_enc->time_base, AV_ROUND_NEAR_INF));
+            while (ret >= 0 && out->overlap_frames_left > 0) {
+                ret = concat_output_picture(out, out->held);
+            }
+        }
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            return ret;
+        }
+        
+        // The next clip starts over the held-back end of this one
+        offset += clip_length;
+        if (out->video_enc) {
+            out->overlap_frames = out->overlap_frames_left = out->tail_count;
+            out->next_video_pts -= out->tail_count;
+            offset -= av_rescale_q(out->tail_count, out->video_enc->time_base, AV_TIME_BASE_Q);
+        }
+        if (out->audio_enc) {
+            out->overlap_samples = out->overlap_samples_left = av_audio_fifo_size(out->pending);
+            out->audio_samples -= out->overlap_samples;
+            if (!out->video_enc) {
+                offset -= av_rescale(out->overlap_samples, AV_TIME_BASE, out->audio_enc->sample_rate);
+            }
+        }
+        concat_clip_close_decoders(clip);
+    }
+    
+    // Flush the encoders
+    ret = 0;
+    if (out->video_enc) {
+        ret = process_packet(out->output_ctx, out->video_enc, NULL, out->pkt, out->video_stream->index);
+    }
+    if (ret >= 0 && out->audio_enc) {
+        ret = encode_audio_fifo(out->output_ctx, out->audio_enc, out->fifo, &out->effects, out->pkt,
+            out->audio_stream->index, &out->next_audio_pts, 1);
+        if (ret >= 0) {
+            ret = process_packet(out->output_ctx, out->audio_enc, NULL, out->pkt, out->audio_stream->index);
+        }
+    }
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+    return ret;
+}
+
+// Join registered inputs 1..concat end to end. Options: concat (the count),
+// concat_reencode=1 to re-encode even when the clips could be copied, and for
+// overlapping joins transition (none, fade, dissolve or wipe) with
+// transition_duration, and audio_crossfade, both in seconds.
+static int transcode_concat(
+    AVDictionary *options,
+    const char *output_format,
+    const char *video_codec_name,
+    const char *audio_codec_name,
+    int video_bitrate,
+    int audio_bitrate,
+    const char *resolution,
+    TranscodeResult *result
+) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    ConcatClip *clips = NULL;
+    ConcatOutput out = { 0 };
+    AVPacket *packet = NULL;
+    AVFrame *frame = NULL;
+    int ret;
+    
+    int nb_clips = atoi(get_option(options, "concat", "0"));
+    if (nb_clips < 1 || nb_clips > nb_extra_inputs) {
+        snprintf(error, error_size, "No clips to join");
+        return AVERROR(EINVAL);
+    }
+    
+    clips = av_calloc(nb_clips, sizeof(*clips));
+    packet = av_packet_alloc();
+    frame = av_frame_alloc();
+    out.pkt = av_packet_alloc();
+    if (!clips || !packet || !frame || !out.pkt) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    for (int i = 0; i < nb_clips; i++) {
+        ret = concat_clip_open(&clips[i], i + 1, error, error_size);
+        if (ret < 0) {
+            goto end;
+        }
+    }
+    
+    ret = avformat_alloc_output_context2(&out.output_ctx, NULL, output_format, NULL);
+    if (ret < 0 || !out.output_ctx) {
+        snprintf(error, error_size, "Failed to create output context");
+        ret = ret < 0 ? ret : AVERROR(EINVAL);
+        goto end;
+    }
+    
+    unsigned char *io_buffer = av_malloc(4096);
+    out.output_ctx->pb = io_buffer ? avio_alloc_context(io_buffer, 4096, 1, result, NULL, write_packet, seek_output) : NULL;
+    if (!out.output_ctx->pb) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Failed to create output IO context");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    // Copying keeps every stream, so it needs an encoder chosen for each kind there is,
+    // and transitions need the pictures and samples decoded
+    int copy = strcmp(resolution, "same") == 0 && strcmp(get_option(options, "concat_reencode", "0"), "1") != 0 &&
+        requested_overlap(options) == 0 &&
+        (!clips[0].video || video_codec_name[0]) && (!clips[0].audio || audio_codec_name[0]) &&
+        clips_can_be_copied(clips, nb_clips, out.output_ctx->oformat);
+    
+    if (copy) {
+        ret = concat_copy(clips, nb_clips, out.output_ctx, options, packet, result);
+    } else {
+        ret = concat_open_encoders(&out, clips, nb_clips, video_codec_name, audio_codec_name,
+            video_bitrate, audio_bitrate, resolution, options, error, error_size);
+        if (ret >= 0) {
+            ret = concat_encode(clips, nb_clips, &out, options, packet, frame, result);
+        }
+    }
+    if (ret < 0) {
+        goto end;
+    }
+    
+    ret = av_write_trailer(out.output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+    }
+    
+end:
+    for (int i = 0; clips && i < nb_clips; i++) {
+        concat_clip_close(&clips[i]);
+    }
+    av_free(clips);
+    if (out.output_ctx) {
+        if (out.output_ctx->pb) {
+            av_freep(&out.output_ctx->pb->buffer);
+            avio_context_free(&out.output_ctx->pb);
+        }
+        avformat_free_context(out.output_ctx);
+    }
+    avcodec_free_context(&out.video_enc);
+    avcodec_free_context(&out.audio_enc);
+    sws_freeContext(out.sws_ctx);
+    av_frame_free(&out.held);
+    av_frame_free(&out.black);
+    for (int i = 0; out.tail && i < out.tail_count; i++) {
+        av_frame_free(&out.tail[(out.tail_start + i) % out.tail_size]);
+    }
+    av_free(out.tail);
+    if (out.fifo) av_audio_fifo_free(out.fifo);
+    if (out.incoming) av_audio_fifo_free(out.incoming);
+    if (out.pending) av_audio_fifo_free(out.pending);
+    if (out.mix_a) av_freep(&out.mix_a[0]);
+    av_freep(&out.mix_a);
+    if (out.mix_b) av_freep(&out.mix_b[0]);
+    av_freep(&out.mix_b);
+    free_audio_effects(&out.effects);
+    av_packet_free(&out.pkt);
+    av_packet_free(&packet);
+    av_frame_free(&frame);
+    return ret;
+}
+
+// Write an HLS package into the output file registry: a master playlist and a media
+// playlist with its segments for the video and audio together or, with
+// "hls_separate_audio=1", for each video and audio stream, the audio as renditions
+// the video playlists refer to. Options: hls_time (target segment length in seconds)
+// and hls_segment_type (mpegts or fmp4).
+static int setup_hls_output(AVFormatContext *output_ctx, AVDictionary *options, AVDictionary **mux_options,
+                            char *error, size_t error_size) {
+    const char *segment_type = get_option(options, "hls_segment_type", "mpegts");
+    const char *extension;
+    if (strcmp(segment_type, "mpegts") == 0) {
+        extension = "ts";
+    } else if (strcmp(segment_type, "fmp4") == 0) {
+        extension = "m4s";
+    } else {
+        snprintf(error, error_size, "Unknown HLS segment type '%s'", segment_type);
+        return AVERROR(EINVAL);
+    }
+    
+    int nb_video = 0;
+    int nb_audio = 0;
+    for (unsigned int i = 0; i < output_ctx->nb_streams; i++) {
+        enum AVMediaType type = output_ctx->streams[i]->codecpar->codec_type;
+        nb_video += type == AVMEDIA_TYPE_VIDEO;
+        nb_audio += type == AVMEDIA_TYPE_AUDIO;
+    }
+    int separate = atoi(get_option(options, "hls_separate_audio", "0")) && nb_video > 0 && nb_audio > 0;
+    
+    // Several variant streams name their files after each one ("%v")
+    const char *name = separate ? "%v" : "stream";
+    char playlist[64];
+    char segments[64];
+    char init[64];
+    snprintf(playlist, sizeof(playlist), "%s.m3u8", name);
+    snprintf(segments, sizeof(segments), "%s_%%03d.%s", name, extension);
+    snprintf(init, sizeof(init), "%s_init.mp4", name);
+    
+    if (separate) {
+        char map[2048];
+        size_t length = 0;
+        
+        for (int k = 0; k < 2; k++) {
+            const char *kind = k == 0 ? "video" : "audio";
+            int count = k == 0 ? nb_video : nb_audio;
+            for (int i = 0; i < count && length < sizeof(map); i++) {
+                if (count == 1) {
+                    length += snprintf(map + length, sizeof(map) - length, "%s%c:%d,agroup:audio,name:%s",
+                        length ? " " : "", kind[0], i, kind);
+                } else {
+                    length += snprintf(map + length, sizeof(map) - length, "%s%c:%d,agroup:audio,name:%s_%d",
+                        length ? " " : "", kind[0], i, kind, i + 1);
+                }
+            }
+        }
+        av_dict_set(mux_options, "var_stream_map", map, 0);
+    }
+    
+    int ret = open_package_output(output_ctx, playlist, error, error_size);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    av_dict_set(mux_options, "hls_time", get_option(options, "hls_time", "6"), 0);
+    av_dict_set(mux_options, "hls_segment_type", segment_type, 0);
+    av_dict_set(mux_options, "hls_segment_filename", segments, 0);
+    av_dict_set(mux_options, "hls_fmp4_init_filename", init, 0);
+    av_dict_set(mux_options, "master_pl_name", "master.m3u8", 0);
+    // A complete playlist of every segment, written once without temporary files
+    av_dict_set(mux_options, "hls_playlist_type", "vod", 0);
+    av_dict_set(mux_options, "hls_list_size", "0", 0);
+    
+    return 0;
+}
+
+// Write a DASH package into the output file registry: "manifest.mpd" with one
+// adaptation set for the video and one for each audio stream, and fragmented MP4
+// initialization and media segments. Option: dash_seg_duration (target segment
+// length in seconds).
+static int setup_dash_output(AVFormatContext *output_ctx, AVDictionary *options, AVDictionary **mux_options,
+                             char *error, size_t error_size) {
+    char sets[1024];
+    size_t length = 0;
+    int nb_sets = 0;
+    int has_video = 0;
+    
+    for (unsigned int i = 0; i < output_ctx->nb_streams && length < sizeof(sets); i++) {
+        enum AVMediaType type = output_ctx->streams[i]->codecpar->codec_type;
+        if (type == AVMEDIA_TYPE_VIDEO && !has_video) {
+            length += snprintf(sets + length, sizeof(sets) - length, "%sid=%d,streams=v", nb_sets ? " " : "", nb_sets);
+            nb_sets++;
+            has_video = 1;
+        } else if (type == AVMEDIA_TYPE_AUDIO) {
+            length += snprintf(sets + length, sizeof(sets) - length, "%sid=%d,streams=%u", nb_sets ? " " : "", nb_sets, i);
+            nb_sets++;
+        }
+    }
+    if (nb_sets == 0) {
+        snprintf(error, error_size, "DASH needs an audio or video stream");
+        return AVERROR(EINVAL);
+    }
+    
+    // dashenc renames its manifest into place when the URL is a local file
+    int ret = open_package_output(output_ctx, PACKAGE_URL_SCHEME "manifest.mpd", error, error_size);
+    if (ret < 0) {
+        return ret;
+    }
+    
+    av_dict_set(mux_options, "adaptation_sets", sets, 0);
+    av_dict_set(mux_options, "seg_duration", get_option(options, "dash_seg_duration", "4"), 0);
+    av_dict_set(mux_options, "dash_segment_type", "mp4", 0);
+    av_dict_set(mux_options, "init_seg_name", "init-$RepresentationID$.mp4", 0);
+    av_dict_set(mux_options, "media_seg_name", "chunk-$RepresentationID$-$Number%05d$.m4s", 0);
+    av_dict_set(mux_options, "use_template", "1", 0);
+    av_dict_set(mux_options, "use_timeline", "1", 0);
+    // A static manifest listing every segment
+    av_dict_set(mux_options, "window_size", "0", 0);
+    
+    return 0;
+}
+
+// Main transcoding function
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* transcode(
+    uint8_t* input_data, 
+    size_t input_size,
+    const char* output_format,
+    const char* video_codec_name,
+    const char* audio_codec_name,
+    int video_bitrate,
+    int audio_bitrate,
+    const char* resolution,
+    const char* options_string
+) {
+    AVFormatContext *input_ctx = NULL;
+    AVFormatContext *output_ctx = NULL;
+    MemoryInput input_mem = {0};
+    AVIOContext *output_io_ctx = NULL;
+    AVFrame *frame = NULL;
+    AVPacket *pkt = NULL;
+    AVPacket *enc_pkt = NULL;
+    AVPacket *cover_pkt = NULL;
+    AVDictionary *options = NULL;
+    AVDictionary *mux_options = NULL;
+    OutputStream output_streams[MAX_OUTPUT_STREAMS];
+    int nb_output_streams = 0;
+    int *stream_map = NULL;
+    int drop_video = 0;
+    int drop_audio = 0;
+    int tracks[MAX_OUTPUT_STREAMS];
+    int nb_tracks = 0;
+    int copy_tracks[MAX_OUTPUT_STREAMS];
+    int nb_copy_tracks = 0;
+    int default_tracks[MAX_OUTPUT_STREAMS];
+    int nb_default_tracks = 0;
+    int subtitle_inputs[MAX_EXTRA_INPUTS];
+    int nb_subtitle_inputs = 0;
+    unsigned char *output_io_buffer = NULL;
+    int ret = 0;
+    
+    memset(output_streams, 0, sizeof(output_streams));
+    
+    // Allocate result structure
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        fprintf(stderr, "Failed to allocate result structure\n");
+        return NULL;
+    }
+    
+    // Set global result for progress tracking
+    global_result = result;
+    
+    // Initialize result
+    result->success = 0;
+    result->progress = 0;
+    result->output_data = NULL;
+    result->output_size = 0;
+    snprintf(result->error_message, sizeof(result->error_message), "");
+    
+    // Parse extended options ("key=value:key=value")
+    if (options_string && options_string[0]) {
+        ret = av_dict_parse_string(&options, options_string, "=", ":", 0);
+        if (ret < 0) {
+            snprintf(result->error_message, sizeof(result->error_message), "Invalid transcode options '%s'", options_string);
+            goto cleanup;
+        }
+    }
+    
+    // Open input from the memory buffer
+    input_mem.data = input_data;
+    input_mem.size = input_size;
+    
+    // A video made from registered images, with no main input
+    if (av_dict_get(options, "images", NULL, 0)) {
+        if (transcode_images(options, output_format, video_codec_name, audio_codec_name,
+                             video_bitrate, audio_bitrate, resolution, result) >= 0) {
+            result->success = 1;
+            update_progress(100);
+        }
+        goto cleanup;
+    }
+    
+    // Registered clips joined end to end, with no main input
+    if (av_dict_get(options, "concat", NULL, 0)) {
+        if (transcode_concat(options, output_format, video_codec_name, audio_codec_name,
+                             video_bitrate, audio_bitrate, resolution, result) >= 0) {
+            result->success = 1;
+            update_progress(100);
+        }
+        goto cleanup;
+    }
+    
+    // GIF has its own two-pass palette pipeline
+    if (strcmp(output_format, "gif") == 0) {
+        if (transcode_gif(&input_mem, options, result) >= 0) {
+            result->success = 1;
+            update_progress(100);
+        }
+        goto cleanup;
+    }
+    
+    // Animated WebP and APNG are encoded from the same frame reader
+    if (strcmp(output_format, "webp") == 0 || strcmp(output_format, "apng") == 0) {
+        if (transcode_animation(&input_mem, options, output_format, result) >= 0) {
+            result->success = 1;
+            update_progress(100);
+        }
+        goto cleanup;
+    }
+    
+    ret = open_memory_input(&input_mem, &input_ctx);
+    if (ret < 0) {
+        av_strerror(ret, result->error_message, sizeof(result->error_message));
+        goto cleanup;
+    }
+    
+    // Create output format context
+    ret = avformat_alloc_output_context2(&output_ctx, NULL, output_format, NULL);
+    if (ret < 0 || !output_ctx) {
+        snprintf(result->error_message, sizeof(result->error_message), "Failed to create output context");
+        goto cleanup;
+    }
+    
+    // Create output IO context for memory output
+    output_io_buffer = av_malloc(4096);
+    if (!output_io_buffer) {
+        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate output buffer");
+        goto cleanup;
+    }
+    
+    output_io_ctx = avio_alloc_context(
+        output_io_buffer,
+        4096,
+        1,
+        result,  // User data - pass result struct to write callback
+        NULL,    // No read callback needed for output
+        write_packet,  // Write callback
+        seek_output    // Muxers seek back to patch headers and indexes
+    );
+    
+    if (!output_io_ctx) {
+        snprintf(result->error_message, sizeof(result->error_message), "Failed to create output IO context");
+        goto cleanup;
+    }
+    
+    output_ctx->pb = output_io_ctx;
+    
+    // Drop modes; a container without a default codec for a type implies dropping it
+    drop_video = atoi(get_option(options, "drop_video", "0")) || output_ctx->oformat->video_codec == AV_CODEC_ID_NONE;
+    drop_audio = atoi(get_option(options, "drop_audio", "0")) || output_ctx->oformat->audio_codec == AV_CODEC_ID_NONE;
+    
+    // Track selection: explicit ordered list, or the first video and audio streams
+    // plus any subtitles the container can hold
+    if (av_dict_get(options, "tracks", NULL, 0)) {
+        nb_tracks = parse_index_list(get_option(options, "tracks", ""), tracks, MAX_OUTPUT_STREAMS);
+        nb_copy_tracks = parse_index_list(get_option(options, "copy", ""), copy_tracks, MAX_OUTPUT_STREAMS);
+        nb_default_tracks = parse_index_list(get_option(options, "default", ""), default_tracks, MAX_OUTPUT_STREAMS);
+    } else {
+        int video_idx = -1;
+        int audio_idx = -1;
+        
+        for (unsigned int i = 0; i < input_ctx->nb_streams; i++) {
+            AVStream *in_stream = input_ctx->streams[i];
+            
+            if (in_stream->codecpar->codec_type == AVMEDIA_TYPE_VIDEO && video_idx < 0 &&
+                !(in_stream->disposition & AV_DISPOSITION_ATTACHED_PIC)) {
+                video_idx = i;
+                tracks[nb_tracks++] = i;
+            } else if (in_stream->codecpar->codec_type == AVMEDIA_TYPE_AUDIO && audio_idx < 0) {
+                audio_idx = i;
+                tracks[nb_tracks++] = i;
+            } else if (in_stream->codecpar->codec_type == AVMEDIA_TYPE_SUBTITLE && nb_tracks < MAX_OUTPUT_STREAMS &&
+                output_ctx->oformat->subtitle_codec != AV_CODEC_ID_NONE && !format_writes_files(output_ctx->oformat)) {
+                // Bitmap subtitles survive only where the container takes them as-is
+                if (is_text_subtitle(in_stream->codecpar)) {
+                    tracks[nb_tracks++] = i;
+                } else if (avformat_query_codec(output_ctx->oformat, in_stream->codecpar->codec_id, FF_COMPLIANCE_NORMAL) == 1) {
+                    copy_tracks[nb_copy_tracks++] = i;
+                    tracks[nb_tracks++] = i;
+                }
+            }
+        }
+    }
+    
+    stream_map = av_malloc_array(input_ctx->nb_streams, sizeof(int));
+    if (!stream_map) {
+        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate stream map");
+        goto cleanup;
+    }
+    for (unsigned int i = 0; i < input_ctx->nb_streams; i++) {
+        stream_map[i] = -1;
+    }
+    
+    // Create one output stream per selected track, in the requested order
+    for (int t = 0; t < nb_tracks; t++) {
+        int index = tracks[t];
+        
+        if (index < 0 || index >= (int)input_ctx->nb_streams) {
+            snprintf(result->error_message, sizeof(result->error_message), "Invalid track index %d", index);
+            goto cleanup;
+        }
+        if (stream_map[index] >= 0) {
+            continue;
+        }
+        
+        AVStream *in_stream = input_ctx->streams[index];
+        enum AVMediaType type = in_stream->codecpar->codec_type;
+        
+        // Cover art is added by add_cover_art
+        if (in_stream->disposition & AV_DISPOSITION_ATTACHED_PIC) {
+            continue;
+        }
+        
+        // Dropped types get neither a decoder nor an output stream
+        if ((type == AVMEDIA_TYPE_VIDEO && drop_video) || (type == AVMEDIA_TYPE_AUDIO && drop_audio)) {
+            continue;
+        }
+        
+        // Containers without subtitle support can't take converted subtitles
+        if (type == AVMEDIA_TYPE_SUBTITLE && output_ctx->oformat->subtitle_codec == AV_CODEC_ID_NONE &&
+            !index_in_list(index, copy_tracks, nb_copy_tracks)) {
+            av_log(NULL, AV_LOG_WARNING, "%s has no subtitle format, dropping track %d\n",
+                output_ctx->oformat->name, index);
+            continue;
+        }
+        
+        // Single-stream containers keep only the first track of each type
+        if (!format_allows_multiple_streams(output_ctx->oformat) &&
+            has_stream_type(output_streams, nb_output_streams, type)) {
+            av_log(NULL, AV_LOG_WARNING, "%s holds one stream per type, dropping track %d\n",
+                output_ctx->oformat->name, index);
+            continue;
+        }
+        
+        OutputStream *ost = add_output_stream(output_ctx, output_streams, &nb_output_streams, in_stream);
+        if (!ost) {
+            snprintf(result->error_message, sizeof(result->error_message), "Failed to create output stream for track %d", index);
+            goto cleanup;
+        }
+        stream_map[index] = nb_output_streams - 1;
+        
+        // Apply the requested default flag
+        if (nb_tracks && av_dict_get(options, "default", NULL, 0)) {
+            ost->out_stream->disposition &= ~AV_DISPOSITION_DEFAULT;
+            if (index_in_list(index, default_tracks, nb_default_tracks)) {
+                ost->out_stream->disposition |= AV_DISPOSITION_DEFAULT;
+            }
+        }
+        
+        if (index_in_list(index, copy_tracks, nb_copy_tracks) ||
+            (type != AVMEDIA_TYPE_VIDEO && type != AVMEDIA_TYPE_AUDIO && type != AVMEDIA_TYPE_SUBTITLE)) {
+            ret = setup_copy_stream(ost, output_ctx, result->error_message, sizeof(result->error_message));
+        } else if (type == AVMEDIA_TYPE_SUBTITLE) {
+            ret = setup_subtitle_stream(ost, output_ctx, options, result->error_message, sizeof(result->error_message));
+        } else if (type == AVMEDIA_TYPE_VIDEO) {
+            ret = setup_video_stream(ost, output_ctx, video_codec_name, video_bitrate, resolution, options,
+                result->error_message, sizeof(result->error_message));
+        } else {
+            ret = setup_audio_stream(ost, input_ctx, output_ctx, input_data, input_size, audio_codec_name, audio_bitrate, options,
+                result->error_message, sizeof(result->error_message));
+        }
+        if (ret < 0) {
+            goto cleanup;
+        }
+    }
+    
+    // External subtitle files, added after the input's own tracks
+    nb_subtitle_inputs = parse_index_list(get_option(options, "subtitle_inputs", ""), subtitle_inputs, MAX_EXTRA_INPUTS);
+    for (int i = 0; i < nb_subtitle_inputs; i++) {
+        if (!format_allows_multiple_streams(output_ctx->oformat) &&
+            has_stream_type(output_streams, nb_output_streams, AVMEDIA_TYPE_SUBTITLE)) {
+            av_log(NULL, AV_LOG_WARNING, "%s holds one stream per type, dropping subtitle file %d\n",
+                output_ctx->oformat->name, subtitle_inputs[i]);
+            continue;
+        }
+        
+        ret = add_subtitle_input(output_ctx, output_streams, &nb_output_streams, subtitle_inputs[i], options,
+            result->error_message, sizeof(result->error_message));
+        if (ret < 0) {
+            goto cleanup;
+        }
+    }
+    
+    // Subtitle burn-in and text overlay onto the first re-encoded video stream
+    int burn_subtitles = av_dict_get(options, "burn_subtitles", NULL, 0) || av_dict_get(options, "burn_subtitles_input", NULL, 0);
+    int draw_text = av_dict_get(options, "drawtext", NULL, 0) != NULL;
+    if (burn_subtitles || draw_text) {
+        OutputStream *video_ost = NULL;
+        for (int o = 0; o < nb_output_streams && !video_ost; o++) {
+            if (!output_streams[o].copy && output_streams[o].in_stream->codecpar->codec_type == AVMEDIA_TYPE_VIDEO) {
+                video_ost = &output_streams[o];
+            }
+        }
+        
+        if (!video_ost) {
+            snprintf(result->error_message, sizeof(result->error_message), "Burning in subtitles or text needs a re-encoded video stream");
+            goto cleanup;
+        }
+        
+        if (burn_subtitles) {
+            ret = init_subtitle_burner(&video_ost->burner, input_data, input_size, input_ctx->start_time, options,
+                video_ost->enc_ctx, result->error_message, sizeof(result->error_message));
+            if (ret < 0) {
+                goto cleanup;
+            }
+        }
+        
+        if (draw_text) {
+            ret = init_text_overlay(&video_ost->text_overlay, options, video_ost->in_stream,
+                video_ost->enc_ctx, result->error_message, sizeof(result->error_message));
+            if (ret < 0) {
+                goto cleanup;
+            }
+        }
+    }
+    
+    // Check if we have at least one stream to process
+    if (nb_output_streams == 0) {
+        snprintf(result->error_message, sizeof(result->error_message),
+            drop_video || drop_audio ? "No streams left to process after dropping video/audio" : "No audio, video or subtitle streams found");
+        goto cleanup;
+    }
+    
+    apply_output_metadata(output_ctx, input_ctx, output_streams, nb_output_streams, options);
+    
+    // After the metadata, which may clear the stream tags an Ogg cover is written to
+    ret = add_cover_art(output_ctx, input_ctx, options, &cover_pkt, result->error_message, sizeof(result->error_message));
+    if (ret < 0) {
+        goto cleanup;
+    }
+    
+    ret = apply_output_chapters(output_ctx, input_ctx, options, result->error_message, sizeof(result->error_message));
+    if (ret < 0) {
+        goto cleanup;
+    }
+    
+    if (format_writes_files(output_ctx->oformat)) {
+        ret = strcmp(output_ctx->oformat->name, "hls") == 0 ?
+            setup_hls_output(output_ctx, options, &mux_options, result->error_message, sizeof(result->error_message)) :
+            setup_dash_output(output_ctx, options, &mux_options, result->error_message, sizeof(result->error_message));
+        if (ret < 0) {
+            goto cleanup;
+        }
+    }
+    
+    ret = setup_mp4_layout(output_ctx, options, &mux_options, result->error_message, sizeof(result->error_message));
+    if (ret < 0) {
+        goto cleanup;
+    }
+    
+    // Write output header
+    ret = avformat_write_header(output_ctx, &mux_options);
+    if (ret < 0) {
+        av_strerror(ret, result->error_message, sizeof(result->error_message));
+        goto cleanup;
+    }
+    
+    // Muxers like mp3 hold back audio until the cover picture has been written
+    if (cover_pkt) {
+        ret = av_interleaved_write_frame(output_ctx, cover_pkt);
+        if (ret < 0) {
+            av_strerror(ret, result->error_message, sizeof(result->error_message));
+            goto cleanup;
+        }
+    }
+    
+    // Allocate frames and packets
+    frame = av_frame_alloc();
+    pkt = av_packet_alloc();
+    enc_pkt = av_packet_alloc();
+    
+    if (!frame || !pkt || !enc_pkt) {
+        snprintf(result->error_message, sizeof(result->error_message), "Failed to allocate frames or packet");
+        goto cleanup;
+    }
+    
+    // Main processing loop
+    while (1) {
+        ret = av_read_frame(input_ctx, pkt);
+        
+        // End of file
+        if (ret == AVERROR_EOF) {
+            break;
+        } else if (ret < 0) {
+            av_strerror(ret, result->error_message, sizeof(result->error_message));
+            goto cleanup;
+        }
+        
+        if (stream_map[pkt->stream_index] < 0) {
+            av_packet_unref(pkt);
+            continue;
+        }
+        
+        OutputStream *ost = &output_streams[stream_map[pkt->stream_index]];
+        
+        // Keep streams from extra inputs in step with the main input
+        if (pkt->pts != AV_NOPTS_VALUE) {
+            int64_t now = av_rescale_q(pkt->pts, ost->in_stream->time_base, AV_TIME_BASE_Q);
+            for (int o = 0; o < nb_output_streams && ret >= 0; o++) {
+                if (output_streams[o].input_ctx) {
+                    ret = mux_extra_input_stream(&output_streams[o], output_ctx, now, frame, enc_pkt);
+                }
+            }
+            if (ret < 0) {
+                av_packet_unref(pkt);
+                av_strerror(ret, result->error_message, sizeof(result->error_message));
+                goto cleanup;
+            }
+        }
+        
+        // Progress from the packet position against the input duration
+        if (input_ctx->duration > 0 && pkt->pts != AV_NOPTS_VALUE) {
+            int64_t position = av_rescale_q(pkt->pts, ost->in_stream->time_base, AV_TIME_BASE_Q);
+            if (input_ctx->start_time != AV_NOPTS_VALUE) {
+                position -= input_ctx->start_time;
+            }
+            int progress = (int)av_clip((int)(position * 100 / input_ctx->duration), 0, 99);
+            if (progress > result->progress) {
+                update_progress(progress);
+            }
+        }
+        
+        if (ost->copy) {
+            ret = copy_packet(ost, output_ctx, pkt);
+        } else {
+            ret = transcode_packet(ost, output_ctx, pkt, frame, enc_pkt);
+        }
+        
+        av_packet_unref(pkt);
+        
+        if (ret < 0) {
+            av_strerror(ret, result->error_message, sizeof(result->error_message));
+            goto cleanup;
+        }
+    }
+    
+    // Flush decoders and encoders, after whatever remains of the extra inputs
+    for (int o = 0; o < nb_output_streams; o++) {
+        if (output_streams[o].input_ctx) {
+            ret = mux_extra_input_stream(&output_streams[o], output_ctx, INT64_MAX, frame, enc_pkt);
+            if (ret < 0) {
+                av_strerror(ret, result->error_message, sizeof(result->error_message));
+                goto cleanup;
+            }
+        }
+    }
+    
+    for (int o = 0; o < nb_output_streams; o++) {
+        ret = flush_output_stream(&output_streams[o], output_ctx, frame, enc_pkt);
+        if (ret < 0) {
+            av_strerror(ret, result->error_message, sizeof(result->error_message));
+            goto cleanup;
+        }
+    }
+    
+    // Write trailer
+    ret = av_write_trailer(output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, result->error_message, sizeof(result->error_message));
+        goto cleanup;
+    }
+    
+    // Set final progress
+    update_progress(100);
+    
+    // Success
+    result->success = 1;
+    
+cleanup:
+    // Clean up resources
+    if (frame) av_frame_free(&frame);
+    if (pkt) av_packet_free(&pkt);
+    if (enc_pkt) av_packet_free(&enc_pkt);
+    av_packet_free(&cover_pkt);
+    
+    for (int o = 0; o < nb_output_streams; o++) {
+        free_output_stream(&output_streams[o]);
+    }
+    av_free(stream_map);
+    
+    if (output_io_buffer) av_free(output_io_buffer);
+    
+    if (output_io_ctx) {
+        output_io_ctx->buffer = NULL; // Prevent double-free
+        avio_context_free(&output_io_ctx);
+    }
+    
+    close_memory_input(&input_ctx);
+    if (output_ctx) avformat_free_context(output_ctx);
+    
+    av_dict_free(&options);
+    av_dict_free(&mux_options);
+    
+    // Clear global result
+    global_result = NULL;
+    
+    return result;
+}
+
+// Write a JSON string literal, escaping quotes, backslashes and control characters
+static void write_json_string(AVIOContext *pb, const char *value) {
+    avio_w8(pb, '"');
+    for (const unsigned char *c = (const unsigned char *)(value ? value : ""); *c; c++) {
+        if (*c == '"' || *c == '\\') {
+            avio_w8(pb, '\\');
+            avio_w8(pb, *c);
+        } else if (*c < 0x20) {
+            avio_printf(pb, "\\u%04x", *c);
+        } else {
+            avio_w8(pb, *c);
+        }
+    }
+    avio_w8(pb, '"');
+}
+
+// Write a dictionary as a JSON object of strings
+static void write_json_metadata(AVIOContext *pb, const AVDictionary *metadata) {
+    const AVDictionaryEntry *entry = NULL;
+    int first = 1;
+    
+    avio_w8(pb, '{');
+    while ((entry = av_dict_get(metadata, "", entry, AV_DICT_IGNORE_SUFFIX))) {
+        if (!first) {
+            avio_w8(pb, ',');
+        }
+        write_json_string(pb, entry->key);
+        avio_w8(pb, ':');
+        write_json_string(pb, entry->value);
+        first = 0;
+    }
+    avio_w8(pb, '}');
+}
+
+static const char* stream_tag(AVStream *stream, const char *key) {
+    AVDictionaryEntry *entry = av_dict_get(stream->metadata, key, NULL, 0);
+    return entry ? entry->value : "";
+}
+
+// Describe the input's container and streams as JSON; caller frees the string
+EMSCRIPTEN_KEEPALIVE
+char* probe_input(uint8_t* input_data, size_t input_size) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    AVIOContext *pb = NULL;
+    uint8_t *json = NULL;
+    char error[200];
     
-    // Set final progress
-    update_progress(100);
+    int ret = open_memory_input(&input, &fmt_ctx);
+    if (ret >= 0) {
+        ret = avio_open_dyn_buf(&pb);
+    }
+    if (ret < 0) {
+        close_memory_input(&fmt_ctx);
+        av_strerror(ret, error, sizeof(error));
+        
+        char *message = malloc(sizeof(error) + 16);
+        if (message) {
+            snprintf(message, sizeof(error) + 16, "{\"error\":\"%s\"}", error);
+        }
+        return message;
+    }
+    
+    avio_printf(pb, "{\"format\":");
+    write_json_string(pb, fmt_ctx->iformat->name);
+    avio_printf(pb, ",\"duration\":%.3f,\"metadata\":",
+        fmt_ctx->duration != AV_NOPTS_VALUE ? fmt_ctx->duration / (double)AV_TIME_BASE : 0.0);
+    write_json_metadata(pb, fmt_ctx->metadata);
+    
+    // Chapter times are relative to the start of the input, like edited chapter lists
+    double start_time = fmt_ctx->start_time != AV_NOPTS_VALUE ? fmt_ctx->start_time / (double)AV_TIME_BASE : 0.0;
+    avio_printf(pb, ",\"chapters\":[");
+    for (unsigned int i = 0; i < fmt_ctx->nb_chapters; i++) {
+        AVChapter *chapter = fmt_ctx->chapters[i];
+        AVDictionaryEntry *title = av_dict_get(chapter->metadata, "title", NULL, 0);
+        
+        avio_printf(pb, "%s{\"start\":%.3f,\"end\":%.3f,\"title\":", i ? "," : "",
+            FFMAX(chapter->start * av_q2d(chapter->time_base) - start_time, 0.0),
+            chapter->end != AV_NOPTS_VALUE ? FFMAX(chapter->end * av_q2d(chapter->time_base) - start_time, 0.0) : 0.0);
+        write_json_string(pb, title ? title->value : "");
+        avio_w8(pb, '}');
+    }
+    avio_printf(pb, "],\"streams\":[");
+    
+    for (unsigned int i = 0; i < fmt_ctx->nb_streams; i++) {
+        AVStream *stream = fmt_ctx->streams[i];
+        AVCodecParameters *par = stream->codecpar;
+        const char *type = av_get_media_type_string(par->codec_type);
+        
+        // Cover art is a video stream with a single picture, report it separately
+        if (par->codec_type == AVMEDIA_TYPE_VIDEO && (stream->disposition & AV_DISPOSITION_ATTACHED_PIC)) {
+            type = "cover";
+        }
+        
+        avio_printf(pb, "%s{\"index\":%d,\"type\":", i ? "," : "", stream->index);
+        write_json_string(pb, type ? type : "unknown");
+        avio_printf(pb, ",\"codec\":");
+        write_json_string(pb, avcodec_get_name(par->codec_id));
+        
+        if (par->codec_type == AVMEDIA_TYPE_VIDEO) {
+            const char *pix_fmt = av_get_pix_fmt_name(par->format);
+            avio_printf(pb, ",\"width\":%d,\"height\":%d,\"fps\":%.3f,\"pix_fmt\":",
+                par->width, par->height, stream->avg_frame_rate.den ? av_q2d(stream->avg_frame_rate) : 0.0);
+            write_json_string(pb, pix_fmt);
+        } else if (par->codec_type == AVMEDIA_TYPE_AUDIO) {
+            char layout[64] = "";
+            av_channel_layout_describe(&par->ch_layout, layout, sizeof(layout));
+            avio_printf(pb, ",\"sample_rate\":%d,\"channels\":%d,\"channel_layout\":",
+                par->sample_rate, par->ch_layout.nb_channels);
+            write_json_string(pb, layout);
+        } else if (par->codec_type == AVMEDIA_TYPE_SUBTITLE) {
+            avio_printf(pb, ",\"text_based\":%s", is_text_subtitle(par) ? "true" : "false");
+        }
+        
+        avio_printf(pb, ",\"default\":%s,\"language\":", (stream->disposition & AV_DISPOSITION_DEFAULT) ? "true" : "false");
+        write_json_string(pb, stream_tag(stream, "language"));
+        avio_printf(pb, ",\"title\":");
+        write_json_string(pb, stream_tag(stream, "title"));
+        avio_printf(pb, ",\"metadata\":");
+        write_json_metadata(pb, stream->metadata);
+        avio_w8(pb, '}');
+    }
+    
+    avio_printf(pb, "]}");
+    avio_w8(pb, 0);
+    
+    int size = avio_close_dyn_buf(pb, &json);
+    close_memory_input(&fmt_ctx);
+    
+    // Hand back a malloc'd copy so JavaScript can release it with _free
+    char *result = malloc(size);
+    if (result) {
+        memcpy(result, json, size);
+    }
+    av_free(json);
+    
+    return result;
+}
+
+// Encoder for a still image format
+static const char* image_encoder_name(const char *format) {
+    if (strcmp(format, "png") == 0) {
+        return "png";
+    } else if (strcmp(format, "jpeg") == 0 || strcmp(format, "jpg") == 0) {
+        return "mjpeg";
+    } else if (strcmp(format, "webp") == 0) {
+        return "libwebp";
+    }
+    
+    return NULL;
+}
+
+// Scale a decoded frame and encode it as one image into the result. A width or
+// height of 0 follows the display aspect ratio; both 0 keep the display size.
+static int encode_image(const AVFrame *frame, const char *format, int width, int height, TranscodeResult *result) {
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    
+    const char *encoder_name = image_encoder_name(format);
+    if (!encoder_name) {
+        snprintf(error, error_size, "Unsupported image format '%s'", format);
+        return AVERROR(EINVAL);
+    }
+    
+    const AVCodec *encoder = avcodec_find_encoder_by_name(encoder_name);
+    if (!encoder) {
+        snprintf(error, error_size, "Image encoder '%s' not found", encoder_name);
+        return AVERROR_ENCODER_NOT_FOUND;
+    }
+    
+    // Non-square pixels are stretched out so the image looks like the video did
+    AVRational sar = frame->sample_aspect_ratio.num > 0 ? frame->sample_aspect_ratio : (AVRational){1, 1};
+    int display_width = FFMAX(1, (int)av_rescale(frame->width, sar.num, sar.den));
+    
+    if (width <= 0 && height <= 0) {
+        width = display_width;
+        height = frame->height;
+    } else if (width <= 0) {
+        width = FFMAX(1, (int)av_rescale(height, display_width, frame->height));
+    } else if (height <= 0) {
+        height = FFMAX(1, (int)av_rescale(width, frame->height, display_width));
+    }
+    
+    AVCodecContext *enc_ctx = avcodec_alloc_context3(encoder);
+    AVFrame *scaled = av_frame_alloc();
+    AVPacket *packet = av_packet_alloc();
+    struct SwsContext *sws_ctx = NULL;
+    int ret;
+    
+    if (!enc_ctx || !scaled || !packet) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(frame->format);
+    int has_alpha = desc && (desc->flags & AV_PIX_FMT_FLAG_ALPHA);
+    
+    enc_ctx->width = width;
+    enc_ctx->height = height;
+    enc_ctx->time_base = (AVRational){1, 25};
+    enc_ctx->sample_aspect_ratio = (AVRational){1, 1};
+    enc_ctx->pix_fmt = encoder->pix_fmts
+        ? avcodec_find_best_pix_fmt_of_list(encoder->pix_fmts, frame->format, has_alpha, NULL)
+        : AV_PIX_FMT_RGB24;
+    
+    if (strcmp(encoder_name, "mjpeg") == 0) {
+        // Full-range YUV at high quality, as JPEG viewers expect
+        enc_ctx->color_range = AVCOL_RANGE_JPEG;
+        enc_ctx->flags |= AV_CODEC_FLAG_QSCALE;
+        enc_ctx->global_quality = FF_QP2LAMBDA * 2;
+    }
+    
+    ret = avcodec_open2(enc_ctx, encoder, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    sws_ctx = sws_getContext(frame->width, frame->height, frame->format,
+                             width, height, enc_ctx->pix_fmt,
+                             SWS_BICUBIC, NULL, NULL, NULL);
+    if (!sws_ctx) {
+        snprintf(error, error_size, "Failed to create scaling context");
+        ret = AVERROR(EINVAL);
+        goto end;
+    }
+    
+    scaled->format = enc_ctx->pix_fmt;
+    scaled->width = width;
+    scaled->height = height;
+    scaled->pts = 0;
+    ret = av_frame_get_buffer(scaled, 0);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    sws_scale(sws_ctx, (const uint8_t * const *)frame->data, frame->linesize, 0, frame->height,
+              scaled->data, scaled->linesize);
+    
+    ret = avcodec_send_frame(enc_ctx, scaled);
+    if (ret >= 0) {
+        ret = avcodec_send_frame(enc_ctx, NULL);
+    }
+    if (ret >= 0) {
+        ret = avcodec_receive_packet(enc_ctx, packet);
+    }
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    result->output_data = malloc(packet->size);
+    if (!result->output_data) {
+        snprintf(error, error_size, "Out of memory");
+        ret = AVERROR(ENOMEM);
+        goto end;
+    }
+    memcpy(result->output_data, packet->data, packet->size);
+    result->output_size = packet->size;
+    
+end:
+    sws_freeContext(sws_ctx);
+    av_packet_free(&packet);
+    av_frame_free(&scaled);
+    avcodec_free_context(&enc_ctx);
+    return ret;
+}
+
+// Decode the frame at a time (in seconds) and encode it as a PNG, JPEG or WebP
+// image, scaled to width x height when either is set
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* extract_frame(uint8_t* input_data, size_t input_size, double timestamp, const char* format, int width, int height) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    AVCodecContext *dec_ctx = NULL;
+    AVStream *stream = NULL;
+    AVFrame *frame = NULL;
+    
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        return NULL;
+    }
+    
+    int ret = open_video_input(&input, &fmt_ctx, &stream, &dec_ctx, result->error_message, sizeof(result->error_message));
+    if (ret < 0) {
+        goto end;
+    }
+    
+    frame = av_frame_alloc();
+    if (!frame) {
+        snprintf(result->error_message, sizeof(result->error_message), "Out of memory");
+        goto end;
+    }
+    
+    ret = decode_frame_at(fmt_ctx, stream, dec_ctx, FFMAX(timestamp, 0.0), frame);
+    if (ret < 0) {
+        snprintf(result->error_message, sizeof(result->error_message), "Could not decode a frame at %.3fs", timestamp);
+        goto end;
+    }
+    
+    if (encode_image(frame, format, width, height, result) >= 0) {
+        result->success = 1;
+    }
+    
+end:
+    av_frame_free(&frame);
+    avcodec_free_context(&dec_ctx);
+    close_memory_input(&fmt_ctx);
+    return result;
+}
+
+// Frames of the input read one at a time for export as numbered images
+typedef struct {
+    MemoryInput input;
+    AVFormatContext *fmt_ctx;
+    AVStream *stream;
+    AVCodecContext *dec_ctx;
+    AVPacket *packet;
+    AVFrame *frame;
+    int every;
+    double start;
+    double duration;
+    int width;
+    int64_t index;
+    int eof;
+    char error[256];
+} ImageSequence;
+
+// Start reading frames from the input, taking ownership of the data. Options: every
+// (keep every Nth frame), start, duration (0 runs to the end) and width (0 keeps the
+// display size). Errors are reported by the first image_sequence_next().
+EMSCRIPTEN_KEEPALIVE
+ImageSequence* image_sequence_open(uint8_t* input_data, size_t input_size, const char* options_string) {
+    AVDictionary *options = NULL;
+    
+    ImageSequence *seq = calloc(1, sizeof(ImageSequence));
+    if (!seq) {
+        free(input_data);
+        return NULL;
+    }
+    seq->input.data = input_data;
+    seq->input.size = input_size;
+    
+    if (options_string && options_string[0] && av_dict_parse_string(&options, options_string, "=", ":", 0) < 0) {
+        snprintf(seq->error, sizeof(seq->error), "Invalid image sequence options '%s'", options_string);
+        goto end;
+    }
+    
+    seq->every = FFMAX(atoi(get_option(options, "every", "1")), 1);
+    seq->start = FFMAX(atof(get_option(options, "start", "0")), 0.0);
+    seq->duration = FFMAX(atof(get_option(options, "duration", "0")), 0.0);
+    seq->width = FFMAX(atoi(get_option(options, "width", "0")), 0);
+    
+    if (open_video_input(&seq->input, &seq->fmt_ctx, &seq->stream, &seq->dec_ctx, seq->error, sizeof(seq->error)) < 0) {
+        goto end;
+    }
+    
+    seq->packet = av_packet_alloc();
+    seq->frame = av_frame_alloc();
+    if (!seq->packet || !seq->frame) {
+        snprintf(seq->error, sizeof(seq->error), "Out of memory");
+        goto end;
+    }
+    
+    if (seq->start > 0) {
+        int64_t start = seq->stream->start_time != AV_NOPTS_VALUE ? seq->stream->start_time : 0;
+        av_seek_frame(seq->fmt_ctx, seq->stream->index,
+            start + av_rescale_q((int64_t)(seq->start * AV_TIME_BASE), AV_TIME_BASE_Q, seq->stream->time_base),
+            AVSEEK_FLAG_BACKWARD);
+    }
+    
+end:
+    av_dict_free(&options);
+    return seq;
+}
+
+// Decode the next frame to export into seq->frame; returns 1, or 0 past the range
+static int image_sequence_read(ImageSequence *seq) {
+    AVStream *stream = seq->stream;
+    int64_t start = stream->start_time != AV_NOPTS_VALUE ? stream->start_time : 0;
+    
+    for (;;) {
+        int ret = avcodec_receive_frame(seq->dec_ctx, seq->frame);
+        if (ret == AVERROR(EAGAIN)) {
+            if (seq->eof) {
+                return 0;
+            }
+            ret = av_read_frame(seq->fmt_ctx, seq->packet);
+            if (ret < 0) {
+                seq->eof = 1;
+                avcodec_send_packet(seq->dec_ctx, NULL);
+            } else {
+                if (seq->packet->stream_index == stream->index) {
+                    avcodec_send_packet(seq->dec_ctx, seq->packet);
+                }
+                av_packet_unref(seq->packet);
+            }
+            continue;
+        } else if (ret < 0) {
+            return ret == AVERROR_EOF ? 0 : ret;
+        }
+        
+        // Frames before the range come from seeking to the keyframe ahead of it
+        int64_t pts = seq->frame->best_effort_timestamp;
+        if (pts != AV_NOPTS_VALUE) {
+            double time = (pts - start) * av_q2d(stream->time_base);
+            if (time < seq->start - 0.0005) {
+                av_frame_unref(seq->frame);
+                continue;
+            } else if (seq->duration > 0 && time >= seq->start + seq->duration) {
+                av_frame_unref(seq->frame);
+                return 0;
+            }
+        }
+        
+        if (seq->index++ % seq->every == 0) {
+            return 1;
+        }
+        av_frame_unref(seq->frame);
+    }
+}
+
+// Encode the next frame as a PNG, JPEG or WebP image; a successful result with no
+// data marks the end of the sequence
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* image_sequence_next(ImageSequence* seq, const char* format) {
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        return NULL;
+    }
+    
+    if (seq->error[0]) {
+        snprintf(result->error_message, sizeof(result->error_message), "%s", seq->error);
+        return result;
+    }
+    
+    int ret = image_sequence_read(seq);
+    if (ret < 0) {
+        av_strerror(ret, result->error_message, sizeof(result->error_message));
+    } else if (ret == 0) {
+        result->success = 1;
+    } else {
+        if (encode_image(seq->frame, format, seq->width, 0, result) >= 0) {
+            result->success = 1;
+        }
+        av_frame_unref(seq->frame);
+    }
+    
+    return result;
+}
+
+EMSCRIPTEN_KEEPALIVE
+void image_sequence_close(ImageSequence* seq) {
+    if (!seq) {
+        return;
+    }
+    
+    av_packet_free(&seq->packet);
+    av_frame_free(&seq->frame);
+    avcodec_free_context(&seq->dec_ctx);
+    close_memory_input(&seq->fmt_ctx);
+    free((void *)seq->input.data);
+    free(seq);
+}
+
+// Ways of choosing where an input is split
+enum {
+    SPLIT_DURATION,
+    SPLIT_SIZE,
+    SPLIT_CHAPTERS,
+};
+
+// An input cut into parts by stream copy, one part at a time. Cuts fall on keyframes
+// of the first video stream (any packet of an audio-only input), so packets are held
+// back a group of pictures at a time until it is known which part they belong to.
+typedef struct {
+    MemoryInput input;
+    AVFormatContext *fmt_ctx;
+    const AVOutputFormat *oformat;
+    // Output stream index of each input stream, or -1 when it is left out
+    int *stream_map;
+    int nb_outputs;
+    AVStream *key_stream;
+    int mode;
+    int64_t duration;
+    int64_t size;
+    // The first cut point still ahead, in AV_TIME_BASE units from the start
+    int64_t next_cut;
+    unsigned int chapter;
+    // The group of pictures being read, its size in bytes and start time
+    AVPacket **group;
+    int group_size;
+    int group_capacity;
+    int64_t group_bytes;
+    int64_t group_time;
+    // Set once the group is complete; `held` then starts the next one
+    int group_complete;
+    AVPacket *held;
+    AVPacket *packet;
+    int eof;
+    char error[256];
+} Splitter;
+
+// Time of a packet in AV_TIME_BASE units from the start of the input
+static int64_t splitter_packet_time(const Splitter *s, const AVPacket *pkt) {
+    int64_t ts = pkt->pts != AV_NOPTS_VALUE ? pkt->pts : pkt->dts;
+    if (ts == AV_NOPTS_VALUE) {
+        return AV_NOPTS_VALUE;
+    }
+    int64_t start = s->fmt_ctx->start_time != AV_NOPTS_VALUE ? s->fmt_ctx->start_time : 0;
+    return av_rescale_q(ts, s->fmt_ctx->streams[pkt->stream_index]->time_base, AV_TIME_BASE_Q) - start;
+}
+
+// Move the cut point past `time`, to the next multiple of the part length or the
+// next chapter starting after it
+static void splitter_advance(Splitter *s, int64_t time) {
+    if (s->mode == SPLIT_DURATION) {
+        while (s->next_cut <= time) {
+            s->next_cut += s->duration;
+        }
+    } else if (s->mode == SPLIT_CHAPTERS) {
+        int64_t start = s->fmt_ctx->start_time != AV_NOPTS_VALUE ? s->fmt_ctx->start_time : 0;
+        s->next_cut = INT64_MAX;
+        for (; s->chapter < s->fmt_ctx->nb_chapters; s->chapter++) {
+            AVChapter *chapter = s->fmt_ctx->chapters[s->chapter];
+            int64_t chapter_start = av_rescale_q(chapter->start, chapter->time_base, AV_TIME_BASE_Q) - start;
+            if (chapter_start > time) {
+                s->next_cut = chapter_start;
+                break;
+            }
+        }
+    }
+}
+
+static int splitter_push(Splitter *s, AVPacket *pkt) {
+    if (s->group_size == s->group_capacity) {
+        int capacity = s->group_capacity ? s->group_capacity * 2 : 64;
+        AVPacket **group = av_realloc_array(s->group, capacity, sizeof(*group));
+        if (!group) {
+            return AVERROR(ENOMEM);
+        }
+        s->group = group;
+        s->group_capacity = capacity;
+    }
+    
+    AVPacket *copy = av_packet_alloc();
+    if (!copy) {
+        return AVERROR(ENOMEM);
+    }
+    av_packet_move_ref(copy, pkt);
+    
+    if (s->group_size == 0) {
+        s->group_time = splitter_packet_time(s, copy);
+    }
+    s->group[s->group_size++] = copy;
+    s->group_bytes += copy->size;
+    return 0;
+}
+
+// Whether the complete group should start a new part rather than end this one
+static int splitter_should_cut(const Splitter *s, int64_t part_bytes) {
+    if (part_bytes == 0) {
+        return 0;
+    }
+    if (s->mode == SPLIT_SIZE) {
+        // Leave a little room for the container's own overhead
+        return part_bytes + s->group_bytes > s->size - s->size / 50;
+    }
+    return s->group_time != AV_NOPTS_VALUE && s->group_time >= s->next_cut;
+}
+
+static int splitter_write_group(Splitter *s, AVFormatContext *output_ctx) {
+    int ret = 0;
+    
+    for (int i = 0; i < s->group_size; i++) {
+        AVPacket *pkt = s->group[i];
+        if (ret >= 0) {
+            AVStream *out = output_ctx->streams[s->stream_map[pkt->stream_index]];
+            av_packet_rescale_ts(pkt, s->fmt_ctx->streams[pkt->stream_index]->time_base, out->time_base);
+            pkt->stream_index = out->index;
+            pkt->pos = -1;
+            ret = av_interleaved_write_frame(output_ctx, pkt);
+        }
+        av_packet_free(&s->group[i]);
+    }
+    
+    s->group_size = 0;
+    s->group_bytes = 0;
+    return ret;
+}
+
+// Start splitting the input, taking ownership of the data. Parts are written in
+// `format`; options: split_mode (duration, size or chapters), split_duration in
+// seconds and split_size in bytes. Errors are reported by the first split_next().
+EMSCRIPTEN_KEEPALIVE
+Splitter* split_open(uint8_t* input_data, size_t input_size, const char* format, const char* options_string) {
+    AVDictionary *options = NULL;
+    
+    Splitter *s = calloc(1, sizeof(Splitter));
+    if (!s) {
+        free(input_data);
+        return NULL;
+    }
+    s->input.data = input_data;
+    s->input.size = input_size;
+    
+    if (options_string && options_string[0] && av_dict_parse_string(&options, options_string, "=", ":", 0) < 0) {
+        snprintf(s->error, sizeof(s->error), "Invalid split options '%s'", options_string);
+        goto end;
+    }
+    
+    const char *mode = get_option(options, "split_mode", "duration");
+    if (strcmp(mode, "duration") == 0) {
+        s->mode = SPLIT_DURATION;
+        s->duration = (int64_t)(atof(get_option(options, "split_duration", "0")) * AV_TIME_BASE);
+        if (s->duration < AV_TIME_BASE) {
+            snprintf(s->error, sizeof(s->error), "Parts must be at least a second long");
+            goto end;
+        }
+    } else if (strcmp(mode, "size") == 0) {
+        s->mode = SPLIT_SIZE;
+        s->size = strtoll(get_option(options, "split_size", "0"), NULL, 10);
+        if (s->size < 65536) {
+            snprintf(s->error, sizeof(s->error), "Parts must be allowed at least 64 KB");
+            goto end;
+        }
+    } else if (strcmp(mode, "chapters") == 0) {
+        s->mode = SPLIT_CHAPTERS;
+    } else {
+        snprintf(s->error, sizeof(s->error), "Unknown split mode '%s'", mode);
+        goto end;
+    }
+    
+    s->oformat = av_guess_format(format, NULL, NULL);
+    if (!s->oformat) {
+        snprintf(s->error, sizeof(s->error), "Unknown output format '%s'", format);
+        goto end;
+    }
+    
+    int ret = open_memory_input(&s->input, &s->fmt_ctx);
+    if (ret < 0) {
+        av_strerror(ret, s->error, sizeof(s->error));
+        goto end;
+    }
+    
+    if (s->mode == SPLIT_CHAPTERS && s->fmt_ctx->nb_chapters == 0) {
+        snprintf(s->error, sizeof(s->error), "The input has no chapters");
+        goto end;
+    }
+    
+    s->stream_map = av_malloc_array(s->fmt_ctx->nb_streams, sizeof(*s->stream_map));
+    s->packet = av_packet_alloc();
+    s->held = av_packet_alloc();
+    if (!s->stream_map || !s->packet || !s->held) {
+        snprintf(s->error, sizeof(s->error), "Out of memory");
+        goto end;
+    }
+    
+    // Every audio, video and subtitle stream the container can hold goes into each part
+    for (unsigned int i = 0; i < s->fmt_ctx->nb_streams; i++) {
+        AVStream *stream = s->fmt_ctx->streams[i];
+        enum AVMediaType type = stream->codecpar->codec_type;
+        s->stream_map[i] = -1;
+        
+        if ((type != AVMEDIA_TYPE_VIDEO && type != AVMEDIA_TYPE_AUDIO && type != AVMEDIA_TYPE_SUBTITLE) ||
+            (stream->disposition & AV_DISPOSITION_ATTACHED_PIC) ||
+            avformat_query_codec(s->oformat, stream->codecpar->codec_id, FF_COMPLIANCE_NORMAL) == 0) {
+            continue;
+        }
+        
+        s->stream_map[i] = s->nb_outputs++;
+        if (type == AVMEDIA_TYPE_VIDEO && (!s->key_stream || s->key_stream->codecpar->codec_type != AVMEDIA_TYPE_VIDEO)) {
+            s->key_stream = stream;
+        } else if (type == AVMEDIA_TYPE_AUDIO && !s->key_stream) {
+            s->key_stream = stream;
+        }
+    }
+    
+    if (!s->key_stream) {
+        snprintf(s->error, sizeof(s->error), "None of the audio or video streams can be copied into %s", s->oformat->name);
+        goto end;
+    }
+    
+    splitter_advance(s, 0);
+    
+end:
+    av_dict_free(&options);
+    return s;
+}
+
+// Read the next part; a successful result with no data marks the end of the input
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* split_next(Splitter* s) {
+    AVFormatContext *output_ctx = NULL;
+    int64_t part_bytes = 0;
+    int ret;
+    
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        return NULL;
+    }
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    
+    if (s->error[0]) {
+        snprintf(error, error_size, "%s", s->error);
+        return result;
+    }
+    if (s->eof && s->group_size == 0) {
+        result->success = 1;
+        return result;
+    }
+    
+    ret = avformat_alloc_output_context2(&output_ctx, s->oformat, NULL, NULL);
+    if (ret < 0 || !output_ctx) {
+        snprintf(error, error_size, "Failed to create output context");
+        goto end;
+    }
+    
+    unsigned char *io_buffer = av_malloc(4096);
+    output_ctx->pb = io_buffer ? avio_alloc_context(io_buffer, 4096, 1, result, NULL, write_packet, seek_output) : NULL;
+    if (!output_ctx->pb) {
+        av_free(io_buffer);
+        snprintf(error, error_size, "Failed to create output IO context");
+        goto end;
+    }
+    
+    for (unsigned int i = 0; i < s->fmt_ctx->nb_streams; i++) {
+        if (s->stream_map[i] < 0) {
+            continue;
+        }
+        
+        AVStream *in_stream = s->fmt_ctx->streams[i];
+        AVStream *out_stream = avformat_new_stream(output_ctx, NULL);
+        if (!out_stream) {
+            snprintf(error, error_size, "Out of memory");
+            goto end;
+        }
+        ret = avcodec_parameters_copy(out_stream->codecpar, in_stream->codecpar);
+        if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            goto end;
+        }
+        
+        // Codec tags are container-specific, let the muxer choose
+        out_stream->codecpar->codec_tag = 0;
+        out_stream->time_base = in_stream->time_base;
+        out_stream->sample_aspect_ratio = in_stream->sample_aspect_ratio;
+        out_stream->disposition = in_stream->disposition;
+        av_dict_copy(&out_stream->metadata, in_stream->metadata, 0);
+    }
+    av_dict_copy(&output_ctx->metadata, s->fmt_ctx->metadata, 0);
+    
+    // Every part starts from zero
+    output_ctx->avoid_negative_ts = AVFMT_AVOID_NEG_TS_MAKE_ZERO;
+    
+    ret = avformat_write_header(output_ctx, NULL);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
+    
+    for (;;) {
+        if (s->group_complete) {
+            if (splitter_should_cut(s, part_bytes)) {
+                break;
+            }
+            
+            part_bytes += s->group_bytes;
+            int64_t group_time = s->group_time;
+            ret = splitter_write_group(s, output_ctx);
+            if (ret < 0) {
+                av_strerror(ret, error, error_size);
+                goto end;
+            }
+            if (group_time != AV_NOPTS_VALUE && group_time >= s->next_cut) {
+                splitter_advance(s, group_time);
+            }
+            
+            s->group_complete = 0;
+            if (s->eof) {
+                break;
+            }
+            ret = splitter_push(s, s->held);
+            if (ret < 0) {
+                snprintf(error, error_size, "Out of memory");
+                goto end;
+            }
+            continue;
+        }
+        
+        ret = av_read_frame(s->fmt_ctx, s->packet);
+        if (ret == AVERROR_EOF) {
+            s->eof = 1;
+            if (s->group_size == 0) {
+                break;
+            }
+            s->group_complete = 1;
+            continue;
+        } else if (ret < 0) {
+            av_strerror(ret, error, error_size);
+            goto end;
+        }
+        
+        if (s->stream_map[s->packet->stream_index] < 0) {
+            av_packet_unref(s->packet);
+            continue;
+        }
+        
+        // A keyframe of the stream cuts are aligned to closes the group
+        if (s->packet->stream_index == s->key_stream->index && (s->packet->flags & AV_PKT_FLAG_KEY) && s->group_size > 0) {
+            av_packet_move_ref(s->held, s->packet);
+            s->group_complete = 1;
+            continue;
+        }
+        
+        ret = splitter_push(s, s->packet);
+        if (ret < 0) {
+            av_packet_unref(s->packet);
+            snprintf(error, error_size, "Out of memory");
+            goto end;
+        }
+    }
     
-    // Success
+    ret = av_write_trailer(output_ctx);
+    if (ret < 0) {
+        av_strerror(ret, error, error_size);
+        goto end;
+    }
     result->success = 1;
     
-cleanup:
-    // Clean up resources
-    if (video_frame) av_frame_free(&video_frame);
-    if (audio_frame) av_frame_free(&audio_frame);
-    if (pkt) av_packet_free(&pkt);
+end:
+    if (!result->success) {
+        // The input can't be picked up again part way through a part
+        snprintf(s->error, sizeof(s->error), "%s", error);
+    }
+    // A part with no packets means the input has ended
+    if (!result->success || part_bytes == 0) {
+        free(result->output_data);
+        result->output_data = NULL;
+        result->output_size = 0;
+    }
+    if (output_ctx) {
+        if (output_ctx->pb) {
+            av_freep(&output_ctx->pb->buffer);
+            avio_context_free(&output_ctx->pb);
+        }
+        avformat_free_context(output_ctx);
+    }
+    return result;
+}
+
+EMSCRIPTEN_KEEPALIVE
+void split_close(Splitter* s) {
+    if (!s) {
+        return;
+    }
+    
+    for (int i = 0; i < s->group_size; i++) {
+        av_packet_free(&s->group[i]);
+    }
+    av_freep(&s->group);
+    av_packet_free(&s->held);
+    av_packet_free(&s->packet);
+    av_freep(&s->stream_map);
+    close_memory_input(&s->fmt_ctx);
+    free((void *)s->input.data);
+    free(s);
+}
+
+#define MAX_RENDITIONS 8
+
+// Time in microseconds each ladder_step() works for before the page gets control back
+#define LADDER_STEP_TIME 100000
+
+// One output of a rendition ladder: the shared decoded video scaled and encoded at
+// its own size and bitrate, plus the audio, written to a file of the output registry
+typedef struct {
+    AVFormatContext *output_ctx;
+    // Video first, then audio when the input has some
+    OutputStream streams[2];
+    int nb_streams;
+    int height;
+    int bitrate;
+    // Input time handed to the video encoder so far, in AV_TIME_BASE units
+    int64_t position;
+    int finished;
+} Rendition;
+
+typedef struct {
+    MemoryInput input;
+    AVFormatContext *fmt_ctx;
+    // Each stream is decoded once, for every rendition
+    AVStream *video_stream;
+    AVStream *audio_stream;
+    AVCodecContext *video_dec;
+    AVCodecContext *audio_dec;
+    Rendition renditions[MAX_RENDITIONS];
+    int nb_renditions;
+    AVPacket *packet;
+    AVPacket *enc_pkt;
+    AVFrame *frame;
+    int eof;
+    char error[256];
+} Ladder;
+
+// Parse "<height>@<kbps>,..." into the renditions of the ladder
+static int parse_ladder(Ladder *l, const char *list) {
+    const char *p = list;
+    
+    while (*p) {
+        char *end;
+        int height = strtol(p, &end, 10);
+        int bitrate = *end == '@' ? strtol(end + 1, &end, 10) : 0;
+        
+        if (height < 16 || height > 4320 || bitrate <= 0 || (*end && *end != ',')) {
+            snprintf(l->error, sizeof(l->error), "Invalid rendition '%.*s', expected <height>@<kbps>", (int)strcspn(p, ","), p);
+            return AVERROR(EINVAL);
+        }
+        if (l->nb_renditions == MAX_RENDITIONS) {
+            snprintf(l->error, sizeof(l->error), "A ladder holds at most %d renditions", MAX_RENDITIONS);
+            return AVERROR(EINVAL);
+        }
+        
+        // Each rendition is stored under its height and bitrate
+        for (int i = 0; i < l->nb_renditions; i++) {
+            if (l->renditions[i].height == height && l->renditions[i].bitrate == bitrate) {
+                snprintf(l->error, sizeof(l->error), "Rendition %dp at %d kbps is listed twice", height, bitrate);
+                return AVERROR(EINVAL);
+            }
+        }
+        
+        l->renditions[l->nb_renditions].height = height;
+        l->renditions[l->nb_renditions].bitrate = bitrate;
+        l->nb_renditions++;
+        p = *end ? end + 1 : end;
+    }
     
-    if (video_dec_ctx) avcodec_free_context(&video_dec_ctx);
-    if (audio_dec_ctx) avcodec_free_context(&audio_dec_ctx);
-    if (video_enc_ctx) avcodec_free_context(&video_enc_ctx);
-    if (audio_enc_ctx) avcodec_free_context(&audio_enc_ctx);
+    if (l->nb_renditions == 0) {
+        snprintf(l->error, sizeof(l->error), "The ladder has no renditions");
+        return AVERROR(EINVAL);
+    }
+    
+    return 0;
+}
+
+// Measure the ladder's audio once for every rendition, unless the first pass already
+// did: it only covers the main audio stream
+static int measure_ladder_loudness(Ladder *l, AVDictionary **options) {
+    AVChannelLayout layout = {0};
+    LoudnessMeter meter = {0};
+    char value[32];
+    int main_audio = av_find_best_stream(l->fmt_ctx, AVMEDIA_TYPE_AUDIO, -1, -1, NULL, 0);
+    int ret = 0;
     
-    if (sws_ctx) sws_freeContext(sws_ctx);
-    if (swr_ctx) swr_free(&swr_ctx);
+    if (!l->audio_stream || !strcmp(get_option(*options, "loudnorm", "off"), "off") ||
+        (av_dict_get(*options, "measured_i", NULL, 0) && l->audio_stream->index == main_audio)) {
+        return 0;
+    }
     
-    if (input_io_buffer) av_free(input_io_buffer);
-    if (output_io_buffer) av_free(output_io_buffer);
+    const char *layout_name = get_option(*options, "channel_layout", "same");
+    if (strcmp(layout_name, "same") != 0) {
+        ret = av_channel_layout_from_string(&layout, layout_name);
+    }
+    if (ret >= 0) {
+        ret = measure_input_loudness((uint8_t *)l->input.data, l->input.size, l->audio_stream->index,
+            layout.nb_channels ? &layout : NULL, &meter);
+    }
     
-    if (input_io_ctx) {
-        input_io_ctx->buffer = NULL; // Prevent double-free since buffer is freed separately
-        avio_context_free(&input_io_ctx);
+    if (ret >= 0) {
+        double threshold;
+        snprintf(value, sizeof(value), "%f", loudness_meter_integrated(&meter, &threshold));
+        av_dict_set(options, "measured_i", value, 0);
+        snprintf(value, sizeof(value), "%f", loudness_meter_true_peak_db(&meter));
+        av_dict_set(options, "measured_tp", value, 0);
+        av_dict_set_int(options, "measured_stream", l->audio_stream->index, 0);
+    } else {
+        av_strerror(ret, l->error, sizeof(l->error));
     }
     
-    if (output_io_ctx) {
-        output_io_ctx->buffer = NULL; // Prevent double-free
-        avio_context_free(&output_io_ctx);
+    loudness_meter_free(&meter);
+    av_channel_layout_uninit(&layout);
+    return ret;
+}
+
+// Create the output of a rendition with its encoders, and write its header
+static int open_rendition(Ladder *l, Rendition *r, const AVOutputFormat *oformat, const char *video_codec_name,
+                          const char *audio_codec_name, int audio_bitrate, AVDictionary *options) {
+    AVCodecParameters *par = l->video_stream->codecpar;
+    char name[64];
+    char resolution[32];
+    int ret;
+    
+    ret = avformat_alloc_output_context2(&r->output_ctx, oformat, NULL, NULL);
+    if (ret < 0 || !r->output_ctx) {
+        snprintf(l->error, sizeof(l->error), "Failed to create output context");
+        return ret < 0 ? ret : AVERROR(ENOMEM);
     }
     
-    if (input_ctx) avformat_close_input(&input_ctx);
-    if (output_ctx) avformat_free_context(output_ctx);
+    snprintf(name, sizeof(name), "%dp_%dk", r->height, r->bitrate);
+    ret = open_output_file(r->output_ctx, &r->output_ctx->pb, name, AVIO_FLAG_WRITE, NULL);
+    if (ret < 0) {
+        snprintf(l->error, sizeof(l->error), "Out of memory");
+        return ret;
+    }
     
-    // Clear global result
-    global_result = NULL;
+    if (!add_output_stream(r->output_ctx, r->streams, &r->nb_streams, l->video_stream) ||
+        (l->audio_stream && !add_output_stream(r->output_ctx, r->streams, &r->nb_streams, l->audio_stream))) {
+        snprintf(l->error, sizeof(l->error), "Out of memory");
+        return AVERROR(ENOMEM);
+    }
+    
+    // The width follows the aspect ratio of the input, rounded to even for chroma subsampling
+    int width = (int)lrint((double)r->height * par->width / par->height / 2) * 2;
+    snprintf(resolution, sizeof(resolution), "%dx%d", FFMAX(width, 2), r->height);
+    
+    ret = setup_video_stream(&r->streams[0], r->output_ctx, video_codec_name, r->bitrate, resolution, options,
+        l->error, sizeof(l->error));
+    if (ret >= 0 && l->audio_stream) {
+        ret = setup_audio_stream(&r->streams[1], l->fmt_ctx, r->output_ctx, (uint8_t *)l->input.data, l->input.size,
+            audio_codec_name, audio_bitrate, options, l->error, sizeof(l->error));
+    }
+    if (ret < 0) {
+        return ret;
+    }
+    
+    // Setting up opened a decoder per stream; the first rendition's become the shared ones
+    for (int s = 0; s < r->nb_streams; s++) {
+        AVCodecContext **shared = s == 0 ? &l->video_dec : &l->audio_dec;
+        if (!*shared) {
+            *shared = r->streams[s].dec_ctx;
+            r->streams[s].dec_ctx = NULL;
+        } else {
+            avcodec_free_context(&r->streams[s].dec_ctx);
+        }
+    }
+    
+    apply_output_metadata(r->output_ctx, l->fmt_ctx, r->streams, r->nb_streams, options);
+    
+    ret = apply_output_chapters(r->output_ctx, l->fmt_ctx, options, l->error, sizeof(l->error));
+    if (ret < 0) {
+        return ret;
+    }
+    
+    return write_output_header(r->output_ctx, options, l->error, sizeof(l->error));
+}
+
+// Open a ladder of renditions of the input, encoded in `format` with the given codecs.
+// Options are those of transcode(), plus the renditions as "ladder=<height>@<kbps>,...";
+// the track selection, audio effects, metadata and chapters apply to every rendition.
+// The ladder takes ownership of the input until ladder_close(); errors are kept for
+// ladder_step() to report.
+EMSCRIPTEN_KEEPALIVE
+Ladder* ladder_open(uint8_t* input_data, size_t input_size, const char* format, const char* video_codec_name,
+                    const char* audio_codec_name, int audio_bitrate, const char* options_string) {
+    AVDictionary *options = NULL;
+    int ret;
+    
+    Ladder *l = calloc(1, sizeof(Ladder));
+    if (!l) {
+        free(input_data);
+        return NULL;
+    }
+    l->input.data = input_data;
+    l->input.size = input_size;
+    
+    // The renditions are collected in the registry, starting from an empty one
+    clear_output_files();
+    
+    if (options_string && options_string[0] && av_dict_parse_string(&options, options_string, "=", ":", 0) < 0) {
+        snprintf(l->error, sizeof(l->error), "Invalid transcode options '%s'", options_string);
+        goto end;
+    }
+    
+    if (parse_ladder(l, get_option(options, "ladder", "")) < 0) {
+        goto end;
+    }
+    
+    const AVOutputFormat *oformat = av_guess_format(format, NULL, NULL);
+    if (!oformat) {
+        snprintf(l->error, sizeof(l->error), "Unknown output format '%s'", format);
+        goto end;
+    }
+    if (format_writes_files(oformat) || oformat->video_codec == AV_CODEC_ID_NONE) {
+        snprintf(l->error, sizeof(l->error), "Renditions can't be written as %s, choose a video container", oformat->name);
+        goto end;
+    }
+    
+    ret = open_memory_input(&l->input, &l->fmt_ctx);
+    if (ret < 0) {
+        av_strerror(ret, l->error, sizeof(l->error));
+        goto end;
+    }
+    
+    // The first video and audio streams, as transcode() picks them, of the selected tracks if any
+    int tracks[MAX_OUTPUT_STREAMS];
+    int nb_tracks = av_dict_get(options, "tracks", NULL, 0) ?
+        parse_index_list(get_option(options, "tracks", ""), tracks, MAX_OUTPUT_STREAMS) : -1;
+    int drop_audio = atoi(get_option(options, "drop_audio", "0")) || !audio_codec_name[0] ||
+        oformat->audio_codec == AV_CODEC_ID_NONE;
+    for (unsigned int i = 0; i < l->fmt_ctx->nb_streams; i++) {
+        AVStream *stream = l->fmt_ctx->streams[i];
+        enum AVMediaType type = stream->codecpar->codec_type;
+        
+        if (nb_tracks >= 0 && !index_in_list(i, tracks, nb_tracks)) {
+            continue;
+        }
+        if (type == AVMEDIA_TYPE_VIDEO && !l->video_stream && !(stream->disposition & AV_DISPOSITION_ATTACHED_PIC)) {
+            l->video_stream = stream;
+        } else if (type == AVMEDIA_TYPE_AUDIO && !l->audio_stream && !drop_audio) {
+            l->audio_stream = stream;
+        }
+    }
+    
+    if (!l->video_stream || l->video_stream->codecpar->height <= 0) {
+        snprintf(l->error, sizeof(l->error), "A ladder needs an input with video");
+        goto end;
+    }
+    
+    if (measure_ladder_loudness(l, &options) < 0) {
+        goto end;
+    }
+    
+    for (int i = 0; i < l->nb_renditions; i++) {
+        if (open_rendition(l, &l->renditions[i], oformat, video_codec_name, audio_codec_name, audio_bitrate, options) < 0) {
+            goto end;
+        }
+    }
+    
+    l->packet = av_packet_alloc();
+    l->enc_pkt = av_packet_alloc();
+    l->frame = av_frame_alloc();
+    if (!l->packet || !l->enc_pkt || !l->frame) {
+        snprintf(l->error, sizeof(l->error), "Out of memory");
+    }
+    
+end:
+    av_dict_free(&options);
+    return l;
+}
+
+// Decode a packet of a shared stream (NULL drains the decoder) and hand every frame
+// to each rendition
+static int ladder_decode(Ladder *l, AVCodecContext *dec_ctx, const AVPacket *packet) {
+    int video = dec_ctx == l->video_dec;
+    
+    int ret = avcodec_send_packet(dec_ctx, packet);
+    if (ret < 0 && !(packet == NULL && ret == AVERROR_EOF)) {
+        return ret;
+    }
+    
+    while (1) {
+        ret = avcodec_receive_frame(dec_ctx, l->frame);
+        if (ret == AVERROR(EAGAIN) || ret == AVERROR_EOF) {
+            return 0;
+        } else if (ret < 0) {
+            return ret;
+        }
+        
+        // Encoding rescales the timestamp in place, each rendition starts from the decoded one
+        int64_t pts = l->frame->pts;
+        for (int i = 0; i < l->nb_renditions && ret >= 0; i++) {
+            Rendition *r = &l->renditions[i];
+            l->frame->pts = pts;
+            
+            if (video) {
+                ret = encode_video_frame(&r->streams[0], r->output_ctx, l->frame, l->enc_pkt);
+                if (pts != AV_NOPTS_VALUE) {
+                    int64_t start = l->fmt_ctx->start_time == AV_NOPTS_VALUE ? 0 : l->fmt_ctx->start_time;
+                    r->position = av_rescale_q(pts, l->video_stream->time_base, AV_TIME_BASE_Q) - start;
+                }
+            } else {
+                ret = encode_audio_frame(&r->streams[1], r->output_ctx, l->frame, l->enc_pkt);
+            }
+        }
+        
+        av_frame_unref(l->frame);
+        if (ret < 0) {
+            return ret;
+        }
+    }
+}
+
+// Drain the encoders of a rendition and complete its file
+static int finish_rendition(Ladder *l, Rendition *r) {
+    int ret = 0;
+    
+    for (int s = 0; s < r->nb_streams && ret >= 0; s++) {
+        ret = flush_output_stream(&r->streams[s], r->output_ctx, l->frame, l->enc_pkt);
+    }
+    if (ret >= 0) {
+        ret = av_write_trailer(r->output_ctx);
+    }
+    if (ret >= 0) {
+        ret = close_output_file(r->output_ctx, r->output_ctx->pb);
+        r->output_ctx->pb = NULL;
+    }
+    
+    r->finished = ret >= 0;
+    return ret;
+}
+
+// Decode and encode for a moment. Returns 1 while there is more to do, 0 once every
+// rendition is complete in the output registry, and a negative value on error, with
+// the message from ladder_error(). Once the input is read, each call completes one
+// rendition.
+EMSCRIPTEN_KEEPALIVE
+int ladder_step(Ladder* l) {
+    int ret = 0;
+    
+    if (l->error[0]) {
+        return -1;
+    }
+    
+    int64_t deadline = av_gettime_relative() + LADDER_STEP_TIME;
+    while (!l->eof && av_gettime_relative() < deadline) {
+        ret = av_read_frame(l->fmt_ctx, l->packet);
+        if (ret == AVERROR_EOF) {
+            l->eof = 1;
+            ret = ladder_decode(l, l->video_dec, NULL);
+            if (ret >= 0 && l->audio_dec) {
+                ret = ladder_decode(l, l->audio_dec, NULL);
+            }
+            break;
+        } else if (ret < 0) {
+            break;
+        }
+        
+        if (l->packet->stream_index == l->video_stream->index) {
+            ret = ladder_decode(l, l->video_dec, l->packet);
+        } else if (l->audio_stream && l->packet->stream_index == l->audio_stream->index) {
+            ret = ladder_decode(l, l->audio_dec, l->packet);
+        }
+        av_packet_unref(l->packet);
+        if (ret < 0) {
+            break;
+        }
+    }
+    
+    if (ret >= 0 && l->eof) {
+        for (int i = 0; i < l->nb_renditions; i++) {
+            if (!l->renditions[i].finished) {
+                ret = finish_rendition(l, &l->renditions[i]);
+                if (ret >= 0) {
+                    return i + 1 < l->nb_renditions;
+                }
+                break;
+            }
+        }
+    }
+    
+    if (ret < 0) {
+        // The encoders can't be picked up again part way through
+        av_strerror(ret, l->error, sizeof(l->error));
+        return -1;
+    }
+    return l->eof && l->renditions[l->nb_renditions - 1].finished ? 0 : 1;
+}
+
+EMSCRIPTEN_KEEPALIVE
+const char* ladder_error(Ladder* l) {
+    return l->error;
+}
+
+// Percentage done of a rendition; 100 only once its file is complete
+EMSCRIPTEN_KEEPALIVE
+int ladder_progress(Ladder* l, int rendition) {
+    if (rendition < 0 || rendition >= l->nb_renditions) {
+        return 0;
+    }
+    
+    Rendition *r = &l->renditions[rendition];
+    if (r->finished) {
+        return 100;
+    }
+    if (!l->fmt_ctx || l->fmt_ctx->duration <= 0) {
+        return 0;
+    }
+    return (int)av_clip64(r->position * 100 / l->fmt_ctx->duration, 0, 99);
+}
+
+EMSCRIPTEN_KEEPALIVE
+void ladder_close(Ladder* l) {
+    if (!l) {
+        return;
+    }
+    
+    for (int i = 0; i < l->nb_renditions; i++) {
+        Rendition *r = &l->renditions[i];
+        for (int s = 0; s < r->nb_streams; s++) {
+            free_output_stream(&r->streams[s]);
+        }
+        if (r->output_ctx) {
+            close_output_file(r->output_ctx, r->output_ctx->pb);
+            avformat_free_context(r->output_ctx);
+        }
+    }
+    avcodec_free_context(&l->video_dec);
+    avcodec_free_context(&l->audio_dec);
+    av_packet_free(&l->packet);
+    av_packet_free(&l->enc_pkt);
+    av_frame_free(&l->frame);
+    close_memory_input(&l->fmt_ctx);
+    free((void *)l->input.data);
+    free(l);
+}
+
+// A view of a rectangle of a YUV444P frame, sharing its buffers
+static void frame_view(const AVFrame *frame, AVFrame *view, int x, int y, int w, int h) {
+    view->format = frame->format;
+    view->width = w;
+    view->height = h;
+    for (int p = 0; p < 3; p++) {
+        view->data[p] = frame->data[p] + y * frame->linesize[p] + x;
+        view->linesize[p] = frame->linesize[p];
+    }
+}
+
+// Render a grid of evenly spaced frames into one PNG or JPEG image. Options:
+// columns, rows, tile_width, labels=1 (timestamp on each tile), header (text above the grid)
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* contact_sheet(uint8_t* input_data, size_t input_size, const char* format, const char* options_string) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    AVCodecContext *dec_ctx = NULL;
+    AVStream *stream = NULL;
+    AVDictionary *options = NULL;
+    AVFrame *frame = NULL, *sheet = NULL, *tile = NULL;
+    struct SwsContext *sws_ctx = NULL;
+    TextOverlay *label_overlay = NULL, *header_overlay = NULL;
+    int tiles_drawn = 0;
+    
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        return NULL;
+    }
+    char *error = result->error_message;
+    size_t error_size = sizeof(result->error_message);
+    
+    if (options_string && options_string[0] && av_dict_parse_string(&options, options_string, "=", ":", 0) < 0) {
+        snprintf(error, error_size, "Invalid contact sheet options");
+        goto end;
+    }
+    
+    if (strcmp(format, "png") != 0 && strcmp(format, "jpeg") != 0) {
+        snprintf(error, error_size, "Contact sheets are PNG or JPEG images");
+        goto end;
+    }
+    
+    int columns = av_clip(atoi(get_option(options, "columns", "4")), 1, 16);
+    int rows = av_clip(atoi(get_option(options, "rows", "4")), 1, 16);
+    int tile_width = av_clip(atoi(get_option(options, "tile_width", "320")), 32, 1920);
+    int labels = atoi(get_option(options, "labels", "0"));
+    const char *header = get_option(options, "header", "");
+    
+    if (open_video_input(&input, &fmt_ctx, &stream, &dec_ctx, error, error_size) < 0) {
+        goto end;
+    }
+    
+    double duration = fmt_ctx->duration != AV_NOPTS_VALUE ? fmt_ctx->duration / (double)AV_TIME_BASE :
+        stream->duration != AV_NOPTS_VALUE ? stream->duration * av_q2d(stream->time_base) : 0;
+    if (duration <= 0) {
+        snprintf(error, error_size, "The input duration is unknown");
+        goto end;
+    }
+    
+    // Tiles keep the display aspect ratio of the video
+    AVRational sar = stream->codecpar->sample_aspect_ratio.num > 0 ? stream->codecpar->sample_aspect_ratio : (AVRational){1, 1};
+    int display_width = FFMAX(1, (int)av_rescale(dec_ctx->width, sar.num, sar.den));
+    int tile_height = FFMAX(1, (int)av_rescale(tile_width, dec_ctx->height, display_width));
+    int spacing = FFMAX(tile_width / 40, 4);
+    int label_size = FFMAX(tile_height / 8, 10);
+    int header_height = 0;
+    
+    if (labels && init_label_overlay(&label_overlay, label_size, "bottom-right", "ffffff", "000000a0", error, error_size) < 0) {
+        goto end;
+    }
+    
+    if (header[0]) {
+        int nb_lines = 1;
+        for (const char *p = header; *p; p++) {
+            nb_lines += *p == '\n';
+        }
+        
+        if (init_label_overlay(&header_overlay, label_size * 3 / 2, "top-left", "ffffff", NULL, error, error_size) < 0) {
+            goto end;
+        }
+        // Half a line of margin above and below
+        header_height = (nb_lines + 1) * text_line_height(header_overlay);
+    }
+    
+    frame = av_frame_alloc();
+    sheet = av_frame_alloc();
+    tile = av_frame_alloc();
+    if (!frame || !sheet || !tile) {
+        snprintf(error, error_size, "Out of memory");
+        goto end;
+    }
+    
+    // 4:4:4 keeps the label edges sharp
+    sheet->format = AV_PIX_FMT_YUV444P;
+    sheet->width = columns * tile_width + (columns + 1) * spacing;
+    sheet->height = header_height + rows * tile_height + (rows + 1) * spacing;
+    sheet->sample_aspect_ratio = (AVRational){1, 1};
+    if (av_frame_get_buffer(sheet, 0) < 0) {
+        snprintf(error, error_size, "Out of memory");
+        goto end;
+    }
+    blend_mask(sheet, NULL, 0, 0, 0, sheet->width, sheet->height, 32, 32, 32, 255);
+    
+    for (int i = 0; i < columns * rows; i++) {
+        // Each tile shows the middle of its share of the input
+        double timestamp = duration * (i + 0.5) / (columns * rows);
+        int x = spacing + (i % columns) * (tile_width + spacing);
+        int y = header_height + spacing + (i / columns) * (tile_height + spacing);
+        
+        // Tiles that can't be decoded stay blank
+        if (decode_frame_at(fmt_ctx, stream, dec_ctx, timestamp, frame) < 0) {
+            continue;
+        }
+        
+        sws_ctx = sws_getCachedContext(sws_ctx, frame->width, frame->height, frame->format,
+                                       tile_width, tile_height, AV_PIX_FMT_YUV444P,
+                                       SWS_BICUBIC, NULL, NULL, NULL);
+        if (!sws_ctx) {
+            snprintf(error, error_size, "Failed to create scaling context");
+            goto end;
+        }
+        
+        frame_view(sheet, tile, x, y, tile_width, tile_height);
+        sws_scale(sws_ctx, (const uint8_t * const *)frame->data, frame->linesize, 0, frame->height,
+                  tile->data, tile->linesize);
+        av_frame_unref(frame);
+        tiles_drawn++;
+        
+        if (label_overlay) {
+            int64_t seconds = (int64_t)timestamp;
+            char label[32];
+            snprintf(label, sizeof(label), "%02d:%02d:%02d", (int)(seconds / 3600), (int)(seconds / 60 % 60), (int)(seconds % 60));
+            draw_text_block(label_overlay, tile, label);
+        }
+    }
+    
+    if (!tiles_drawn) {
+        snprintf(error, error_size, "Could not decode any frames");
+        goto end;
+    }
+    
+    if (header_overlay) {
+        frame_view(sheet, tile, 0, 0, sheet->width, header_height);
+        draw_text_block(header_overlay, tile, header);
+    }
+    
+    if (encode_image(sheet, format, 0, 0, result) >= 0) {
+        result->success = 1;
+    }
+    
+end:
+    free_text_overlay(&label_overlay);
+    free_text_overlay(&header_overlay);
+    sws_freeContext(sws_ctx);
+    av_frame_free(&tile);
+    av_frame_free(&sheet);
+    av_frame_free(&frame);
+    avcodec_free_context(&dec_ctx);
+    close_memory_input(&fmt_ctx);
+    av_dict_free(&options);
+    return result;
+}
+
+// Copy the input's cover picture out as an image file (JPEG, PNG, ...)
+EMSCRIPTEN_KEEPALIVE
+TranscodeResult* extract_cover(uint8_t* input_data, size_t input_size) {
+    MemoryInput input = { input_data, input_size, 0 };
+    AVFormatContext *fmt_ctx = NULL;
+    
+    TranscodeResult *result = calloc(1, sizeof(TranscodeResult));
+    if (!result) {
+        return NULL;
+    }
+    
+    int ret = open_memory_input(&input, &fmt_ctx);
+    if (ret < 0) {
+        av_strerror(ret, result->error_message, sizeof(result->error_message));
+        return result;
+    }
+    
+    for (unsigned int i = 0; i < fmt_ctx->nb_streams && !result->success; i++) {
+        AVStream *stream = fmt_ctx->streams[i];
+        if (!(stream->disposition & AV_DISPOSITION_ATTACHED_PIC) || stream->attached_pic.size <= 0) {
+            continue;
+        }
+        
+        result->output_data = malloc(stream->attached_pic.size);
+        if (!result->output_data) {
+            snprintf(result->error_message, sizeof(result->error_message), "Out of memory");
+            break;
+        }
+        memcpy(result->output_data, stream->attached_pic.data, stream->attached_pic.size);
+        result->output_size = stream->attached_pic.size;
+        result->success = 1;
+    }
+    
+    if (!result->success && !result->error_message[0]) {
+        snprintf(result->error_message, sizeof(result->error_message), "The input has no cover art");
+    }
     
+    close_memory_input(&fmt_ctx);
     return result;
 }
 
@@ -1,8 +1,10 @@
 use wasm_bindgen::prelude::*;
 
 mod app;
+mod chapters;
 mod components;
 mod ffmpeg;
+mod zip;
 
 #[wasm_bindgen(start)]
 pub fn run_app() {
@@ -0,0 +1,378 @@
+//! ZIP archives built in memory, with entries stored uncompressed, and read back
+//! from stored or deflated entries
+
+/// Entries past this need the ZIP64 end records
+const MAX_ENTRIES: usize = 0xFFFF;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// The current local time in MS-DOS format: (time, date)
+fn dos_time() -> (u16, u16) {
+    let now = js_sys::Date::new_0();
+    let time = ((now.get_hours() << 11) | (now.get_minutes() << 5) | (now.get_seconds() / 2)) as u16;
+    let date = (((now.get_full_year().max(1980) - 1980) << 9) | ((now.get_month() + 1) << 5) | now.get_date()) as u16;
+    (time, date)
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes files into a ZIP archive as they are added, so only the archive itself is kept
+pub struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+    time: u16,
+    date: u16,
+}
+
+impl Default for ZipWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        let (time, date) = dos_time();
+        Self { data: Vec::new(), entries: Vec::new(), time, date }
+    }
+
+    /// Append a file; `name` may contain "/" for folders
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) {
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32(contents),
+            size: contents.len() as u32,
+            offset: self.data.len() as u32,
+        };
+
+        self.put_u32(0x0403_4B50);
+        self.put_entry_fields(&entry);
+        self.put_u16(0); // Extra field length
+        self.data.extend_from_slice(entry.name.as_bytes());
+        self.data.extend_from_slice(contents);
+
+        self.entries.push(entry);
+    }
+
+    /// Write the central directory and return the archive
+    pub fn finish(mut self) -> Vec<u8> {
+        let directory_offset = self.data.len() as u32;
+
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.put_u32(0x0201_4B50);
+            self.put_u16(20); // Made by version 2.0
+            self.put_entry_fields(entry);
+            self.put_u16(0); // Extra field length
+            self.put_u16(0); // Comment length
+            self.put_u16(0); // Disk number
+            self.put_u16(0); // Internal attributes
+            self.put_u32(0); // External attributes
+            self.put_u32(entry.offset);
+            self.data.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let directory_size = self.data.len() as u32 - directory_offset;
+
+        // Too many entries for the classic end record, so the count goes in ZIP64 records
+        if entries.len() > MAX_ENTRIES {
+            let record_offset = self.data.len() as u64;
+
+            self.put_u32(0x0606_4B50);
+            self.put_u64(44); // Size of the rest of the record
+            self.put_u16(45); // Made by version 4.5
+            self.put_u16(45); // Needs version 4.5
+            self.put_u32(0); // This disk
+            self.put_u32(0); // Disk with the directory
+            self.put_u64(entries.len() as u64);
+            self.put_u64(entries.len() as u64);
+            self.put_u64(directory_size as u64);
+            self.put_u64(directory_offset as u64);
+
+            self.put_u32(0x0706_4B50);
+            self.put_u32(0); // Disk with the ZIP64 record
+            self.put_u64(record_offset);
+            self.put_u32(1); // Total disks
+        }
+
+        let count = entries.len().min(MAX_ENTRIES) as u16;
+        self.put_u32(0x0605_4B50);
+        self.put_u16(0); // This disk
+        self.put_u16(0); // Disk with the directory
+        self.put_u16(count);
+        self.put_u16(count);
+        self.put_u32(directory_size);
+        self.put_u32(directory_offset);
+        self.put_u16(0); // Comment length
+
+        self.data
+    }
+
+    /// Fields shared by local and central headers, from the needed version to the name length
+    fn put_entry_fields(&mut self, entry: &Entry) {
+        self.put_u16(20); // Needs version 2.0
+        self.put_u16(0x0800); // Names are UTF-8
+        self.put_u16(0); // Stored
+        self.put_u16(self.time);
+        self.put_u16(self.date);
+        self.put_u32(entry.crc);
+        self.put_u32(entry.size);
+        self.put_u32(entry.size);
+        self.put_u16(entry.name.len() as u16);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    data.get(pos..pos + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos..pos + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn u64_at(data: &[u8], pos: usize) -> Option<u64> {
+    u32_at(data, pos).zip(u32_at(data, pos + 4)).map(|(low, high)| ((high as u64) << 32) | low as u64)
+}
+
+/// Entry count and central directory offset, from the ZIP64 records when the classic
+/// end record has run out of room
+fn find_directory(data: &[u8]) -> Option<(usize, usize)> {
+    // The end record sits in the last 22 bytes plus up to 64 KiB of comment
+    let search_start = data.len().saturating_sub(22 + 0xFFFF);
+    let end = (search_start..=data.len().checked_sub(22)?)
+        .rev()
+        .find(|&pos| u32_at(data, pos) == Some(0x0605_4B50))?;
+
+    let count = u16_at(data, end + 10)?;
+    let offset = u32_at(data, end + 16)?;
+    if count != 0xFFFF && offset != 0xFFFF_FFFF {
+        return Some((count as usize, offset as usize));
+    }
+
+    let locator = end.checked_sub(20)?;
+    if u32_at(data, locator) != Some(0x0706_4B50) {
+        return Some((count as usize, offset as usize));
+    }
+    let record = u64_at(data, locator + 8)? as usize;
+    if u32_at(data, record) != Some(0x0606_4B50) {
+        return None;
+    }
+    Some((u64_at(data, record + 32)? as usize, u64_at(data, record + 48)? as usize))
+}
+
+/// Read every file out of a ZIP archive as (name, contents), in archive order.
+/// Folders are skipped; entries must be stored or deflated.
+pub fn read_archive(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let corrupt = || "Not a ZIP archive or the archive is damaged".to_string();
+    let (count, mut pos) = find_directory(data).ok_or_else(corrupt)?;
+    let mut files = Vec::new();
+
+    for _ in 0..count {
+        if u32_at(data, pos) != Some(0x0201_4B50) {
+            return Err(corrupt());
+        }
+        let method = u16_at(data, pos + 10).ok_or_else(corrupt)?;
+        let compressed_size = u32_at(data, pos + 20).ok_or_else(corrupt)? as usize;
+        let size = u32_at(data, pos + 24).ok_or_else(corrupt)? as usize;
+        let name_length = u16_at(data, pos + 28).ok_or_else(corrupt)? as usize;
+        let extra_length = u16_at(data, pos + 30).ok_or_else(corrupt)? as usize;
+        let comment_length = u16_at(data, pos + 32).ok_or_else(corrupt)? as usize;
+        let local = u32_at(data, pos + 42).ok_or_else(corrupt)? as usize;
+        let name = data.get(pos + 46..pos + 46 + name_length).ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_length + extra_length + comment_length;
+
+        if name.ends_with('/') {
+            continue;
+        }
+
+        // Sizes come from the directory, since local headers may defer them to a data descriptor
+        if u32_at(data, local) != Some(0x0403_4B50) {
+            return Err(corrupt());
+        }
+        let start = local + 30
+            + u16_at(data, local + 26).ok_or_else(corrupt)? as usize
+            + u16_at(data, local + 28).ok_or_else(corrupt)? as usize;
+        let contents = data.get(start..start + compressed_size).ok_or_else(corrupt)?;
+
+        let contents = match method {
+            0 => contents.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(contents, size)
+                .map_err(|_| format!("Could not decompress '{}'", name))?,
+            _ => return Err(format!("'{}' uses an unsupported compression method", name)),
+        };
+        files.push((name, contents));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer with a fixed timestamp, since the clock comes from JavaScript
+    fn writer() -> ZipWriter {
+        ZipWriter { data: Vec::new(), entries: Vec::new(), time: 0, date: 0 }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn writes_entries_and_central_directory() {
+        let files: [(&str, &[u8]); 3] = [
+            ("check.txt", b"123456789"),
+            ("frames/img2.png", b"\x89PNG two"),
+            ("empty", b""),
+        ];
+
+        let mut zip = writer();
+        for (name, contents) in files {
+            zip.add_file(name, contents);
+        }
+        let data = zip.finish();
+
+        let end = data.len() - 22;
+        assert_eq!(u32_at(&data, end), Some(0x0605_4B50));
+        assert_eq!(u16_at(&data, end + 8), Some(files.len() as u16));
+        assert_eq!(u16_at(&data, end + 10), Some(files.len() as u16));
+        let directory_size = u32_at(&data, end + 12).unwrap() as usize;
+        let directory_offset = u32_at(&data, end + 16).unwrap() as usize;
+        assert_eq!(directory_offset + directory_size, end);
+
+        let mut pos = directory_offset;
+        let mut local_offset = 0;
+        for (name, contents) in files {
+            assert_eq!(u32_at(&data, pos), Some(0x0201_4B50));
+            assert_eq!(u32_at(&data, pos + 16), Some(crc32(contents)));
+            assert_eq!(u32_at(&data, pos + 20), Some(contents.len() as u32));
+            assert_eq!(u32_at(&data, pos + 24), Some(contents.len() as u32));
+            assert_eq!(u16_at(&data, pos + 28), Some(name.len() as u16));
+            assert_eq!(&data[pos + 46..pos + 46 + name.len()], name.as_bytes());
+
+            // Each local header follows the previous entry's contents
+            assert_eq!(u32_at(&data, pos + 42), Some(local_offset as u32));
+            assert_eq!(u32_at(&data, local_offset), Some(0x0403_4B50));
+            assert_eq!(u32_at(&data, local_offset + 14), Some(crc32(contents)));
+            let start = local_offset + 30 + name.len();
+            assert_eq!(&data[start..start + contents.len()], contents);
+
+            local_offset = start + contents.len();
+            pos += 46 + name.len();
+        }
+        assert_eq!(local_offset, directory_offset);
+        assert_eq!(pos, end);
+
+        let read: Vec<(String, Vec<u8>)> = files.iter().map(|(name, contents)| (name.to_string(), contents.to_vec())).collect();
+        assert_eq!(read_archive(&data).unwrap(), read);
+    }
+
+    #[test]
+    fn zip64_end_records_past_max_entries() {
+        let mut zip = writer();
+        for index in 0..=MAX_ENTRIES {
+            zip.add_file(&index.to_string(), b"");
+        }
+        let data = zip.finish();
+
+        // The classic end record is saturated and points back through the locator
+        let end = data.len() - 22;
+        assert_eq!(u16_at(&data, end + 10), Some(0xFFFF));
+        let locator = end - 20;
+        assert_eq!(u32_at(&data, locator), Some(0x0706_4B50));
+        let record = u64_at(&data, locator + 8).unwrap() as usize;
+        assert_eq!(u32_at(&data, record), Some(0x0606_4B50));
+        assert_eq!(u64_at(&data, record + 32), Some(MAX_ENTRIES as u64 + 1));
+        assert_eq!(u64_at(&data, record + 48), u32_at(&data, end + 16).map(u64::from));
+    }
+
+    #[test]
+    fn rejects_damaged_archives() {
+        let mut zip = writer();
+        zip.add_file("a.txt", b"a");
+        let mut data = zip.finish();
+
+        assert!(read_archive(&data[..data.len() - 1]).is_err());
+        assert!(read_archive(b"not a zip").is_err());
+
+        // Point the only entry at something that isn't a local header
+        let directory_offset = u32_at(&data, data.len() - 6).unwrap() as usize;
+        data[directory_offset + 42] = 1;
+        assert!(read_archive(&data).is_err());
+    }
+
+    #[test]
+    fn zip64_round_trip() {
+        let count = MAX_ENTRIES + 2;
+        let mut zip = writer();
+        for index in 0..count {
+            zip.add_file(&format!("{}.txt", index), index.to_string().as_bytes());
+        }
+        let data = zip.finish();
+
+        // The classic end record is saturated, so the reader needs the ZIP64 records
+        assert_eq!(u16_at(&data, data.len() - 12), Some(0xFFFF));
+        assert_eq!(u32_at(&data, data.len() - 42), Some(0x0706_4B50));
+
+        let files = read_archive(&data).unwrap();
+        assert_eq!(files.len(), count);
+        for (index, (name, contents)) in files.iter().enumerate() {
+            assert_eq!(*name, format!("{}.txt", index));
+            assert_eq!(*contents, index.to_string().into_bytes());
+        }
+    }
+
+    #[test]
+    fn full_classic_directory_round_trip() {
+        let mut zip = writer();
+        for index in 0..MAX_ENTRIES {
+            zip.add_file(&index.to_string(), b"");
+        }
+        let data = zip.finish();
+
+        // Exactly 0xFFFF entries still fit without ZIP64 records
+        assert_ne!(u32_at(&data, data.len() - 42), Some(0x0706_4B50));
+        assert_eq!(read_archive(&data).unwrap().len(), MAX_ENTRIES);
+    }
+}